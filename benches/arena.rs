@@ -0,0 +1,61 @@
+//! Compare parsing and evaluating batches into boxed trees against one reused arena
+
+use compute_mcp::{
+    eval_expr_with_env, evaluate_batch_arena, evaluate_batch_with, parse_expression, Bindings, EvalOptions, ExprArena,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+
+/// A batch of distinct expressions, each nested `depth` operators deep
+fn batch(depth: usize) -> Vec<String> {
+    (0..200)
+        .map(|i| {
+            let mut expr = format!("{} / 7", i);
+            for level in 0..depth {
+                expr = format!("({} + {}) * 1.5 - max({}, 2)", expr, level, level % 3);
+            }
+            expr
+        })
+        .collect()
+}
+
+fn parse_and_eval(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_and_eval");
+    for depth in [1, 4, 16] {
+        let batch = batch(depth);
+        group.bench_with_input(BenchmarkId::new("boxed", depth), &batch, |b, batch| {
+            let env = Bindings::new();
+            b.iter(|| {
+                for expr in batch {
+                    let ast = parse_expression(expr).unwrap();
+                    black_box(eval_expr_with_env(&ast, &env).ok());
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("arena", depth), &batch, |b, batch| {
+            let env = Bindings::new();
+            let mut arena = ExprArena::new();
+            b.iter(|| {
+                for expr in batch {
+                    arena.clear();
+                    let root = arena.parse(expr, &Default::default()).unwrap();
+                    black_box(arena.eval(root, &env, Default::default()).ok());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn batch_api(c: &mut Criterion) {
+    let batch = batch(4);
+    let batch: Vec<&str> = batch.iter().map(String::as_str).collect();
+    let options = EvalOptions::default();
+    let mut group = c.benchmark_group("batch_api");
+    group.bench_function("evaluate_batch_with", |b| b.iter(|| black_box(evaluate_batch_with(&batch, &options))));
+    group.bench_function("evaluate_batch_arena", |b| b.iter(|| black_box(evaluate_batch_arena(&batch, &options))));
+    group.finish();
+}
+
+criterion_group!(benches, parse_and_eval, batch_api);
+criterion_main!(benches);
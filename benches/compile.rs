@@ -0,0 +1,51 @@
+//! Compare evaluating a parsed tree against its compiled form, across many variable values and in batches
+
+use compute_mcp::{compile, eval_expr_with_env, evaluate_with, parse_expression, Bindings, EvalBackend, EvalOptions};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+const PLOTTED: &str = "x ^ 3 - 2 * x ^ 2 + sin(x) * 10% + (x > 0 ? sqrt(x) : 0)";
+
+fn repeated_evaluation(c: &mut Criterion) {
+    let expr = parse_expression(PLOTTED).unwrap();
+    let compiled = compile(&expr);
+    let xs: Vec<f64> = (0..1000).map(|i| i as f64 / 100.0 - 5.0).collect();
+    let mut group = c.benchmark_group("repeated_evaluation");
+    group.bench_function("tree", |b| {
+        let mut env = Bindings::new();
+        b.iter(|| {
+            for &x in &xs {
+                env.insert("x".to_string(), x);
+                black_box(eval_expr_with_env(&expr, &env).ok());
+            }
+        })
+    });
+    group.bench_function("compiled", |b| {
+        b.iter(|| {
+            for &x in &xs {
+                black_box(compiled.eval(&[x]).ok());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn one_shot(c: &mut Criterion) {
+    // Each expression is parsed and evaluated once, as in a batch request
+    let batch: Vec<String> = (0..100).map(|i| PLOTTED.replace('x', &format!("({} / 7)", i))).collect();
+    let mut group = c.benchmark_group("one_shot");
+    for (name, eval_backend) in [("tree", EvalBackend::Tree), ("bytecode", EvalBackend::Bytecode)] {
+        let options = EvalOptions { eval_backend, ..Default::default() };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for expr in &batch {
+                    black_box(evaluate_with(expr, &options).ok());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, repeated_evaluation, one_shot);
+criterion_main!(benches);
@@ -0,0 +1,23 @@
+//! Compare the flat `a op b` fast path against the full pest/Pratt pipeline
+
+use compute_mcp::{eval_expr, evaluate, parse_expression};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+const SIMPLE: &[&str] = &["2 + 3", "1.5e3 * 2.25", "10 - 4 / 2"];
+
+fn simple_expressions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("simple_expressions");
+    for &expr in SIMPLE {
+        group.bench_function(format!("fast_path/{}", expr), |b| {
+            b.iter(|| evaluate(black_box(expr)))
+        });
+        group.bench_function(format!("full_parser/{}", expr), |b| {
+            b.iter(|| parse_expression(black_box(expr)).and_then(|ast| eval_expr(&ast)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, simple_expressions);
+criterion_main!(benches);
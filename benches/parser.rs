@@ -0,0 +1,27 @@
+//! Compare the recursive-descent parser against the pest/Pratt parser
+
+use compute_mcp::{descent, parse_expression};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+const EXPRESSIONS: &[&str] = &[
+    "2 + 3 * 4",
+    "-(1.5e3 - x) / (y + 2) * --7",
+    "((((1 + 2) * 3 - 4) / 5 + 6) * 7 - 8) / 9",
+];
+
+fn parsers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parsers");
+    for &expr in EXPRESSIONS {
+        group.bench_function(format!("descent/{}", expr), |b| {
+            b.iter(|| descent::parse_expression(black_box(expr)))
+        });
+        group.bench_function(format!("pest/{}", expr), |b| {
+            b.iter(|| parse_expression(black_box(expr)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parsers);
+criterion_main!(benches);
@@ -0,0 +1,76 @@
+//! Parse, eval and batch throughput across expression sizes and nesting depths
+//!
+//! Every input is generated from a fixed seed, so runs on different
+//! branches measure the same corpus. Parse and batch groups report bytes
+//! and expressions per second, to compare changes such as the arena AST,
+//! the bytecode backend or a different parser across input shapes.
+
+use compute_mcp::{
+    eval_expr, evaluate_batch_with, parse_expression, EvalBackend, EvalOptions, ExprGenerator, GeneratorConfig,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+
+const SEED: u64 = 0x5eed;
+
+/// Random expressions nested at most `depth` operators deep
+fn corpus(depth: u32, count: usize) -> Vec<String> {
+    let config = GeneratorConfig { max_depth: depth, decimals: 2, ..Default::default() };
+    ExprGenerator::new(config, SEED).take(count).map(|generated| generated.source).collect()
+}
+
+/// A flat chain of `terms` additions and multiplications, as long as it is shallow
+fn chain(terms: usize) -> String {
+    (1..=terms).map(|i| i.to_string()).collect::<Vec<_>>().join(" + 2 * ")
+}
+
+/// `depth` additions, each parenthesized inside the previous one, as in `(1 + (1 + 1))`
+fn nested(depth: usize) -> String {
+    format!("{}1{}", "(1 + ".repeat(depth), ")".repeat(depth))
+}
+
+/// Inputs by shape: flat chains of growing length and nestings of growing depth
+fn shapes() -> Vec<(String, String)> {
+    let chains = [10, 100, 1000].map(|terms| (format!("chain/{}", terms), chain(terms)));
+    let nestings = [10, 50, 200].map(|depth| (format!("nested/{}", depth), nested(depth)));
+    chains.into_iter().chain(nestings).collect()
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, expr) in shapes() {
+        group.throughput(Throughput::Bytes(expr.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &expr, |b, expr| {
+            b.iter(|| parse_expression(black_box(expr)))
+        });
+    }
+    group.finish();
+}
+
+fn eval(c: &mut Criterion) {
+    let mut group = c.benchmark_group("eval");
+    for (name, expr) in shapes() {
+        let ast = parse_expression(&expr).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(name), &ast, |b, ast| b.iter(|| eval_expr(black_box(ast))));
+    }
+    group.finish();
+}
+
+fn batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch");
+    for depth in [2, 4, 8] {
+        let corpus = corpus(depth, 500);
+        let batch: Vec<&str> = corpus.iter().map(String::as_str).collect();
+        group.throughput(Throughput::Elements(batch.len() as u64));
+        for (backend, eval_backend) in [("tree", EvalBackend::Tree), ("bytecode", EvalBackend::Bytecode)] {
+            let options = EvalOptions { eval_backend, ..Default::default() };
+            group.bench_with_input(BenchmarkId::new(backend, depth), &batch, |b, batch| {
+                b.iter(|| evaluate_batch_with(black_box(batch), &options))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, eval, batch);
+criterion_main!(benches);
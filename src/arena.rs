@@ -0,0 +1,585 @@
+//! Expression trees stored flat in an arena, for batches that parse and evaluate many expressions
+
+use crate::functions::{self, MAX_ARITY};
+use crate::prelude::*;
+use crate::{
+    add_percent, eval_expr_rounding, is_true, not_a_number, parse_literal, truth_value, AngleMode, Bindings,
+    ComputeError, ComputeParser, EvalOptions, EvaluationResult, Expr, ParseOptions, Relation, Result, RoundingMode,
+    Rule, PRATT_PARSER,
+};
+use core::cell::RefCell;
+use pest::iterators::{Pair, Pairs};
+use pest::Parser;
+
+/// A node's place in its `ExprArena`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(u32);
+
+/// A variable, function or text interned in an `ExprArena`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// The arguments of a call or elements of a list, stored contiguously in an `ExprArena`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Children {
+    start: u32,
+    len: u32,
+}
+
+/// One node of an arena-backed tree, an `Expr` whose children are `NodeId`s
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Node {
+    Number(f64),
+    Percent(f64),
+    Var(Symbol),
+    Add(NodeId, NodeId),
+    Sub(NodeId, NodeId),
+    Mul(NodeId, NodeId),
+    Div(NodeId, NodeId),
+    Pow(NodeId, NodeId),
+    Neg(NodeId),
+    Call(Symbol, Children),
+    Compare(Relation, NodeId, NodeId),
+    If(NodeId, NodeId, NodeId),
+    And(NodeId, NodeId),
+    Or(NodeId, NodeId),
+    Not(NodeId),
+    List(Children),
+    Range(NodeId, NodeId),
+    Text(Symbol),
+}
+
+/// Storage for expression trees without a heap allocation per node
+///
+/// Nodes live in one vector and refer to their children by `NodeId`, and
+/// names are interned once per arena. Children are always added before
+/// their parent. `clear` empties the arena but keeps its memory, so a batch
+/// that parses and evaluates each expression into the same arena
+/// allocates only while the arena grows to fit its largest expression.
+#[derive(Debug, Clone, Default)]
+pub struct ExprArena {
+    nodes: Vec<Node>,
+    children: Vec<NodeId>,
+    symbols: Vec<String>,
+    symbol_ids: HashMap<String, Symbol>,
+}
+
+impl ExprArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of nodes in the arena, over every tree it holds
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Remove every tree, keeping the memory for reuse; earlier `NodeId`s become invalid
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.children.clear();
+        self.symbols.clear();
+        self.symbol_ids.clear();
+    }
+
+    pub fn node(&self, id: NodeId) -> Node {
+        self.nodes[id.0 as usize]
+    }
+
+    /// Position of `id` among the arena's nodes, for tables indexed by node
+    pub(crate) fn index(&self, id: NodeId) -> usize {
+        id.0 as usize
+    }
+
+    pub fn children(&self, children: Children) -> &[NodeId] {
+        &self.children[children.start as usize..(children.start + children.len) as usize]
+    }
+
+    pub fn name(&self, symbol: Symbol) -> &str {
+        &self.symbols[symbol.0 as usize]
+    }
+
+    /// Add `node`, whose children must already be in the arena
+    pub fn push(&mut self, node: Node) -> NodeId {
+        self.nodes.push(node);
+        NodeId(self.nodes.len() as u32 - 1)
+    }
+
+    /// The symbol for `name`, added on first use
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbol_ids.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.symbols.len() as u32);
+        self.symbols.push(name.to_string());
+        self.symbol_ids.insert(name.to_string(), symbol);
+        symbol
+    }
+
+    /// Store `ids` contiguously as the children of a call or list
+    pub fn push_children(&mut self, ids: &[NodeId]) -> Children {
+        let start = self.children.len() as u32;
+        self.children.extend_from_slice(ids);
+        Children { start, len: ids.len() as u32 }
+    }
+
+    /// Copy a boxed tree into the arena
+    pub fn add(&mut self, expr: &Expr) -> NodeId {
+        let node = match expr {
+            Expr::Number(n) => Node::Number(*n),
+            Expr::Percent(n) => Node::Percent(*n),
+            Expr::Var(name) => Node::Var(self.intern(name)),
+            Expr::Text(text) => Node::Text(self.intern(text)),
+            Expr::Add(l, r) => Node::Add(self.add(l), self.add(r)),
+            Expr::Sub(l, r) => Node::Sub(self.add(l), self.add(r)),
+            Expr::Mul(l, r) => Node::Mul(self.add(l), self.add(r)),
+            Expr::Div(l, r) => Node::Div(self.add(l), self.add(r)),
+            Expr::Pow(l, r) => Node::Pow(self.add(l), self.add(r)),
+            Expr::And(l, r) => Node::And(self.add(l), self.add(r)),
+            Expr::Or(l, r) => Node::Or(self.add(l), self.add(r)),
+            Expr::Range(l, r) => Node::Range(self.add(l), self.add(r)),
+            Expr::Compare(relation, l, r) => Node::Compare(*relation, self.add(l), self.add(r)),
+            Expr::Neg(e) => Node::Neg(self.add(e)),
+            Expr::Not(e) => Node::Not(self.add(e)),
+            Expr::If(cond, then, otherwise) => Node::If(self.add(cond), self.add(then), self.add(otherwise)),
+            Expr::Call(name, args) => {
+                let ids: Vec<NodeId> = args.iter().map(|arg| self.add(arg)).collect();
+                Node::Call(self.intern(name), self.push_children(&ids))
+            }
+            Expr::List(elements) => {
+                let ids: Vec<NodeId> = elements.iter().map(|element| self.add(element)).collect();
+                Node::List(self.push_children(&ids))
+            }
+        };
+        self.push(node)
+    }
+
+    /// The tree rooted at `id` as a boxed `Expr`
+    pub fn to_expr(&self, id: NodeId) -> Expr {
+        let boxed = |id| Box::new(self.to_expr(id));
+        let all = |children| self.children(children).iter().map(|&id| self.to_expr(id)).collect();
+        match self.node(id) {
+            Node::Number(n) => Expr::Number(n),
+            Node::Percent(n) => Expr::Percent(n),
+            Node::Var(name) => Expr::Var(self.name(name).to_string()),
+            Node::Text(text) => Expr::Text(self.name(text).to_string()),
+            Node::Add(l, r) => Expr::Add(boxed(l), boxed(r)),
+            Node::Sub(l, r) => Expr::Sub(boxed(l), boxed(r)),
+            Node::Mul(l, r) => Expr::Mul(boxed(l), boxed(r)),
+            Node::Div(l, r) => Expr::Div(boxed(l), boxed(r)),
+            Node::Pow(l, r) => Expr::Pow(boxed(l), boxed(r)),
+            Node::And(l, r) => Expr::And(boxed(l), boxed(r)),
+            Node::Or(l, r) => Expr::Or(boxed(l), boxed(r)),
+            Node::Range(l, r) => Expr::Range(boxed(l), boxed(r)),
+            Node::Compare(relation, l, r) => Expr::Compare(relation, boxed(l), boxed(r)),
+            Node::Neg(e) => Expr::Neg(boxed(e)),
+            Node::Not(e) => Expr::Not(boxed(e)),
+            Node::If(cond, then, otherwise) => Expr::If(boxed(cond), boxed(then), boxed(otherwise)),
+            Node::Call(name, args) => Expr::Call(self.name(name).to_string(), all(args)),
+            Node::List(elements) => Expr::List(all(elements)),
+        }
+    }
+
+    /// The ids of the direct children of `id`
+    fn for_each_child(&self, id: NodeId, mut f: impl FnMut(NodeId)) {
+        match self.node(id) {
+            Node::Number(_) | Node::Percent(_) | Node::Var(_) | Node::Text(_) => {}
+            Node::Neg(e) | Node::Not(e) => f(e),
+            Node::Add(l, r) | Node::Sub(l, r) | Node::Mul(l, r) | Node::Div(l, r) | Node::Pow(l, r) => {
+                f(l);
+                f(r);
+            }
+            Node::Compare(_, l, r) | Node::And(l, r) | Node::Or(l, r) | Node::Range(l, r) => {
+                f(l);
+                f(r);
+            }
+            Node::If(cond, then, otherwise) => [cond, then, otherwise].into_iter().for_each(f),
+            Node::Call(_, children) | Node::List(children) => self.children(children).iter().copied().for_each(f),
+        }
+    }
+
+    /// Levels and nodes in the tree rooted at `id`, as `Expr::depth` and `Expr::node_count` count them
+    pub fn size(&self, id: NodeId) -> (usize, usize) {
+        let (mut depth, mut nodes) = (0, 0);
+        let mut stack = vec![(id, 1)];
+        while let Some((id, level)) = stack.pop() {
+            depth = depth.max(level);
+            nodes += 1;
+            self.for_each_child(id, |child| stack.push((child, level + 1)));
+        }
+        (depth, nodes)
+    }
+
+    /// Parse `expr` straight into the arena, giving the root of the tree `parse_expression_with` would build
+    pub fn parse(&mut self, expr: &str, options: &ParseOptions) -> Result<NodeId> {
+        options.check_limits(expr)?;
+        let expr = &*options.group_separators(expr);
+        let rule = if options.implicit_multiplication { Rule::equation_implicit } else { Rule::equation };
+        let pairs = ComputeParser::parse(rule, expr).map_err(|e| ComputeError::ParseError(Box::new(e)))?;
+        let expr_pair = pairs
+            .into_iter()
+            .next()
+            .ok_or_else(|| ComputeError::InvalidStructure("No expression found".into()))?;
+        let root = self.parse_pairs(expr_pair.into_inner(), options)?;
+        if options.max_depth.is_some() || options.max_nodes.is_some() {
+            let (depth, nodes) = self.size(root);
+            options.check_size(depth, nodes)?;
+        }
+        Ok(root)
+    }
+
+    fn parse_pairs(&mut self, pairs: Pairs<Rule>, options: &ParseOptions) -> Result<NodeId> {
+        let branches = pairs.clone().find(|pair| pair.as_rule() == Rule::conditional);
+        // The operator callbacks each need the arena, one at a time
+        let arena = RefCell::new(&mut *self);
+        let id = PRATT_PARSER
+            .map_primary(|primary| arena.borrow_mut().parse_primary(primary, options))
+            .map_prefix(|op, rhs| {
+                let node = match op.as_rule() {
+                    Rule::neg => Node::Neg(rhs?),
+                    Rule::not => Node::Not(rhs?),
+                    rule => {
+                        return Err(ComputeError::InvalidStructure(format!("Unknown prefix operator: {:?}", rule)))
+                    }
+                };
+                Ok(arena.borrow_mut().push(node))
+            })
+            .map_infix(|lhs, op, rhs| {
+                let (l, r) = (lhs?, rhs?);
+                let node = match op.as_rule() {
+                    Rule::add => Node::Add(l, r),
+                    Rule::subtract => Node::Sub(l, r),
+                    Rule::multiply | Rule::implicit_multiply => Node::Mul(l, r),
+                    Rule::divide => Node::Div(l, r),
+                    Rule::power => Node::Pow(l, r),
+                    Rule::and => Node::And(l, r),
+                    Rule::or => Node::Or(l, r),
+                    rule => match Relation::from_rule(rule) {
+                        Some(relation) => Node::Compare(relation, l, r),
+                        None => {
+                            return Err(ComputeError::InvalidStructure(format!("Unknown infix operator: {:?}", rule)))
+                        }
+                    },
+                };
+                Ok(arena.borrow_mut().push(node))
+            })
+            .parse(pairs.filter(|pair| pair.as_rule() != Rule::conditional))?;
+
+        let Some(branches) = branches else {
+            return Ok(id);
+        };
+        let mut ids = Vec::with_capacity(2);
+        for branch in branches.into_inner() {
+            ids.push(self.parse_pairs(branch.into_inner(), options)?);
+        }
+        match ids[..] {
+            [then, otherwise] => Ok(self.push(Node::If(id, then, otherwise))),
+            _ => Err(ComputeError::InvalidStructure("Missing branch".into())),
+        }
+    }
+
+    fn parse_primary(&mut self, primary: Pair<Rule>, options: &ParseOptions) -> Result<NodeId> {
+        let node = match primary.as_rule() {
+            Rule::number => Node::Number(parse_literal(primary.as_str(), options)?),
+            Rule::percent => Node::Percent(parse_literal(primary.as_str().trim_end_matches('%'), options)?),
+            Rule::uncertain => {
+                let mut args = Vec::new();
+                for number in primary.into_inner() {
+                    args.push(self.push(Node::Number(parse_literal(number.as_str(), options)?)));
+                }
+                Node::Call(self.intern("pm"), self.push_children(&args))
+            }
+            Rule::ident => Node::Var(self.intern(primary.as_str())),
+            Rule::text => {
+                let quoted = primary.as_str();
+                Node::Text(self.intern(&quoted[1..quoted.len() - 1]))
+            }
+            Rule::expr => return self.parse_pairs(primary.into_inner(), options),
+            Rule::call => {
+                let mut inner = primary.into_inner();
+                let name = inner
+                    .next()
+                    .ok_or_else(|| ComputeError::InvalidStructure("Missing function name in call".into()))?;
+                let mut inner = inner.peekable();
+                let mut args = Vec::new();
+                while let Some(arg) = inner.next() {
+                    let arg = self.parse_pairs(arg.into_inner(), options)?;
+                    args.push(match inner.next_if(|next| next.as_rule() == Rule::range) {
+                        Some(range) => {
+                            let upper = range
+                                .into_inner()
+                                .next()
+                                .ok_or_else(|| ComputeError::InvalidStructure("Missing range bound".into()))?;
+                            let upper = self.parse_pairs(upper.into_inner(), options)?;
+                            self.push(Node::Range(arg, upper))
+                        }
+                        None => arg,
+                    });
+                }
+                Node::Call(self.intern(name.as_str()), self.push_children(&args))
+            }
+            Rule::list => {
+                let mut elements = Vec::new();
+                for element in primary.into_inner() {
+                    elements.push(self.parse_pairs(element.into_inner(), options)?);
+                }
+                Node::List(self.push_children(&elements))
+            }
+            Rule::primary => {
+                let mut inner = primary.into_inner().peekable();
+                let mut neg_count = 0;
+                while inner.next_if(|pair| pair.as_rule() == Rule::neg).is_some() {
+                    neg_count += 1;
+                }
+                let atom = inner
+                    .next()
+                    .ok_or_else(|| ComputeError::InvalidStructure("Missing atom in primary".into()))?;
+                let mut id = match atom.as_rule() {
+                    Rule::number
+                    | Rule::percent
+                    | Rule::uncertain
+                    | Rule::ident
+                    | Rule::call
+                    | Rule::list
+                    | Rule::text
+                    | Rule::expr => self.parse_primary(atom, options)?,
+                    rule => return Err(ComputeError::InvalidStructure(format!("Unexpected atom: {:?}", rule))),
+                };
+                for _ in 0..neg_count {
+                    id = self.push(Node::Neg(id));
+                }
+                return Ok(id);
+            }
+            rule => return Err(ComputeError::InvalidStructure(format!("Unexpected primary: {:?}", rule))),
+        };
+        Ok(self.push(node))
+    }
+
+    /// Evaluate the tree rooted at `id` exactly as `eval_expr_in` evaluates the same `Expr`
+    pub fn eval(&self, id: NodeId, env: &Bindings, angle_mode: AngleMode) -> Result<f64> {
+        self.eval_rounding(id, env, angle_mode, RoundingMode::default())
+    }
+
+    /// Like `eval`, with `round` rounding in `rounding`
+    pub(crate) fn eval_rounding(
+        &self,
+        id: NodeId,
+        env: &Bindings,
+        angle_mode: AngleMode,
+        rounding: RoundingMode,
+    ) -> Result<f64> {
+        self.eval_node(id, env, angle_mode, rounding, &|child| self.eval_rounding(child, env, angle_mode, rounding))
+    }
+
+    /// Evaluate the node `id`, getting the value of each child it needs from `eval`
+    pub(crate) fn eval_node(
+        &self,
+        id: NodeId,
+        env: &Bindings,
+        angle_mode: AngleMode,
+        rounding: RoundingMode,
+        eval: &dyn Fn(NodeId) -> Result<f64>,
+    ) -> Result<f64> {
+        if let Some((a, p)) = self.percent_change(id) {
+            return Ok(add_percent(eval(a)?, p));
+        }
+        match self.node(id) {
+            Node::Number(n) => Ok(n),
+            Node::Percent(n) => Ok(n / 100.0),
+            Node::Var(name) => {
+                let name = self.name(name);
+                env.get(name).copied().ok_or_else(|| ComputeError::UndefinedVariable(name.to_string()))
+            }
+            Node::Add(l, r) => Ok(eval(l)? + eval(r)?),
+            Node::Sub(l, r) => Ok(eval(l)? - eval(r)?),
+            Node::Mul(l, r) => Ok(eval(l)? * eval(r)?),
+            Node::Div(l, r) => {
+                let divisor = eval(r)?;
+                if divisor != 0.0 {
+                    Ok(eval(l)? / divisor)
+                } else {
+                    Err(ComputeError::DivisionByZero)
+                }
+            }
+            Node::Pow(l, r) => Ok(eval(l)?.powf(eval(r)?)),
+            Node::Neg(e) => eval(e).map(|n| -n),
+            Node::Call(name, args) => self.call(id, name, args, env, angle_mode, rounding, eval),
+            Node::Compare(relation, l, r) => Ok(relation.eval(eval(l)?, eval(r)?)),
+            Node::If(cond, then, otherwise) => eval(if is_true(eval(cond)?) { then } else { otherwise }),
+            Node::And(l, r) | Node::Or(l, r) => {
+                // A true left operand decides `or` and a false one decides `and`
+                let decisive = matches!(self.node(id), Node::Or(..));
+                let holds = if is_true(eval(l)?) == decisive { decisive } else { is_true(eval(r)?) };
+                Ok(truth_value(holds))
+            }
+            Node::Not(e) => Ok(truth_value(!is_true(eval(e)?))),
+            Node::List(_) | Node::Range(..) | Node::Text(_) => Err(not_a_number(&self.to_expr(id))),
+        }
+    }
+
+    /// `a + p%` or `a - p%` as `(a, ±p)`, like `Expr::percent_change`
+    fn percent_change(&self, id: NodeId) -> Option<(NodeId, f64)> {
+        match self.node(id) {
+            Node::Add(a, r) => match self.node(r) {
+                Node::Percent(p) => Some((a, p)),
+                _ => None,
+            },
+            Node::Sub(a, r) => match self.node(r) {
+                Node::Percent(p) => Some((a, -p)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Evaluate a call; calls taking lists, units or giving text go through the boxed evaluator
+    #[allow(clippy::too_many_arguments)]
+    fn call(
+        &self,
+        id: NodeId,
+        name: Symbol,
+        args: Children,
+        env: &Bindings,
+        angle_mode: AngleMode,
+        rounding: RoundingMode,
+        eval: &dyn Fn(NodeId) -> Result<f64>,
+    ) -> Result<f64> {
+        let args = self.children(args);
+        let function = functions::resolve(self.name(name), args.len())?;
+        if function.takes_list() || function.takes_units() || function.radix().is_some() {
+            return eval_expr_rounding(&self.to_expr(id), env, angle_mode, rounding);
+        }
+        if args.len() > MAX_ARITY {
+            let values = args.iter().map(|&arg| eval(arg)).collect::<Result<Vec<_>>>()?;
+            return Ok(function.eval(&values, angle_mode, rounding));
+        }
+        let mut values = [0.0; MAX_ARITY];
+        for (value, &arg) in values.iter_mut().zip(args) {
+            *value = eval(arg)?;
+        }
+        Ok(function.eval(&values[..args.len()], angle_mode, rounding))
+    }
+}
+
+/// Evaluate a batch like `evaluate_batch_with`, parsing every expression into one reused arena
+///
+/// Only plain float arithmetic with the pest parser runs in the arena; any
+/// other `arithmetic`, `backend` or `eval_backend`, a `fuel` budget,
+/// `compensated_sums`, `verify`, `uncertainty` or `metadata` hands the whole
+/// batch to `evaluate_batch_with`. Results are identical either way.
+pub fn evaluate_batch_arena(expressions: &[&str], options: &EvalOptions) -> Vec<EvaluationResult> {
+    if *options != (EvalOptions { parse: options.parse.clone(), ..arena_options(options) }) {
+        return crate::evaluate_batch_with(expressions, options);
+    }
+    let mut arena = ExprArena::new();
+    let env = Bindings::new();
+    expressions
+        .iter()
+        .map(|&expr| {
+            arena.clear();
+            let value = options
+                .parse
+                .check_len(expr)
+                .and_then(|_| match expr.trim() {
+                    "" => Err(ComputeError::EmptyExpression),
+                    trimmed => arena.parse(trimmed, &options.parse),
+                })
+                .and_then(|root| arena.eval_rounding(root, &env, options.angle_mode, options.rounding))
+                .and_then(|value| options.check_finite(value))
+                .map(|value| options.round_result(value));
+            EvaluationResult {
+                expression: expr.to_string(),
+                value,
+                verification: None,
+                metadata: None,
+                uncertainty: None,
+            }
+        })
+        .collect()
+}
+
+/// `options` with every setting the arena does not implement at its default
+fn arena_options(options: &EvalOptions) -> EvalOptions {
+    EvalOptions {
+        rounding: options.rounding,
+        result_places: options.result_places,
+        result_rounding: options.result_rounding,
+        angle_mode: options.angle_mode,
+        strict_finite: options.strict_finite,
+        number_format: options.number_format,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval_expr_in, evaluate_batch_with, parse_expression, parse_expression_with};
+
+    const SOURCES: &[&str] = &[
+        "1 + 2 * 3 - 4 / 5 ^ 2",
+        "-(-x) ^ 2",
+        "(1 / 0) * y",
+        "50 - 10% + x + 5%",
+        "sum(1..x) + max(x, 2, 3, 5, -1) + mean([x, y, 1])",
+        "x > 3 and not (x == 4) ? 1 : 2",
+        "y < 0 or 1 / 0",
+        "x ? y : nope(1)",
+        "convert(x, \"ft\", \"in\") + sin(90)",
+        "atan2(x)",
+        "[1, 2] + 1",
+        "z * 2",
+    ];
+
+    #[test]
+    fn test_matches_boxed_tree() {
+        let env: Bindings = [("x".to_string(), 4.0), ("y".to_string(), -0.5)].into_iter().collect();
+        let mut arena = ExprArena::new();
+        for source in SOURCES {
+            let expr = parse_expression(source).unwrap();
+            let root = arena.parse(source, &ParseOptions::default()).unwrap();
+            assert_eq!(arena.to_expr(root), expr, "{}", source);
+            let copied = arena.add(&expr);
+            assert_eq!(arena.to_expr(copied), expr, "{}", source);
+            assert_eq!(arena.size(root), (expr.depth(), expr.node_count()), "{}", source);
+            let expected = eval_expr_in(&expr, &env, AngleMode::Degrees);
+            let value = arena.eval(root, &env, AngleMode::Degrees);
+            assert!(value == expected || value.as_ref().is_ok_and(|v| v.is_nan()), "{}: {:?}", source, value);
+        }
+        // Names are stored once however often they occur
+        assert_eq!(arena.symbols.iter().filter(|name| *name == "x").count(), 1);
+    }
+
+    #[test]
+    fn test_parse_errors_and_limits() {
+        let mut arena = ExprArena::new();
+        let options = ParseOptions::default();
+        assert!(matches!(arena.parse("2 +", &options), Err(ComputeError::ParseError(_))));
+        assert_eq!(arena.parse("1e999", &options), Err(ComputeError::Overflow));
+        let limited = ParseOptions { max_depth: Some(3), ..Default::default() };
+        assert_eq!(
+            arena.parse("((1 + 2) * 3) - 4", &limited).unwrap_err(),
+            parse_expression_with("((1 + 2) * 3) - 4", &limited).unwrap_err()
+        );
+        assert!(arena.parse("1 + 2", &limited).is_ok());
+
+        arena.clear();
+        assert!(arena.is_empty());
+    }
+
+    #[test]
+    fn test_batch() {
+        let batch = ["1 + 2", "", "2 ^ 0.5", "1 / 0", "ln(0)", "round(2 / 3, 2)"];
+        for options in [
+            EvalOptions::default(),
+            EvalOptions { result_places: Some(1), strict_finite: true, ..Default::default() },
+            EvalOptions { verify: Some(1e-9), ..Default::default() },
+        ] {
+            assert_eq!(evaluate_batch_arena(&batch, &options), evaluate_batch_with(&batch, &options));
+        }
+    }
+}
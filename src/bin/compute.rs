@@ -0,0 +1,677 @@
+//! Command-line tools for working with arithmetic expressions
+
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use compute_mcp::compare::DEFAULT_TOLERANCE;
+use compute_mcp::session::split_assignment;
+use compute_mcp::{
+    classify, diagnose, equivalent, evaluate, evaluate_integer, evaluate_lines_measured, evaluate_measured,
+    evaluate_spanned, evaluate_value, format_source, hint, interpolate_env, latex_to_source, parse_expression,
+    parse_rpn, simplify_with_steps, suggest_fix, to_latex, to_mathml, to_source, tokenize, BatchSummary, Bindings,
+    ComputeError, EvalOptions, EvaluationResult, ExprGenerator, GeneratorConfig, Notation, NumberFormat,
+    ResultMetadata, RoundingMode, Session, Severity, TokenClass, Value, MAX_FORMAT_DIGITS,
+};
+use serde_json::json;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Parser)]
+#[command(name = "compute", version, about = "Arithmetic expression tools")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print random valid expressions, one per line
+    Generate {
+        /// Number of expressions to generate
+        #[arg(long, default_value_t = 10)]
+        count: usize,
+        /// Maximum operator nesting depth
+        #[arg(long, default_value_t = 4)]
+        depth: u32,
+        /// Seed for reproducible output (defaults to the current time)
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Smallest literal value
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        min: f64,
+        /// Largest literal value
+        #[arg(long, default_value_t = 100.0, allow_negative_numbers = true)]
+        max: f64,
+        /// Decimal places kept on literals
+        #[arg(long, default_value_t = 0)]
+        decimals: u32,
+        /// Emit JSON lines with each expression's expected value
+        #[arg(long)]
+        json: bool,
+    },
+    /// Evaluate one expression per line, printing each result as soon as it is ready; exits 1 if any fail
+    Batch {
+        /// File of expressions ("-" for stdin)
+        #[arg(default_value = "-")]
+        path: PathBuf,
+        /// Emit one JSON object per line
+        #[arg(long)]
+        json: bool,
+        /// Add each result's parse and evaluation time, tree size and exactness to the JSON
+        #[arg(long, requires = "json")]
+        metadata: bool,
+        /// Print counts and the min, max and mean of the results to stderr when done
+        #[arg(long)]
+        summary: bool,
+        #[command(flatten)]
+        variables: Variables,
+        #[command(flatten)]
+        rounding: Rounding,
+    },
+    /// Report errors and lint warnings for an expression; exits 1 if there are errors
+    Check {
+        #[arg(allow_hyphen_values = true)]
+        expr: String,
+    },
+    /// Check whether two expressions are equivalent; exits 1 if they are not
+    Equiv {
+        left: String,
+        right: String,
+        /// Maximum absolute or relative difference between values
+        #[arg(long, default_value_t = DEFAULT_TOLERANCE)]
+        tolerance: f64,
+    },
+    /// Evaluate an expression and print its value
+    Eval {
+        #[arg(allow_hyphen_values = true)]
+        expr: String,
+        /// Show an error with the expression and a caret under the part that failed
+        #[arg(long)]
+        pretty: bool,
+        /// Compute exactly and print every digit of a whole-number result, as in `2 ^ 200`
+        #[arg(long, conflicts_with_all = ["round", "decimals", "significant", "thousands_separator"])]
+        integer: bool,
+        /// Read the expression in Reverse Polish Notation, as in `3 4 + 2 *`
+        #[arg(long, conflicts_with = "env")]
+        rpn: bool,
+        #[command(flatten)]
+        variables: Variables,
+        #[command(flatten)]
+        rounding: Rounding,
+    },
+    /// Reprint an expression with canonical spacing and minimal parentheses
+    Fmt {
+        #[arg(allow_hyphen_values = true)]
+        expr: String,
+        /// Read the expression as LaTeX math, as in `\frac{1}{2} + 3 \cdot 4`
+        #[arg(long)]
+        latex: bool,
+    },
+    /// Print the LaTeX rendering of an expression
+    Latex {
+        #[arg(allow_hyphen_values = true)]
+        expr: String,
+        /// Print presentation MathML instead
+        #[arg(long)]
+        mathml: bool,
+    },
+    /// Evaluate expressions and `name = expr` assignments interactively
+    Repl {
+        #[command(flatten)]
+        rounding: Rounding,
+    },
+    /// Print the simplified form of an expression
+    Simplify {
+        #[arg(allow_hyphen_values = true)]
+        expr: String,
+        /// Print each rewrite before the result
+        #[arg(long)]
+        steps: bool,
+    },
+    /// Print the token stream with byte spans and categories
+    Tokens {
+        #[arg(allow_hyphen_values = true)]
+        expr: String,
+        /// Emit one JSON object per token
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Args)]
+struct Variables {
+    /// Bind a variable for the expressions; VALUE may itself be an expression
+    #[arg(short = 'D', long = "define", value_name = "NAME=VALUE", value_parser = parse_define)]
+    define: Vec<(String, f64)>,
+    /// Replace `$NAME` and `${NAME}` with numeric environment variables before evaluating
+    #[arg(long)]
+    env: bool,
+}
+
+impl Variables {
+    fn bindings(&self) -> Bindings {
+        self.define.iter().cloned().collect()
+    }
+
+    /// The result of `expr` with its metadata, see `evaluate_measured`, reported for the text as written
+    fn evaluate_measured(&self, expr: &str, bindings: &Bindings, options: &EvalOptions) -> EvaluationResult {
+        let result = if self.env {
+            match interpolate_env(expr) {
+                Ok(source) => evaluate_measured(&source, bindings, options),
+                Err(e) => EvaluationResult {
+                    expression: expr.to_string(),
+                    value: Err(e),
+                    verification: None,
+                    metadata: None,
+                    uncertainty: None,
+                },
+            }
+        } else {
+            evaluate_measured(expr, bindings, options)
+        };
+        EvaluationResult { expression: expr.to_string(), ..result }
+    }
+
+    /// The value of `expr`, which may be a list or the text of `to_hex` and the like, rounded by `options`
+    fn evaluate_value(&self, expr: &str, bindings: &Bindings, options: &EvalOptions) -> compute_mcp::Result<Value> {
+        if self.env {
+            evaluate_value(&interpolate_env(expr)?, bindings, options)
+        } else {
+            evaluate_value(expr, bindings, options)
+        }
+    }
+
+    /// The caret diagram of `error` from evaluating `expr`, if re-evaluating it can locate the error
+    fn render_error(
+        &self,
+        expr: &str,
+        error: &ComputeError,
+        bindings: &Bindings,
+        options: &EvalOptions,
+    ) -> Option<String> {
+        let source = if self.env { interpolate_env(expr).ok()? } else { expr.to_string() };
+        let located = evaluate_spanned(&source, bindings, options).err()?;
+        (located.error == *error).then(|| located.render(&source))
+    }
+
+    /// Every digit of the whole-number value of `expr`
+    fn evaluate_integer(&self, expr: &str, bindings: &Bindings) -> compute_mcp::Result<String> {
+        let options = EvalOptions::default();
+        let value = if self.env {
+            evaluate_integer(&interpolate_env(expr)?, bindings, &options)
+        } else {
+            evaluate_integer(expr, bindings, &options)
+        };
+        value.map(|n| n.to_string())
+    }
+}
+
+#[derive(Args)]
+struct Rounding {
+    /// Round every final result in this direction; `round()` and the like in the expression are unaffected
+    #[arg(long, value_enum, value_name = "DIRECTION")]
+    round: Option<RoundDirection>,
+    /// Decimal places kept by --round; negative values round to tens, hundreds, ...
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true, requires = "round")]
+    places: i32,
+    /// Print results with exactly this many digits after the decimal point
+    #[arg(long, value_name = "DIGITS", value_parser = clap::value_parser!(u32).range(..=MAX_FORMAT_DIGITS as i64))]
+    decimals: Option<u32>,
+    /// Print results to this many significant figures
+    #[arg(
+        long,
+        value_name = "FIGURES",
+        conflicts_with = "decimals",
+        value_parser = clap::value_parser!(u32).range(..=MAX_FORMAT_DIGITS as i64)
+    )]
+    significant: Option<u32>,
+    /// Print results in scientific or engineering notation
+    #[arg(long, value_enum, default_value_t = NotationChoice::Auto)]
+    notation: NotationChoice,
+    /// Separate groups of three integer digits with this character, as in `1,234,567`
+    #[arg(long, value_name = "CHAR")]
+    thousands_separator: Option<char>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum NotationChoice {
+    /// Positional, unless the magnitude is extreme
+    Auto,
+    /// One digit before the point, as in `1.2345e3`
+    Scientific,
+    /// Exponents that are multiples of three, as in `12.5e-6`
+    Engineering,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RoundDirection {
+    /// Toward positive infinity
+    Up,
+    /// Toward negative infinity
+    Down,
+    /// To the nearest value, ties away from zero
+    Nearest,
+    TowardZero,
+}
+
+impl Rounding {
+    fn options(&self) -> EvalOptions {
+        let number_format = NumberFormat {
+            notation: match self.notation {
+                NotationChoice::Auto => Notation::Auto,
+                NotationChoice::Scientific => Notation::Scientific,
+                NotationChoice::Engineering => Notation::Engineering,
+            },
+            decimals: self.decimals,
+            significant: self.significant,
+            thousands_separator: self.thousands_separator,
+        };
+        let Some(direction) = self.round else {
+            return EvalOptions { number_format, ..Default::default() };
+        };
+        let result_rounding = match direction {
+            RoundDirection::Up => RoundingMode::Ceiling,
+            RoundDirection::Down => RoundingMode::Floor,
+            RoundDirection::Nearest => RoundingMode::HalfUp,
+            RoundDirection::TowardZero => RoundingMode::TowardZero,
+        };
+        EvalOptions {
+            result_rounding: Some(result_rounding),
+            result_places: Some(self.places),
+            number_format,
+            ..Default::default()
+        }
+    }
+}
+
+fn parse_define(arg: &str) -> std::result::Result<(String, f64), String> {
+    let (name, value) = split_assignment(arg).ok_or("expected NAME=VALUE with NAME an identifier")?;
+    let value = evaluate(value).map_err(|e| e.message())?;
+    Ok((name.to_string(), value))
+}
+
+/// Stream results for every line of `path`, returning the summary of all of them
+///
+/// Input is read line by line and output is flushed per result, so arbitrarily
+/// large files run in constant memory and results can be piped onward as
+/// they arrive.
+fn batch(path: &Path, json: bool, variables: &Variables, options: &EvalOptions) -> Result<BatchSummary> {
+    let started = Instant::now();
+    let bindings = variables.bindings();
+    let eval = |expr: &str| (expr.to_string(), variables.evaluate_value(expr, &bindings, options));
+    let mut stdout = io::stdout().lock();
+    let mut summary = BatchSummary::default();
+    let mut write_error = None;
+    let measure = |expr: &str| variables.evaluate_measured(expr, &bindings, options);
+    let mut sink = |line, expression: &str, value: compute_mcp::Result<Value>, metadata: Option<ResultMetadata>| {
+        if write_error.is_some() {
+            return;
+        }
+        summary.record_value(value.as_ref().ok());
+        let text = match (&value, json) {
+            (Ok(value), true) => {
+                let mut object = json!({ "line": line, "expression": expression, "result": value });
+                if options.number_format != NumberFormat::default() {
+                    object["formatted"] = json!(value.format(&options.number_format));
+                }
+                if let Some(metadata) = metadata {
+                    object["metadata"] = metadata.to_json();
+                }
+                object.to_string()
+            }
+            (Err(e), true) => {
+                let mut object = json!({
+                    "line": line,
+                    "expression": expression,
+                    "error": e.to_string(),
+                    "error_details": e.to_json(),
+                });
+                if let Some(metadata) = metadata {
+                    object["metadata"] = metadata.to_json();
+                }
+                object.to_string()
+            }
+            (Ok(value), false) => value.format(&options.number_format),
+            (Err(e), false) => format!("line {}: error: {}", line, e.message()),
+        };
+        if let Err(e) = writeln!(stdout, "{}", text).and_then(|_| stdout.flush()) {
+            write_error = Some(e);
+        }
+    };
+    let reader: Box<dyn BufRead> = if path.as_os_str() == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        let file = fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        Box::new(io::BufReader::new(file))
+    };
+    if options.metadata {
+        evaluate_lines_measured(reader, measure, |line, result: EvaluationResult| {
+            sink(line, &result.expression, result.value.map(Value::Number), result.metadata)
+        })?;
+    } else {
+        evaluate_lines_measured(reader, eval, |line, (expression, value)| sink(line, &expression, value, None))?;
+    }
+    if let Some(e) = write_error {
+        return Err(e.into());
+    }
+    summary.elapsed = started.elapsed();
+    Ok(summary)
+}
+
+/// One line describing `summary`, with statistics written in `format`
+fn describe_summary(summary: &BatchSummary, format: &NumberFormat) -> String {
+    let mut text = format!("{} evaluated, {} failed", summary.total, summary.failed);
+    if let (Some(min), Some(max), Some(mean)) = (summary.min, summary.max, summary.mean()) {
+        text += &format!("; min {}, max {}, mean {}", format.format(min), format.format(max), format.format(mean));
+    }
+    text + &format!(" in {:.3} ms", summary.elapsed.as_secs_f64() * 1e3)
+}
+
+fn main() -> Result<ExitCode> {
+    match Cli::parse().command {
+        Command::Generate {
+            count,
+            depth,
+            seed,
+            min,
+            max,
+            decimals,
+            json,
+        } => {
+            anyhow::ensure!(min <= max, "--min must not exceed --max");
+            let seed = seed.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or_default()
+            });
+            let config = GeneratorConfig {
+                max_depth: depth,
+                min_value: min,
+                max_value: max,
+                decimals,
+                ..Default::default()
+            };
+
+            let mut stdout = io::BufWriter::new(io::stdout().lock());
+            for generated in ExprGenerator::new(config, seed).take(count) {
+                if json {
+                    let entry = match &generated.expected {
+                        Ok(value) => json!({ "expression": generated.source, "expected": value }),
+                        Err(e) => json!({
+                            "expression": generated.source,
+                            "error": e.to_string(),
+                            "error_details": e.to_json(),
+                        }),
+                    };
+                    writeln!(stdout, "{}", entry)?;
+                } else {
+                    writeln!(stdout, "{}", generated.source)?;
+                }
+            }
+            stdout.flush()?;
+        }
+        Command::Batch {
+            path,
+            json,
+            metadata,
+            summary,
+            variables,
+            rounding,
+        } => {
+            let options = EvalOptions { metadata, ..rounding.options() };
+            let report = batch(&path, json, &variables, &options)?;
+            if summary && json {
+                eprintln!("{}", report.to_json());
+            } else if summary {
+                eprintln!("{}", describe_summary(&report, &options.number_format));
+            }
+            if report.failed > 0 {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Check { expr } => {
+            let diagnostics = diagnose(&expr);
+            for diagnostic in &diagnostics {
+                println!("{}", diagnostic);
+            }
+            if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Equiv {
+            left,
+            right,
+            tolerance,
+        } => {
+            let a = parse_expression(&left).map_err(|e| anyhow::anyhow!("left: {}", e))?;
+            let b = parse_expression(&right).map_err(|e| anyhow::anyhow!("right: {}", e))?;
+            let result = equivalent(&a, &b, tolerance);
+            if result.canonical_match {
+                println!("equivalent: both reduce to {}", result.left_canonical);
+            } else if result.equivalent {
+                println!("equivalent: values agree at every probe point");
+            } else {
+                println!("not equivalent");
+                println!("  left:  {}", result.left_canonical);
+                println!("  right: {}", result.right_canonical);
+                if let Some(counterexample) = &result.counterexample {
+                    println!("  {}", counterexample);
+                }
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Eval {
+            expr,
+            pretty,
+            integer,
+            rpn,
+            variables,
+            rounding,
+        } => {
+            // RPN input is rewritten as infix source, which the rest of the command takes as usual
+            let expr = match rpn.then(|| parse_rpn(&expr)) {
+                None => expr,
+                Some(Ok(ast)) => to_source(&ast),
+                Some(Err(e)) => {
+                    eprintln!("error: {}", e);
+                    return Ok(ExitCode::FAILURE);
+                }
+            };
+            let bindings = variables.bindings();
+            let options = rounding.options();
+            let result = if integer {
+                variables.evaluate_integer(&expr, &bindings)
+            } else {
+                variables.evaluate_value(&expr, &bindings, &options).map(|value| value.format(&options.number_format))
+            };
+            match result {
+                Ok(text) => println!("{}", text),
+                Err(e) => {
+                    let rendered = pretty.then(|| variables.render_error(&expr, &e, &bindings, &options)).flatten();
+                    match (rendered, hint(&expr, &e, &bindings)) {
+                        (Some(rendered), _) => eprintln!("{}", rendered),
+                        (None, Some(hint)) => eprintln!("error: {} ({})", e.message(), hint),
+                        (None, None) => eprintln!("error: {}", e),
+                    }
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+        }
+        Command::Fmt { expr, latex: false } => println!("{}", format_source(&expr)?),
+        Command::Fmt { expr, latex: true } => println!("{}", format_source(&latex_to_source(&expr)?)?),
+        Command::Latex { expr, mathml } => {
+            let ast = parse_expression(&expr)?;
+            if mathml {
+                println!("{}", to_mathml(&ast));
+            } else {
+                println!("{}", to_latex(&ast));
+            }
+        }
+        Command::Repl { rounding } => return repl(rounding.options()),
+        Command::Simplify { expr, steps } => {
+            let (simplified, rewrites) = simplify_with_steps(&parse_expression(&expr)?);
+            if steps {
+                for rewrite in &rewrites {
+                    println!("{}", rewrite);
+                }
+            }
+            println!("{}", simplified);
+        }
+        Command::Tokens { expr, json } => {
+            for token in tokenize(&expr) {
+                let text = token.span.slice(&expr);
+                if json {
+                    let entry = json!({
+                        "start": token.span.start,
+                        "end": token.span.end,
+                        "kind": token.kind.to_string(),
+                        "text": text,
+                    });
+                    println!("{}", entry);
+                } else {
+                    println!("{:<8} {:<10} {}", token.span.to_string(), token.kind, text);
+                }
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+const REPL_HELP: &str = "\
+:save PATH  write history, variables and settings to a JSON file
+:load PATH  replace the session with one saved by :save
+:quit       exit (end of input also exits)";
+
+/// Run the REPL, returning failure if a piped session hit any error
+///
+/// When stdin or stdout is not a terminal the REPL acts as a line-oriented
+/// evaluator for scripts: no prompt, one result per line on stdout, errors with
+/// their line number and `:command` messages on stderr, and a failing exit
+/// status if any line erred.
+fn repl(options: EvalOptions) -> Result<ExitCode> {
+    let mut session = Session::with_options(options);
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout();
+    let interactive = stdout.is_terminal() && io::stdin().is_terminal();
+    let color = stdout.is_terminal();
+    let mut failed = false;
+    let mut line = String::new();
+    let mut line_number = 0;
+    loop {
+        if interactive {
+            write!(stdout, "> ")?;
+            stdout.flush()?;
+        }
+        line.clear();
+        if stdin.read_line(&mut line)? == 0 {
+            break;
+        }
+        line_number += 1;
+        let input = line.trim();
+        let error = match input.strip_prefix(':') {
+            Some("quit" | "q") => break,
+            Some(command) => match repl_command(&mut session, command) {
+                Ok(message) => {
+                    // Only results go to stdout when piped, so `:save` and friends report on stderr
+                    if interactive {
+                        println!("{}", message);
+                    } else {
+                        eprintln!("{}", message);
+                    }
+                    continue;
+                }
+                Err(e) => format!("{:#}", e),
+            },
+            None if input.is_empty() => continue,
+            None => match session.eval(input) {
+                Ok(value) => {
+                    println!("{}", session.options.number_format.format(value));
+                    continue;
+                }
+                Err(e @ ComputeError::ParseError(_)) => {
+                    if interactive && color {
+                        let expr = split_assignment(input).map_or(input, |(_, expr)| expr);
+                        println!("  {}", highlight(expr));
+                    }
+                    match parse_hint(input) {
+                        Some(hint) => hint,
+                        None if interactive => e.to_string(),
+                        None => e.message(),
+                    }
+                }
+                Err(e) => match hint(input, &e, session.variables()) {
+                    Some(hint) => format!("{} ({})", e, hint),
+                    None => e.to_string(),
+                },
+            },
+        };
+        if interactive {
+            println!("error: {}", error);
+        } else {
+            failed = true;
+            eprintln!("line {}: error: {}", line_number, error);
+        }
+    }
+    Ok(if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+/// `expr` with ANSI colors by token class, errors in bold red
+fn highlight(expr: &str) -> String {
+    let mut out = String::new();
+    let mut end = 0;
+    for (span, class) in classify(expr) {
+        let color = match class {
+            TokenClass::Number => "36",
+            TokenClass::Variable => "33",
+            TokenClass::Operator => "35",
+            TokenClass::Paren => "2",
+            TokenClass::Text => "32",
+            TokenClass::Error => "1;31",
+        };
+        out.push_str(&expr[end..span.start]);
+        out.push_str(&format!("\x1b[{}m{}\x1b[0m", color, span.slice(expr)));
+        end = span.end;
+    }
+    out.push_str(&expr[end..]);
+    out
+}
+
+/// A suggested fix for an input that failed to parse, keeping any assignment target
+fn parse_hint(input: &str) -> Option<String> {
+    let (target, expr) = match split_assignment(input) {
+        Some((name, expr)) => (Some(name), expr),
+        None => (None, input),
+    };
+    let mut suggestion = suggest_fix(expr)?;
+    if let (Some(name), Some(replacement)) = (target, &mut suggestion.replacement) {
+        *replacement = format!("{} = {}", name, replacement);
+    }
+    Some(suggestion.to_string())
+}
+
+/// Run a `:command` other than `:quit`, returning the message to print
+fn repl_command(session: &mut Session, command: &str) -> Result<String> {
+    let (name, path) = match command.split_once(char::is_whitespace) {
+        Some((name, path)) => (name, path.trim()),
+        None => (command, ""),
+    };
+    match name {
+        "save" | "load" if path.is_empty() => anyhow::bail!("usage: :{} PATH", name),
+        "save" => {
+            let json = serde_json::to_string_pretty(session)?;
+            fs::write(path, json).with_context(|| format!("cannot write {}", path))?;
+            Ok(format!("saved {} entries to {}", session.history().len(), path))
+        }
+        "load" => {
+            let json = fs::read_to_string(path).with_context(|| format!("cannot read {}", path))?;
+            *session = serde_json::from_str(&json)
+                .with_context(|| format!("{} is not a saved session", path))?;
+            Ok(format!("loaded {} entries from {}", session.history().len(), path))
+        }
+        "help" => Ok(REPL_HELP.to_string()),
+        _ => anyhow::bail!("unknown command :{} (try :help)", name),
+    }
+}
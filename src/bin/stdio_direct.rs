@@ -1,23 +1,640 @@
 //! MCP server for arithmetic expression evaluation
 
 use anyhow::Result;
-use compute_mcp::evaluate_batch;
-use mcpr::schema::json_rpc::{JSONRPCMessage, JSONRPCResponse};
-use serde::Serialize;
+use clap::Parser;
+use compute_mcp::compare::DEFAULT_TOLERANCE;
+use compute_mcp::session::split_assignment;
+use compute_mcp::{
+    approx_eq, convert_base, diagnose, evaluate_batch_with, evaluate_lines_with, evaluate_sheet_with, evaluate_spanned,
+    evaluate_with, hint, parse_expression, parse_recovering, AngleMode, ApproxMode, BatchSummary, Bindings, Comparison,
+    ComputeError, Diagnostic, EvalOptions, EvaluationResult, HistoryEntry, Ledger, LruCache, NumberFormat,
+    ParseOptions, Session, Severity, BUILTINS, DEFAULT_MAX_EXPRESSION_LEN, DEFAULT_MAX_CHAIN, DEFAULT_MAX_NESTING, MAX_FORMAT_DIGITS,
+};
+use mcpr::schema::json_rpc::{JSONRPCMessage, JSONRPCNotification, JSONRPCResponse, RequestId};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::fs::{File, OpenOptions};
 use std::io::{self, BufRead, Write};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 const GRAMMAR: &str = include_str!("../compute.pest");
 
+/// Input schema of the per-call `pretty` argument shared by every tool
+fn pretty_schema() -> Value {
+    json!({
+        "type": "boolean",
+        "description": "Indent the JSON text content (defaults to the server's --pretty setting)"
+    })
+}
+
+#[derive(Parser)]
+#[command(version, about = "Arithmetic expression evaluator MCP server")]
+struct Args {
+    /// Append a JSONL record of every tool call to this file
+    #[arg(long, value_name = "PATH")]
+    audit_log: Option<PathBuf>,
+
+    /// Evaluate each line of this file ("-" for stdin) with the other evaluation flags, print JSONL results, and exit
+    #[arg(long, value_name = "PATH")]
+    eval_file: Option<PathBuf>,
+
+    /// Reject expressions whose parse would need more than this many bytes
+    #[arg(long, value_name = "BYTES")]
+    memory_limit: Option<usize>,
+
+    /// Reject expressions longer than this many bytes before parsing
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_EXPRESSION_LEN)]
+    max_expression_len: usize,
+
+    /// Reject expressions nesting brackets, prefix operators, `^` or `?` more than this many levels deep before parsing
+    #[arg(long, value_name = "LEVELS", default_value_t = DEFAULT_MAX_NESTING)]
+    max_nesting: usize,
+
+    /// Reject expressions chaining more than this many binary operators, counting enclosing chains, before parsing
+    #[arg(long, value_name = "OPERATORS", default_value_t = DEFAULT_MAX_CHAIN)]
+    max_chain: usize,
+
+    /// Reject expressions whose parsed tree is nested deeper than this
+    #[arg(long, value_name = "LEVELS")]
+    max_depth: Option<usize>,
+
+    /// Reject expressions whose parsed tree has more nodes than this
+    #[arg(long, value_name = "NODES")]
+    max_nodes: Option<usize>,
+
+    /// Reject expressions whose evaluation takes more than this many steps, one per node evaluated
+    #[arg(long, value_name = "STEPS")]
+    fuel: Option<u64>,
+
+    /// Report infinite and NaN results as errors rather than values
+    #[arg(long)]
+    strict_finite: bool,
+
+    /// Add chains of `+` and `-` with compensated summation, so `0.1` added a thousand times is `100`
+    #[arg(long)]
+    compensated_sums: bool,
+
+    /// Accept commas between digit groups, as in `1,000,000`
+    #[arg(long)]
+    comma_separators: bool,
+
+    /// Read `2(3 + 4)`, `(2)(3)` and `2x` as products
+    #[arg(long)]
+    implicit_multiplication: bool,
+
+    /// Remember this many batch results across tool calls (disabled by default)
+    #[arg(long, value_name = "ENTRIES")]
+    cache_size: Option<usize>,
+
+    /// Indent the JSON text content of tool results unless a call sets `pretty`
+    #[arg(long)]
+    pretty: bool,
+
+    /// Stream batches larger than this as progress notifications, one per chunk
+    #[arg(long, value_name = "EXPRESSIONS", default_value = "10000")]
+    chunk_size: NonZeroUsize,
+
+    /// Persist the calculation history to this JSONL file so it survives restarts
+    #[arg(long, value_name = "PATH")]
+    history_file: Option<PathBuf>,
+
+    /// Rotate the history file to `<PATH>.1` once it would grow past this size
+    #[arg(long, value_name = "BYTES", default_value_t = 10 << 20)]
+    history_max_bytes: u64,
+}
+
+/// Calculations kept in memory for the history resource
+const HISTORY_CAPACITY: usize = 1000;
+const HISTORY_URI: &str = "history://calculations";
+
+/// Append-only JSONL log of tool invocations
+struct AuditLog {
+    file: File,
+}
+
+impl AuditLog {
+    fn open(path: &PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog { file })
+    }
+
+    fn record(&mut self, tool: Option<&str>, arguments: &Value, result: &Value, latency: Duration) {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let entry = json!({
+            "timestamp_ms": timestamp_ms,
+            "method": "tools/call",
+            "tool": tool,
+            "arguments": arguments,
+            "result": result,
+            "latency_us": latency.as_micros(),
+        });
+        if let Err(e) = writeln!(self.file, "{}", entry).and_then(|_| self.file.flush()) {
+            log::error!("Failed to write audit log entry: {}", e);
+        }
+    }
+}
+
 // Response types
 #[derive(Serialize)]
 struct BatchResult {
     expression: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     result: Option<f64>,
+    /// The result written out as the call's `number_format` asks; absent without one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    formatted: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     error: Option<String>,
+    /// The error's code, category and message, see `ComputeError::to_json`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_details: Option<Value>,
+    /// A "did you mean" fix for the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggestion: Option<String>,
+    /// The expression with carets under the part that failed, see `SpannedError::render`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rendered: Option<String>,
+    /// Every syntax error in the expression, not just the first, see `parse_recovering`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    syntax_errors: Option<Vec<Diagnostic>>,
     success: bool,
+    /// Whether the result came from the cross-call cache; absent when caching is off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_hit: Option<bool>,
+    /// Whether exact arithmetic confirms the result; absent unless the call asked to verify
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verified: Option<bool>,
+    /// The exact-arithmetic value, reported only when it disagrees with `result`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<f64>,
+    /// Parse and evaluation cost, see `ResultMetadata::to_json`; absent unless the call asked for metadata
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<Value>,
+    /// Standard uncertainty of `result` propagated from `±` literals; absent unless the call asked for it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uncertainty: Option<f64>,
+}
+
+impl BatchResult {
+    /// The response for `r`, whose expression was evaluated with `options`
+    fn new(r: EvaluationResult, options: &EvalOptions) -> Self {
+        let error = r.value.as_ref().err();
+        let disagreement = r.verification.as_ref().filter(|v| !v.agrees);
+        BatchResult {
+            verified: r.verification.as_ref().map(|v| v.agrees),
+            reference: disagreement.and_then(|v| v.reference.as_ref().ok().copied()),
+            metadata: r.metadata.map(|metadata| metadata.to_json()),
+            uncertainty: r.uncertainty,
+            suggestion: error.and_then(|e| hint(&r.expression, e, &Bindings::new())),
+            error: error.map(|e| e.to_string()),
+            error_details: error.map(|e| e.to_json()),
+            rendered: error.and_then(|_| rendered(&r.expression, &Bindings::new(), options)),
+            syntax_errors: match error {
+                Some(ComputeError::ParseError(_)) => parse_recovering(&r.expression).err(),
+                _ => None,
+            },
+            result: r.value.as_ref().ok().copied(),
+            formatted: r
+                .value
+                .as_ref()
+                .ok()
+                .filter(|_| options.number_format != NumberFormat::default())
+                .map(|&value| options.number_format.format(value)),
+            success: r.value.is_ok(),
+            cache_hit: None,
+            expression: r.expression,
+        }
+    }
+}
+
+/// The caret diagram of the error evaluating `expression` gives, located by re-evaluating it
+fn rendered(expression: &str, env: &Bindings, options: &EvalOptions) -> Option<String> {
+    evaluate_spanned(expression, env, options).err().map(|e| e.render(expression))
+}
+
+/// Results of earlier batch tool calls, keyed by expression text
+type ResultCache = LruCache<String, compute_mcp::Result<f64>>;
+
+/// Evaluate a batch, answering from and filling `cache` when one is configured
+fn evaluate_cached(
+    expressions: &[&str],
+    options: &EvalOptions,
+    cache: Option<&mut ResultCache>,
+) -> Vec<BatchResult> {
+    let Some(cache) = cache else {
+        return evaluate_batch_with(expressions, options)
+            .into_iter()
+            .map(|result| BatchResult::new(result, options))
+            .collect();
+    };
+    let hits: Vec<_> = expressions.iter().map(|expr| cache.get(*expr).cloned()).collect();
+    let misses: Vec<&str> = expressions
+        .iter()
+        .zip(&hits)
+        .filter(|(_, hit)| hit.is_none())
+        .map(|(expr, _)| *expr)
+        .collect();
+    let mut fresh = evaluate_batch_with(&misses, options).into_iter();
+
+    expressions
+        .iter()
+        .zip(hits)
+        .map(|(expr, hit)| {
+            let cache_hit = hit.is_some();
+            let result = match hit {
+                Some(value) => EvaluationResult {
+                    expression: expr.to_string(),
+                    value,
+                    verification: None,
+                    metadata: None,
+                    uncertainty: None,
+                },
+                None => {
+                    let result = fresh.next().expect("one fresh result per miss");
+                    cache.insert(result.expression.clone(), result.value.clone());
+                    result
+                }
+            };
+            BatchResult {
+                cache_hit: Some(cache_hit),
+                ..BatchResult::new(result, options)
+            }
+        })
+        .collect()
+}
+
+/// Run the `evaluate_batch` tool, streaming chunks of large batches to `out`
+///
+/// A batch of at most `chunk_size` expressions is answered in one response.
+/// Larger batches are evaluated one chunk at a time: each chunk's results go
+/// out in a `notifications/progress` message as soon as they are ready, and
+/// the final response only summarizes, so memory use is bounded by the chunk
+/// size rather than the batch size. Progress uses the request's
+/// `_meta.progressToken`, or the request id when the client sent none.
+///
+/// A sheet, whose entries depend on one another, is evaluated whole, and
+/// its results are then streamed in the same chunks when there are more
+/// than `chunk_size` of them.
+fn evaluate_batch_tool(
+    params: &Value,
+    id: &RequestId,
+    options: &EvalOptions,
+    mut cache: Option<&mut ResultCache>,
+    history: &mut Ledger,
+    chunk_size: usize,
+    out: &mut impl Write,
+) -> Result<Value> {
+    let Some(exprs) = params
+        .get("arguments")
+        .and_then(|args| args.get("expressions"))
+        .and_then(|e| e.as_array())
+    else {
+        return Ok(json!({ "error": "expressions must be array" }));
+    };
+    let exprs: Vec<&str> = exprs.iter().filter_map(|v| v.as_str()).collect();
+    let verify = params
+        .pointer("/arguments/verify")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let metadata = params
+        .pointer("/arguments/metadata")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let uncertainty = params
+        .pointer("/arguments/uncertainty")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let angle_mode = match params.pointer("/arguments/angle_mode").and_then(Value::as_str) {
+        None => options.angle_mode,
+        Some("radians") => AngleMode::Radians,
+        Some("degrees") => AngleMode::Degrees,
+        Some(other) => return Ok(json!({ "error": format!("unknown angle_mode `{}`", other) })),
+    };
+    let number_format = match params.pointer("/arguments/number_format") {
+        None => options.number_format,
+        Some(format) => {
+            let format = NumberFormat::deserialize(format)
+                .map_err(|e| e.to_string())
+                .and_then(|format| format.check().map(|()| format).map_err(|e| e.to_string()));
+            match format {
+                Ok(format) => format,
+                Err(e) => return Ok(json!({ "error": format!("invalid number_format: {}", e) })),
+            }
+        }
+    };
+    let adjusted;
+    // Cached results carry no verification, metadata or uncertainty and use the server's angle mode, so these calls
+    // bypass the cache
+    let uncached = verify || metadata || uncertainty || angle_mode != options.angle_mode;
+    let options = if uncached || number_format != options.number_format {
+        if uncached {
+            cache = None;
+        }
+        adjusted = EvalOptions {
+            verify: if verify { Some(DEFAULT_TOLERANCE) } else { options.verify },
+            metadata: metadata || options.metadata,
+            uncertainty: uncertainty || options.uncertainty,
+            angle_mode,
+            number_format,
+            ..options.clone()
+        };
+        &adjusted
+    } else {
+        options
+    };
+    let started = Instant::now();
+    let mut summary = BatchSummary::default();
+    let sheet = exprs.iter().any(|expr| split_assignment(expr.trim()).is_some());
+    if exprs.len() <= chunk_size {
+        let results = if sheet { evaluate_sheet_tool(&exprs, options) } else { evaluate_cached(&exprs, options, cache) };
+        record_history(history, &results);
+        summarize(&mut summary, &results, started);
+        return Ok(json!({ "success": true, "results": results, "summary": summary.to_json() }));
+    }
+
+    let token = params
+        .pointer("/_meta/progressToken")
+        .cloned()
+        .map_or_else(|| serde_json::to_value(id), Ok)?;
+    let mut sheet_results = if sheet { evaluate_sheet_tool(&exprs, options) } else { Vec::new() }.into_iter();
+    for (i, chunk) in exprs.chunks(chunk_size).enumerate() {
+        let offset = i * chunk_size;
+        let results = if sheet {
+            sheet_results.by_ref().take(chunk.len()).collect()
+        } else {
+            evaluate_cached(chunk, options, cache.as_deref_mut())
+        };
+        record_history(history, &results);
+        summarize(&mut summary, &results, started);
+        let notification = JSONRPCNotification::new(
+            "notifications/progress".to_string(),
+            Some(json!({
+                "progressToken": token,
+                "progress": offset + chunk.len(),
+                "total": exprs.len(),
+                "offset": offset,
+                "results": results,
+            })),
+        );
+        writeln!(out, "{}", serde_json::to_string(&JSONRPCMessage::Notification(notification))?)?;
+        out.flush()?;
+    }
+    Ok(json!({
+        "success": true,
+        "chunked": true,
+        "total": summary.total,
+        "failed": summary.failed,
+        "summary": summary.to_json(),
+    }))
+}
+
+/// Add `results` to `summary`, timing the batch from `started`
+fn summarize(summary: &mut BatchSummary, results: &[BatchResult], started: Instant) {
+    for result in results {
+        summary.record(result.result);
+    }
+    summary.elapsed = started.elapsed();
+}
+
+/// Evaluate a batch that names results with `name = expr`, whole and in dependency order
+///
+/// Each entry's result depends on the rest of the batch, so sheets are never
+/// cached and are evaluated in one piece even when their results are then
+/// streamed in chunks; the caret diagram and suggestion, which re-evaluate
+/// an expression on its own, are left out.
+fn evaluate_sheet_tool(exprs: &[&str], options: &EvalOptions) -> Vec<BatchResult> {
+    evaluate_sheet_with(exprs, options)
+        .into_iter()
+        .map(|result| {
+            let entry = result.expression.trim();
+            let expression = split_assignment(entry).map_or(entry, |(_, expr)| expr).to_string();
+            BatchResult {
+                expression: result.expression.clone(),
+                rendered: None,
+                suggestion: None,
+                ..BatchResult::new(EvaluationResult { expression, ..result }, options)
+            }
+        })
+        .collect()
+}
+
+fn record_history(history: &mut Ledger, results: &[BatchResult]) {
+    for result in results {
+        let entry = HistoryEntry {
+            input: result.expression.clone(),
+            result: match (&result.result, &result.error) {
+                (Some(value), _) => Ok(*value),
+                (None, error) => Err(error.clone().unwrap_or_default()),
+            },
+        };
+        if let Err(e) = history.record(entry) {
+            log::error!("Failed to write history entry: {}", e);
+        }
+    }
+}
+
+fn session_eval_tool(args: &Value, session: &mut Session) -> Value {
+    let Some(input) = args.get("input").and_then(|i| i.as_str()) else {
+        return json!({ "error": "input must be a string" });
+    };
+    match session.eval(input) {
+        Ok(value) => json!({ "success": true, "result": value }),
+        Err(e) => {
+            let mut result = json!({ "success": false, "error": e.to_string(), "error_details": e.to_json() });
+            let expr = split_assignment(input.trim()).map_or(input.trim(), |(_, expr)| expr);
+            if let Some(rendered) = rendered(expr, session.variables(), &session.options) {
+                result["rendered"] = json!(rendered);
+            }
+            if let Some(suggestion) = hint(input, &e, session.variables()) {
+                result["suggestion"] = json!(suggestion);
+            }
+            result
+        }
+    }
+}
+
+/// Replace `session` with an exported one, keeping the server's parse limits and step budget
+fn session_import_tool(args: &Value, session: &mut Session) -> Value {
+    // Accept the exported object itself or its JSON text
+    let imported = match args.get("session") {
+        Some(Value::String(text)) => serde_json::from_str::<Session>(text),
+        Some(value) => Session::deserialize(value),
+        None => return json!({ "error": "session must be an object or a JSON string" }),
+    };
+    match imported {
+        Ok(mut imported) => {
+            imported.options.parse = session.options.parse.clone();
+            imported.options.fuel = session.options.fuel;
+            *session = imported;
+            json!({
+                "success": true,
+                "variables": session.variables().len(),
+                "history": session.history().len(),
+            })
+        }
+        Err(e) => json!({ "success": false, "error": format!("not an exported session: {}", e) }),
+    }
+}
+
+fn read_resource(params: &Value, history: &Ledger) -> Value {
+    match params.get("uri").and_then(|u| u.as_str()) {
+        Some(HISTORY_URI) => {
+            let entries: Vec<_> = history.entries().collect();
+            json!({
+                "contents": [{
+                    "uri": HISTORY_URI,
+                    "mimeType": "application/json",
+                    "text": serde_json::to_string(&entries).unwrap_or_default(),
+                }]
+            })
+        }
+        _ => json!({ "contents": [] }),
+    }
+}
+
+#[derive(Serialize)]
+struct LineResult {
+    line: usize,
+    #[serde(flatten)]
+    result: BatchResult,
+}
+
+/// Stream results for every line of `path` to stdout without buffering the input
+fn eval_file(path: &PathBuf, options: &EvalOptions) -> Result<()> {
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    let mut write_error = None;
+    let mut sink = |line, result: EvaluationResult| {
+        if write_error.is_some() {
+            return;
+        }
+        let entry = LineResult {
+            line,
+            result: BatchResult::new(result, options),
+        };
+        if let Err(e) = serde_json::to_writer(&mut stdout, &entry)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(stdout))
+        {
+            write_error = Some(e);
+        }
+    };
+
+    let eval = |expr: &str| evaluate_with(expr, options);
+    if path.as_os_str() == "-" {
+        evaluate_lines_with(io::stdin().lock(), eval, &mut sink)?;
+    } else {
+        evaluate_lines_with(io::BufReader::new(File::open(path)?), eval, &mut sink)?;
+    }
+    if let Some(e) = write_error {
+        return Err(e.into());
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Diagnostics for one expression, split into blocking errors and lint warnings
+fn validate_tool(args: &Value) -> Value {
+    let Some(expression) = args.get("expression").and_then(|e| e.as_str()) else {
+        return json!({ "error": "expression must be a string" });
+    };
+    let (errors, warnings): (Vec<_>, Vec<_>) = diagnose(expression)
+        .into_iter()
+        .partition(|d| d.severity == Severity::Error);
+    let mut result = json!({
+        "success": true,
+        "valid": errors.is_empty(),
+        "errors": errors,
+        "warnings": warnings,
+    });
+    if let Ok(expr) = parse_expression(expression) {
+        result["metrics"] = json!({
+            "depth": expr.depth(),
+            "node_count": expr.node_count(),
+            "operators": expr.operator_histogram(),
+        });
+    }
+    result
+}
+
+fn convert_base_tool(args: &Value) -> Value {
+    // Accept plain JSON integers as well as digit strings
+    let value = match args.get("value") {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Number(n)) if n.is_i64() || n.is_u64() => n.to_string(),
+        _ => return json!({ "error": "value must be a string or an integer" }),
+    };
+    let base = |key, default| match args.get(key) {
+        None => Some(default),
+        Some(b) => b.as_u64().and_then(|b| u32::try_from(b).ok()),
+    };
+    let (Some(from_base), Some(to_base)) = (base("from_base", 10), base("to_base", 10)) else {
+        return json!({ "error": "from_base and to_base must be integers" });
+    };
+    let width = match args.get("width") {
+        None => None,
+        Some(w) => match w.as_u64().and_then(|w| u32::try_from(w).ok()) {
+            Some(w) => Some(w),
+            None => return json!({ "error": "width must be an integer" }),
+        },
+    };
+
+    match convert_base(&value, from_base, to_base, width) {
+        Ok(result) => json!({ "success": true, "result": result }),
+        Err(e) => json!({ "success": false, "error": e.to_string(), "error_details": e.to_json() }),
+    }
+}
+
+#[derive(Serialize)]
+struct CompareResult {
+    equal: bool,
+    left: f64,
+    right: f64,
+    abs_diff: f64,
+    rel_diff: f64,
+    ulps: u64,
+    tolerance: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_ulps: Option<u64>,
+}
+
+fn compare_tool(args: &Value, options: &EvalOptions) -> Value {
+    let side = |key| args.get(key).and_then(|v| v.as_str());
+    let (Some(a), Some(b)) = (side("a"), side("b")) else {
+        return json!({ "error": "a and b must be strings" });
+    };
+    let tolerance = args
+        .get("tolerance")
+        .and_then(|t| t.as_f64())
+        .unwrap_or(DEFAULT_TOLERANCE);
+    let max_ulps = args.get("ulps").and_then(|u| u.as_u64());
+
+    let comparison = evaluate_with(a, options)
+        .and_then(|left| Ok(Comparison::of(left, evaluate_with(b, options)?, tolerance)));
+    match comparison {
+        Ok(cmp) => json!({
+            "success": true,
+            "comparison": CompareResult {
+                // An explicit ULP bound replaces the tolerance check
+                equal: match max_ulps {
+                    Some(max) => approx_eq(cmp.left, cmp.right, ApproxMode::Ulps(max)),
+                    None => cmp.equal,
+                },
+                left: cmp.left,
+                right: cmp.right,
+                abs_diff: cmp.abs_diff,
+                rel_diff: cmp.rel_diff,
+                ulps: cmp.ulps,
+                tolerance,
+                max_ulps,
+            }
+        }),
+        Err(e) => json!({ "success": false, "error": e.to_string(), "error_details": e.to_json() }),
+    }
 }
 
 fn main() -> Result<()> {
@@ -25,12 +642,42 @@ fn main() -> Result<()> {
         .target(env_logger::Target::Stderr)
         .init();
 
+    let args = Args::parse();
+    let options = EvalOptions {
+        parse: ParseOptions {
+            memory_limit: args.memory_limit,
+            max_expression_len: args.max_expression_len,
+            max_nesting: args.max_nesting,
+            max_chain: args.max_chain,
+            max_depth: args.max_depth,
+            max_nodes: args.max_nodes,
+            comma_separators: args.comma_separators,
+            implicit_multiplication: args.implicit_multiplication,
+            ..Default::default()
+        },
+        fuel: args.fuel,
+        strict_finite: args.strict_finite,
+        compensated_sums: args.compensated_sums,
+        ..Default::default()
+    };
+    if let Some(path) = &args.eval_file {
+        return eval_file(path, &options);
+    }
+    let mut audit_log = args.audit_log.as_ref().map(AuditLog::open).transpose()?;
+
+    let mut cache = args.cache_size.map(ResultCache::new);
+    let mut session = Session::with_options(options.clone());
+    let mut history = match &args.history_file {
+        Some(path) => Ledger::open(path, HISTORY_CAPACITY, args.history_max_bytes)?,
+        None => Ledger::new(HISTORY_CAPACITY),
+    };
+
     log::info!("Compute MCP starting...");
 
     let stdin = io::stdin();
     let mut stdout = io::stdout();
 
-    for line in stdin.lock().lines().flatten() {
+    for line in stdin.lock().lines().map_while(io::Result::ok) {
         if line.trim().is_empty() {
             continue;
         }
@@ -43,9 +690,11 @@ fn main() -> Result<()> {
                         json!({
                             "protocolVersion": "2024-11-05",
                             "capabilities": {
+                                "resources": {},
                                 "arithmetic": {
-                                    "operations": ["+", "-", "*", "/"],
-                                    "features": ["precedence", "parentheses", "decimals", "negatives", "div-by-zero"],
+                                    "operations": ["+", "-", "*", "/", "^", "<", "<=", ">", ">=", "==", "!=", "?:", "and", "or", "not"],
+                                    "functions": BUILTINS.iter().map(|f| f.name).collect::<Vec<_>>(),
+                                    "features": ["precedence", "parentheses", "decimals", "negatives", "percentages", "lists", "ranges", "div-by-zero"],
                                     "grammar": GRAMMAR
                                 }
                             },
@@ -67,47 +716,209 @@ fn main() -> Result<()> {
                                     "properties": {
                                         "expressions": {
                                             "type": "array",
-                                            "items": { "type": "string" }
-                                        }
+                                            "items": { "type": "string" },
+                                            "description": "Entries written `name = expr` define names other entries can use, like spreadsheet cells; the batch is then evaluated in dependency order"
+                                        },
+                                        "verify": {
+                                            "type": "boolean",
+                                            "description": "Cross-check each result with exact rational arithmetic and flag disagreements"
+                                        },
+                                        "metadata": {
+                                            "type": "boolean",
+                                            "description": "Report each result's parse and evaluation time in microseconds, tree depth and node count, and whether it is exact"
+                                        },
+                                        "uncertainty": {
+                                            "type": "boolean",
+                                            "description": "Report the uncertainty each result inherits from measured values written `2.0 ± 0.1` or `2.0 +/- 0.1`"
+                                        },
+                                        "angle_mode": {
+                                            "type": "string",
+                                            "enum": ["radians", "degrees"],
+                                            "default": "radians",
+                                            "description": "Unit trigonometric functions take and return angles in"
+                                        },
+                                        "number_format": {
+                                            "type": "object",
+                                            "description": "Also write each result out as text, e.g. 0.3 for 0.1 + 0.2 with 15 significant figures",
+                                            "properties": {
+                                                "notation": { "type": "string", "enum": ["auto", "scientific", "engineering"] },
+                                                "decimals": { "type": "integer", "minimum": 0, "maximum": MAX_FORMAT_DIGITS },
+                                                "significant": { "type": "integer", "minimum": 1, "maximum": MAX_FORMAT_DIGITS },
+                                                "thousands_separator": { "type": "string", "maxLength": 1 }
+                                            }
+                                        },
+                                        "pretty": pretty_schema()
                                     },
                                     "required": ["expressions"]
                                 }
+                            }, {
+                                "name": "validate",
+                                "description": "Check an expression without evaluating it, listing errors, lint warnings and size metrics",
+                                "inputSchema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "expression": { "type": "string" },
+                                        "pretty": pretty_schema()
+                                    },
+                                    "required": ["expression"]
+                                }
+                            }, {
+                                "name": "convert_base",
+                                "description": "Convert an integer between bases such as decimal, hex, binary and octal",
+                                "inputSchema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "value": {
+                                            "type": ["string", "integer"],
+                                            "description": "Digits in from_base, optionally signed or prefixed with 0x, 0o or 0b"
+                                        },
+                                        "from_base": { "type": "integer", "minimum": 2, "maximum": 36, "default": 10 },
+                                        "to_base": { "type": "integer", "minimum": 2, "maximum": 36, "default": 10 },
+                                        "width": {
+                                            "type": "integer",
+                                            "minimum": 1,
+                                            "maximum": 128,
+                                            "description": "Treat values as two's complement with this many bits"
+                                        },
+                                        "pretty": pretty_schema()
+                                    },
+                                    "required": ["value"]
+                                }
+                            }, {
+                                "name": "compare",
+                                "description": "Evaluate two expressions and check whether they are equal within a tolerance",
+                                "inputSchema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "a": { "type": "string" },
+                                        "b": { "type": "string" },
+                                        "tolerance": {
+                                            "type": "number",
+                                            "description": "Maximum absolute or relative difference (default 1e-9)"
+                                        },
+                                        "ulps": {
+                                            "type": "integer",
+                                            "minimum": 0,
+                                            "description": "Compare by units in the last place instead of tolerance"
+                                        },
+                                        "pretty": pretty_schema()
+                                    },
+                                    "required": ["a", "b"]
+                                }
+                            }, {
+                                "name": "session/eval",
+                                "description": "Evaluate an expression or `name = expr` assignment in the server's session, where variables and `ans` persist between calls",
+                                "inputSchema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "input": { "type": "string" },
+                                        "pretty": pretty_schema()
+                                    },
+                                    "required": ["input"]
+                                }
+                            }, {
+                                "name": "session/export",
+                                "description": "Snapshot the session's variables, options and history as JSON for session/import",
+                                "inputSchema": {
+                                    "type": "object",
+                                    "properties": { "pretty": pretty_schema() }
+                                }
+                            }, {
+                                "name": "session/import",
+                                "description": "Replace the session with a snapshot from session/export; the server's limits stay in force",
+                                "inputSchema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "session": {
+                                            "type": ["object", "string"],
+                                            "description": "The exported session object, or its JSON text"
+                                        },
+                                        "pretty": pretty_schema()
+                                    },
+                                    "required": ["session"]
+                                }
+                            }, {
+                                "name": "history/clear",
+                                "description": "Erase the calculation history, including any history file on disk",
+                                "inputSchema": {
+                                    "type": "object",
+                                    "properties": { "pretty": pretty_schema() }
+                                }
                             }]
                         }),
                     ),
-                    "resources/list" => JSONRPCResponse::new(req.id, json!({"resources": []})),
+                    "resources/list" => JSONRPCResponse::new(
+                        req.id,
+                        json!({
+                            "resources": [{
+                                "uri": HISTORY_URI,
+                                "name": "Calculation history",
+                                "description": "Recent evaluate_batch expressions and their results, oldest first",
+                                "mimeType": "application/json"
+                            }]
+                        }),
+                    ),
+                    "resources/read" => {
+                        let params = req.params.as_ref().unwrap_or(&Value::Null);
+                        JSONRPCResponse::new(req.id, read_resource(params, &history))
+                    }
                     "prompts/list" => JSONRPCResponse::new(req.id, json!({"prompts": []})),
                     "tools/call" => {
                         let params = req.params.unwrap_or(Value::Null);
-                        let result = match params.get("name").and_then(|n| n.as_str()) {
-                            Some("evaluate_batch") => params
-                                .get("arguments")
-                                .and_then(|args| args.get("expressions"))
-                                .and_then(|e| e.as_array())
-                                .map(|exprs| {
-                                    let expr_strs: Vec<&str> =
-                                        exprs.iter().filter_map(|v| v.as_str()).collect();
-                                    let results: Vec<_> = evaluate_batch(&expr_strs)
-                                        .into_iter()
-                                        .map(|r| BatchResult {
-                                            expression: r.expression,
-                                            result: r.value.as_ref().ok().copied(),
-                                            error: r.value.as_ref().err().map(|e| e.to_string()),
-                                            success: r.value.is_ok(),
-                                        })
-                                        .collect();
-                                    json!({ "success": true, "results": results })
-                                })
-                                .unwrap_or_else(|| json!({ "error": "expressions must be array" })),
+                        let started = Instant::now();
+                        let tool = params.get("name").and_then(|n| n.as_str());
+                        let result = match tool {
+                            Some("evaluate_batch") => evaluate_batch_tool(
+                                &params,
+                                &req.id,
+                                &options,
+                                cache.as_mut(),
+                                &mut history,
+                                args.chunk_size.get(),
+                                &mut stdout,
+                            )?,
+                            Some("validate") => validate_tool(params.get("arguments").unwrap_or(&Value::Null)),
+                            Some("convert_base") => {
+                                convert_base_tool(params.get("arguments").unwrap_or(&Value::Null))
+                            }
+                            Some("session/eval") => {
+                                session_eval_tool(params.get("arguments").unwrap_or(&Value::Null), &mut session)
+                            }
+                            Some("session/export") => json!({ "success": true, "session": session }),
+                            Some("session/import") => {
+                                session_import_tool(params.get("arguments").unwrap_or(&Value::Null), &mut session)
+                            }
+                            Some("history/clear") => match history.clear() {
+                                Ok(()) => json!({ "success": true }),
+                                Err(e) => json!({ "success": false, "error": e.to_string() }),
+                            },
+                            Some("compare") => {
+                                compare_tool(params.get("arguments").unwrap_or(&Value::Null), &options)
+                            }
                             _ => json!({ "error": "Unknown tool" }),
                         };
 
+                        let pretty = params
+                            .get("arguments")
+                            .and_then(|arguments| arguments.get("pretty"))
+                            .and_then(Value::as_bool)
+                            .unwrap_or(args.pretty);
+
+                        if let Some(audit_log) = audit_log.as_mut() {
+                            let arguments = params.get("arguments").unwrap_or(&Value::Null);
+                            audit_log.record(tool, arguments, &result, started.elapsed());
+                        }
+
                         JSONRPCResponse::new(
                             req.id,
                             json!({
                                 "content": [{
                                     "type": "text",
-                                    "text": serde_json::to_string(&result)?
+                                    "text": if pretty {
+                                        serde_json::to_string_pretty(&result)?
+                                    } else {
+                                        serde_json::to_string(&result)?
+                                    }
                                 }]
                             }),
                         )
@@ -0,0 +1,388 @@
+//! Evaluating one expression over many sets of variable bindings
+
+use crate::functions::{self, AngleMode, Builtin};
+use crate::prelude::*;
+use crate::{add_percent, eval_expr, is_true, truth_value, Expr, Relation, RoundingMode};
+use alloc::borrow::Cow;
+
+/// Values for the variables of an expression, keyed by name
+pub type Bindings = HashMap<String, f64>;
+
+/// Number of binding sets evaluated together in one pass over the program
+const LANES: usize = 8;
+
+type Lane = [f64; LANES];
+
+/// Which lanes of a value failed to evaluate, carried beside the value so no NaN is mistaken for a result
+type Mask = [bool; LANES];
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Const(f64),
+    /// A value that fails in every lane, such as a call to an unknown function
+    Fail,
+    Load(usize),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Neg,
+    Not,
+    /// Raise the operand by a percentage of itself, as in `a + 10%`
+    AddPercent(f64),
+    /// A built-in applied to the given number of operands
+    Call(&'static Builtin, usize),
+    Compare(Relation),
+    /// Pick the then or else operand by the condition beneath them, lane by lane
+    Select,
+    /// Logical connectives, computed with both operands evaluated
+    And,
+    Or,
+}
+
+/// Postfix program with variables resolved to column slots
+struct Program {
+    ops: Vec<Op>,
+    slots: Vec<String>,
+}
+
+impl Program {
+    fn compile(expr: &Expr) -> Self {
+        let mut program = Program {
+            ops: Vec::new(),
+            slots: Vec::new(),
+        };
+        program.emit(expr);
+        program
+    }
+
+    /// Append the ops computing `expr`
+    fn emit(&mut self, expr: &Expr) {
+        enum Visit<'a> {
+            Enter(&'a Expr),
+            Emit(Op),
+        }
+
+        let program = self;
+        let mut stack = vec![Visit::Enter(expr)];
+        while let Some(visit) = stack.pop() {
+            if let Visit::Enter(expr) = visit {
+                if let Some((a, p)) = expr.percent_change() {
+                    stack.extend([Visit::Emit(Op::AddPercent(p)), Visit::Enter(a)]);
+                    continue;
+                }
+            }
+            let (l, op, r) = match visit {
+                Visit::Emit(op) => {
+                    program.ops.push(op);
+                    continue;
+                }
+                Visit::Enter(Expr::Number(n)) => {
+                    program.ops.push(Op::Const(*n));
+                    continue;
+                }
+                Visit::Enter(Expr::Percent(n)) => {
+                    program.ops.push(Op::Const(n / 100.0));
+                    continue;
+                }
+                Visit::Enter(Expr::Var(name)) => {
+                    let slot = program.slot(name);
+                    program.ops.push(Op::Load(slot));
+                    continue;
+                }
+                Visit::Enter(Expr::Neg(e)) => {
+                    stack.extend([Visit::Emit(Op::Neg), Visit::Enter(e)]);
+                    continue;
+                }
+                Visit::Enter(Expr::Not(e)) => {
+                    stack.extend([Visit::Emit(Op::Not), Visit::Enter(e)]);
+                    continue;
+                }
+                Visit::Enter(Expr::Call(name, args)) => {
+                    let resolved = functions::resolve(name, args.len())
+                        .and_then(|function| Ok((function, function.operands(args, eval_expr)?)));
+                    match resolved {
+                        Ok((function, Cow::Borrowed(operands))) => {
+                            stack.push(Visit::Emit(Op::Call(function, operands.len())));
+                            stack.extend(operands.iter().rev().map(Visit::Enter));
+                        }
+                        // Operands built for the call, such as a range's numbers, do not outlive this step
+                        Ok((function, Cow::Owned(operands))) => {
+                            operands.iter().for_each(|operand| program.emit(operand));
+                            program.ops.push(Op::Call(function, operands.len()));
+                        }
+                        Err(_) => program.ops.push(Op::Fail),
+                    }
+                    continue;
+                }
+                Visit::Enter(Expr::List(_) | Expr::Range(..) | Expr::Text(_)) => {
+                    program.ops.push(Op::Fail);
+                    continue;
+                }
+                Visit::Enter(Expr::If(cond, then, otherwise)) => {
+                    // Both branches run; a failed branch only matters in lanes that select it
+                    stack.extend([Visit::Emit(Op::Select), Visit::Enter(otherwise), Visit::Enter(then)]);
+                    stack.push(Visit::Enter(cond));
+                    continue;
+                }
+                Visit::Enter(Expr::Compare(relation, l, r)) => (l, Op::Compare(*relation), r),
+                Visit::Enter(Expr::Add(l, r)) => (l, Op::Add, r),
+                Visit::Enter(Expr::Sub(l, r)) => (l, Op::Sub, r),
+                Visit::Enter(Expr::Mul(l, r)) => (l, Op::Mul, r),
+                Visit::Enter(Expr::Div(l, r)) => (l, Op::Div, r),
+                Visit::Enter(Expr::Pow(l, r)) => (l, Op::Pow, r),
+                Visit::Enter(Expr::And(l, r)) => (l, Op::And, r),
+                Visit::Enter(Expr::Or(l, r)) => (l, Op::Or, r),
+            };
+            stack.extend([Visit::Emit(op), Visit::Enter(r), Visit::Enter(l)]);
+        }
+    }
+
+    fn slot(&mut self, name: &str) -> usize {
+        match self.slots.iter().position(|s| s == name) {
+            Some(slot) => slot,
+            None => {
+                self.slots.push(name.to_string());
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    /// Run the program over up to `LANES` binding sets at once, returning the values and which lanes failed
+    ///
+    /// Each value on `stack` has its mask at the same depth in `failed`. A
+    /// lane fails when an operand it depends on fails; a `?:`, `and` or `or`
+    /// depends only on the operands it would evaluate in that lane.
+    fn run(&self, columns: &[(Lane, Mask)], stack: &mut Vec<Lane>, failed: &mut Vec<Mask>) -> (Lane, Mask) {
+        stack.clear();
+        failed.clear();
+        for &op in &self.ops {
+            let apply: fn(f64, f64) -> f64 = match op {
+                Op::Const(n) => {
+                    stack.push([n; LANES]);
+                    failed.push([false; LANES]);
+                    continue;
+                }
+                Op::Fail => {
+                    stack.push([f64::NAN; LANES]);
+                    failed.push([true; LANES]);
+                    continue;
+                }
+                Op::Load(slot) => {
+                    let (column, unbound) = columns[slot];
+                    stack.push(column);
+                    failed.push(unbound);
+                    continue;
+                }
+                Op::Neg => {
+                    let top = stack.last_mut().expect("operand on stack");
+                    top.iter_mut().for_each(|x| *x = -*x);
+                    continue;
+                }
+                Op::Not => {
+                    let top = stack.last_mut().expect("operand on stack");
+                    top.iter_mut().for_each(|x| *x = truth_value(!is_true(*x)));
+                    continue;
+                }
+                Op::AddPercent(p) => {
+                    let top = stack.last_mut().expect("operand on stack");
+                    top.iter_mut().for_each(|x| *x = add_percent(*x, p));
+                    continue;
+                }
+                Op::Call(function, 1) => {
+                    let top = stack.last_mut().expect("operand on stack");
+                    top.iter_mut().for_each(|x| *x = function.eval(&[*x], AngleMode::Radians, RoundingMode::default()));
+                    continue;
+                }
+                Op::Call(function, 2) => {
+                    let rhs = stack.pop().expect("operand on stack");
+                    let lhs = stack.last_mut().expect("operand on stack");
+                    for (l, r) in lhs.iter_mut().zip(rhs) {
+                        *l = function.eval(&[*l, r], AngleMode::Radians, RoundingMode::default());
+                    }
+                    merge_failures(failed, 2);
+                    continue;
+                }
+                Op::Call(function, arity) => {
+                    let operands = stack.split_off(stack.len() - arity);
+                    let mut values = Vec::with_capacity(arity);
+                    let mut result = [0.0; LANES];
+                    for (lane, out) in result.iter_mut().enumerate() {
+                        values.clear();
+                        values.extend(operands.iter().map(|column| column[lane]));
+                        *out = function.eval(&values, AngleMode::Radians, RoundingMode::default());
+                    }
+                    stack.push(result);
+                    merge_failures(failed, arity);
+                    continue;
+                }
+                Op::Compare(relation) => {
+                    let rhs = stack.pop().expect("operand on stack");
+                    let lhs = stack.last_mut().expect("operand on stack");
+                    for (l, r) in lhs.iter_mut().zip(rhs) {
+                        *l = relation.eval(*l, r);
+                    }
+                    merge_failures(failed, 2);
+                    continue;
+                }
+                Op::Select => {
+                    let otherwise = stack.pop().expect("operand on stack");
+                    let then = stack.pop().expect("operand on stack");
+                    let cond = stack.last_mut().expect("operand on stack");
+                    let otherwise_failed = failed.pop().expect("operand on stack");
+                    let then_failed = failed.pop().expect("operand on stack");
+                    let cond_failed = failed.last_mut().expect("operand on stack");
+                    for lane in 0..LANES {
+                        let taken = if is_true(cond[lane]) { then_failed } else { otherwise_failed };
+                        cond_failed[lane] |= taken[lane];
+                    }
+                    for ((c, t), o) in cond.iter_mut().zip(then).zip(otherwise) {
+                        *c = if is_true(*c) { t } else { o };
+                    }
+                    continue;
+                }
+                Op::Add => |l, r| l + r,
+                Op::Sub => |l, r| l - r,
+                Op::Mul => |l, r| l * r,
+                Op::Div => |l, r| if r == 0.0 { f64::NAN } else { l / r },
+                Op::Pow => f64::powf,
+                Op::And => |l, r| truth_value(is_true(l) && is_true(r)),
+                Op::Or => |l, r| truth_value(is_true(l) || is_true(r)),
+            };
+            let rhs = stack.pop().expect("operand on stack");
+            let lhs = stack.last_mut().expect("operand on stack");
+            let rhs_failed = failed.pop().expect("operand on stack");
+            let lhs_failed = failed.last_mut().expect("operand on stack");
+            for lane in 0..LANES {
+                // The right side of `and` and `or` only counts where the left side does not decide
+                lhs_failed[lane] |= match op {
+                    Op::Div => rhs_failed[lane] || rhs[lane] == 0.0,
+                    Op::And => rhs_failed[lane] && is_true(lhs[lane]),
+                    Op::Or => rhs_failed[lane] && !is_true(lhs[lane]),
+                    _ => rhs_failed[lane],
+                };
+            }
+            for (l, r) in lhs.iter_mut().zip(rhs) {
+                *l = apply(*l, r);
+            }
+        }
+        (stack.pop().expect("program leaves one result"), failed.pop().expect("program leaves one result"))
+    }
+}
+
+/// Combine the masks of the top `arity` operands into one, failing each lane where any of them fails
+fn merge_failures(failed: &mut Vec<Mask>, arity: usize) {
+    let operands = failed.split_off(failed.len() - arity);
+    let mut merged = [false; LANES];
+    for mask in operands {
+        merged.iter_mut().zip(mask).for_each(|(m, f)| *m |= f);
+    }
+    failed.push(merged);
+}
+
+/// Evaluate `expr` once per entry in `bindings`, returning results in order
+///
+/// The expression is compiled once into a postfix program whose variables
+/// are resolved to columns, then run over fixed-width chunks of binding sets
+/// so the inner arithmetic loops vectorize. Rows that cannot be evaluated
+/// (an unbound variable, an unknown function, a misplaced list or a division
+/// by zero) produce `NaN`, as do all rows when a range has a variable bound.
+/// Failures are tracked apart from the values, so a comparison, `?:` or
+/// `not` applied to one never turns it into a plausible number. Trigonometric
+/// functions measure angles in radians.
+pub fn evaluate_many(expr: &Expr, bindings: &[Bindings]) -> Vec<f64> {
+    let program = Program::compile(expr);
+    let mut results = Vec::with_capacity(bindings.len());
+    let mut columns = vec![([0.0; LANES], [false; LANES]); program.slots.len()];
+    let (mut stack, mut failed) = (Vec::new(), Vec::new());
+
+    for chunk in bindings.chunks(LANES) {
+        for ((column, unbound), name) in columns.iter_mut().zip(&program.slots) {
+            for (lane, env) in chunk.iter().enumerate() {
+                let value = env.get(name).copied();
+                (column[lane], unbound[lane]) = (value.unwrap_or(f64::NAN), value.is_none());
+            }
+        }
+        let (lanes, lanes_failed) = program.run(&columns, &mut stack, &mut failed);
+        let values = lanes.iter().zip(lanes_failed).map(|(&value, failed)| if failed { f64::NAN } else { value });
+        results.extend(values.take(chunk.len()));
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_expression;
+
+    fn bindings(pairs: &[(&str, f64)]) -> Bindings {
+        pairs.iter().map(|&(k, v)| (k.to_string(), v)).collect()
+    }
+
+    #[test]
+    fn test_sweep_over_bindings() {
+        let expr = parse_expression("x * 2 + sqrt(y)").unwrap();
+        let inputs: Vec<Bindings> = (0..20)
+            .map(|i| bindings(&[("x", i as f64), ("y", 0.25)]))
+            .collect();
+        let results = evaluate_many(&expr, &inputs);
+        assert_eq!(results.len(), 20);
+        for (i, result) in results.iter().enumerate() {
+            assert_eq!(*result, i as f64 * 2.0 + 0.5);
+        }
+
+        let angle = parse_expression("atan2(y, x) + sin(x)").unwrap();
+        let results = evaluate_many(&angle, &inputs[..3]);
+        for (x, result) in results.into_iter().enumerate() {
+            let x = x as f64;
+            assert_eq!(result, 0.25f64.atan2(x) + x.sin());
+        }
+    }
+
+    #[test]
+    fn test_constant_expression_and_empty_input() {
+        let expr = parse_expression("-(2 + 3) / 4").unwrap();
+        assert_eq!(evaluate_many(&expr, &[Bindings::new(), Bindings::new()]), vec![-1.25, -1.25]);
+        assert!(evaluate_many(&expr, &[]).is_empty());
+
+        let percent = parse_expression("x + 10% - 50% * 2").unwrap();
+        let rows = [bindings(&[("x", 200.0)]), bindings(&[("x", -10.0)])];
+        assert_eq!(evaluate_many(&percent, &rows), vec![219.0, -12.0]);
+
+        let built = parse_expression("convert(x * 10, \"C\", \"F\") + sum(1..3)").unwrap();
+        assert_eq!(evaluate_many(&built, &rows), vec![3638.0, -142.0]);
+    }
+
+    #[test]
+    fn test_failed_rows_are_nan() {
+        let expr = parse_expression("1 / (x - 1) + y").unwrap();
+        let results = evaluate_many(
+            &expr,
+            &[
+                bindings(&[("x", 3.0), ("y", 1.0)]),
+                bindings(&[("x", 1.0), ("y", 1.0)]),
+                bindings(&[("x", 3.0)]),
+            ],
+        );
+        assert_eq!(results[0], 1.5);
+        assert!(results[1].is_nan());
+        assert!(results[2].is_nan());
+
+        let guarded = parse_expression("x == 1 ? 0 : 1 / (x - 1)").unwrap();
+        let rows = [bindings(&[("x", 3.0)]), bindings(&[("x", 1.0)]), bindings(&[("x", 0.0)])];
+        assert_eq!(evaluate_many(&guarded, &rows), vec![0.5, 0.0, -1.0]);
+        let checked = parse_expression("x != 1 and 1 / (x - 1) > 0 or not x").unwrap();
+        assert_eq!(evaluate_many(&checked, &rows), vec![1.0, 0.0, 1.0]);
+
+        let unknown = parse_expression("nope(2) + 1").unwrap();
+        assert!(evaluate_many(&unknown, &[Bindings::new()])[0].is_nan());
+
+        // A failed operand fails whatever uses it, however the failure would otherwise read
+        let x = [bindings(&[("x", 1.0)])];
+        for source in ["1 / (x - 1) ? 5 : 6", "1 / (x - 1) > 0", "nope(x) or 1", "not (1 / (x - 1))", "y == y"] {
+            assert!(evaluate_many(&parse_expression(source).unwrap(), &x)[0].is_nan(), "{}", source);
+        }
+        assert_eq!(evaluate_many(&parse_expression("1 or nope(x)").unwrap(), &x), vec![1.0]);
+    }
+}
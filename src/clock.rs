@@ -0,0 +1,28 @@
+//! Timing for result metadata, sheets and batch reports
+//!
+//! There is no clock without the standard library, so without the `std`
+//! feature every measured time is zero.
+
+use core::time::Duration;
+
+/// Time elapsed since it was started
+pub struct Stopwatch {
+    #[cfg(feature = "std")]
+    started: std::time::Instant,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        Stopwatch {
+            #[cfg(feature = "std")]
+            started: std::time::Instant::now(),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        #[cfg(feature = "std")]
+        return self.started.elapsed();
+        #[cfg(not(feature = "std"))]
+        Duration::ZERO
+    }
+}
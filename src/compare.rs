@@ -0,0 +1,162 @@
+//! Tolerance-based comparison of two expressions
+
+use crate::{evaluate, Result};
+
+/// Tolerance used when callers do not specify one
+pub const DEFAULT_TOLERANCE: f64 = 1e-9;
+
+/// Outcome of comparing the values of two expressions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub left: f64,
+    pub right: f64,
+    /// `|left - right|`
+    pub abs_diff: f64,
+    /// `|left - right|` scaled by the larger magnitude (0 when both sides are 0)
+    pub rel_diff: f64,
+    /// Representable values between `left` and `right` (`u64::MAX` if either is NaN)
+    pub ulps: u64,
+    /// Whether either difference is within the tolerance
+    pub equal: bool,
+}
+
+/// How close two values must be to count as equal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApproxMode {
+    /// At most this many representable values apart
+    Ulps(u64),
+    /// `|a - b|` at most this fraction of the larger magnitude
+    Relative(f64),
+    /// `|a - b|` at most this much
+    Absolute(f64),
+}
+
+/// Whether `a` and `b` are equal under `mode`
+///
+/// Identical values, including infinities of the same sign and zeros of
+/// either sign, are always equal; NaN is never equal to anything.
+pub fn approx_eq(a: f64, b: f64, mode: ApproxMode) -> bool {
+    if a == b {
+        return true;
+    }
+    if !a.is_finite() || !b.is_finite() {
+        return false;
+    }
+    match mode {
+        ApproxMode::Ulps(max) => ulps_between(a, b) <= max,
+        ApproxMode::Relative(eps) => (a - b).abs() <= eps * a.abs().max(b.abs()),
+        ApproxMode::Absolute(eps) => (a - b).abs() <= eps,
+    }
+}
+
+/// Number of representable values between `a` and `b`, or `u64::MAX` if either is NaN
+///
+/// The two zeros count as the same value.
+pub fn ulps_between(a: f64, b: f64) -> u64 {
+    if a.is_nan() || b.is_nan() {
+        return u64::MAX;
+    }
+    // Map the sign-magnitude bit patterns onto a monotonic integer line
+    let ordered = |x: f64| {
+        let bits = x.to_bits() as i64;
+        if bits < 0 { i64::MIN - bits } else { bits }
+    };
+    ordered(a).abs_diff(ordered(b))
+}
+
+impl Comparison {
+    /// Compare two already-computed values
+    pub fn of(left: f64, right: f64, tolerance: f64) -> Self {
+        let abs_diff = (left - right).abs();
+        let scale = left.abs().max(right.abs());
+        let rel_diff = if scale == 0.0 { 0.0 } else { abs_diff / scale };
+        let equal = approx_eq(left, right, ApproxMode::Absolute(tolerance))
+            || approx_eq(left, right, ApproxMode::Relative(tolerance));
+        Comparison {
+            left,
+            right,
+            abs_diff,
+            rel_diff,
+            ulps: ulps_between(left, right),
+            equal,
+        }
+    }
+}
+
+/// Evaluate both expressions and compare their values within `tolerance`
+///
+/// The values are considered equal when either the absolute or the relative
+/// difference is at most `tolerance`, so the same threshold works for values
+/// near zero and for large magnitudes.
+pub fn compare(a_expr: &str, b_expr: &str, tolerance: f64) -> Result<Comparison> {
+    let left = evaluate(a_expr)?;
+    let right = evaluate(b_expr)?;
+    Ok(Comparison::of(left, right, tolerance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ComputeError;
+
+    #[test]
+    fn test_equal_within_tolerance() {
+        let cmp = compare("0.1 + 0.2", "0.3", DEFAULT_TOLERANCE).unwrap();
+        assert!(cmp.equal);
+        assert!(cmp.abs_diff > 0.0);
+
+        let cmp = compare("2 * 3", "6", 0.0).unwrap();
+        assert!(cmp.equal);
+        assert_eq!(cmp.abs_diff, 0.0);
+        assert_eq!(cmp.rel_diff, 0.0);
+    }
+
+    #[test]
+    fn test_not_equal() {
+        let cmp = compare("1", "1.1", 0.01).unwrap();
+        assert!(!cmp.equal);
+        assert!((cmp.abs_diff - 0.1).abs() < 1e-12);
+        assert!((cmp.rel_diff - 0.1 / 1.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_relative_difference_for_large_values() {
+        let cmp = compare("1e20", "1e20 + 1e5", 1e-9).unwrap();
+        assert!(cmp.abs_diff > 1e-9);
+        assert!(cmp.equal);
+    }
+
+    #[test]
+    fn test_special_values() {
+        assert!(Comparison::of(f64::INFINITY, f64::INFINITY, 0.0).equal);
+        assert!(!Comparison::of(f64::INFINITY, f64::NEG_INFINITY, 1.0).equal);
+        assert!(!Comparison::of(f64::NAN, f64::NAN, 1.0).equal);
+    }
+
+    #[test]
+    fn test_approx_eq_modes() {
+        let next = f64::from_bits(1.0f64.to_bits() + 1);
+        assert_eq!(ulps_between(1.0, next), 1);
+        assert_eq!(ulps_between(0.0, -0.0), 0);
+        assert_eq!(ulps_between(-f64::from_bits(1), f64::from_bits(1)), 2);
+        assert!(approx_eq(0.1 + 0.2, 0.3, ApproxMode::Ulps(1)));
+        assert!(!approx_eq(1.0, 1.0 + 1e-12, ApproxMode::Ulps(4)));
+
+        assert!(approx_eq(1e20, 1e20 + 1e5, ApproxMode::Relative(1e-9)));
+        assert!(!approx_eq(1e-20, 2e-20, ApproxMode::Relative(1e-9)));
+        assert!(approx_eq(1e-20, 2e-20, ApproxMode::Absolute(1e-9)));
+        assert!(!approx_eq(1.0, 1.1, ApproxMode::Absolute(0.01)));
+
+        for mode in [ApproxMode::Ulps(u64::MAX), ApproxMode::Relative(1.0), ApproxMode::Absolute(f64::MAX)] {
+            assert!(approx_eq(f64::INFINITY, f64::INFINITY, mode));
+            assert!(!approx_eq(f64::INFINITY, f64::MAX, mode));
+            assert!(!approx_eq(f64::NAN, f64::NAN, mode));
+        }
+    }
+
+    #[test]
+    fn test_errors_propagate() {
+        assert_eq!(compare("1 / 0", "1", 0.1), Err(ComputeError::DivisionByZero));
+        assert!(matches!(compare("1", "2 +", 0.1), Err(ComputeError::ParseError(_))));
+    }
+}
@@ -0,0 +1,158 @@
+//! Evaluation that adds chains of `+` and `-` with compensated summation
+
+use crate::prelude::*;
+use crate::{
+    add_percent, functions, is_true, not_a_number, truth_value, AngleMode, Bindings, ComputeError, Expr, Result,
+    RoundingMode,
+};
+
+/// Evaluate `expr` like `eval_expr_in`, adding each chain of `+` and `-` with compensated summation
+///
+/// Plain addition rounds after every term, so adding `0.1` a thousand
+/// times drifts to `99.9999999999986`. Here each chain carries the rounding
+/// error of its additions along and adds it back once at the end, as in
+/// Neumaier's variant of Kahan summation, giving `100`. A chain is the run
+/// of `+` and `-` as written left to right; a parenthesized sum within it,
+/// such as `(b - c)` in `a - (b - c)`, is summed on its own as one term, and
+/// a percentage change such as `200 + 10%` ends it. Terms are evaluated in
+/// the same order as `eval_expr_in`, so errors are the same, and a chain
+/// that overflows to infinity or NaN gives the same value too.
+pub fn eval_compensated(expr: &Expr, env: &Bindings, angle_mode: AngleMode) -> Result<f64> {
+    eval_compensated_rounding(expr, env, angle_mode, RoundingMode::default())
+}
+
+/// Like `eval_compensated`, with `round` rounding in `rounding`
+pub(crate) fn eval_compensated_rounding(
+    expr: &Expr,
+    env: &Bindings,
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+) -> Result<f64> {
+    Compensated { env, angle_mode, rounding }.eval(expr)
+}
+
+struct Compensated<'a> {
+    env: &'a Bindings,
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+}
+
+impl Compensated<'_> {
+    fn eval(&self, expr: &Expr) -> Result<f64> {
+        let eval = |e: &Expr| self.eval(e);
+        if let Some((a, p)) = expr.percent_change() {
+            return Ok(add_percent(eval(a)?, p));
+        }
+        match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Percent(n) => Ok(n / 100.0),
+            Expr::Var(name) => self
+                .env
+                .get(name)
+                .copied()
+                .ok_or_else(|| ComputeError::UndefinedVariable(name.clone())),
+            Expr::Add(..) | Expr::Sub(..) => self.sum(expr),
+            Expr::Mul(l, r) => Ok(eval(l)? * eval(r)?),
+            Expr::Div(l, r) => {
+                let divisor = eval(r)?;
+                if divisor != 0.0 {
+                    Ok(eval(l)? / divisor)
+                } else {
+                    Err(ComputeError::DivisionByZero)
+                }
+            }
+            Expr::Pow(l, r) => Ok(eval(l)?.powf(eval(r)?)),
+            Expr::Neg(e) => eval(e).map(|n| -n),
+            Expr::Call(name, args) => functions::call(name, args, self.angle_mode, self.rounding, eval),
+            Expr::Compare(relation, l, r) => Ok(relation.eval(eval(l)?, eval(r)?)),
+            Expr::If(cond, then, otherwise) => eval(if is_true(eval(cond)?) { then } else { otherwise }),
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                // A true left operand decides `or` and a false one decides `and`
+                let decisive = matches!(expr, Expr::Or(..));
+                let holds = if is_true(eval(l)?) == decisive { decisive } else { is_true(eval(r)?) };
+                Ok(truth_value(holds))
+            }
+            Expr::Not(e) => Ok(truth_value(!is_true(eval(e)?))),
+            Expr::List(_) | Expr::Range(..) | Expr::Text(_) => Err(not_a_number(expr)),
+        }
+    }
+
+    /// Sum the chain of `+` and `-` rooted at `expr`
+    fn sum(&self, expr: &Expr) -> Result<f64> {
+        // Walk down the left operands, so even a very long chain takes no recursion
+        let mut terms = Vec::new();
+        let mut first = expr;
+        while let (Expr::Add(l, r) | Expr::Sub(l, r), None) = (first, first.percent_change()) {
+            terms.push((r, matches!(first, Expr::Sub(..))));
+            first = l;
+        }
+        let mut sum = self.eval(first)?;
+        let mut compensation = 0.0;
+        for (term, subtract) in terms.into_iter().rev() {
+            let term = if subtract { -self.eval(term)? } else { self.eval(term)? };
+            let next = sum + term;
+            // The low-order bits lost from whichever addend is smaller
+            compensation += if sum.abs() >= term.abs() { (sum - next) + term } else { (term - next) + sum };
+            sum = next;
+        }
+        // `sum` is exactly what plain addition gives, which stands when nothing was lost, keeping the sign of a
+        // zero, and past infinity, where the compensation is meaningless
+        Ok(if sum.is_finite() && compensation != 0.0 { sum + compensation } else { sum })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval_expr_in, evaluate, evaluate_batch_arena, evaluate_with, parse_expression, EvalOptions};
+
+    fn compensated(source: &str) -> Result<f64> {
+        eval_compensated(&parse_expression(source).unwrap(), &Bindings::new(), AngleMode::Radians)
+    }
+
+    #[test]
+    fn test_matches_eval_expr() {
+        let env: Bindings = [("x".to_string(), 4.0)].into_iter().collect();
+        for source in [
+            "1 + 2 * 3 - 4",
+            "x / (x - 4)",
+            "50 - 10% + 1",
+            "sum(1..x) + max(x, 2, 3)",
+            "x > 3 and not (x == 4) ? 1 : 2",
+            "-0 - 0",
+            "1e308 + 1e308 - 1e308",
+            "1 - x + y",
+            "[1, 2] + 1",
+        ] {
+            let expr = parse_expression(source).unwrap();
+            let expected = eval_expr_in(&expr, &env, AngleMode::Degrees);
+            let value = eval_compensated(&expr, &env, AngleMode::Degrees);
+            assert!(value == expected || value.as_ref().is_ok_and(|v| v.is_nan()), "{}", source);
+            if let (Ok(value), Ok(expected)) = (value, expected) {
+                assert_eq!(value.is_sign_negative(), expected.is_sign_negative(), "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compensation() {
+        let tenths = vec!["0.1"; 1000].join(" + ");
+        let plain = eval_expr_in(&parse_expression(&tenths).unwrap(), &Bindings::new(), AngleMode::Radians);
+        assert_eq!(plain, Ok(99.9999999999986));
+        assert_eq!(compensated(&tenths), Ok(100.0));
+        // The exact sum of the three doubles, where plain addition gives twice that
+        assert_eq!(compensated("0.1 + 0.2 - 0.3"), Ok(2.7755575615628914e-17));
+        // A large term does not swallow the small ones around it
+        assert_eq!(compensated("1e100 + 1 - 1e100"), Ok(1.0));
+        assert_eq!(compensated("2 * (1e100 + 1 - 1e100) - (1e100 + 1 - 1e100)"), Ok(1.0));
+    }
+
+    #[test]
+    fn test_compensated_sums_option() {
+        // Compensated sums bypass the fast path, which would add the three literals plainly
+        let options = EvalOptions { compensated_sums: true, ..Default::default() };
+        assert_eq!(evaluate("0.1 + 0.2 + 0.3"), Ok(0.6000000000000001));
+        assert_eq!(evaluate_with("0.1 + 0.2 + 0.3", &options), Ok(0.6));
+        assert_eq!(evaluate_batch_arena(&["0.1 + 0.2 + 0.3"], &options)[0].value, Ok(0.6));
+    }
+}
@@ -0,0 +1,339 @@
+//! Expressions compiled once into flat stack-machine code, for evaluating at many variable values
+
+use crate::functions::{self, Builtin};
+use crate::prelude::*;
+use crate::{
+    add_percent, is_true, not_a_number, truth_value, AngleMode, Bindings, ComputeError, Expr, Relation, Result,
+    RoundingMode,
+};
+
+/// An expression compiled by `compile`, ready to evaluate at any values of its variables
+///
+/// Function names, units and the slots of variables are all resolved up
+/// front, so evaluating is a single pass over a flat instruction vector
+/// with no tree walking or name lookups. Results and errors are exactly
+/// those `eval_expr_in` gives; an error in a branch that is never taken,
+/// such as an unknown function, stays silent there too.
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+    code: Vec<Op>,
+    variables: Vec<String>,
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Number(f64),
+    /// The value of the variable in this slot
+    Load(usize),
+    Add,
+    Sub,
+    Mul,
+    /// Divide the top by the value below it, which is the divisor, evaluated first as `eval_expr_in` does
+    Div,
+    /// Fail with `DivisionByZero` if the top, a divisor, is zero
+    CheckDivisor,
+    Pow,
+    Neg,
+    /// `a + p%` applied to the top of the stack
+    PercentChange(f64),
+    Compare(Relation),
+    Not,
+    /// Replace the top with its truth value, 1 or 0
+    Truth,
+    Jump(usize),
+    /// Pop the top and jump if it is false
+    JumpUnless(usize),
+    /// Pop the top and jump if it is true
+    JumpIf(usize),
+    /// Apply a builtin to the top `argc` values
+    Call(&'static Builtin, usize),
+    /// Apply a list-taking builtin to every number between the top two values
+    CallRange(&'static Builtin),
+    /// An error evaluating reaches here, such as calling an unknown function
+    Fail(ComputeError),
+}
+
+/// Compile `expr` for repeated evaluation, with trigonometry in the default angle mode
+pub fn compile(expr: &Expr) -> CompiledExpr {
+    compile_in(expr, AngleMode::default())
+}
+
+/// Compile `expr` for repeated evaluation, with trigonometric functions taking and returning angles in `angle_mode`
+pub fn compile_in(expr: &Expr, angle_mode: AngleMode) -> CompiledExpr {
+    compile_rounding(expr, angle_mode, RoundingMode::default())
+}
+
+/// Like `compile_in`, with `round` rounding in `rounding`
+pub(crate) fn compile_rounding(expr: &Expr, angle_mode: AngleMode, rounding: RoundingMode) -> CompiledExpr {
+    let mut compiled = CompiledExpr {
+        code: Vec::new(),
+        variables: Vec::new(),
+        angle_mode,
+        rounding,
+    };
+    compiled.emit(expr);
+    compiled
+}
+
+impl CompiledExpr {
+    /// The variables the expression uses, in the order `eval` takes their values
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// Evaluate with `values[i]` bound to `variables()[i]`
+    ///
+    /// Fails with `InvalidStructure` unless there is exactly one value per variable.
+    pub fn eval(&self, values: &[f64]) -> Result<f64> {
+        if values.len() != self.variables.len() {
+            return Err(ComputeError::InvalidStructure(format!(
+                "the expression has {} variable{} but was given {} value{}",
+                self.variables.len(),
+                if self.variables.len() == 1 { "" } else { "s" },
+                values.len(),
+                if values.len() == 1 { "" } else { "s" }
+            )));
+        }
+        self.run(|slot| Ok(values[slot]))
+    }
+
+    /// Evaluate with variables looked up in `env`; like `eval_expr_in`, a variable missing
+    /// from `env` is only an error if evaluation reaches it
+    pub fn eval_with_env(&self, env: &Bindings) -> Result<f64> {
+        let values: Vec<Option<f64>> = self.variables.iter().map(|name| env.get(name).copied()).collect();
+        self.run(|slot| values[slot].ok_or_else(|| ComputeError::UndefinedVariable(self.variables[slot].clone())))
+    }
+
+    fn run(&self, load: impl Fn(usize) -> Result<f64>) -> Result<f64> {
+        let mut stack: Vec<f64> = Vec::new();
+        let mut pc = 0;
+        while let Some(op) = self.code.get(pc) {
+            pc += 1;
+            match op {
+                Op::Number(n) => stack.push(*n),
+                Op::Load(slot) => stack.push(load(*slot)?),
+                Op::Neg => {
+                    let top = stack.last_mut().expect("an operand");
+                    *top = -*top;
+                }
+                Op::PercentChange(p) => {
+                    let top = stack.last_mut().expect("an operand");
+                    *top = add_percent(*top, *p);
+                }
+                Op::Not | Op::Truth => {
+                    let top = stack.last_mut().expect("an operand");
+                    *top = truth_value(is_true(*top) != matches!(op, Op::Not));
+                }
+                Op::CheckDivisor => {
+                    if *stack.last().expect("a divisor") == 0.0 {
+                        return Err(ComputeError::DivisionByZero);
+                    }
+                }
+                Op::Jump(target) => pc = *target,
+                Op::JumpUnless(target) | Op::JumpIf(target) => {
+                    if is_true(stack.pop().expect("a condition")) == matches!(op, Op::JumpIf(_)) {
+                        pc = *target;
+                    }
+                }
+                Op::Call(function, argc) => {
+                    let start = stack.len() - argc;
+                    let value = function.eval(&stack[start..], self.angle_mode, self.rounding);
+                    stack.truncate(start);
+                    stack.push(value);
+                }
+                Op::CallRange(function) => {
+                    let (hi, lo) = (stack.pop().expect("a bound"), stack.pop().expect("a bound"));
+                    let values: Vec<f64> = functions::range(lo, hi)?.collect();
+                    stack.push(function.eval(&values, self.angle_mode, self.rounding));
+                }
+                Op::Fail(error) => return Err(error.clone()),
+                binary => {
+                    let r = stack.pop().expect("two operands");
+                    let l = stack.last_mut().expect("two operands");
+                    *l = match binary {
+                        Op::Add => *l + r,
+                        Op::Sub => *l - r,
+                        Op::Mul => *l * r,
+                        // The divisor was pushed, and checked, first
+                        Op::Div => r / *l,
+                        Op::Pow => l.powf(r),
+                        Op::Compare(relation) => relation.eval(*l, r),
+                        _ => unreachable!("every other instruction is handled above"),
+                    };
+                }
+            }
+        }
+        Ok(stack.pop().expect("a result"))
+    }
+
+    /// Append code leaving the value of `expr` on the stack, in `eval_expr_in`'s evaluation order
+    fn emit(&mut self, expr: &Expr) {
+        if let Some((a, p)) = expr.percent_change() {
+            self.emit(a);
+            self.code.push(Op::PercentChange(p));
+            return;
+        }
+        match expr {
+            Expr::Number(n) => self.code.push(Op::Number(*n)),
+            Expr::Percent(n) => self.code.push(Op::Number(n / 100.0)),
+            Expr::Var(name) => {
+                let slot = match self.variables.iter().position(|v| v == name) {
+                    Some(slot) => slot,
+                    None => {
+                        self.variables.push(name.clone());
+                        self.variables.len() - 1
+                    }
+                };
+                self.code.push(Op::Load(slot));
+            }
+            Expr::Add(l, r) => self.binary(l, r, Op::Add),
+            Expr::Sub(l, r) => self.binary(l, r, Op::Sub),
+            Expr::Mul(l, r) => self.binary(l, r, Op::Mul),
+            Expr::Div(l, r) => {
+                // A zero divisor fails before the dividend is evaluated
+                self.emit(r);
+                self.code.push(Op::CheckDivisor);
+                self.emit(l);
+                self.code.push(Op::Div);
+            }
+            Expr::Pow(l, r) => self.binary(l, r, Op::Pow),
+            Expr::Compare(relation, l, r) => self.binary(l, r, Op::Compare(*relation)),
+            Expr::Neg(e) => {
+                self.emit(e);
+                self.code.push(Op::Neg);
+            }
+            Expr::Not(e) => {
+                self.emit(e);
+                self.code.push(Op::Not);
+            }
+            Expr::If(cond, then, otherwise) => {
+                self.emit(cond);
+                let to_otherwise = self.placeholder();
+                self.emit(then);
+                let to_end = self.placeholder();
+                self.code[to_otherwise] = Op::JumpUnless(self.code.len());
+                self.emit(otherwise);
+                self.code[to_end] = Op::Jump(self.code.len());
+            }
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                // A false left operand decides `and` and a true one decides `or`
+                let decisive = matches!(expr, Expr::Or(..));
+                self.emit(l);
+                let to_decided = self.placeholder();
+                self.emit(r);
+                self.code.push(Op::Truth);
+                let to_end = self.placeholder();
+                self.code[to_decided] =
+                    if decisive { Op::JumpIf(self.code.len()) } else { Op::JumpUnless(self.code.len()) };
+                self.code.push(Op::Number(truth_value(decisive)));
+                self.code[to_end] = Op::Jump(self.code.len());
+            }
+            Expr::Call(name, args) => self.call(name, args),
+            Expr::List(_) | Expr::Range(..) | Expr::Text(_) => self.code.push(Op::Fail(not_a_number(expr))),
+        }
+    }
+
+    fn binary(&mut self, first: &Expr, second: &Expr, op: Op) {
+        self.emit(first);
+        self.emit(second);
+        self.code.push(op);
+    }
+
+    /// Reserve an instruction for a jump whose target is not yet known
+    fn placeholder(&mut self) -> usize {
+        self.code.push(Op::Jump(usize::MAX));
+        self.code.len() - 1
+    }
+
+    /// Append a call, resolving the function and its operands as `functions::call` does when evaluating
+    fn call(&mut self, name: &str, args: &[Expr]) {
+        let function = match functions::resolve(name, args.len()) {
+            Ok(function) => function,
+            Err(e) => return self.code.push(Op::Fail(e)),
+        };
+        if let (true, [Expr::Range(lo, hi)]) = (function.takes_list(), args) {
+            self.binary(lo, hi, Op::CallRange(function));
+            return;
+        }
+        // The closure only bounds ranges, which are handled above
+        match function.operands(args, |_| Ok(0.0)) {
+            Ok(operands) => {
+                operands.iter().for_each(|operand| self.emit(operand));
+                self.code.push(Op::Call(function, operands.len()));
+            }
+            Err(e) => self.code.push(Op::Fail(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval_expr_in, evaluate_with, parse_expression, ComputeError, EvalBackend, EvalOptions};
+
+    #[test]
+    fn test_matches_eval_expr() {
+        let env: Bindings = [("x".to_string(), 4.0), ("y".to_string(), -0.5)].into_iter().collect();
+        for source in [
+            "1 + 2 * 3 - 4 / 5 ^ 2",
+            "x / (x - 4)",
+            "(1 / 0) * y",
+            "50 - 10% + x + 5%",
+            "sum(1..x) + max(x, 2, 3) + mean([x, y, 1])",
+            "x > 3 and not (x == 4) ? 1 : 2",
+            "y < 0 or 1 / 0",
+            "0 and undefined",
+            "x ? y : nope(1)",
+            "sqrt(-x) or 0",
+            "convert(x, \"ft\", \"in\") + sin(90)",
+            "convert(x, \"ft\", \"kg\")",
+            "atan2(x)",
+            "to_hex(x) + 1",
+            "sum(1..1e12)",
+            "[1, 2] + 1",
+            "z * 2",
+            "x / 0",
+            "z / 0",
+            "nope(1) / (x - 4)",
+        ] {
+            let expr = parse_expression(source).unwrap();
+            let expected = eval_expr_in(&expr, &env, AngleMode::Degrees);
+            let value = compile_in(&expr, AngleMode::Degrees).eval_with_env(&env);
+            assert!(value == expected || value.as_ref().is_ok_and(|v| v.is_nan()), "{}: {:?}", source, value);
+        }
+    }
+
+    #[test]
+    fn test_variable_slots() {
+        let compiled = compile(&parse_expression("x ^ 2 + 3 * x - rate").unwrap());
+        assert_eq!(compiled.variables(), ["x", "rate"]);
+        let values: Vec<f64> = (0..4).map(|x| compiled.eval(&[x as f64, 1.0]).unwrap()).collect();
+        assert_eq!(values, [-1.0, 3.0, 9.0, 17.0]);
+        assert_eq!(
+            compiled.eval(&[1.0]).unwrap_err().to_string(),
+            "the expression has 2 variables but was given 1 value"
+        );
+        assert_eq!(compile(&parse_expression("2 * 21").unwrap()).eval(&[]), Ok(42.0));
+    }
+
+    #[test]
+    fn test_bytecode_backend_matches_tree() {
+        for eval_backend in [EvalBackend::Tree, EvalBackend::Bytecode] {
+            let options = EvalOptions { eval_backend, angle_mode: AngleMode::Degrees, ..Default::default() };
+            assert_eq!(evaluate_with("sum(1..4) * 2 - 10% + cos(90) ^ 2", &options), Ok(18.0));
+            assert_eq!(evaluate_with("1 > 0 ? 2 / (1 - 1) : 0", &options), Err(ComputeError::DivisionByZero));
+            // With several errors, both backends report the one evaluation reaches first
+            for (expr, error) in [
+                ("x / 0", ComputeError::DivisionByZero),
+                ("nope(1) / (2 - 2)", ComputeError::DivisionByZero),
+                ("x / nope(1)", ComputeError::UnknownFunction("nope".into())),
+                ("x + 1 / 0", ComputeError::UndefinedVariable("x".into())),
+                ("0 and x or y", ComputeError::UndefinedVariable("y".into())),
+            ] {
+                assert_eq!(evaluate_with(expr, &options), Err(error), "{}", expr);
+            }
+        }
+    }
+}
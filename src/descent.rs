@@ -0,0 +1,481 @@
+//! Hand-rolled recursive-descent parser for the core grammar
+//!
+//! Accepts exactly the language of `compute.pest` and builds the same `Expr`
+//! trees as the Pratt parser, but reads the input in a single pass without
+//! materializing a pest parse tree. With the `descent` feature enabled,
+//! `evaluate` parses through this module; the pest grammar remains the
+//! reference that the differential property tests check it against.
+
+use crate::prelude::*;
+use crate::{parse_literal, ComputeError, Expr, ParseOptions, Relation, Result, Rule};
+use pest::error::{Error, ErrorVariant};
+use pest::Position;
+
+/// Parse an expression string into an AST without going through pest
+pub fn parse_expression(expr: &str) -> Result<Expr> {
+    parse_expression_with(expr, &ParseOptions::default())
+}
+
+/// Parse an expression string into an AST with explicit parse options
+pub fn parse_expression_with(expr: &str, options: &ParseOptions) -> Result<Expr> {
+    options.check_limits(expr)?;
+    let expr = &*options.group_separators(expr);
+    let mut parser = Parser {
+        src: expr,
+        bytes: expr.as_bytes(),
+        pos: 0,
+        options,
+        literal_error: None,
+    };
+    let ast = parser.expr()?;
+    if !parser.at_end() {
+        return Err(parser.error("expected operator or end of input"));
+    }
+    // Like the pest backend, syntax errors take priority over bad literals
+    match parser.literal_error {
+        Some(e) => Err(e),
+        None => options.check_tree(ast),
+    }
+}
+
+/// Words the grammar reserves for logical operators
+const KEYWORDS: [&str; 3] = ["and", "or", "not"];
+
+struct Parser<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    options: &'a ParseOptions,
+    /// First literal rejected by the overflow policy, reported once parsing succeeds
+    literal_error: Option<ComputeError>,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> ComputeError {
+        let pos = Position::new(self.src, self.pos).expect("position within input");
+        ComputeError::ParseError(Box::new(Error::<Rule>::new_from_pos(
+            ErrorVariant::CustomError {
+                message: message.to_string(),
+            },
+            pos,
+        )))
+    }
+
+    /// Skip whitespace and return the next byte, reading `−`, `×` and `÷` as `-`, `*` and `/`
+    fn peek(&mut self) -> Option<u8> {
+        loop {
+            let c = self.src[self.pos..].chars().next()?;
+            match c {
+                ' ' | '\t' | '\n' | '\r' | '\u{A0}' | '\u{202F}' | '\u{3000}' => self.pos += c.len_utf8(),
+                '−' => return Some(b'-'),
+                '×' => return Some(b'*'),
+                '÷' => return Some(b'/'),
+                _ => return self.bytes.get(self.pos).copied(),
+            }
+        }
+    }
+
+    /// Step past the operator that `peek` returned
+    fn advance(&mut self) {
+        self.pos += self.src[self.pos..].chars().next().map_or(0, char::len_utf8);
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    /// Consume `word` if it comes next as a whole word
+    fn keyword(&mut self, word: &str) -> bool {
+        self.peek();
+        let end = self.pos + word.len();
+        let whole = self.bytes[self.pos..].starts_with(word.as_bytes())
+            && !self.bytes.get(end).is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_');
+        if whole {
+            self.pos = end;
+        }
+        whole
+    }
+
+    /// Whether the `(` or name coming next is the right operand of an implicit multiplication
+    fn implicit_operand(&mut self) -> bool {
+        let start = self.pos;
+        let keyword = KEYWORDS.iter().any(|word| self.keyword(word));
+        self.pos = start;
+        self.options.implicit_multiplication && !keyword
+    }
+
+    /// `disjunction ("?" expr ":" expr)?`
+    fn expr(&mut self) -> Result<Expr> {
+        let cond = self.disjunction()?;
+        if self.peek() != Some(b'?') {
+            return Ok(cond);
+        }
+        self.pos += 1;
+        let then = self.expr()?;
+        if self.peek() != Some(b':') {
+            return Err(self.error("expected `:`"));
+        }
+        self.pos += 1;
+        let otherwise = self.expr()?;
+        Ok(Expr::If(Box::new(cond), Box::new(then), Box::new(otherwise)))
+    }
+
+    /// `conjunction ("or" conjunction)*`
+    fn disjunction(&mut self) -> Result<Expr> {
+        let mut lhs = self.conjunction()?;
+        while self.keyword("or") {
+            let rhs = self.conjunction()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    /// `negation ("and" negation)*`
+    fn conjunction(&mut self) -> Result<Expr> {
+        let mut lhs = self.negation()?;
+        while self.keyword("and") {
+            let rhs = self.negation()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    /// `"not"* comparison`
+    fn negation(&mut self) -> Result<Expr> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.negation()?)));
+        }
+        self.comparison()
+    }
+
+    /// `sum (relation sum)*`
+    fn comparison(&mut self) -> Result<Expr> {
+        let mut lhs = self.sum()?;
+        loop {
+            let (relation, len) = match (self.peek(), self.bytes.get(self.pos + 1)) {
+                (Some(b'<'), Some(b'=')) => (Relation::LessEq, 2),
+                (Some(b'>'), Some(b'=')) => (Relation::GreaterEq, 2),
+                (Some(b'='), Some(b'=')) => (Relation::Equal, 2),
+                (Some(b'!'), Some(b'=')) => (Relation::NotEqual, 2),
+                (Some(b'<'), _) => (Relation::Less, 1),
+                (Some(b'>'), _) => (Relation::Greater, 1),
+                _ => return Ok(lhs),
+            };
+            self.pos += len;
+            let rhs = self.sum()?;
+            lhs = Expr::Compare(relation, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    /// `term (("+" | "-") term)*`
+    fn sum(&mut self) -> Result<Expr> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Some(b'+') => Expr::Add,
+                Some(b'-') => Expr::Sub,
+                _ => return Ok(lhs),
+            };
+            self.advance();
+            let rhs = self.term()?;
+            lhs = op(Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    /// `power (("*" | "/" | implicit) power)*`
+    fn term(&mut self) -> Result<Expr> {
+        let mut lhs = self.power()?;
+        loop {
+            let op = match self.peek() {
+                Some(b'*') => Expr::Mul,
+                Some(b'/') => Expr::Div,
+                Some(b'(' | b'a'..=b'z' | b'A'..=b'Z' | b'_') if self.implicit_operand() => {
+                    // An implied `*` has no operator to consume
+                    lhs = Expr::Mul(Box::new(lhs), Box::new(self.power()?));
+                    continue;
+                }
+                _ => return Ok(lhs),
+            };
+            self.advance();
+            let rhs = self.power()?;
+            lhs = op(Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    /// `primary ("^" primary)*`, grouped from the right
+    fn power(&mut self) -> Result<Expr> {
+        let mut operands = vec![self.primary()?];
+        while self.peek() == Some(b'^') {
+            self.pos += 1;
+            operands.push(self.primary()?);
+        }
+        let mut rhs = operands.pop().expect("at least one operand");
+        while let Some(lhs) = operands.pop() {
+            rhs = Expr::Pow(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(rhs)
+    }
+
+    /// `("-" | "+")* (number | call | ident | list | text | "(" expr ")")`
+    fn primary(&mut self) -> Result<Expr> {
+        let mut neg_count = 0;
+        // A unary plus leaves its operand unchanged
+        while let Some(sign @ (b'-' | b'+')) = self.peek() {
+            neg_count += usize::from(sign == b'-');
+            self.advance();
+        }
+
+        let mut expr = match self.peek() {
+            Some(b'0'..=b'9') => self.uncertain()?,
+            Some(b'.') if self.bytes.get(self.pos + 1).is_some_and(u8::is_ascii_digit) => self.uncertain()?,
+            Some(b'a'..=b'z' | b'A'..=b'Z' | b'_') => {
+                let start = self.pos;
+                let name = self.ident();
+                if KEYWORDS.contains(&name.as_str()) {
+                    self.pos = start;
+                    return Err(self.error("expected number, variable, or `(`"));
+                }
+                if self.peek() == Some(b'(') {
+                    self.pos += 1;
+                    Expr::Call(name, self.arguments(b')')?)
+                } else {
+                    Expr::Var(name)
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                Expr::List(self.arguments(b']')?)
+            }
+            Some(b'"') => {
+                let len = self.src[self.pos + 1..].find('"').ok_or_else(|| self.error("expected closing `\"`"))?;
+                let text = self.src[self.pos + 1..self.pos + 1 + len].to_string();
+                self.pos += len + 2;
+                Expr::Text(text)
+            }
+            Some(b'(') => {
+                self.pos += 1;
+                let inner = self.expr()?;
+                if self.peek() != Some(b')') {
+                    return Err(self.error("expected `)`"));
+                }
+                self.pos += 1;
+                inner
+            }
+            _ => return Err(self.error("expected number, variable, or `(`")),
+        };
+
+        for _ in 0..neg_count {
+            expr = Expr::Neg(Box::new(expr));
+        }
+        Ok(expr)
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    /// Digits optionally grouped by single underscores, as in `1_000`
+    fn grouped_digits(&mut self) {
+        if self.digits() == 0 {
+            return;
+        }
+        while self.bytes.get(self.pos) == Some(&b'_') && self.bytes.get(self.pos + 1).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+            self.digits();
+        }
+    }
+
+    /// Longest match of `scientific | decimal | integer`, with no inner whitespace, and an optional `%`
+    fn number(&mut self) -> Expr {
+        let start = self.pos;
+        self.grouped_digits();
+        // Called at a digit or at a point followed by one, so either side of the point may be empty;
+        // a point followed by another is a range
+        if self.bytes.get(self.pos) == Some(&b'.') && self.bytes.get(self.pos + 1) != Some(&b'.') {
+            self.pos += 1;
+            self.grouped_digits();
+        }
+        if let Some(b'e' | b'E') = self.bytes.get(self.pos) {
+            // An exponent marker without digits is not part of the literal
+            let mark = self.pos;
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.bytes.get(self.pos) {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                self.pos = mark;
+            }
+        }
+
+        let value = parse_literal(&self.src[start..self.pos], self.options).unwrap_or_else(|e| {
+            self.literal_error.get_or_insert(e);
+            0.0
+        });
+        if self.bytes.get(self.pos) == Some(&b'%') {
+            self.pos += 1;
+            Expr::Percent(value)
+        } else {
+            Expr::Number(value)
+        }
+    }
+
+    /// A number, which `±` or `+/-` and another number make the call `pm(a, b)`
+    fn uncertain(&mut self) -> Result<Expr> {
+        let value = self.number();
+        if !matches!(value, Expr::Number(_)) {
+            return Ok(value);
+        }
+        self.peek();
+        let Some(sign) = ["±", "+/-"].into_iter().find(|sign| self.src[self.pos..].starts_with(sign)) else {
+            return Ok(value);
+        };
+        self.pos += sign.len();
+        let starts_number = match self.peek() {
+            Some(b'0'..=b'9') => true,
+            Some(b'.') => self.bytes.get(self.pos + 1).is_some_and(u8::is_ascii_digit),
+            _ => false,
+        };
+        if !starts_number {
+            return Err(self.error("expected number"));
+        }
+        match self.number() {
+            uncertainty @ Expr::Number(_) => Ok(Expr::Call("pm".to_string(), vec![value, uncertainty])),
+            _ => Err(self.error("expected number without `%`")),
+        }
+    }
+
+    fn ident(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_')
+        {
+            self.pos += 1;
+        }
+        self.src[start..self.pos].to_string()
+    }
+
+    /// `(expr ("," expr)*)? close`, after the `(` of a call or the `[` of a list
+    fn arguments(&mut self, close: u8) -> Result<Vec<Expr>> {
+        let mut args = Vec::new();
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expr()?);
+            // A range `lo..hi` is only allowed as the sole argument of a call
+            let range = self.peek() == Some(b'.') && self.bytes.get(self.pos + 1) == Some(&b'.');
+            if close == b')' && args.len() == 1 && range {
+                self.pos += 2;
+                let lo = args.pop().expect("one argument");
+                args.push(Expr::Range(Box::new(lo), Box::new(self.expr()?)));
+                if self.peek() != Some(close) {
+                    return Err(self.error("expected `)`"));
+                }
+                self.pos += 1;
+                return Ok(args);
+            }
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b) if b == close => {
+                    self.pos += 1;
+                    return Ok(args);
+                }
+                _ => return Err(self.error(&format!("expected `,` or `{}`", close as char))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LiteralOverflow;
+
+    #[test]
+    fn test_matches_pest_trees() {
+        for expr in [
+            "1 + 2 * 3",
+            "10 - 5 - 2",
+            "-(2 + 3) * --4",
+            "((x)) / y_1 - 2.5e-3",
+            " \t1.5E+2\n",
+            "-2 ^ 3 ^ -x * 4 ^ 0.5",
+            "sqrt (x) + f() * g(1, -y ^ 2)",
+            "a < b + 1 <= c == d != e > -f >= 2",
+            "x > 0 ? x : y ? 1 : 2 == 2 ? 3 : 4",
+            "a ? b ? c : d : f(p ? 1 : 0, q)",
+            "not not a < b and c or not d and (e or f) ? 1 : 0",
+            "x and y_1 or android or notx",
+            "2and(3)or not(4)",
+            "200 + 10% - 2.5e1% * x",
+            "1_000_000.000_1 - 1_0e5",
+            "2\u{A0}×\u{3000}−3 ÷ 4 −\u{202F}x",
+            "sum([1, -x, f([])]) / count( [ ] ) + [2]",
+            "sum(1..100) + product(-x .. 2 * y) * f(1...5) - g(2.)",
+            "+5 - -+x * +(+2) ++ 3",
+            "convert(x + 1, \"°C\",\"F\") * f(\"\", \" a b \") - -\"km\"",
+            "2.0±0.1 * -3 +/- .5 ^ 1e1 ± 1_0",
+        ] {
+            assert_eq!(parse_expression(expr), crate::parse_expression(expr), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for expr in [
+            "", "2 +", "(1 + 2", "1 2", "1..2", ".", "2x", "1e", "3 ^", "()", "f(1,)", "f(1", "a = b", "a ! b", "a <", "a ? b",
+            "a ? b :", "a : b", "a and", "not", "and = 1", "a < not b", "x andy", "-not a", "f(or)",
+            "10 %", "%", "x%", "10%%", "1__0", "1_", "2e1_0", "[1", "[1,]", "[1)", "sum[1]", "sum(1..)", "sum(1..2, 3)",
+            "sum(1, 2..3)", "[1..2]", "sum((1..2))", "sum(1..2..3)",
+            "\"km", "f(\"a\"\"b\")", "\"a\"(1)",
+            "2 ±", "2 ± 1%", "2% ± 1", "x ± 1", "2 ± x", "2 ± 1 ± 1", "2 + / - 1",
+        ] {
+            assert!(
+                matches!(parse_expression(expr), Err(ComputeError::ParseError(_))),
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_implicit_multiplication() {
+        let implicit = ParseOptions {
+            implicit_multiplication: true,
+            ..Default::default()
+        };
+        for expr in ["2(3 + 4)", "(2)(3)", "2pi", "-2 x y ^ 2", "1 / 2x + 3 sqrt(4)", "2 and x or not y", "x (2)"] {
+            assert_eq!(
+                parse_expression_with(expr, &implicit),
+                crate::parse_expression_with(expr, &implicit),
+                "{}",
+                expr
+            );
+        }
+        for expr in ["2 3", "x not y", "(2)3"] {
+            assert!(parse_expression_with(expr, &implicit).is_err(), "{}", expr);
+        }
+        assert!(parse_expression("2(3)").is_err());
+    }
+
+    #[test]
+    fn test_literal_overflow_policy() {
+        assert_eq!(parse_expression("1e999 + 1"), Err(ComputeError::Overflow));
+        // A later syntax error wins over an earlier bad literal
+        assert!(matches!(parse_expression("1e999 +"), Err(ComputeError::ParseError(_))));
+
+        let clamp = ParseOptions {
+            literal_overflow: LiteralOverflow::Clamp,
+            ..Default::default()
+        };
+        assert_eq!(parse_expression_with("1e999", &clamp), Ok(Expr::Number(f64::MAX)));
+        assert_eq!(parse_expression_with("1e999%", &clamp), Ok(Expr::Percent(f64::MAX)));
+    }
+}
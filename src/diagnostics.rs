@@ -0,0 +1,806 @@
+//! Editor-oriented diagnostics: every problem in a source string, with spans
+
+use crate::prelude::*;
+use crate::{
+    builtin, eval_expr, format_number, parse_expression, tokenize, ComputeError, ComputeParser, Expr, Relation, Rule,
+    Span, TokenKind,
+};
+use core::fmt;
+use pest::error::{ErrorVariant, InputLocation};
+use pest::iterators::Pair;
+use pest::Parser;
+use serde::Serialize;
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The expression cannot be evaluated
+    Error,
+    /// The expression evaluates, but probably not as intended
+    Warning,
+    /// A stylistic note
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Info => write!(f, "info"),
+        }
+    }
+}
+
+/// A single problem found in a source string
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, span: Span, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}: {}", self.severity, self.span, self.message)
+    }
+}
+
+/// Report every problem in `source`, ordered by position
+///
+/// Unlike `evaluate`, this never stops at the first problem: unknown
+/// identifiers and functions are reported alongside syntax errors, and a parseable
+/// expression is checked for zero divisors and suspicious constructs.
+/// An empty result means the expression evaluates cleanly.
+pub fn diagnose(source: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    if source.trim().is_empty() {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            Span::new(0, source.len()),
+            "Empty expression",
+        ));
+        return diagnostics;
+    }
+
+    let identifiers = identifier_spans(source);
+    for &span in &identifiers {
+        let name = span.slice(source);
+        let message = if source[span.end..].trim_start().starts_with('(') {
+            if builtin(name).is_some() {
+                continue;
+            }
+            format!("Unknown function `{}`", name)
+        } else {
+            format!("Unknown identifier `{}`", name)
+        };
+        diagnostics.push(Diagnostic::new(Severity::Error, span, message));
+    }
+
+    match ComputeParser::parse(Rule::equation, source) {
+        Ok(mut pairs) => {
+            if let Some(expr) = pairs.next() {
+                check_expr(expr, Context::default(), &mut diagnostics);
+            }
+        }
+        Err(_) => {
+            // An unknown identifier already explains a failure at its position
+            let syntax_errors = syntax_errors(source).into_iter();
+            diagnostics.extend(syntax_errors.filter(|d| !identifiers.iter().any(|id| id.contains(d.span.start))));
+        }
+    }
+
+    diagnostics.sort_by_key(|d| (d.span.start, d.severity));
+    diagnostics
+}
+
+/// Most syntax errors reported for one source string before recovery gives up
+const MAX_SYNTAX_ERRORS: usize = 16;
+
+/// Parse `source`, or report every syntax error in it rather than only the first
+///
+/// After each syntax error the input is patched where parsing stopped and
+/// parsed again: a missing operand becomes `0`, two adjacent operands get a
+/// `*` between them, a missing `)` is added, and a stray token is dropped.
+/// Each error is reported at its position in `source`, up to 16 of them.
+/// Errors other than syntax errors, such as an overflowing literal, are
+/// reported alone, spanning the whole input.
+pub fn parse_recovering(source: &str) -> Result<Expr, Vec<Diagnostic>> {
+    match parse_expression(source) {
+        Ok(expr) => Ok(expr),
+        Err(ComputeError::ParseError(_)) => Err(syntax_errors(source)),
+        Err(e) => Err(vec![Diagnostic::new(Severity::Error, Span::new(0, source.len()), e.message())]),
+    }
+}
+
+/// A patch that lets parsing continue past a syntax error
+enum Repair {
+    Insert(usize, String),
+    /// Overwrite a token with text no longer than it, padded with spaces so later offsets stay put
+    Overwrite(Span, &'static str),
+}
+
+/// The syntax errors in `source`, found by repairing each one in turn and parsing again
+fn syntax_errors(source: &str) -> Vec<Diagnostic> {
+    let mut errors: Vec<Diagnostic> = Vec::new();
+    let mut patched = source.to_string();
+    // Insertions so far, as offset into the text at the time and length, to map offsets back to `source`
+    let mut insertions: Vec<(usize, usize)> = Vec::new();
+    let original = |insertions: &[(usize, usize)], mut pos: usize| {
+        for &(at, len) in insertions.iter().rev() {
+            pos = if pos >= at + len { pos - len } else { pos.min(at) };
+        }
+        pos
+    };
+    // Every syntax error takes at most a few patches, so this bounds the work however recovery goes
+    for _ in 0..MAX_SYNTAX_ERRORS * 4 {
+        let Err(e) = ComputeParser::parse(Rule::equation, &patched) else {
+            break;
+        };
+        let (start, end) = match e.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span(span) => span,
+        };
+        let span = Span::new(original(&insertions, start), original(&insertions, end));
+        // A patch can leave the same spot wrong in another way, which is still one problem
+        if errors.last().is_none_or(|last| last.span.start != span.start) {
+            errors.push(Diagnostic::new(
+                Severity::Error,
+                span,
+                format!("Syntax error: {}", e.variant.message()),
+            ));
+        }
+        if errors.len() == MAX_SYNTAX_ERRORS {
+            break;
+        }
+        match repair(&patched, start, &e) {
+            Some(Repair::Insert(at, text)) => {
+                patched.insert_str(at, &text);
+                insertions.push((at, text.len()));
+            }
+            Some(Repair::Overwrite(span, text)) => {
+                let padded = format!("{:1$}", text, span.end - span.start);
+                patched.replace_range(span.start..span.end, &padded);
+            }
+            None => break,
+        }
+    }
+    errors
+}
+
+/// How to patch `source` so that parsing gets past the error at byte `pos`
+fn repair(source: &str, pos: usize, error: &pest::error::Error<Rule>) -> Option<Repair> {
+    let wants_operand = match &error.variant {
+        ErrorVariant::ParsingError { positives, .. } => {
+            positives.iter().any(|rule| matches!(rule, Rule::primary | Rule::expr))
+        }
+        ErrorVariant::CustomError { .. } => false,
+    };
+    let tokens = tokenize(source);
+    let Some(at) = tokens.iter().position(|t| t.span.start == pos) else {
+        if pos < source.len() {
+            // Inside a token the tokenizer reads differently from the grammar, so drop one character
+            let len = source[pos..].chars().next().map_or(1, char::len_utf8);
+            return Some(Repair::Overwrite(Span::new(pos, pos + len), ""));
+        }
+        // The input ended early: supply the missing operand, or else close what is still open
+        if wants_operand {
+            return Some(Repair::Insert(pos, " 0".into()));
+        }
+        let closers = unclosed(source);
+        return (!closers.is_empty()).then_some(Repair::Insert(pos, closers));
+    };
+    let token = &tokens[at];
+    let operand_follows = tokens.get(at + 1).is_some_and(|next| starts_operand(next.kind));
+    Some(match token.kind {
+        TokenKind::Operator | TokenKind::RightParen | TokenKind::RightBracket if wants_operand => {
+            Repair::Insert(pos, "0 ".into())
+        }
+        kind if starts_operand(kind) && !wants_operand => Repair::Insert(pos, "* ".into()),
+        // Stand a stray token in for what was expected where that keeps the rest in shape
+        TokenKind::Invalid if wants_operand && !operand_follows => Repair::Overwrite(token.span, "0"),
+        TokenKind::Invalid if !wants_operand && operand_follows => Repair::Overwrite(token.span, "+"),
+        _ => Repair::Overwrite(token.span, ""),
+    })
+}
+
+fn starts_operand(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Number | TokenKind::Identifier | TokenKind::LeftParen | TokenKind::LeftBracket | TokenKind::Text
+    )
+}
+
+/// The closing brackets and parentheses `source` lacks, innermost first
+fn unclosed(source: &str) -> String {
+    let mut open = Vec::new();
+    for c in source.chars() {
+        match c {
+            '(' => open.push(')'),
+            '[' => open.push(']'),
+            ')' | ']' => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    open.iter().rev().collect()
+}
+
+/// Spans of alphabetic words other than keywords: variable and function names
+fn identifier_spans(source: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            // Skip whole numbers so exponent markers and digit separators are not mistaken for words
+            while let Some(&(_, c)) = chars.peek() {
+                if c.is_ascii_digit() || c == '.' || c == '_' {
+                    chars.next();
+                } else if c == 'e' || c == 'E' {
+                    chars.next();
+                    if let Some(&(_, '+' | '-')) = chars.peek() {
+                        chars.next();
+                    }
+                } else {
+                    break;
+                }
+            }
+        } else if c == '"' {
+            // Quoted unit names are text, not identifiers
+            chars.by_ref().find(|&(_, c)| c == '"');
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start + c.len_utf8();
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let span = Span::new(start, end);
+            if !matches!(span.slice(source), "and" | "or" | "not") {
+                spans.push(span);
+            }
+        }
+    }
+    spans
+}
+
+/// Where a primary sits, for deciding whether parentheses around it are needed
+#[derive(Debug, Clone, Copy, Default)]
+struct Context {
+    /// Operator immediately to the left
+    before: Option<Rule>,
+    /// Operator immediately to the right
+    after: Option<Rule>,
+    /// Whether a prefix minus applies to the primary
+    negated: bool,
+}
+
+/// Binding strength of `rule` as an infix operator, or as the prefix `not`
+fn precedence(rule: Rule) -> u8 {
+    match rule {
+        Rule::conditional => CONDITIONAL,
+        Rule::or => OR,
+        Rule::and => AND,
+        Rule::not => NOT,
+        Rule::add | Rule::subtract => SUM,
+        Rule::multiply | Rule::divide => PRODUCT,
+        Rule::power => POWER,
+        _ => COMPARE,
+    }
+}
+
+/// Whether `rule` ends a sum, so terms on either side of it never cancel
+fn separates_sums(rule: Rule) -> bool {
+    matches!(rule, Rule::conditional | Rule::or | Rule::and | Rule::not) || Relation::from_rule(rule).is_some()
+}
+
+const CONDITIONAL: u8 = 1;
+const OR: u8 = 2;
+const AND: u8 = 3;
+const NOT: u8 = 4;
+const COMPARE: u8 = 5;
+const SUM: u8 = 6;
+const PRODUCT: u8 = 7;
+const POWER: u8 = 8;
+const NEG: u8 = 9;
+const ATOM: u8 = 10;
+
+fn check_expr(expr: Pair<Rule>, outer: Context, diagnostics: &mut Vec<Diagnostic>) {
+    let children: Vec<_> = expr.into_inner().collect();
+    // A lone primary inherits the surroundings of the group it fills
+    let sole = children.len() == 1;
+    let rule_at = |i: Option<usize>| i.and_then(|i| children.get(i)).map(|p| p.as_rule());
+    for (i, pair) in children.iter().enumerate() {
+        if pair.as_rule() == Rule::conditional {
+            for branch in pair.clone().into_inner() {
+                check_expr(branch, Context::default(), diagnostics);
+            }
+        }
+        if pair.as_rule() != Rule::primary {
+            continue;
+        }
+        let before = rule_at(i.checked_sub(1));
+        let after = rule_at(Some(i + 1));
+        // A base or exponent is not itself the divisor or factor
+        let powered = before == Some(Rule::power) || after == Some(Rule::power);
+        if before == Some(Rule::divide) && !powered {
+            check_divisor(pair, diagnostics);
+        }
+        if (before == Some(Rule::multiply) || after == Some(Rule::multiply)) && !powered {
+            check_factor(pair, diagnostics);
+        }
+        let context = if sole {
+            outer
+        } else {
+            Context {
+                before,
+                after,
+                negated: false,
+            }
+        };
+        check_primary(pair.clone(), context, diagnostics);
+    }
+    for sum in children.split(|pair| separates_sums(pair.as_rule())) {
+        check_self_subtraction(sum, diagnostics);
+    }
+}
+
+fn check_primary(primary: Pair<Rule>, context: Context, diagnostics: &mut Vec<Diagnostic>) {
+    let span = Span::from(primary.as_span());
+    let negations = primary.clone().into_inner().filter(|p| p.as_rule() == Rule::neg).count();
+    for pair in primary.into_inner() {
+        match pair.as_rule() {
+            Rule::number => check_number(&pair, diagnostics),
+            Rule::percent | Rule::uncertain => pair.into_inner().for_each(|number| check_number(&number, diagnostics)),
+            Rule::expr => {
+                let sole = sole_primary(&pair);
+                let nested_group = sole
+                    .as_ref()
+                    .is_some_and(|inner| inner.clone().into_inner().all(|p| p.as_rule() == Rule::expr));
+                let lone_number = sole
+                    .as_ref()
+                    .is_some_and(|inner| {
+                        inner
+                            .clone()
+                            .into_inner()
+                            .all(|p| matches!(p.as_rule(), Rule::number | Rule::percent))
+                    });
+                // A negation binds to the group, so the group's own surroundings no longer matter
+                let inner = if negations > 0 {
+                    Context {
+                        negated: true,
+                        ..Context::default()
+                    }
+                } else {
+                    context
+                };
+                if nested_group {
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Warning,
+                        span,
+                        "Redundant nested parentheses",
+                    ));
+                } else if lone_number {
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Info,
+                        span,
+                        "Parentheses around a single number are unnecessary",
+                    ));
+                } else if !parentheses_needed(&pair, inner) {
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Info,
+                        span,
+                        "Redundant parentheses",
+                    ));
+                }
+                check_expr(pair, inner, diagnostics);
+            }
+            Rule::call | Rule::list => {
+                // The bounds of a range are checked like arguments
+                let args = pair.into_inner().flat_map(|p| match p.as_rule() {
+                    Rule::range => p.into_inner().collect(),
+                    _ => vec![p],
+                });
+                for arg in args.filter(|p| p.as_rule() == Rule::expr) {
+                    check_expr(arg, Context::default(), diagnostics);
+                }
+            }
+            _ => {}
+        }
+    }
+    if negations > 1 {
+        diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            span,
+            format!("{} consecutive negations", negations),
+        ));
+    }
+}
+
+/// Whether removing the parentheses around `group` would change the parse tree
+fn parentheses_needed(group: &Pair<Rule>, context: Context) -> bool {
+    let children: Vec<_> = group.clone().into_inner().collect();
+    let binding = if children.iter().any(|p| p.as_rule() == Rule::conditional) {
+        CONDITIONAL
+    } else if children.iter().any(|p| p.as_rule() == Rule::or) {
+        OR
+    } else if children.iter().any(|p| p.as_rule() == Rule::and) {
+        AND
+    } else if children[0].as_rule() == Rule::not {
+        // `not` takes in any comparison that follows it
+        NOT
+    } else if children.iter().any(|p| Relation::from_rule(p.as_rule()).is_some()) {
+        COMPARE
+    } else if children.iter().any(|p| matches!(p.as_rule(), Rule::add | Rule::subtract)) {
+        SUM
+    } else if children.iter().any(|p| matches!(p.as_rule(), Rule::multiply | Rule::divide)) {
+        PRODUCT
+    } else if children.len() > 1 {
+        POWER
+    } else if children[0].clone().into_inner().any(|p| p.as_rule() == Rule::neg) {
+        NEG
+    } else {
+        ATOM
+    };
+    // `^`, `?:` and the prefix `not` are right associative and the rest left
+    // associative, so an equal-precedence operand on the other side keeps its group
+    let right_assoc = |op: Rule| u8::from(matches!(op, Rule::power | Rule::conditional | Rule::not));
+    (context.negated && binding < NEG)
+        || context.before.is_some_and(|op| binding < precedence(op) + 1 - right_assoc(op))
+        || context.after.is_some_and(|op| binding < precedence(op) + right_assoc(op))
+}
+
+/// Warn about a factor that is a literal zero
+fn check_factor(factor: &Pair<Rule>, diagnostics: &mut Vec<Diagnostic>) {
+    let value = literal_value(factor);
+    if value == Some(0.0) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            Span::from(factor.as_span()),
+            "Multiplication by zero",
+        ));
+    }
+}
+
+/// The value of a primary that is a possibly negated number literal
+fn literal_value(primary: &Pair<Rule>) -> Option<f64> {
+    let mut value = None;
+    for pair in primary.clone().into_inner() {
+        match pair.as_rule() {
+            Rule::neg => {}
+            Rule::number => value = pair.as_str().parse::<f64>().ok(),
+            _ => return None,
+        }
+    }
+    value
+}
+
+/// Warn about `a - a`, where a term is subtracted right after being added
+fn check_self_subtraction(children: &[Pair<Rule>], diagnostics: &mut Vec<Diagnostic>) {
+    // Split into terms at `+` and `-`, keeping each term's sign, span and text
+    let mut terms: Vec<(Rule, Span, String)> = Vec::new();
+    let mut sign = Rule::add;
+    let mut in_product = false;
+    for pair in children {
+        let text: String = pair.as_str().chars().filter(|c| !c.is_whitespace()).collect();
+        match pair.as_rule() {
+            Rule::add | Rule::subtract => {
+                sign = pair.as_rule();
+                in_product = false;
+            }
+            Rule::multiply | Rule::divide | Rule::power => {
+                in_product = true;
+                if let Some((_, _, term)) = terms.last_mut() {
+                    term.push_str(&text);
+                }
+            }
+            _ => {
+                let span = Span::from(pair.as_span());
+                match terms.last_mut() {
+                    Some((_, term_span, term)) if in_product => {
+                        term_span.end = span.end;
+                        term.push_str(&text);
+                    }
+                    _ => terms.push((sign, span, text)),
+                }
+            }
+        }
+    }
+    for pair in terms.windows(2) {
+        let ((first_sign, first_span, first), (second_sign, second_span, second)) = (&pair[0], &pair[1]);
+        if *first_sign == Rule::add && *second_sign == Rule::subtract && first == second {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                Span::new(first_span.start, second_span.end),
+                format!("`{} - {}` is always zero", first, second),
+            ));
+        }
+    }
+}
+
+fn check_number(number: &Pair<Rule>, diagnostics: &mut Vec<Diagnostic>) {
+    let literal = number.as_str();
+    if let Ok(value) = literal.parse::<f64>() {
+        if value.is_infinite() {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                Span::from(number.as_span()),
+                "Literal exceeds the largest finite number",
+            ));
+        } else if decimal_digits(literal) != decimal_digits(&format_number(value)) {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                Span::from(number.as_span()),
+                format!("Literal is too large or precise for f64 and reads as {}", format_number(value)),
+            ));
+        }
+    }
+}
+
+/// Significant digits and power-of-ten scale of a decimal literal, ignoring padding zeros
+fn decimal_digits(literal: &str) -> (String, i64) {
+    let (mantissa, exponent) = match literal.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse().unwrap_or(0)),
+        None => (literal, 0),
+    };
+    let fraction = mantissa
+        .split_once('.')
+        .map_or(0, |(_, f)| f.bytes().filter(u8::is_ascii_digit).count() as i64);
+    let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+    let trimmed = digits.trim_end_matches('0');
+    let scale = exponent - fraction + (digits.len() - trimmed.len()) as i64;
+    (trimmed.trim_start_matches('0').to_string(), scale)
+}
+
+fn check_divisor(divisor: &Pair<Rule>, diagnostics: &mut Vec<Diagnostic>) {
+    let span = Span::from(divisor.as_span());
+    let literal = divisor
+        .clone()
+        .into_inner()
+        .find(|p| p.as_rule() != Rule::neg)
+        .is_some_and(|atom| atom.as_rule() == Rule::number);
+    let value = parse_expression(divisor.as_str()).and_then(|ast| eval_expr(&ast));
+    if value == Ok(0.0) {
+        let message = if literal {
+            "Division by literal zero"
+        } else {
+            "Divisor evaluates to zero"
+        };
+        diagnostics.push(Diagnostic::new(Severity::Error, span, message));
+    }
+}
+
+/// The single primary inside a parenthesized expression, if there is only one
+fn sole_primary<'i>(expr: &Pair<'i, Rule>) -> Option<Pair<'i, Rule>> {
+    let mut inner = expr.clone().into_inner();
+    match (inner.next(), inner.next()) {
+        (Some(primary), None) if primary.as_rule() == Rule::primary => {
+            // A negated group is meaningful, so only bare atoms count
+            let has_negation = primary.clone().into_inner().any(|p| p.as_rule() == Rule::neg);
+            (!has_negation).then_some(primary)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(source: &str) -> Vec<(Severity, String)> {
+        diagnose(source)
+            .into_iter()
+            .map(|d| (d.severity, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_expression() {
+        assert!(diagnose("2 + 3 * (4 - 1)").is_empty());
+        assert!(diagnose("-(1 + 2)").is_empty());
+        assert!(diagnose("1 < 2 and not (3 > 4 or 0)").is_empty());
+    }
+
+    #[test]
+    fn test_empty_and_syntax_errors() {
+        assert_eq!(messages("  ")[0], (Severity::Error, "Empty expression".into()));
+
+        let diagnostics = diagnose("2 + * 3");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].span.start, 4);
+        assert!(diagnostics[0].message.starts_with("Syntax error"));
+    }
+
+    #[test]
+    fn test_recovers_after_syntax_errors() {
+        let starts = |source| match parse_recovering(source) {
+            Ok(_) => panic!("{} parsed", source),
+            Err(diagnostics) => diagnostics.iter().map(|d| d.span.start).collect::<Vec<_>>(),
+        };
+        assert_eq!(starts("1 + * 2 + (3 * "), [4, 15]);
+        assert_eq!(starts("2 3 + $ 4"), [2, 6]);
+        assert_eq!(starts("1 + ) * 2 )"), [4, 10]);
+        assert_eq!(starts("1 $ 2 $ 3"), [2, 6]);
+        // A missing operand and the brackets left open after it are one problem
+        assert_eq!(starts("1 + 2 ) + [1, "), [6, 14]);
+        assert_eq!(starts("((1 + 2"), [7]);
+        assert_eq!(starts(&"1 + ".repeat(40).replace('1', "*")).len(), MAX_SYNTAX_ERRORS);
+        assert_eq!(parse_recovering("1 + 2 * 3"), Ok(parse_expression("1 + 2 * 3").unwrap()));
+        assert_eq!(parse_recovering("1e999").unwrap_err()[0].message, "Numeric overflow");
+
+        // `diagnose` reports them all too, except where an unknown name explains the failure
+        assert_eq!(messages("foo bar + * 2").len(), 3);
+        assert_eq!(diagnose("1 + * 2 )").iter().filter(|d| d.message.starts_with("Syntax error")).count(), 2);
+    }
+
+    #[test]
+    fn test_unknown_identifiers_all_reported() {
+        let diagnostics = diagnose("x * 2 + foo_bar");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].message, "Unknown identifier `x`");
+        assert_eq!(diagnostics[0].span, Span::new(0, 1));
+        assert_eq!(diagnostics[1].message, "Unknown identifier `foo_bar`");
+        assert_eq!(diagnostics[1].span, Span::new(8, 15));
+
+        // Exponent markers and digit separators are part of numbers, not identifiers
+        assert!(diagnose("1e10 + 2.5E-3 + 1_000.000_1").is_empty());
+        // Nor are quoted unit names
+        assert_eq!(messages("convert(y, \"ft\", \"in\")"), vec![(Severity::Error, "Unknown identifier `y`".into())]);
+
+        // Built-in function names are known; other called names are not
+        assert_eq!(
+            messages("sqrt(4) + sqr (4)"),
+            vec![(Severity::Error, "Unknown function `sqr`".into())]
+        );
+    }
+
+    #[test]
+    fn test_zero_divisors() {
+        let diagnostics = diagnose("1 / 0 + 2 / (3 - 3)");
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].message, "Division by literal zero");
+        assert_eq!(diagnostics[0].span, Span::new(4, 5));
+        assert_eq!(diagnostics[1].message, "Divisor evaluates to zero");
+        assert_eq!(diagnostics[1].span, Span::new(12, 19));
+        // The zero divisor is itself a self-subtraction
+        assert_eq!(diagnostics[2].message, "`3 - 3` is always zero");
+
+        assert_eq!(
+            messages("5 / -0"),
+            vec![(Severity::Error, "Division by literal zero".into())]
+        );
+        // The divisor is the whole power, which is one
+        assert!(diagnose("1 / 0 ^ 0").is_empty());
+        assert_eq!(
+            messages("abs(1 / 0)"),
+            vec![(Severity::Error, "Division by literal zero".into())]
+        );
+    }
+
+    #[test]
+    fn test_suspicious_constructs() {
+        assert_eq!(
+            messages("--5"),
+            vec![(Severity::Warning, "2 consecutive negations".into())]
+        );
+        assert_eq!(
+            messages("((1 + 2)) * 3"),
+            vec![(Severity::Warning, "Redundant nested parentheses".into())]
+        );
+        assert_eq!(messages("(4) * 3")[0].0, Severity::Info);
+        assert_eq!(
+            messages("x + (2 * 3)"),
+            vec![
+                (Severity::Error, "Unknown identifier `x`".into()),
+                (Severity::Info, "Redundant parentheses".into()),
+            ]
+        );
+        assert_eq!(
+            messages("1e999 - 1"),
+            vec![(
+                Severity::Error,
+                "Literal exceeds the largest finite number".into()
+            )]
+        );
+        assert_eq!(
+            messages("200 + 1e999%"),
+            vec![(
+                Severity::Error,
+                "Literal exceeds the largest finite number".into()
+            )]
+        );
+        assert_eq!(
+            messages("200 + (10%)"),
+            vec![(Severity::Info, "Parentheses around a single number are unnecessary".into())]
+        );
+    }
+
+    #[test]
+    fn test_lint_warnings() {
+        let warnings = |source| -> Vec<String> {
+            diagnose(source)
+                .into_iter()
+                .filter(|d| d.severity == Severity::Warning)
+                .map(|d| d.message)
+                .collect()
+        };
+        assert_eq!(warnings("3 * 0 + 0 * (1 + 2)"), vec!["Multiplication by zero"; 2]);
+        assert_eq!(warnings("2 + 4 * 5 - 4 * 5"), vec!["`4*5 - 4*5` is always zero"]);
+        assert!(warnings("2 * 5 - 5").is_empty());
+        assert!(warnings("1 + 2 < 2 - 1").is_empty());
+        assert_eq!(warnings("1 > 0 ? 0 : 2 - 2"), vec!["`2 - 2` is always zero"]);
+        assert!(warnings("x - 1 and not x - 1").is_empty());
+        assert_eq!(warnings("a or not x - x"), vec!["`x - x` is always zero"]);
+        assert_eq!(
+            warnings("9007199254740993 + 0.10000000000000001"),
+            vec![
+                "Literal is too large or precise for f64 and reads as 9007199254740992",
+                "Literal is too large or precise for f64 and reads as 0.1",
+            ]
+        );
+        assert!(warnings("1.50e3 + 0.000 + 1e20 + 00012").is_empty());
+    }
+
+    #[test]
+    fn test_needed_parentheses_are_not_reported() {
+        for source in [
+            "(1 + 2) * 3",
+            "1 - (2 - 3)",
+            "2 / (3 * 4)",
+            "-(1 + 2)",
+            "-((1 + 2))",
+            "(1 + 2) / -(3)",
+            "(2 ^ 3) ^ 2",
+            "2 ^ (3 * 4)",
+            "-(2 ^ 2)",
+            "a != (b >= c)",
+            "a < (b < c)",
+            "(a ? b : c) ? d : e",
+            "-(a > 0)",
+            "(a > 0) * 2",
+            "(a or b) and c",
+            "a and (b and c)",
+            "not (a and b)",
+            "(not a) + 1",
+            "-(not a)",
+            "(not a) < b",
+        ] {
+            assert!(
+                diagnose(source).iter().all(|d| d.message != "Redundant parentheses"),
+                "{}",
+                source
+            );
+        }
+        assert_eq!(messages("(1 * 2) + 3"), vec![(Severity::Info, "Redundant parentheses".into())]);
+        assert_eq!(messages("(1 + 2)"), vec![(Severity::Info, "Redundant parentheses".into())]);
+        assert_eq!(messages("(-1) * 2"), vec![(Severity::Info, "Redundant parentheses".into())]);
+        assert_eq!(messages("2 ^ (3 ^ 2)"), vec![(Severity::Info, "Redundant parentheses".into())]);
+        assert_eq!(messages("(2 ^ 3) * 4"), vec![(Severity::Info, "Redundant parentheses".into())]);
+        assert_eq!(messages("(1 + 2) < 4"), vec![(Severity::Info, "Redundant parentheses".into())]);
+        assert_eq!(
+            messages("not (not 1 < 2) or (0 and 1) and (not 0)"),
+            vec![(Severity::Info, "Redundant parentheses".into()); 3]
+        );
+        assert_eq!(
+            messages("1 > 0 ? (2 * 3) : 1 ? 2 : (3 ? 4 : 5)"),
+            vec![(Severity::Info, "Redundant parentheses".into()); 2]
+        );
+    }
+}
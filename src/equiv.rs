@@ -0,0 +1,397 @@
+//! Equivalence checking of two expressions by canonical form and numeric probing
+
+use crate::compare::{approx_eq, ApproxMode};
+use crate::prelude::*;
+use crate::visit::{walk_expr, Visitor};
+use crate::{eval_expr_with_env, format_number, Bindings, Expr, Result};
+use alloc::collections::BTreeSet;
+use core::fmt;
+use core::mem;
+
+/// Outcome of checking whether two expressions are equivalent
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equivalence {
+    pub equivalent: bool,
+    /// Both sides reduce to the same canonical form, so no probing was needed
+    pub canonical_match: bool,
+    pub left_canonical: Expr,
+    pub right_canonical: Expr,
+    /// The first probe point at which the two sides disagree
+    pub counterexample: Option<Counterexample>,
+}
+
+/// Variable values at which two expressions evaluate differently
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    pub bindings: Bindings,
+    pub left: Result<f64>,
+    pub right: Result<f64>,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<_> = self.bindings.keys().collect();
+        names.sort();
+        if !names.is_empty() {
+            let point: Vec<_> = names
+                .iter()
+                .map(|name| format!("{} = {}", name, format_number(self.bindings[*name])))
+                .collect();
+            write!(f, "at {}: ", point.join(", "))?;
+        }
+        let show = |r: &Result<f64>| match r {
+            Ok(v) => format_number(*v),
+            Err(e) => format!("error ({})", e),
+        };
+        write!(f, "left = {}, right = {}", show(&self.left), show(&self.right))
+    }
+}
+
+/// Probe values tried first; later probes are spread pseudo-randomly
+const PROBE_VALUES: &[f64] = &[0.0, 1.0, -1.0, 2.0, -2.5, 0.5, 3.0, 10.0, -7.25, 100.0, 0.001, 1e6];
+
+/// Number of variable assignments tried when canonical forms differ
+const PROBES: usize = 32;
+
+/// Decide whether `a` and `b` are equivalent within `tolerance`
+///
+/// The two sides are first reduced to a canonical form that folds constants,
+/// combines like terms, and orders commutative operands. Identical forms are
+/// equivalent outright. Otherwise both sides are evaluated at a fixed set of
+/// variable assignments and compared as `compare` does; they are equivalent
+/// if they agree everywhere, including failing with the same error.
+pub fn equivalent(a: &Expr, b: &Expr, tolerance: f64) -> Equivalence {
+    let left_canonical = canonical(a);
+    let right_canonical = canonical(b);
+    let canonical_match = left_canonical == right_canonical;
+    let counterexample = if canonical_match {
+        None
+    } else {
+        let mut names = BTreeSet::new();
+        collect_variables(a, &mut names);
+        collect_variables(b, &mut names);
+        probe(a, b, &names, tolerance)
+    };
+    Equivalence {
+        equivalent: counterexample.is_none(),
+        canonical_match,
+        left_canonical,
+        right_canonical,
+        counterexample,
+    }
+}
+
+/// Whether `a` and `b` have the same canonical form, as `2 + 3 * 4` and `4 * 3 + 2` do
+///
+/// Unlike `equivalent`, this never evaluates either side, so it is cheap and
+/// gives no false positives beyond the rounding of folded constants; but it
+/// can miss equalities such as `(x + 1) * (x + 1)` and `x * x + 2 * x + 1`
+/// that only probing finds.
+pub fn semantically_eq(a: &Expr, b: &Expr) -> bool {
+    canonical(a) == canonical(b)
+}
+
+fn probe(a: &Expr, b: &Expr, names: &BTreeSet<String>, tolerance: f64) -> Option<Counterexample> {
+    // Constant expressions need only one evaluation
+    let probes = if names.is_empty() { 1 } else { PROBES };
+    for i in 0..probes {
+        let bindings: Bindings = names
+            .iter()
+            .enumerate()
+            .map(|(j, name)| (name.clone(), probe_value(i, j)))
+            .collect();
+        let left = eval_expr_with_env(a, &bindings);
+        let right = eval_expr_with_env(b, &bindings);
+        let agree = match (&left, &right) {
+            (Ok(l), Ok(r)) => {
+                (l.is_nan() && r.is_nan())
+                    || approx_eq(*l, *r, ApproxMode::Absolute(tolerance))
+                    || approx_eq(*l, *r, ApproxMode::Relative(tolerance))
+            }
+            (Err(l), Err(r)) => mem::discriminant(l) == mem::discriminant(r),
+            _ => false,
+        };
+        if !agree {
+            return Some(Counterexample {
+                bindings,
+                left,
+                right,
+            });
+        }
+    }
+    None
+}
+
+fn probe_value(i: usize, j: usize) -> f64 {
+    if i < PROBE_VALUES.len() {
+        // Offset per variable so that variables differ from one another
+        PROBE_VALUES[(i + 5 * j) % PROBE_VALUES.len()]
+    } else {
+        ((i * 7919 + j * 104_729) as f64).sin() * 50.0
+    }
+}
+
+fn collect_variables(expr: &Expr, names: &mut BTreeSet<String>) {
+    struct Variables<'a>(&'a mut BTreeSet<String>);
+
+    impl Visitor for Variables<'_> {
+        fn visit_expr(&mut self, expr: &Expr) {
+            if let Expr::Var(name) = expr {
+                self.0.insert(name.clone());
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    Variables(names).visit_expr(expr);
+}
+
+/// Printed numerator and denominator factors, identifying like terms
+type TermKey = (Vec<String>, Vec<String>);
+
+/// A product `coeff * num[0] * ... / den[0] / ...` of canonical factors
+struct Term {
+    coeff: f64,
+    num: Vec<Expr>,
+    den: Vec<Expr>,
+}
+
+impl Term {
+    fn key(&self) -> TermKey {
+        let strings = |v: &[Expr]| v.iter().map(|e| e.to_string()).collect();
+        (strings(&self.num), strings(&self.den))
+    }
+
+    fn collect(&mut self, expr: &Expr, inverted: bool) {
+        if let Some((a, p)) = expr.percent_change() {
+            // `a + p%` is `a` scaled by `1 + p / 100`
+            let scale = 1.0 + p / 100.0;
+            self.coeff = if inverted { self.coeff / scale } else { self.coeff * scale };
+            return self.collect(a, inverted);
+        }
+        match expr {
+            Expr::Mul(l, r) => {
+                self.collect(l, inverted);
+                self.collect(r, inverted);
+            }
+            Expr::Div(l, r) => {
+                self.collect(l, inverted);
+                self.collect(r, !inverted);
+            }
+            Expr::Neg(e) => {
+                self.coeff = -self.coeff;
+                self.collect(e, inverted);
+            }
+            // A literal zero divisor stays symbolic so the error is preserved
+            Expr::Number(n) if !(inverted && *n == 0.0) => {
+                self.coeff = if inverted { self.coeff / n } else { self.coeff * n };
+            }
+            Expr::Percent(n) if !(inverted && *n == 0.0) => {
+                self.coeff = if inverted { self.coeff / (n / 100.0) } else { self.coeff * (n / 100.0) };
+            }
+            other => {
+                let factor = match other {
+                    Expr::Add(..) | Expr::Sub(..) => canonical(other),
+                    Expr::Pow(l, r) => Expr::Pow(Box::new(canonical(l)), Box::new(canonical(r))),
+                    Expr::Call(name, args) => Expr::Call(name.clone(), args.iter().map(canonical).collect()),
+                    Expr::List(elements) => Expr::List(elements.iter().map(canonical).collect()),
+                    Expr::Range(lo, hi) => Expr::Range(Box::new(canonical(lo)), Box::new(canonical(hi))),
+                    Expr::Compare(relation, l, r) => {
+                        Expr::Compare(*relation, Box::new(canonical(l)), Box::new(canonical(r)))
+                    }
+                    Expr::If(cond, then, otherwise) => Expr::If(
+                        Box::new(canonical(cond)),
+                        Box::new(canonical(then)),
+                        Box::new(canonical(otherwise)),
+                    ),
+                    Expr::And(l, r) => Expr::And(Box::new(canonical(l)), Box::new(canonical(r))),
+                    Expr::Or(l, r) => Expr::Or(Box::new(canonical(l)), Box::new(canonical(r))),
+                    Expr::Not(e) => Expr::Not(Box::new(canonical(e))),
+                    atom => atom.clone(),
+                };
+                if inverted {
+                    self.den.push(factor);
+                } else {
+                    self.num.push(factor);
+                }
+            }
+        }
+    }
+
+    /// Rebuild the term with a non-negative coefficient, returning whether it was negative
+    fn into_expr(mut self) -> (bool, Expr) {
+        let negative = self.coeff.is_sign_negative();
+        let magnitude = self.coeff.abs();
+        let mut factors = mem::take(&mut self.num).into_iter();
+        let mut expr = match factors.next() {
+            None => Expr::Number(magnitude),
+            Some(first) if magnitude == 1.0 => first,
+            Some(first) => Expr::Mul(Box::new(Expr::Number(magnitude)), Box::new(first)),
+        };
+        for factor in factors {
+            expr = Expr::Mul(Box::new(expr), Box::new(factor));
+        }
+        for divisor in mem::take(&mut self.den) {
+            expr = Expr::Div(Box::new(expr), Box::new(divisor));
+        }
+        (negative, expr)
+    }
+}
+
+/// Canonical form: like terms combined, constants folded, commutative operands sorted
+pub(crate) fn canonical(expr: &Expr) -> Expr {
+    let mut signed = Vec::new();
+    collect_terms(expr, false, &mut signed);
+
+    let mut terms: Vec<(TermKey, Term)> = Vec::new();
+    for (negated, e) in signed {
+        let mut term = Term {
+            coeff: if negated { -1.0 } else { 1.0 },
+            num: Vec::new(),
+            den: Vec::new(),
+        };
+        term.collect(e, false);
+        term.num.sort_by_cached_key(|f| f.to_string());
+        term.den.sort_by_cached_key(|f| f.to_string());
+        let key = term.key();
+        match terms.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => existing.coeff += term.coeff,
+            None => terms.push((key, term)),
+        }
+    }
+    // Variable terms in a stable order, with the constant term last; a
+    // cancelled term stays when dropping it could hide an error or a NaN
+    terms.retain(|(_, term)| term.coeff != 0.0 || !(term.den.is_empty() && term.num.iter().all(cannot_fail)));
+    terms.sort_by(|(a, _), (b, _)| {
+        let constant = |k: &TermKey| k.0.is_empty() && k.1.is_empty();
+        constant(a).cmp(&constant(b)).then_with(|| a.cmp(b))
+    });
+
+    let mut result: Option<Expr> = None;
+    for (_, term) in terms {
+        let (negative, e) = term.into_expr();
+        result = Some(match result {
+            None if negative => Expr::Neg(Box::new(e)),
+            None => e,
+            Some(acc) if negative => Expr::Sub(Box::new(acc), Box::new(e)),
+            Some(acc) => Expr::Add(Box::new(acc), Box::new(e)),
+        });
+    }
+    result.unwrap_or(Expr::Number(0.0))
+}
+
+/// Whether a canonical factor is free of divisions, calls and powers, so a zero multiple of it is just zero
+fn cannot_fail(factor: &Expr) -> bool {
+    match factor {
+        Expr::Number(n) | Expr::Percent(n) => n.is_finite(),
+        Expr::Var(_) => true,
+        Expr::Add(l, r)
+        | Expr::Sub(l, r)
+        | Expr::Mul(l, r)
+        | Expr::Compare(_, l, r)
+        | Expr::And(l, r)
+        | Expr::Or(l, r) => cannot_fail(l) && cannot_fail(r),
+        Expr::Neg(e) | Expr::Not(e) => cannot_fail(e),
+        Expr::If(cond, then, otherwise) => cannot_fail(cond) && cannot_fail(then) && cannot_fail(otherwise),
+        _ => false,
+    }
+}
+
+/// Flatten sums and differences into signed terms
+fn collect_terms<'a>(expr: &'a Expr, negated: bool, out: &mut Vec<(bool, &'a Expr)>) {
+    match expr {
+        // A percentage change is a product, not a sum of its operands
+        _ if expr.percent_change().is_some() => out.push((negated, expr)),
+        Expr::Add(l, r) => {
+            collect_terms(l, negated, out);
+            collect_terms(r, negated, out);
+        }
+        Expr::Sub(l, r) => {
+            collect_terms(l, negated, out);
+            collect_terms(r, !negated, out);
+        }
+        Expr::Neg(e) if matches!(**e, Expr::Add(..) | Expr::Sub(..) | Expr::Neg(_)) => {
+            collect_terms(e, !negated, out)
+        }
+        _ => out.push((negated, expr)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare::DEFAULT_TOLERANCE;
+    use crate::parse_expression;
+
+    fn check(a: &str, b: &str) -> Equivalence {
+        equivalent(
+            &parse_expression(a).unwrap(),
+            &parse_expression(b).unwrap(),
+            DEFAULT_TOLERANCE,
+        )
+    }
+
+    #[test]
+    fn test_canonical_forms_match() {
+        for (a, b) in [
+            ("2 + 3 * 4", "4 * 3 + 2"),
+            ("x * 2 + y", "y + 2 * x"),
+            ("x + x - y", "2 * x - y"),
+            ("-(a - b)", "b - a"),
+            ("x / 2 * y", "0.5 * (y * x)"),
+            ("x - x + 1", "1"),
+            ("x + 50% - y", "1.5 * x - y"),
+            ("x * 25%", "x / 4"),
+        ] {
+            let result = check(a, b);
+            assert!(result.canonical_match, "{} vs {}: {} / {}", a, b, result.left_canonical, result.right_canonical);
+            assert!(result.equivalent);
+        }
+    }
+
+    #[test]
+    fn test_semantic_equality() {
+        let parse = |source| parse_expression(source).unwrap();
+        assert!(semantically_eq(&parse("2 + 3 * 4"), &parse("4 * 3 + 2")));
+        assert!(semantically_eq(&parse("a * (b * c)"), &parse("(c * b) * a")));
+        assert!(semantically_eq(&parse("x - y + z"), &parse("z + x - y")));
+        assert!(!semantically_eq(&parse("x - y"), &parse("y - x")));
+        assert!(!semantically_eq(&parse("(x + 1) * (x + 1)"), &parse("x * x + 2 * x + 1")));
+        assert_eq!(parse("y * 2 + x").canonicalize(), parse("x + 2 * y").canonicalize());
+        assert_eq!(parse("3 * 4 + 2").canonicalize(), Expr::Number(14.0));
+    }
+
+    #[test]
+    fn test_probing_decides_remaining_cases() {
+        let result = check("0.1 + 0.2", "0.3");
+        assert!(!result.canonical_match);
+        assert!(result.equivalent);
+
+        let result = check("(x + 1) * (x + 1)", "x * x + 2 * x + 1");
+        assert!(result.equivalent);
+        assert!(result.counterexample.is_none());
+    }
+
+    #[test]
+    fn test_counterexamples() {
+        let result = check("x * 2", "x + 2");
+        assert!(!result.equivalent);
+        let counterexample = result.counterexample.unwrap();
+        assert_eq!(counterexample.to_string(), "at x = 0: left = 0, right = 2");
+
+        // Differing domains count as a difference
+        let result = check("x / x", "1");
+        assert!(!result.equivalent);
+        assert_eq!(result.counterexample.unwrap().bindings["x"], 0.0);
+
+        assert!(check("1 / 0", "2 / (1 - 1)").equivalent);
+
+        // Cancelled terms that can fail are not dropped
+        for (a, b) in [("0 * (1 / y)", "0"), ("x / 0 - x / 0", "0"), ("0 * sqrt(x)", "0")] {
+            let result = check(a, b);
+            assert!(!result.canonical_match, "{}", a);
+            assert!(!result.equivalent, "{}", a);
+        }
+        assert!(check("0 * (x + y)", "0").canonical_match);
+    }
+}
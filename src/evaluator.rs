@@ -0,0 +1,461 @@
+//! Reusable evaluator carrying options, a cache of parsed expressions and an optional per-operation hook
+
+use crate::cache::{CacheStats, LruCache};
+use crate::prelude::*;
+use crate::{
+    add_percent, functions, is_true, not_a_number, truth_value, AngleMode, Bindings, ComputeError, EvalOptions,
+    Expr, Relation, Result, RoundingMode,
+};
+use alloc::rc::Rc;
+use core::fmt;
+
+/// Kind of arithmetic operation reported to an operation hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpKind {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Neg,
+    /// `a + p%`, raising `a` by `p` percent of itself
+    AddPercent,
+    /// `a - p%`, lowering `a` by `p` percent of itself
+    SubPercent,
+    /// A call of the named built-in function
+    Call(&'static str),
+    Compare(Relation),
+    And,
+    Or,
+    Not,
+    /// `cond ? a : b`, reported once the branch it took has a value
+    If,
+}
+
+impl fmt::Display for OpKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpKind::Add => write!(f, "+"),
+            OpKind::Sub => write!(f, "-"),
+            OpKind::Mul => write!(f, "*"),
+            OpKind::Div => write!(f, "/"),
+            OpKind::Pow => write!(f, "^"),
+            OpKind::Neg => write!(f, "neg"),
+            OpKind::AddPercent => write!(f, "+%"),
+            OpKind::SubPercent => write!(f, "-%"),
+            OpKind::Call(name) => f.write_str(name),
+            OpKind::Compare(relation) => f.write_str(relation.symbol()),
+            OpKind::And => write!(f, "and"),
+            OpKind::Or => write!(f, "or"),
+            OpKind::Not => write!(f, "not"),
+            OpKind::If => write!(f, "?:"),
+        }
+    }
+}
+
+/// One operation performed during evaluation, as seen by an operation hook
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation<'a> {
+    pub kind: OpKind,
+    /// Operand values in source order: one for `Neg` and `Not`, one per argument for `Call`,
+    /// one for an `And` or `Or` decided by its left operand, just the condition for `If`,
+    /// just the divisor for a `Div` by zero, and two otherwise; the second operand of
+    /// `AddPercent` and `SubPercent` is the percentage as written
+    pub operands: &'a [f64],
+    pub result: &'a Result<f64>,
+}
+
+type Hook = Box<dyn FnMut(&Operation)>;
+
+/// Parsed expressions an `Evaluator` keeps by default
+pub const DEFAULT_PARSE_CACHE: usize = 256;
+
+/// Evaluates expressions with fixed options, optionally reporting every operation
+///
+/// Parsed expressions, and parse errors, are kept in an LRU cache keyed by
+/// their source, so evaluating the same string again skips parsing. Results
+/// are exactly those of `evaluate_with`, whatever the options. An installed
+/// hook is shown a plain float walk of the tree after evaluation, skipped
+/// when a `fuel` budget ran out; an unused hook costs nothing.
+pub struct Evaluator {
+    options: EvalOptions,
+    hook: Option<Hook>,
+    parses: LruCache<String, Rc<Result<Expr>>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::with_options(EvalOptions::default())
+    }
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: EvalOptions) -> Self {
+        Evaluator {
+            options,
+            hook: None,
+            parses: LruCache::new(DEFAULT_PARSE_CACHE),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn options(&self) -> &EvalOptions {
+        &self.options
+    }
+
+    /// Install a callback invoked after every operation, replacing any previous one
+    pub fn on_operation(&mut self, hook: impl FnMut(&Operation) + 'static) -> &mut Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Remove the operation callback
+    pub fn clear_hook(&mut self) -> &mut Self {
+        self.hook = None;
+        self
+    }
+
+    /// Keep up to `capacity` parsed expressions, emptying the cache and its statistics; zero disables caching
+    pub fn parse_cache(&mut self, capacity: usize) -> &mut Self {
+        self.parses = LruCache::new(capacity);
+        self.hits = 0;
+        self.misses = 0;
+        self
+    }
+
+    /// Hits, misses and occupancy of the parse cache
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            len: self.parses.len(),
+            capacity: self.parses.capacity(),
+        }
+    }
+
+    /// Parse and evaluate an expression string
+    pub fn evaluate(&mut self, expr: &str) -> Result<f64> {
+        self.options.parse.check_len(expr)?;
+        let expr = expr.trim();
+        if expr.is_empty() {
+            return Err(ComputeError::EmptyExpression);
+        }
+        let parsed = match self.parses.get(expr) {
+            Some(parsed) => {
+                self.hits += 1;
+                Rc::clone(parsed)
+            }
+            None => {
+                self.misses += 1;
+                let parsed = Rc::new(self.options.parse(expr));
+                self.parses.insert(expr.to_string(), Rc::clone(&parsed));
+                parsed
+            }
+        };
+        let ast = parsed.as_ref().as_ref().map_err(Clone::clone)?;
+        self.eval(ast)
+    }
+
+    /// Evaluate an already-parsed expression
+    pub fn eval(&mut self, expr: &Expr) -> Result<f64> {
+        let value = self.options.eval(expr, &Bindings::new());
+        if let Some(hook) = self.hook.as_mut() {
+            // The walk only reports operations; the value is always that of `EvalOptions::eval`
+            if !matches!(value, Err(ComputeError::LimitExceeded(_))) {
+                let _ = eval_hooked(expr, self.options.angle_mode, self.options.rounding, hook);
+            }
+        }
+        value.map(|value| self.options.round_result(value))
+    }
+}
+
+impl fmt::Debug for Evaluator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Evaluator")
+            .field("options", &self.options)
+            .field("hook", &self.hook.is_some())
+            .field("parse_cache", &self.cache_stats())
+            .finish()
+    }
+}
+
+/// Same semantics and evaluation order as `eval_expr_in`, reporting each operation
+fn eval_hooked(expr: &Expr, angle_mode: AngleMode, rounding: RoundingMode, hook: &mut Hook) -> Result<f64> {
+    if let Some((a, p)) = expr.percent_change() {
+        let value = eval_hooked(a, angle_mode, rounding, hook)?;
+        let (kind, operands) = match expr {
+            Expr::Add(..) => (OpKind::AddPercent, [value, p]),
+            _ => (OpKind::SubPercent, [value, -p]),
+        };
+        let result = Ok(add_percent(value, p));
+        hook(&Operation {
+            kind,
+            operands: &operands,
+            result: &result,
+        });
+        return result;
+    }
+    let (kind, l, r) = match expr {
+        Expr::Number(n) => return Ok(*n),
+        Expr::Percent(n) => return Ok(n / 100.0),
+        Expr::Var(name) => return Err(ComputeError::UndefinedVariable(name.clone())),
+        Expr::List(_) | Expr::Range(..) | Expr::Text(_) => return Err(not_a_number(expr)),
+        Expr::Neg(e) | Expr::Not(e) => {
+            let value = eval_hooked(e, angle_mode, rounding, hook)?;
+            let (kind, result) = match expr {
+                Expr::Neg(_) => (OpKind::Neg, Ok(-value)),
+                _ => (OpKind::Not, Ok(truth_value(!is_true(value)))),
+            };
+            hook(&Operation {
+                kind,
+                operands: &[value],
+                result: &result,
+            });
+            return result;
+        }
+        Expr::And(l, r) | Expr::Or(l, r) => {
+            let (kind, decisive) = match expr {
+                Expr::And(..) => (OpKind::And, false),
+                _ => (OpKind::Or, true),
+            };
+            let a = eval_hooked(l, angle_mode, rounding, hook)?;
+            // The right operand is skipped when the left one decides the result
+            let (operands, result) = if is_true(a) == decisive {
+                (vec![a], Ok(truth_value(decisive)))
+            } else {
+                let b = eval_hooked(r, angle_mode, rounding, hook)?;
+                (vec![a, b], Ok(truth_value(is_true(b))))
+            };
+            hook(&Operation {
+                kind,
+                operands: &operands,
+                result: &result,
+            });
+            return result;
+        }
+        Expr::Call(name, args) => {
+            let function = functions::resolve(name, args.len())?;
+            let values = function
+                .operands(args, |bound| eval_hooked(bound, angle_mode, rounding, hook))?
+                .iter()
+                .map(|arg| eval_hooked(arg, angle_mode, rounding, hook))
+                .collect::<Result<Vec<_>>>()?;
+            let result = Ok(function.eval(&values, angle_mode, rounding));
+            hook(&Operation {
+                kind: OpKind::Call(function.name),
+                operands: &values,
+                result: &result,
+            });
+            return result;
+        }
+        Expr::If(cond, then, otherwise) => {
+            // Only the chosen branch is evaluated
+            let condition = eval_hooked(cond, angle_mode, rounding, hook)?;
+            let branch = if is_true(condition) { then } else { otherwise };
+            let result = Ok(eval_hooked(branch, angle_mode, rounding, hook)?);
+            hook(&Operation {
+                kind: OpKind::If,
+                operands: &[condition],
+                result: &result,
+            });
+            return result;
+        }
+        Expr::Compare(relation, l, r) => (OpKind::Compare(*relation), l, r),
+        Expr::Add(l, r) => (OpKind::Add, l, r),
+        Expr::Sub(l, r) => (OpKind::Sub, l, r),
+        Expr::Mul(l, r) => (OpKind::Mul, l, r),
+        Expr::Div(l, r) => (OpKind::Div, l, r),
+        Expr::Pow(l, r) => (OpKind::Pow, l, r),
+    };
+
+    let (a, b) = if kind == OpKind::Div {
+        // The divisor is evaluated and checked first, as in eval_expr_in
+        let b = eval_hooked(r, angle_mode, rounding, hook)?;
+        if b == 0.0 {
+            let result = Err(ComputeError::DivisionByZero);
+            hook(&Operation {
+                kind,
+                operands: &[b],
+                result: &result,
+            });
+            return result;
+        }
+        (eval_hooked(l, angle_mode, rounding, hook)?, b)
+    } else {
+        (eval_hooked(l, angle_mode, rounding, hook)?, eval_hooked(r, angle_mode, rounding, hook)?)
+    };
+    let result = match kind {
+        OpKind::Add => Ok(a + b),
+        OpKind::Sub => Ok(a - b),
+        OpKind::Mul => Ok(a * b),
+        OpKind::Pow => Ok(a.powf(b)),
+        OpKind::Compare(relation) => Ok(relation.eval(a, b)),
+        _ => Ok(a / b),
+    };
+    hook(&Operation {
+        kind,
+        operands: &[a, b],
+        result: &result,
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    fn recording() -> (Evaluator, Rc<RefCell<Vec<String>>>) {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&log);
+        let mut evaluator = Evaluator::new();
+        evaluator.on_operation(move |op| {
+            sink.borrow_mut()
+                .push(format!("{} {:?} = {:?}", op.kind, op.operands, op.result));
+        });
+        (evaluator, log)
+    }
+
+    #[test]
+    fn test_hook_sees_every_operation() {
+        let (mut evaluator, log) = recording();
+        assert_eq!(evaluator.evaluate("2 + 3 * -4"), Ok(-10.0));
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "neg [4.0] = Ok(-4.0)",
+                "* [3.0, -4.0] = Ok(-12.0)",
+                "+ [2.0, -12.0] = Ok(-10.0)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hook_sees_failed_operation() {
+        let (mut evaluator, log) = recording();
+        assert_eq!(evaluator.evaluate("1 / (2 - 2)"), Err(ComputeError::DivisionByZero));
+        assert_eq!(log.borrow().last().unwrap(), "/ [0.0] = Err(DivisionByZero)");
+    }
+
+    #[test]
+    fn test_hook_sees_function_calls() {
+        let (mut evaluator, log) = recording();
+        assert_eq!(evaluator.evaluate("sqrt(9) ^ 2"), Ok(9.0));
+        assert_eq!(*log.borrow(), vec!["sqrt [9.0] = Ok(3.0)", "^ [3.0, 2.0] = Ok(9.0)"]);
+        assert_eq!(evaluator.evaluate("cbrt(8)"), Err(ComputeError::UnknownFunction("cbrt".into())));
+        log.borrow_mut().clear();
+        assert_eq!(evaluator.evaluate("atan2(0, -1)"), Ok(core::f64::consts::PI));
+        assert_eq!(
+            *log.borrow(),
+            vec!["neg [1.0] = Ok(-1.0)", "atan2 [0.0, -1.0] = Ok(3.141592653589793)"]
+        );
+    }
+
+    #[test]
+    fn test_hook_sees_short_circuit() {
+        let (mut evaluator, log) = recording();
+        assert_eq!(evaluator.evaluate("0 and 1 / 0 or not 2 > 1"), Ok(0.0));
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "and [0.0] = Ok(0.0)",
+                "> [2.0, 1.0] = Ok(1.0)",
+                "not [1.0] = Ok(0.0)",
+                "or [0.0, 0.0] = Ok(0.0)",
+            ]
+        );
+        log.borrow_mut().clear();
+        assert_eq!(evaluator.evaluate("1 < 2 ? 3 : 1 / 0"), Ok(3.0));
+        assert_eq!(*log.borrow(), vec!["< [1.0, 2.0] = Ok(1.0)", "?: [1.0] = Ok(3.0)"]);
+    }
+
+    #[test]
+    fn test_hook_sees_percent_changes() {
+        let (mut evaluator, log) = recording();
+        assert_eq!(evaluator.evaluate("200 + 10% - 50% * 2"), Ok(219.0));
+        assert_eq!(
+            *log.borrow(),
+            vec!["+% [200.0, 10.0] = Ok(220.0)", "* [0.5, 2.0] = Ok(1.0)", "- [220.0, 1.0] = Ok(219.0)"]
+        );
+        log.borrow_mut().clear();
+        assert_eq!(evaluator.evaluate("80 - 25%"), Ok(60.0));
+        assert_eq!(*log.borrow(), vec!["-% [80.0, 25.0] = Ok(60.0)"]);
+    }
+
+    #[test]
+    fn test_angle_mode() {
+        let degrees = EvalOptions {
+            angle_mode: AngleMode::Degrees,
+            ..EvalOptions::default()
+        };
+        let mut evaluator = Evaluator::with_options(degrees);
+        assert_eq!(evaluator.evaluate("sin(90) + acos(0)"), Ok(91.0));
+        evaluator.on_operation(|_| {});
+        assert_eq!(evaluator.evaluate("sin(90) + acos(0)"), Ok(91.0));
+        assert_eq!(evaluator.eval(&crate::parse_expression("cos(180)").unwrap()), Ok(-1.0));
+    }
+
+    #[test]
+    fn test_parse_cache() {
+        let (mut evaluator, log) = recording();
+        for _ in 0..3 {
+            assert_eq!(evaluator.evaluate(" 2 * (3 + 4) "), Ok(14.0));
+        }
+        assert_eq!(log.borrow().len(), 6);
+        assert!(evaluator.evaluate("2 *").is_err());
+        assert!(evaluator.evaluate("2 *").is_err());
+        let stats = evaluator.cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.len, stats.capacity), (3, 2, 2, DEFAULT_PARSE_CACHE));
+        assert_eq!(stats.hit_rate(), 0.6);
+
+        evaluator.parse_cache(1);
+        assert_eq!(evaluator.evaluate("1 + 1"), Ok(2.0));
+        assert_eq!(evaluator.evaluate("1 + 2"), Ok(3.0));
+        assert_eq!(evaluator.evaluate("1 + 1"), Ok(2.0));
+        assert_eq!(evaluator.cache_stats(), CacheStats { hits: 0, misses: 3, len: 1, capacity: 1 });
+
+        evaluator.parse_cache(0);
+        assert_eq!(evaluator.evaluate("1 + 1"), Ok(2.0));
+        assert_eq!(evaluator.cache_stats().len, 0);
+    }
+
+    #[test]
+    fn test_without_hook_matches_evaluate() {
+        let mut evaluator = Evaluator::new();
+        for expr in ["2 + 3", "-(1 + 2) / 4", "x", "1 / 0", "", "abs(-2)", "f(1)"] {
+            assert_eq!(evaluator.evaluate(expr), crate::evaluate(expr), "{}", expr);
+        }
+
+        let (mut evaluator, _) = recording();
+        evaluator.clear_hook();
+        assert_eq!(evaluator.evaluate("2 * 3"), Ok(6.0));
+    }
+
+    #[test]
+    fn test_hook_does_not_change_results() {
+        let options = [
+            EvalOptions { strict_finite: true, ..EvalOptions::default() },
+            EvalOptions { fuel: Some(3), ..EvalOptions::default() },
+            EvalOptions { arithmetic: crate::Arithmetic::Integer, ..EvalOptions::default() },
+        ];
+        for options in options {
+            let mut evaluator = Evaluator::with_options(options.clone());
+            evaluator.on_operation(|_| {});
+            for expr in ["1e308 * 10", "1 + 2 + 3 + 4", "0.1 + 0.2", "7 / 2", "x / 0", "sum([1, 2])"] {
+                assert_eq!(evaluator.evaluate(expr), crate::evaluate_with(expr, &options), "{}", expr);
+            }
+        }
+
+        let (mut evaluator, log) = recording();
+        assert_eq!(evaluator.evaluate("x / 0"), Err(ComputeError::DivisionByZero));
+        assert_eq!(*log.borrow(), vec!["/ [0.0] = Err(DivisionByZero)"]);
+    }
+}
@@ -0,0 +1,212 @@
+//! Prose, step-by-step explanations of how an expression is evaluated
+
+use crate::prelude::*;
+use crate::{format_number, is_true, Evaluator, Expr, OpKind, Operation};
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+/// Describe the evaluation of `expr` as a numbered, tutoring-style derivation
+///
+/// Each arithmetic operation becomes one sentence in the order the evaluator
+/// performs it, followed by the final result or the reason evaluation failed:
+///
+/// ```text
+/// 1. First, add 2 and 3: 2 + 3 = 5.
+/// 2. Finally, multiply 5 by 4: 5 * 4 = 20.
+/// Result: 20
+/// ```
+pub fn explain_evaluation(expr: &Expr) -> String {
+    let steps = Rc::new(RefCell::new(Vec::new()));
+    let sink = Rc::clone(&steps);
+    let mut evaluator = Evaluator::new();
+    evaluator.on_operation(move |op| sink.borrow_mut().push(describe(op)));
+    let result = evaluator.eval(expr);
+
+    let steps = steps.borrow();
+    let mut out = String::new();
+    if steps.is_empty() {
+        if let Ok(value) = result {
+            out.push_str(&format!("The expression is just the number {}.\n", format_number(value)));
+        }
+    }
+    for (i, step) in steps.iter().enumerate() {
+        let connective = match i {
+            0 if steps.len() > 1 => "First, ",
+            _ if i + 1 == steps.len() && steps.len() > 1 => "Finally, ",
+            0 => "",
+            _ => "Then ",
+        };
+        let step = if connective.is_empty() { capitalize(step) } else { step.clone() };
+        out.push_str(&format!("{}. {}{}.\n", i + 1, connective, step));
+    }
+    match result {
+        Ok(value) => out.push_str(&format!("Result: {}", format_number(value))),
+        Err(e) => out.push_str(&format!("The expression cannot be evaluated: {}", e)),
+    }
+    out
+}
+
+fn describe(op: &Operation) -> String {
+    let outcome = match op.result {
+        Ok(value) => format!("= {}", format_number(*value)),
+        Err(_) => "is undefined, because division by zero has no value".to_string(),
+    };
+    if let OpKind::Call(name) = op.kind {
+        let args: Vec<String> = op.operands.iter().map(|&arg| format_number(arg)).collect();
+        if args.is_empty() {
+            // An aggregate over an empty range or list
+            return format!("apply {} to nothing: {}() {}", name, name, outcome);
+        }
+        return format!("apply {} to {}: {}({}) {}", name, args.join(" and "), name, args.join(", "), outcome);
+    }
+    let Some(&a) = op.operands.first() else {
+        return format!("evaluate {} {}", op.kind, outcome);
+    };
+    if op.kind == OpKind::Neg {
+        return format!("negate {}: -{} {}", format_number(a), operand(a), outcome);
+    }
+    if op.kind == OpKind::Not {
+        return format!("negate the truth of {}: not {} {}", format_number(a), operand(a), outcome);
+    }
+    if let (OpKind::If, Ok(value)) = (op.kind, op.result) {
+        let (fa, value) = (format_number(a), format_number(*value));
+        let (ordinal, truth, branches) = if is_true(a) {
+            ("first", "true", format!("{} : ...", value))
+        } else {
+            ("second", "false", format!("... : {}", value))
+        };
+        return format!("take the {} branch, because {} is {}: {} ? {} = {}", ordinal, fa, truth, fa, branches, value);
+    }
+    if let (OpKind::Div, [_]) = (op.kind, op.operands) {
+        // A zero divisor fails before the dividend is evaluated
+        return format!("divide by {}: ... / {} {}", format_number(a), operand(a), outcome);
+    }
+    if let [_] = op.operands {
+        // An `and` or `or` decided by its left operand
+        let truth = if op.kind == OpKind::Or { "true" } else { "false" };
+        return format!(
+            "skip the right side, because {} is {}: {} {} ... {}",
+            format_number(a),
+            truth,
+            format_number(a),
+            op.kind,
+            outcome
+        );
+    }
+    let b = op.operands[1];
+    let (fa, fb) = (format_number(a), format_number(b));
+    if let OpKind::AddPercent | OpKind::SubPercent = op.kind {
+        let (verb, sign) = if op.kind == OpKind::AddPercent { ("increase", '+') } else { ("decrease", '-') };
+        return format!("{} {} by {}%: {} {} {}% {}", verb, fa, fb, fa, sign, fb, outcome);
+    }
+    let action = match op.kind {
+        OpKind::Add => format!("add {} and {}", fa, fb),
+        OpKind::Sub => format!("subtract {} from {}", fb, fa),
+        OpKind::Mul => format!("multiply {} by {}", fa, fb),
+        OpKind::Pow => format!("raise {} to the power {}", fa, fb),
+        OpKind::Compare(_) => format!("compare {} with {}", fa, fb),
+        OpKind::And => format!("check whether {} and {} are both true", fa, fb),
+        OpKind::Or => format!("check whether {} or {} is true", fa, fb),
+        _ => format!("divide {} by {}", fa, fb),
+    };
+    format!("{}: {} {} {} {}", action, fa, op.kind, operand(b), outcome)
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+        .unwrap_or_default()
+}
+
+/// Format a value as the right-hand operand of a written-out operation
+fn operand(value: f64) -> String {
+    if value.is_sign_negative() {
+        format!("({})", format_number(value))
+    } else {
+        format_number(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_expression;
+
+    fn explain(src: &str) -> String {
+        explain_evaluation(&parse_expression(src).unwrap())
+    }
+
+    #[test]
+    fn test_numbered_steps() {
+        assert_eq!(
+            explain("(2 + 3) * 4"),
+            "1. First, add 2 and 3: 2 + 3 = 5.\n\
+             2. Finally, multiply 5 by 4: 5 * 4 = 20.\n\
+             Result: 20"
+        );
+        assert_eq!(
+            explain("10 - 2 * -3"),
+            "1. First, negate 3: -3 = -3.\n\
+             2. Then multiply 2 by -3: 2 * (-3) = -6.\n\
+             3. Finally, subtract -6 from 10: 10 - (-6) = 16.\n\
+             Result: 16"
+        );
+    }
+
+    #[test]
+    fn test_trivial_and_failing_expressions() {
+        assert_eq!(explain("7"), "The expression is just the number 7.\nResult: 7");
+        assert_eq!(explain("1 / 4"), "1. Divide 1 by 4: 1 / 4 = 0.25.\nResult: 0.25");
+        assert_eq!(explain("sqrt(16)"), "1. Apply sqrt to 16: sqrt(16) = 4.\nResult: 4");
+        assert_eq!(explain("atan2(0, 1)"), "1. Apply atan2 to 0 and 1: atan2(0, 1) = 0.\nResult: 0");
+        assert_eq!(
+            explain("5 / (1 - 1)"),
+            "1. First, subtract 1 from 1: 1 - 1 = 0.\n\
+             2. Finally, divide by 0: ... / 0 is undefined, because division by zero has no value.\n\
+             The expression cannot be evaluated: Division by zero"
+        );
+        assert_eq!(explain("x + 1"), "The expression cannot be evaluated: Undefined variable: x");
+        assert_eq!(explain("product(2..1)"), "1. Apply product to nothing: product() = 1.\nResult: 1");
+        assert_eq!(explain("mean(2..1)"), "1. Apply mean to nothing: mean() = NaN.\nResult: NaN");
+        assert_eq!(explain("sum([])"), "1. Apply sum to nothing: sum() = 0.\nResult: 0");
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        assert_eq!(
+            explain("2 < 3 and not 0"),
+            "1. First, compare 2 with 3: 2 < 3 = 1.\n\
+             2. Then negate the truth of 0: not 0 = 1.\n\
+             3. Finally, check whether 1 and 1 are both true: 1 and 1 = 1.\n\
+             Result: 1"
+        );
+        assert_eq!(
+            explain("1 or 1 / 0"),
+            "1. Skip the right side, because 1 is true: 1 or ... = 1.\nResult: 1"
+        );
+        assert_eq!(
+            explain("2 > 3 ? 1 / 0 : 4 * 5"),
+            "1. First, compare 2 with 3: 2 > 3 = 0.\n\
+             2. Then multiply 4 by 5: 4 * 5 = 20.\n\
+             3. Finally, take the second branch, because 0 is false: 0 ? ... : 20 = 20.\n\
+             Result: 20"
+        );
+        assert_eq!(
+            explain("1 ? 7 : x"),
+            "1. Take the first branch, because 1 is true: 1 ? 7 : ... = 7.\nResult: 7"
+        );
+    }
+
+    #[test]
+    fn test_percentages() {
+        assert_eq!(
+            explain("200 + 10% - 25%"),
+            "1. First, increase 200 by 10%: 200 + 10% = 220.\n\
+             2. Finally, decrease 220 by 25%: 220 - 25% = 165.\n\
+             Result: 165"
+        );
+        assert_eq!(explain("50%"), "The expression is just the number 0.5.\nResult: 0.5");
+    }
+}
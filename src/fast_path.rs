@@ -0,0 +1,183 @@
+//! Allocation-free evaluation of flat `a op b [op c]` expressions
+//!
+//! Most traffic is a single binary operation on two literals. For those
+//! inputs, building a pest parse tree and a boxed AST dominates the cost, so
+//! this module lexes the string directly and evaluates it in place. Anything
+//! outside the narrow accepted shape returns `None` and the caller falls back
+//! to the full parser, which stays the single source of truth for syntax.
+
+use crate::{ComputeError, ParseOptions, Result};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    fn binds_tighter_than(self, other: Op) -> bool {
+        matches!(self, Op::Mul | Op::Div) && matches!(other, Op::Add | Op::Sub)
+    }
+
+    fn apply(self, l: f64, r: f64) -> Result<f64> {
+        match self {
+            Op::Add => Ok(l + r),
+            Op::Sub => Ok(l - r),
+            Op::Mul => Ok(l * r),
+            Op::Div if r == 0.0 => Err(ComputeError::DivisionByZero),
+            Op::Div => Ok(l / r),
+        }
+    }
+}
+
+struct Lexer<'a> {
+    bytes: &'a [u8],
+    src: &'a str,
+    pos: usize,
+    options: &'a ParseOptions,
+}
+
+impl<'a> Lexer<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    /// Lex a literal matching the grammar's `number` rule exactly
+    fn number(&mut self) -> Option<Result<f64>> {
+        self.skip_whitespace();
+        let start = self.pos;
+        // Either side of the point may be empty, but not both
+        let mut digits = self.digits();
+        if self.bytes.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            digits += self.digits();
+        }
+        if digits == 0 {
+            return None;
+        }
+        if let Some(b'e' | b'E') = self.bytes.get(self.pos) {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.bytes.get(self.pos) {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                return None;
+            }
+        }
+        let n: f64 = self.src[start..self.pos].parse().ok()?;
+        Some(self.options.literal_value(n))
+    }
+
+    fn op(&mut self) -> Option<Op> {
+        self.skip_whitespace();
+        let op = match self.bytes.get(self.pos)? {
+            b'+' => Op::Add,
+            b'-' => Op::Sub,
+            b'*' => Op::Mul,
+            b'/' => Op::Div,
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(op)
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.pos == self.bytes.len()
+    }
+}
+
+/// Evaluate `a op b` or `a op b op c` over plain literals, or `None` for any other shape
+///
+/// Literals too large for an `f64` follow `options.literal_overflow`.
+pub(crate) fn evaluate_simple(expr: &str, options: &ParseOptions) -> Option<Result<f64>> {
+    let mut lexer = Lexer {
+        bytes: expr.as_bytes(),
+        src: expr,
+        pos: 0,
+        options,
+    };
+
+    let a = lexer.number()?;
+    let op1 = lexer.op()?;
+    let b = lexer.number()?;
+    if lexer.at_end() {
+        // Literal errors surface before evaluation errors, as in the full parser
+        return Some(a.and_then(|a| op1.apply(a, b?)));
+    }
+
+    let op2 = lexer.op()?;
+    let c = lexer.number()?;
+    if !lexer.at_end() {
+        return None;
+    }
+
+    Some(a.and_then(|a| {
+        let (b, c) = (b?, c?);
+        if op2.binds_tighter_than(op1) {
+            op1.apply(a, op2.apply(b, c)?)
+        } else {
+            op2.apply(op1.apply(a, b)?, c)
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LiteralOverflow;
+
+    fn evaluate_simple(expr: &str) -> Option<Result<f64>> {
+        super::evaluate_simple(expr, &ParseOptions::default())
+    }
+
+    #[test]
+    fn test_binary_expressions() {
+        assert_eq!(evaluate_simple("2 + 3"), Some(Ok(5.0)));
+        assert_eq!(evaluate_simple("10-4"), Some(Ok(6.0)));
+        assert_eq!(evaluate_simple("1.5e3 * 2"), Some(Ok(3000.0)));
+        assert_eq!(evaluate_simple("1. + .5e1"), Some(Ok(6.0)));
+        assert_eq!(evaluate_simple("1 / 0"), Some(Err(ComputeError::DivisionByZero)));
+        assert_eq!(evaluate_simple("1e999 + 1"), Some(Err(ComputeError::Overflow)));
+    }
+
+    #[test]
+    fn test_three_terms_respect_precedence() {
+        assert_eq!(evaluate_simple("2 + 3 * 4"), Some(Ok(14.0)));
+        assert_eq!(evaluate_simple("2 * 3 + 4"), Some(Ok(10.0)));
+        assert_eq!(evaluate_simple("10 - 5 - 2"), Some(Ok(3.0)));
+        assert_eq!(evaluate_simple("20 / 4 / 2"), Some(Ok(2.5)));
+        assert_eq!(evaluate_simple("1 + 2 / 0"), Some(Err(ComputeError::DivisionByZero)));
+    }
+
+    #[test]
+    fn test_other_shapes_fall_back() {
+        for expr in [
+            "42", "-2 + 3", "2 + -3", "(2 + 3)", "1 + 2 + 3 + 4", ". + 2", "1 + .", "2 ^ 3", "2 + x",
+            "200 + 10%", "50% * 2",
+        ] {
+            assert_eq!(evaluate_simple(expr), None, "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_literal_overflow_policy() {
+        let options = |literal_overflow| ParseOptions { literal_overflow, ..ParseOptions::default() };
+        let clamp = options(LiteralOverflow::Clamp);
+        assert_eq!(super::evaluate_simple("1e999 - 1e308", &clamp), Some(Ok(f64::MAX - 1e308)));
+        let propagate = options(LiteralOverflow::Propagate);
+        assert_eq!(super::evaluate_simple("1e999 * 2", &propagate), Some(Ok(f64::INFINITY)));
+    }
+}
@@ -0,0 +1,315 @@
+//! Q-format fixed-point evaluation mirroring embedded firmware arithmetic
+
+use crate::prelude::*;
+use crate::{functions, not_a_number, AngleMode, ComputeError, Expr, Result, RoundingMode};
+use core::fmt;
+
+/// What to do when a fixed-point result does not fit in an `i64`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OverflowMode {
+    /// Clamp to the largest or smallest representable value
+    #[default]
+    Saturate,
+    /// Wrap around like unchecked two's-complement integer arithmetic
+    Wrap,
+    /// Abort evaluation with `ComputeError::Overflow`
+    Error,
+}
+
+/// Layout of a signed 64-bit fixed-point number with `frac_bits` fractional bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QFormat {
+    pub frac_bits: u32,
+    pub overflow: OverflowMode,
+}
+
+impl QFormat {
+    /// Largest supported number of fractional bits
+    pub const MAX_FRAC_BITS: u32 = 63;
+
+    /// Q-format with the given fractional bits and saturating overflow
+    pub fn new(frac_bits: u32) -> Self {
+        QFormat {
+            frac_bits,
+            overflow: OverflowMode::default(),
+        }
+    }
+
+    /// Same format with a different overflow behaviour
+    pub fn with_overflow(self, overflow: OverflowMode) -> Self {
+        QFormat { overflow, ..self }
+    }
+
+    fn scale(&self) -> f64 {
+        2f64.powi(self.frac_bits as i32)
+    }
+
+    /// Narrow a wide intermediate result to `i64` according to the overflow mode
+    fn narrow(&self, wide: i128) -> Result<i64> {
+        if let Ok(raw) = i64::try_from(wide) {
+            return Ok(raw);
+        }
+        match self.overflow {
+            OverflowMode::Saturate if wide < 0 => Ok(i64::MIN),
+            OverflowMode::Saturate => Ok(i64::MAX),
+            OverflowMode::Wrap => Ok(wide as i64),
+            OverflowMode::Error => Err(ComputeError::Overflow),
+        }
+    }
+
+    /// Multiply two raw values, shifting the product back into this format
+    fn multiply(&self, a: i64, b: i64) -> Result<i64> {
+        self.narrow((i128::from(a) * i128::from(b)) >> self.frac_bits)
+    }
+}
+
+/// Formats past `MAX_FRAC_BITS`, which evaluation rejects, show no integer bits
+impl fmt::Display for QFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Q{}.{}", QFormat::MAX_FRAC_BITS.saturating_sub(self.frac_bits), self.frac_bits)
+    }
+}
+
+/// A fixed-point value together with the format it was computed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fixed {
+    raw: i64,
+    format: QFormat,
+}
+
+impl Fixed {
+    /// The underlying integer, as a device register would hold it
+    pub fn raw(&self) -> i64 {
+        self.raw
+    }
+
+    pub fn format(&self) -> QFormat {
+        self.format
+    }
+
+    /// Nearest `f64` to the fixed-point value
+    pub fn to_f64(&self) -> f64 {
+        self.raw as f64 / self.format.scale()
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&crate::format_number(self.to_f64()))
+    }
+}
+
+/// Evaluate an expression string in the given fixed-point format
+pub fn evaluate_fixed(expr: &str, format: &QFormat) -> Result<Fixed> {
+    let expr = expr.trim();
+    if expr.is_empty() {
+        return Err(ComputeError::EmptyExpression);
+    }
+    crate::parse_expression(expr).and_then(|ast| eval_fixed(&ast, format))
+}
+
+/// Evaluate an AST using fixed-point arithmetic
+///
+/// Literals round to the nearest representable value, multiplication
+/// truncates toward negative infinity (an arithmetic shift) and division
+/// truncates toward zero, matching typical C firmware implementations.
+/// Powers take a non-negative integer exponent and are computed by repeated
+/// squaring with the same truncating multiplication. Built-in functions are
+/// computed in f64 and rounded back to the format, like a lookup table.
+pub fn eval_fixed(expr: &Expr, format: &QFormat) -> Result<Fixed> {
+    if format.frac_bits > QFormat::MAX_FRAC_BITS {
+        return Err(ComputeError::InvalidStructure(format!(
+            "Fixed-point format supports at most {} fractional bits",
+            QFormat::MAX_FRAC_BITS
+        )));
+    }
+    eval_raw(expr, format).map(|raw| Fixed {
+        raw,
+        format: *format,
+    })
+}
+
+fn eval_raw(expr: &Expr, format: &QFormat) -> Result<i64> {
+    let shift = format.frac_bits;
+    if let Some((a, p)) = expr.percent_change() {
+        // `a + p%` is `a + a * p%`, with the product truncated like any other
+        let a = eval_raw(a, format)?;
+        let change = format.multiply(a, from_f64(p / 100.0, format)?)?;
+        return format.narrow(i128::from(a) + i128::from(change));
+    }
+    match expr {
+        Expr::Number(n) => from_f64(*n, format),
+        Expr::Percent(n) => from_f64(n / 100.0, format),
+        Expr::Var(name) => Err(ComputeError::UndefinedVariable(name.clone())),
+        Expr::Add(l, r) => {
+            format.narrow(i128::from(eval_raw(l, format)?) + i128::from(eval_raw(r, format)?))
+        }
+        Expr::Sub(l, r) => {
+            format.narrow(i128::from(eval_raw(l, format)?) - i128::from(eval_raw(r, format)?))
+        }
+        Expr::Mul(l, r) => format.multiply(eval_raw(l, format)?, eval_raw(r, format)?),
+        Expr::Div(l, r) => {
+            let divisor = eval_raw(r, format)?;
+            if divisor == 0 {
+                return Err(ComputeError::DivisionByZero);
+            }
+            let dividend = i128::from(eval_raw(l, format)?) << shift;
+            format.narrow(dividend / i128::from(divisor))
+        }
+        Expr::Pow(l, r) => {
+            let mut base = eval_raw(l, format)?;
+            let exponent = eval_raw(r, format)?;
+            if exponent < 0 || exponent as u64 & ((1 << shift) - 1) != 0 {
+                return Err(ComputeError::InvalidStructure(
+                    "Fixed-point powers need a non-negative integer exponent".into(),
+                ));
+            }
+            let mut n = exponent >> shift;
+            let mut result = format.narrow(1 << shift)?;
+            while n > 0 {
+                if n & 1 == 1 {
+                    result = format.multiply(result, base)?;
+                }
+                n >>= 1;
+                if n > 0 {
+                    base = format.multiply(base, base)?;
+                }
+            }
+            Ok(result)
+        }
+        Expr::Neg(e) => format.narrow(-i128::from(eval_raw(e, format)?)),
+        Expr::Call(name, args) => {
+            let function = functions::resolve(name, args.len())?;
+            let args = function
+                .operands(args, |bound| Ok(eval_raw(bound, format)? as f64 / format.scale()))?
+                .iter()
+                .map(|arg| Ok(eval_raw(arg, format)? as f64 / format.scale()))
+                .collect::<Result<Vec<_>>>()?;
+            from_f64(function.eval(&args, AngleMode::Radians, RoundingMode::default()), format)
+        }
+        Expr::Compare(relation, l, r) => {
+            let ordering = eval_raw(l, format)?.cmp(&eval_raw(r, format)?);
+            truth_value(relation.accepts(Some(ordering)), format)
+        }
+        Expr::If(cond, then, otherwise) => {
+            eval_raw(if eval_raw(cond, format)? != 0 { then } else { otherwise }, format)
+        }
+        Expr::And(l, r) | Expr::Or(l, r) => {
+            // A true left operand decides `or` and a false one decides `and`
+            let decisive = matches!(expr, Expr::Or(..));
+            if (eval_raw(l, format)? != 0) == decisive {
+                return truth_value(decisive, format);
+            }
+            truth_value(eval_raw(r, format)? != 0, format)
+        }
+        Expr::Not(e) => truth_value(eval_raw(e, format)? == 0, format),
+        Expr::List(_) | Expr::Range(..) | Expr::Text(_) => Err(not_a_number(expr)),
+    }
+}
+
+/// The fixed-point 1 or 0 for a comparison or logical result
+fn truth_value(holds: bool, format: &QFormat) -> Result<i64> {
+    format.narrow(if holds { 1 << format.frac_bits } else { 0 })
+}
+
+fn from_f64(n: f64, format: &QFormat) -> Result<i64> {
+    if n.is_nan() {
+        return Err(ComputeError::InvalidStructure(
+            "NaN has no fixed-point representation".into(),
+        ));
+    }
+    let scaled = (n * format.scale()).round();
+    if scaled >= -(i64::MIN as f64) || scaled < i64::MIN as f64 {
+        return match format.overflow {
+            OverflowMode::Error => Err(ComputeError::Overflow),
+            // Wrapping an out-of-range literal is meaningless; clamp it instead
+            OverflowMode::Saturate | OverflowMode::Wrap => {
+                Ok(if scaled < 0.0 { i64::MIN } else { i64::MAX })
+            }
+        };
+    }
+    Ok(scaled as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn q16() -> QFormat {
+        QFormat::new(16)
+    }
+
+    #[test]
+    fn test_basic_arithmetic() {
+        assert_eq!(evaluate_fixed("1.5 + 2.25", &q16()).unwrap().to_f64(), 3.75);
+        assert_eq!(evaluate_fixed("3 * 0.5", &q16()).unwrap().to_f64(), 1.5);
+        assert_eq!(evaluate_fixed("-(7 - 10)", &q16()).unwrap().to_f64(), 3.0);
+        assert_eq!(evaluate_fixed("1", &q16()).unwrap().raw(), 1 << 16);
+        assert_eq!(evaluate_fixed("1.5 ^ 2 ^ 2", &q16()).unwrap().to_f64(), 5.0625);
+        assert_eq!(evaluate_fixed("-2 ^ 0", &q16()).unwrap().to_f64(), 1.0);
+        assert_eq!(evaluate_fixed("sqrt(2.25) * 2", &q16()).unwrap().to_f64(), 3.0);
+        assert_eq!(evaluate_fixed("0.1 + 0.2 == 0.3 ? 1.5 : 2", &q16()).unwrap().to_f64(), 1.5);
+        assert_eq!(evaluate_fixed("1 / 3 > 0.33", &q16()).unwrap().to_f64(), 1.0);
+        assert_eq!(evaluate_fixed("0 and 1 / 0 or not 0.5 < 0", &q16()).unwrap().to_f64(), 1.0);
+        assert_eq!(evaluate_fixed("200 + 25% - 50% * 2", &q16()).unwrap().to_f64(), 249.0);
+    }
+
+    #[test]
+    fn test_truncation_matches_firmware() {
+        // 1/3 in Q8 is 85/256, not the nearest 85.33..
+        let third = evaluate_fixed("1 / 3", &QFormat::new(8)).unwrap();
+        assert_eq!(third.raw(), 85);
+        // Division truncates toward zero
+        assert_eq!(evaluate_fixed("-1 / 3", &QFormat::new(8)).unwrap().raw(), -85);
+        // Multiplication shifts, flooring negative results
+        assert_eq!(evaluate_fixed("-0.00390625 * 0.5", &QFormat::new(8)).unwrap().raw(), -1);
+    }
+
+    #[test]
+    fn test_overflow_modes() {
+        let expr = "1000000 * 1000000";
+        let saturate = evaluate_fixed(expr, &QFormat::new(32)).unwrap();
+        assert_eq!(saturate.raw(), i64::MAX);
+
+        let error = QFormat::new(32).with_overflow(OverflowMode::Error);
+        assert_eq!(evaluate_fixed(expr, &error), Err(ComputeError::Overflow));
+
+        let wrap = QFormat::new(0).with_overflow(OverflowMode::Wrap);
+        let wrapped = evaluate_fixed("9223372036854775807 + 1", &wrap);
+        // The literal itself rounds up past i64::MAX and is clamped before wrapping
+        assert_eq!(wrapped.unwrap().raw(), i64::MIN);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            evaluate_fixed("1 / 0", &q16()),
+            Err(ComputeError::DivisionByZero)
+        );
+        // Divisors below half an LSB round to zero and still trip the check
+        assert_eq!(
+            evaluate_fixed("1 / 0.000001", &q16()),
+            Err(ComputeError::DivisionByZero)
+        );
+        assert!(matches!(
+            evaluate_fixed("ln(0 - 1)", &q16()),
+            Err(ComputeError::InvalidStructure(_))
+        ));
+        assert!(matches!(
+            evaluate_fixed("2 ^ 0.5", &q16()),
+            Err(ComputeError::InvalidStructure(_))
+        ));
+        assert!(matches!(
+            evaluate_fixed("1", &QFormat::new(64)),
+            Err(ComputeError::InvalidStructure(_))
+        ));
+    }
+
+    #[test]
+    fn test_format_display() {
+        assert_eq!(QFormat::new(16).to_string(), "Q47.16");
+        assert_eq!(QFormat::new(31).to_string(), "Q32.31");
+        assert_eq!(QFormat::new(64).to_string(), "Q0.64");
+        assert_eq!(QFormat::new(u32::MAX).to_string(), format!("Q0.{}", u32::MAX));
+    }
+}
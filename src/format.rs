@@ -0,0 +1,295 @@
+//! Canonical source formatting: one space around binary operators, minimal parentheses
+
+use crate::prelude::*;
+use crate::visit::children;
+use crate::{format_number, parse_expression_with, Expr, ParseOptions, Result};
+
+/// Reprint `source` in canonical form
+///
+/// The result parses back to exactly the same tree as `source`: parentheses
+/// are kept only where precedence or associativity requires them, binary
+/// operators get one space on each side, and literals are written in their
+/// shortest exact form. Formatting is idempotent. Printing does not recurse,
+/// so operator chains of any length are accepted.
+pub fn format_source(source: &str) -> Result<String> {
+    let options = ParseOptions { max_chain: usize::MAX, ..Default::default() };
+    parse_expression_with(source, &options).map(|expr| to_source(&expr))
+}
+
+/// Print `expr` as canonical source text
+pub fn to_source(expr: &Expr) -> String {
+    enum Item<'a> {
+        Expr(&'a Expr),
+        Text(&'static str),
+    }
+
+    // Pushed in reverse so items pop in reading order
+    fn push_operand<'a>(stack: &mut Vec<Item<'a>>, expr: &'a Expr, parens: bool) {
+        if parens {
+            stack.extend([Item::Text(")"), Item::Expr(expr), Item::Text("(")]);
+        } else {
+            stack.push(Item::Expr(expr));
+        }
+    }
+
+    let mut out = String::new();
+    // Walk with an explicit stack so long operator chains cannot overflow the call stack
+    let mut stack = vec![Item::Expr(expr)];
+    while let Some(item) = stack.pop() {
+        let (l, op, r, precedence) = match item {
+            Item::Text(text) => {
+                out.push_str(text);
+                continue;
+            }
+            Item::Expr(Expr::Number(n)) => {
+                out.push_str(&format_number(*n));
+                continue;
+            }
+            Item::Expr(Expr::Percent(n)) => {
+                out.push_str(&format_number(*n));
+                out.push('%');
+                continue;
+            }
+            Item::Expr(Expr::Var(name)) => {
+                out.push_str(name);
+                continue;
+            }
+            Item::Expr(Expr::Text(text)) => {
+                out.push('"');
+                out.push_str(text);
+                out.push('"');
+                continue;
+            }
+            Item::Expr(Expr::Neg(e)) => {
+                push_operand(&mut stack, e, binding(e) < NEG);
+                stack.push(Item::Text("-"));
+                continue;
+            }
+            Item::Expr(Expr::Not(e)) => {
+                push_operand(&mut stack, e, binding(e) < NOT);
+                stack.push(Item::Text("not "));
+                continue;
+            }
+            Item::Expr(Expr::Call(name, args)) => {
+                out.push_str(name);
+                stack.push(Item::Text(")"));
+                for (i, arg) in args.iter().enumerate().rev() {
+                    stack.push(Item::Expr(arg));
+                    if i > 0 {
+                        stack.push(Item::Text(", "));
+                    }
+                }
+                stack.push(Item::Text("("));
+                continue;
+            }
+            Item::Expr(Expr::Range(lo, hi)) => {
+                // A range is a whole call argument, so neither bound needs parentheses
+                stack.extend([Item::Expr(hi), Item::Text(".."), Item::Expr(lo)]);
+                continue;
+            }
+            Item::Expr(Expr::List(elements)) => {
+                out.push('[');
+                stack.push(Item::Text("]"));
+                for (i, element) in elements.iter().enumerate().rev() {
+                    stack.push(Item::Expr(element));
+                    if i > 0 {
+                        stack.push(Item::Text(", "));
+                    }
+                }
+                continue;
+            }
+            Item::Expr(Expr::If(cond, then, otherwise)) => {
+                // Only the condition is restricted; either branch may be another conditional
+                stack.extend([Item::Expr(otherwise), Item::Text(" : "), Item::Expr(then), Item::Text(" ? ")]);
+                push_operand(&mut stack, cond, binding(cond) <= CONDITIONAL);
+                continue;
+            }
+            Item::Expr(Expr::Or(l, r)) => (l, "or", r, OR),
+            Item::Expr(Expr::And(l, r)) => (l, "and", r, AND),
+            Item::Expr(Expr::Compare(relation, l, r)) => (l, relation.symbol(), r, COMPARE),
+            Item::Expr(Expr::Add(l, r)) => (l, "+", r, SUM),
+            Item::Expr(Expr::Sub(l, r)) => (l, "-", r, SUM),
+            Item::Expr(Expr::Mul(l, r)) => (l, "*", r, PRODUCT),
+            Item::Expr(Expr::Div(l, r)) => (l, "/", r, PRODUCT),
+            Item::Expr(Expr::Pow(l, r)) => (l, "^", r, POWER),
+        };
+        // `^` is right associative and the rest left associative, so an
+        // equal-precedence operand on the other side needs parentheses
+        let right_assoc = precedence == POWER;
+        push_operand(&mut stack, r, binding(r) < precedence + u8::from(!right_assoc));
+        stack.extend([Item::Text(" "), Item::Text(op), Item::Text(" ")]);
+        push_operand(&mut stack, l, binding(l) < precedence + u8::from(right_assoc));
+    }
+    out
+}
+
+/// Print `expr` as an S-expression, as in `(+ 1 (* 2 x))`
+///
+/// Every operator node is a parenthesized list of its operator and operands;
+/// negation is `neg`, a conditional `if`, a list `list` and a range `range`,
+/// and a call is headed by the function name, so `max(a, 1)` is `(max a 1)`.
+/// Literals, variables and text print as in source.
+pub fn to_sexpr(expr: &Expr) -> String {
+    enum Item<'a> {
+        Expr(&'a Expr),
+        Text(&'static str),
+    }
+
+    let mut out = String::new();
+    let mut stack = vec![Item::Expr(expr)];
+    while let Some(item) = stack.pop() {
+        let expr = match item {
+            Item::Text(text) => {
+                out.push_str(text);
+                continue;
+            }
+            Item::Expr(expr) => expr,
+        };
+        let Some(head) = head(expr) else {
+            out.push_str(&atom(expr));
+            continue;
+        };
+        out.push('(');
+        out.push_str(head);
+        stack.push(Item::Text(")"));
+        for child in children(expr).into_iter().rev() {
+            stack.extend([Item::Expr(child), Item::Text(" ")]);
+        }
+    }
+    out
+}
+
+/// Print `expr` in Polish notation, as in `+ 1 * 2 x`
+///
+/// Operators come before their operands and no parentheses are needed. The
+/// operator names are those of `to_sexpr`; a call or list, which may take any
+/// number of operands, is written with its count, as in `max/2 a 1`.
+pub fn to_prefix(expr: &Expr) -> String {
+    let mut words = Vec::new();
+    let mut stack = vec![expr];
+    while let Some(expr) = stack.pop() {
+        let operands = children(expr);
+        words.push(match (head(expr), expr) {
+            (None, _) => atom(expr),
+            (Some(head), Expr::Call(..) | Expr::List(_)) => format!("{}/{}", head, operands.len()),
+            (Some(head), _) => head.to_string(),
+        });
+        stack.extend(operands.into_iter().rev());
+    }
+    words.join(" ")
+}
+
+/// The operator name of `expr` for the prefix printers, or `None` for a literal, variable or text
+pub(crate) fn head(expr: &Expr) -> Option<&str> {
+    Some(match expr {
+        Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_) => return None,
+        Expr::Add(..) => "+",
+        Expr::Sub(..) => "-",
+        Expr::Mul(..) => "*",
+        Expr::Div(..) => "/",
+        Expr::Pow(..) => "^",
+        Expr::Neg(_) => "neg",
+        Expr::Compare(relation, ..) => relation.symbol(),
+        Expr::If(..) => "if",
+        Expr::And(..) => "and",
+        Expr::Or(..) => "or",
+        Expr::Not(_) => "not",
+        Expr::Call(name, _) => name,
+        Expr::List(_) => "list",
+        Expr::Range(..) => "range",
+    })
+}
+
+/// A leaf of the tree as it is written in source
+fn atom(expr: &Expr) -> String {
+    match expr {
+        Expr::Number(n) => format_number(*n),
+        Expr::Percent(n) => format!("{}%", format_number(*n)),
+        Expr::Var(name) => name.clone(),
+        Expr::Text(text) => format!("\"{}\"", text),
+        _ => unreachable!("only leaves have no head"),
+    }
+}
+
+const CONDITIONAL: u8 = 1;
+const OR: u8 = 2;
+const AND: u8 = 3;
+// `not` applies to a whole comparison, so `not a < b` is `not (a < b)`
+const NOT: u8 = 4;
+const COMPARE: u8 = 5;
+const SUM: u8 = 6;
+const PRODUCT: u8 = 7;
+const POWER: u8 = 8;
+// Negation is part of the operand it applies to, so `-2 ^ 2` is `(-2) ^ 2`
+const NEG: u8 = 9;
+const ATOM: u8 = 10;
+
+/// How tightly the printed form of `expr` binds
+fn binding(expr: &Expr) -> u8 {
+    match expr {
+        Expr::If(..) | Expr::Range(..) => CONDITIONAL,
+        Expr::Or(..) => OR,
+        Expr::And(..) => AND,
+        Expr::Not(_) => NOT,
+        Expr::Compare(..) => COMPARE,
+        Expr::Add(..) | Expr::Sub(..) => SUM,
+        Expr::Mul(..) | Expr::Div(..) => PRODUCT,
+        Expr::Pow(..) => POWER,
+        Expr::Neg(_) => NEG,
+        // A negative literal prints with a leading minus
+        Expr::Number(n) | Expr::Percent(n) if n.is_sign_negative() => NEG,
+        Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_) | Expr::Call(..) | Expr::List(_) => ATOM,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_expression;
+
+    #[test]
+    fn test_canonical_spacing_and_parentheses() {
+        assert_eq!(format_source("((1+2))*3").unwrap(), "(1 + 2) * 3");
+        assert_eq!(format_source("1+(2*3)").unwrap(), "1 + 2 * 3");
+        assert_eq!(format_source("(1-2)-3").unwrap(), "1 - 2 - 3");
+        assert_eq!(format_source("1-(2-3)").unwrap(), "1 - (2 - 3)");
+        assert_eq!(format_source("a/(b*c)").unwrap(), "a / (b * c)");
+        assert_eq!(format_source("-(x)*-(2+y)").unwrap(), "-x * -(2 + y)");
+        assert_eq!(format_source("- - 1.50e1").unwrap(), "--15");
+        assert_eq!(format_source("2^(3^2)").unwrap(), "2 ^ 3 ^ 2");
+        assert_eq!(format_source("(2^3)^2").unwrap(), "(2 ^ 3) ^ 2");
+        assert_eq!(format_source("200+(10%)-5.0%*2").unwrap(), "200 + 10% - 5% * 2");
+        assert_eq!(format_source("(-x)^2*-(y^2)").unwrap(), "-x ^ 2 * -(y ^ 2)");
+        assert_eq!(format_source("(sqrt((x+1)))^2").unwrap(), "sqrt(x + 1) ^ 2");
+        assert_eq!(format_source("(a<b)==(c>=d+1)").unwrap(), "a < b == (c >= d + 1)");
+        assert_eq!(format_source("(x>0?x:-x)*2").unwrap(), "(x > 0 ? x : -x) * 2");
+        assert_eq!(format_source("(a?b:c)?d:(e?f:g)").unwrap(), "(a ? b : c) ? d : e ? f : g");
+        assert_eq!(format_source("a?(b?c:d):e").unwrap(), "a ? b ? c : d : e");
+        assert_eq!(format_source("(a and b) or (not (c<1) and d)").unwrap(), "a and b or not c < 1 and d");
+        assert_eq!(format_source("a and (b or c)").unwrap(), "a and (b or c)");
+        assert_eq!(format_source("not (a and b) == (not c)").unwrap(), "not (a and b) == (not c)");
+        assert_eq!(format_source("-(not x) * 2").unwrap(), "-(not x) * 2");
+        assert!(format_source("1 +").is_err());
+    }
+
+    #[test]
+    fn test_prefix_printers() {
+        let expr = parse_expression("-x + max(2, 10%) * 3 ^ y").unwrap();
+        assert_eq!(to_sexpr(&expr), "(+ (neg x) (* (max 2 10%) (^ 3 y)))");
+        assert_eq!(to_prefix(&expr), "+ neg x * max/2 2 10% ^ 3 y");
+        let expr = parse_expression("x < 1 and not y ? sum(1..n) : count([])").unwrap();
+        assert_eq!(to_sexpr(&expr), "(if (and (< x 1) (not y)) (sum (range 1 n)) (count (list)))");
+        assert_eq!(to_prefix(&expr), "if and < x 1 not y sum/1 range 1 n count/1 list/0");
+        let expr = parse_expression("convert(1.5, \"km\", \"mi\")").unwrap();
+        assert_eq!(to_sexpr(&expr), "(convert 1.5 \"km\" \"mi\")");
+        assert_eq!(expr.to_prefix(), "convert/3 1.5 \"km\" \"mi\"");
+        assert_eq!(parse_expression("7").unwrap().to_sexpr(), "7");
+    }
+
+    #[test]
+    fn test_long_chains_do_not_overflow() {
+        let chain = vec!["1"; 100_000].join("+");
+        let formatted = format_source(&chain).unwrap();
+        assert_eq!(formatted.len(), 100_000 + 99_999 * 3);
+    }
+}
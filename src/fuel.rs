@@ -0,0 +1,150 @@
+//! Evaluation under a step budget, so that no expression can keep a server busy indefinitely
+
+use crate::prelude::*;
+use crate::{
+    add_percent, functions, is_true, not_a_number, truth_value, AngleMode, Bindings, ComputeError, Expr, Result,
+    RoundingMode,
+};
+use core::cell::Cell;
+
+/// Evaluate `expr` like `eval_expr_in`, failing with `LimitExceeded` once it takes more than `fuel` steps
+///
+/// Every node evaluated is one step, including each number a range expands
+/// to, so `sum(1..100000)` takes about a hundred thousand steps however
+/// short it looks. Branches that a conditional, `and` or `or` skips cost
+/// nothing.
+pub fn eval_with_fuel(expr: &Expr, env: &Bindings, angle_mode: AngleMode, fuel: u64) -> Result<f64> {
+    eval_with_fuel_rounding(expr, env, angle_mode, RoundingMode::default(), fuel)
+}
+
+/// Like `eval_with_fuel`, with `round` rounding in `rounding`
+pub(crate) fn eval_with_fuel_rounding(
+    expr: &Expr,
+    env: &Bindings,
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+    fuel: u64,
+) -> Result<f64> {
+    let tank = Tank {
+        left: Cell::new(fuel),
+        fuel,
+        env,
+        angle_mode,
+        rounding,
+    };
+    tank.eval(expr)
+}
+
+struct Tank<'a> {
+    left: Cell<u64>,
+    fuel: u64,
+    env: &'a Bindings,
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+}
+
+impl Tank<'_> {
+    fn eval(&self, expr: &Expr) -> Result<f64> {
+        let Some(left) = self.left.get().checked_sub(1) else {
+            return Err(ComputeError::LimitExceeded(format!(
+                "evaluation takes more than the {} step budget",
+                self.fuel
+            )));
+        };
+        self.left.set(left);
+        let eval = |e: &Expr| self.eval(e);
+        if let Some((a, p)) = expr.percent_change() {
+            return Ok(add_percent(eval(a)?, p));
+        }
+        match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Percent(n) => Ok(n / 100.0),
+            Expr::Var(name) => self
+                .env
+                .get(name)
+                .copied()
+                .ok_or_else(|| ComputeError::UndefinedVariable(name.clone())),
+            Expr::Add(l, r) => Ok(eval(l)? + eval(r)?),
+            Expr::Sub(l, r) => Ok(eval(l)? - eval(r)?),
+            Expr::Mul(l, r) => Ok(eval(l)? * eval(r)?),
+            Expr::Div(l, r) => {
+                let divisor = eval(r)?;
+                if divisor != 0.0 {
+                    Ok(eval(l)? / divisor)
+                } else {
+                    Err(ComputeError::DivisionByZero)
+                }
+            }
+            Expr::Pow(l, r) => Ok(eval(l)?.powf(eval(r)?)),
+            Expr::Neg(e) => eval(e).map(|n| -n),
+            Expr::Call(name, args) => functions::call(name, args, self.angle_mode, self.rounding, eval),
+            Expr::Compare(relation, l, r) => Ok(relation.eval(eval(l)?, eval(r)?)),
+            Expr::If(cond, then, otherwise) => eval(if is_true(eval(cond)?) { then } else { otherwise }),
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                // A true left operand decides `or` and a false one decides `and`
+                let decisive = matches!(expr, Expr::Or(..));
+                let holds = if is_true(eval(l)?) == decisive { decisive } else { is_true(eval(r)?) };
+                Ok(truth_value(holds))
+            }
+            Expr::Not(e) => Ok(truth_value(!is_true(eval(e)?))),
+            Expr::List(_) | Expr::Range(..) | Expr::Text(_) => Err(not_a_number(expr)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval_expr_in, parse_expression};
+
+    fn fueled(source: &str, fuel: u64) -> Result<f64> {
+        eval_with_fuel(&parse_expression(source).unwrap(), &Bindings::new(), AngleMode::Radians, fuel)
+    }
+
+    #[test]
+    fn test_matches_eval_expr() {
+        let env: Bindings = [("x".to_string(), 4.0)].into_iter().collect();
+        for source in [
+            "1 + 2 * 3",
+            "x / (x - 4)",
+            "50 - 10%",
+            "sum(1..x) + max(x, 2, 3)",
+            "x > 3 and not (x == 4) ? 1 : 2",
+            "sqrt(-x) or 0",
+            "convert(x, \"ft\", \"in\")",
+            "[1, 2] + 1",
+            "y",
+        ] {
+            let expr = parse_expression(source).unwrap();
+            let expected = eval_expr_in(&expr, &env, AngleMode::Degrees);
+            let value = eval_with_fuel(&expr, &env, AngleMode::Degrees, u64::MAX);
+            assert!(value == expected || value.as_ref().is_ok_and(|v| v.is_nan()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_budget() {
+        // Each node evaluated is a step, and a skipped branch costs nothing
+        assert_eq!(fueled("1 + 2", 3), Ok(3.0));
+        assert_eq!(
+            fueled("1 + 2", 2).unwrap_err().to_string(),
+            "Limit exceeded: evaluation takes more than the 2 step budget"
+        );
+        assert_eq!(fueled("1 ? 2 : 3 * 4 * 5", 3), Ok(2.0));
+        assert_eq!(fueled("0 and 1 / 0", 2), Ok(0.0));
+        // The call and both bounds, then a step for every number of the range
+        assert_eq!(fueled("sum(1..1000)", 1003), Ok(500_500.0));
+        assert!(matches!(fueled("sum(1..1000)", 1002), Err(ComputeError::LimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_selected_through_options() {
+        use crate::{evaluate_with, Arithmetic, EvalOptions};
+        let options = EvalOptions { fuel: Some(3), ..Default::default() };
+        assert_eq!(evaluate_with("1 + 2", &options), Ok(3.0));
+        assert!(matches!(evaluate_with("1 + 2 + 3", &options), Err(ComputeError::LimitExceeded(_))));
+        let integer = EvalOptions { arithmetic: Arithmetic::Integer, ..options };
+        assert_eq!(evaluate_with("7 - 2", &integer), Ok(5.0));
+        assert!(matches!(evaluate_with("product(1..5)", &integer), Err(ComputeError::LimitExceeded(_))));
+    }
+}
@@ -0,0 +1,534 @@
+//! Registry of built-in functions callable as `name(x)`, `name(x, y)`, `name(x, y, ...)`,
+//! `name([x, y, ...])`, `name(a..b)` or `convert(x, "from", "to")`, and of
+//! `to_hex(x)`, `to_bin(x)` and `to_oct(x)`, which give text
+
+use crate::prelude::*;
+use crate::{format_number, units, ComputeError, Expr, Result, RoundingMode};
+use alloc::borrow::Cow;
+use serde::{Deserialize, Serialize};
+
+/// The unit trigonometric functions take and return angles in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum AngleMode {
+    #[default]
+    Radians,
+    Degrees,
+}
+
+impl AngleMode {
+    /// Sine and cosine of `angle`
+    ///
+    /// Degrees are reduced to a quarter turn before converting, so whole
+    /// multiples of 90 give exact zeros and ones: `sin(180)` is `0` rather
+    /// than the `1.2e-16` that `sin(pi)` gives.
+    fn sin_cos(self, angle: f64) -> (f64, f64) {
+        if self == AngleMode::Radians {
+            return angle.sin_cos();
+        }
+        // `angle.rem_euclid(360.0)`, which core does not provide
+        let turn = match angle % 360.0 {
+            turn if turn < 0.0 => turn + 360.0,
+            turn => turn,
+        };
+        let quadrant = (turn / 90.0).floor();
+        // The subtraction is exact, so reducing adds no rounding error
+        let (sin, cos) = (turn - 90.0 * quadrant).to_radians().sin_cos();
+        // `0.0 - x` rather than `-x` so that `cos(90)` is `0`, not `-0`
+        match quadrant as u8 % 4 {
+            0 => (sin, cos),
+            1 => (cos, 0.0 - sin),
+            2 => (0.0 - sin, 0.0 - cos),
+            _ => (0.0 - cos, sin),
+        }
+    }
+
+    /// Convert an angle in radians to this unit
+    fn convert_radians(self, radians: f64) -> f64 {
+        match self {
+            AngleMode::Radians => radians,
+            AngleMode::Degrees => radians.to_degrees(),
+        }
+    }
+}
+
+/// How a built-in computes its value
+#[derive(Debug, Clone, Copy)]
+pub enum Apply {
+    /// A function of one argument
+    Unary(fn(f64) -> f64),
+    /// A function of two arguments
+    Binary(fn(f64, f64) -> f64),
+    /// A function of three arguments
+    Ternary(fn(f64, f64, f64) -> f64),
+    /// A function of two or more arguments
+    Variadic(fn(&[f64]) -> f64),
+    /// Rounding of the first argument to the decimal places given by an optional second, 0 by default, in the given
+    /// mode or, for `None`, the configured one
+    Rounding(Option<RoundingMode>),
+    /// A function of a single list or range argument, applied to its elements
+    Aggregate(fn(&[f64]) -> f64),
+    /// A function of one angle, computed from the angle's sine and cosine
+    Trig(fn(f64, f64) -> f64),
+    /// Conversion of a value between the units named by two text arguments
+    Convert,
+    /// Formatting of a whole number as text in a base, valid only as a whole expression
+    Radix(u32),
+}
+
+/// Most arguments a call evaluates without allocating
+pub(crate) const MAX_ARITY: usize = 3;
+
+/// Most numbers a range such as `1..100` may expand to
+pub const MAX_RANGE_ITEMS: usize = 100_000;
+
+/// A built-in function
+#[derive(Debug, Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    /// The f64 implementation; arguments outside the domain give `NaN` or an infinity
+    pub apply: Apply,
+    /// Whether the result is an angle, as for the inverse trigonometric functions
+    pub returns_angle: bool,
+}
+
+impl Builtin {
+    const fn new(name: &'static str, apply: Apply) -> Self {
+        Builtin {
+            name,
+            apply,
+            returns_angle: false,
+        }
+    }
+
+    const fn inverse_trig(name: &'static str, apply: Apply) -> Self {
+        Builtin {
+            name,
+            apply,
+            returns_angle: true,
+        }
+    }
+
+    /// Fewest arguments the function takes
+    pub fn arity(&self) -> usize {
+        match self.apply {
+            Apply::Unary(_) | Apply::Trig(_) | Apply::Rounding(_) | Apply::Aggregate(_) | Apply::Radix(_) => 1,
+            Apply::Binary(_) | Apply::Variadic(_) => 2,
+            Apply::Ternary(_) | Apply::Convert => 3,
+        }
+    }
+
+    /// Most arguments the function takes, `None` when there is no limit
+    pub fn max_arity(&self) -> Option<usize> {
+        match self.apply {
+            Apply::Variadic(_) => None,
+            Apply::Rounding(_) => Some(2),
+            _ => Some(self.arity()),
+        }
+    }
+
+    /// Whether a call with `count` arguments is valid
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.arity() && self.max_arity().is_none_or(|max| count <= max)
+    }
+
+    /// Whether the function aggregates the elements of a list or range argument, as `sum` does
+    pub fn takes_list(&self) -> bool {
+        matches!(self.apply, Apply::Aggregate(_))
+    }
+
+    /// Whether the function takes unit names after its value, as `convert` does
+    pub fn takes_units(&self) -> bool {
+        matches!(self.apply, Apply::Convert)
+    }
+
+    /// The base the function writes its argument in, for a function such as `to_hex` whose value is text
+    pub fn radix(&self) -> Option<u32> {
+        match self.apply {
+            Apply::Radix(base) => Some(base),
+            _ => None,
+        }
+    }
+
+    /// The expressions whose values `eval` takes for a call with `args`
+    ///
+    /// These are the arguments themselves, except for an aggregate function,
+    /// which takes the elements of its list or the numbers of its range, with
+    /// the range's bounds computed by `bound`, and for `convert`, which takes
+    /// its value followed by the numbers of `units::conversion`. Any other
+    /// argument to these is a `TypeMismatch`, as is every call to a function
+    /// with a `radix`, whose text no arithmetic can use.
+    pub fn operands<'a>(
+        &self,
+        args: &'a [Expr],
+        mut bound: impl FnMut(&Expr) -> Result<f64>,
+    ) -> Result<Cow<'a, [Expr]>> {
+        if self.takes_units() {
+            let [value, Expr::Text(from), Expr::Text(to)] = args else {
+                return Err(ComputeError::TypeMismatch(format!(
+                    "{}() takes a value and two quoted unit names, as in {}(5, \"km\", \"mi\")",
+                    self.name, self.name
+                )));
+            };
+            let mut operands = vec![value.clone()];
+            operands.extend(units::conversion(from, to)?.map(Expr::Number));
+            return Ok(Cow::Owned(operands));
+        }
+        if self.radix().is_some() {
+            return Err(ComputeError::TypeMismatch(format!(
+                "{}() gives text, so it can only be the whole expression, as in {}(255)",
+                self.name, self.name
+            )));
+        }
+        if !self.takes_list() {
+            return Ok(Cow::Borrowed(args));
+        }
+        match args {
+            [Expr::List(elements)] => Ok(Cow::Borrowed(elements)),
+            [Expr::Range(lo, hi)] => {
+                let (lo, hi) = (bound(lo)?, bound(hi)?);
+                Ok(Cow::Owned(range(lo, hi)?.map(Expr::Number).collect()))
+            }
+            _ => Err(ComputeError::TypeMismatch(format!(
+                "{}() takes a list or range, as in {}([1, 2, 3]) or {}(1..10)",
+                self.name, self.name, self.name
+            ))),
+        }
+    }
+
+    /// Whether the value depends on the `AngleMode`
+    pub fn uses_angles(&self) -> bool {
+        self.returns_angle || matches!(self.apply, Apply::Trig(_))
+    }
+
+    /// Apply the function to `args`, measuring angles in `angle_mode` and rounding as `round` does in `rounding`
+    ///
+    /// `args` must number what the function accepts, or be the `operands` for
+    /// an aggregate function or a conversion.
+    pub fn eval(&self, args: &[f64], angle_mode: AngleMode, rounding: RoundingMode) -> f64 {
+        let value = match self.apply {
+            Apply::Unary(f) => f(args[0]),
+            Apply::Binary(f) => f(args[0], args[1]),
+            Apply::Ternary(f) => f(args[0], args[1], args[2]),
+            Apply::Variadic(f) | Apply::Aggregate(f) => f(args),
+            Apply::Rounding(mode) => round_places(mode.unwrap_or(rounding), args[0], args.get(1).copied().unwrap_or(0.0)),
+            Apply::Convert => units::apply(args),
+            // Never reached, as `operands` refuses every call
+            Apply::Radix(_) => f64::NAN,
+            Apply::Trig(f) => {
+                let (sin, cos) = angle_mode.sin_cos(args[0]);
+                f(sin, cos)
+            }
+        };
+        if self.returns_angle {
+            angle_mode.convert_radians(value)
+        } else {
+            value
+        }
+    }
+}
+
+/// Every built-in function, in alphabetical order
+pub const BUILTINS: &[Builtin] = &[
+    Builtin::new("abs", Apply::Unary(f64::abs)),
+    Builtin::inverse_trig("acos", Apply::Unary(f64::acos)),
+    Builtin::inverse_trig("asin", Apply::Unary(f64::asin)),
+    Builtin::inverse_trig("atan", Apply::Unary(f64::atan)),
+    Builtin::inverse_trig("atan2", Apply::Binary(f64::atan2)),
+    Builtin::new("ceil", Apply::Rounding(Some(RoundingMode::Ceiling))),
+    Builtin::new("clamp", Apply::Ternary(clamp)),
+    Builtin::new("convert", Apply::Convert),
+    Builtin::new("cos", Apply::Trig(|_, cos| cos)),
+    Builtin::new("count", Apply::Aggregate(|args| args.len() as f64)),
+    Builtin::new("exp", Apply::Unary(f64::exp)),
+    Builtin::new("floor", Apply::Rounding(Some(RoundingMode::Floor))),
+    Builtin::new("ln", Apply::Unary(f64::ln)),
+    Builtin::new("log10", Apply::Unary(f64::log10)),
+    Builtin::new("max", Apply::Variadic(|args| extreme(args, f64::max))),
+    Builtin::new("mean", Apply::Aggregate(mean)),
+    Builtin::new("median", Apply::Aggregate(median)),
+    Builtin::new("min", Apply::Variadic(|args| extreme(args, f64::min))),
+    // A measured value and its uncertainty, which only `eval_uncertain` propagates
+    Builtin::new("pm", Apply::Binary(|value, _| value)),
+    Builtin::new("product", Apply::Aggregate(|args| args.iter().product())),
+    Builtin::new("round", Apply::Rounding(None)),
+    Builtin::new("sin", Apply::Trig(|sin, _| sin)),
+    Builtin::new("sqrt", Apply::Unary(f64::sqrt)),
+    Builtin::new("stddev", Apply::Aggregate(|args| variance(args).sqrt())),
+    Builtin::new("sum", Apply::Aggregate(|args| args.iter().fold(0.0, |a, b| a + b))),
+    Builtin::new("tan", Apply::Trig(|sin, cos| sin / cos)),
+    Builtin::new("to_bin", Apply::Radix(2)),
+    Builtin::new("to_hex", Apply::Radix(16)),
+    Builtin::new("to_oct", Apply::Radix(8)),
+    Builtin::new("trunc", Apply::Rounding(Some(RoundingMode::TowardZero))),
+    Builtin::new("variance", Apply::Aggregate(variance)),
+];
+
+/// The largest or smallest of `args` as `pick` chooses, or `NaN` if any argument is `NaN`
+fn extreme(args: &[f64], pick: fn(f64, f64) -> f64) -> f64 {
+    if args.iter().any(|x| x.is_nan()) {
+        return f64::NAN;
+    }
+    args.iter().copied().reduce(pick).unwrap_or(f64::NAN)
+}
+
+/// `x` limited to the range `lo..=hi`, or `NaN` if the range is empty or any argument is `NaN`
+fn clamp(x: f64, lo: f64, hi: f64) -> f64 {
+    if x.is_nan() || lo.is_nan() || hi.is_nan() || lo > hi {
+        return f64::NAN;
+    }
+    x.clamp(lo, hi)
+}
+
+/// The numbers `lo`, `lo + 1`, ... up to and including `hi`, none if `hi < lo`
+///
+/// Fails with `InvalidStructure` for a bound that is not finite and with
+/// `LimitExceeded` for more than `MAX_RANGE_ITEMS` numbers.
+pub(crate) fn range(lo: f64, hi: f64) -> Result<impl Iterator<Item = f64>> {
+    if !lo.is_finite() || !hi.is_finite() {
+        return Err(ComputeError::InvalidStructure(format!(
+            "range {}..{} needs finite bounds",
+            format_number(lo),
+            format_number(hi)
+        )));
+    }
+    let count = if hi < lo { 0.0 } else { (hi - lo).floor() + 1.0 };
+    if count > MAX_RANGE_ITEMS as f64 {
+        return Err(ComputeError::LimitExceeded(format!(
+            "range {}..{} has more than {} items",
+            format_number(lo),
+            format_number(hi),
+            MAX_RANGE_ITEMS
+        )));
+    }
+    Ok((0..count as usize).map(move |i| lo + i as f64))
+}
+
+/// Arithmetic mean, `NaN` for an empty list
+fn mean(args: &[f64]) -> f64 {
+    args.iter().sum::<f64>() / args.len() as f64
+}
+
+/// Middle element, or the mean of the two middle elements; `NaN` for an empty list or one containing `NaN`
+fn median(args: &[f64]) -> f64 {
+    if args.is_empty() || args.iter().any(|x| x.is_nan()) {
+        return f64::NAN;
+    }
+    let mut sorted = args.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    if sorted.len() % 2 == 1 {
+        sorted[middle]
+    } else {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    }
+}
+
+/// Sample variance, dividing by `n - 1`; `NaN` for fewer than two elements
+fn variance(args: &[f64]) -> f64 {
+    if args.len() < 2 {
+        return f64::NAN;
+    }
+    let mean = mean(args);
+    args.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (args.len() - 1) as f64
+}
+
+/// Places beyond which rounding cannot change any f64
+const MAX_PLACES: f64 = 400.0;
+
+/// `x` rounded with `mode` to `places` decimal places, or `NaN` if `places` is not a whole number
+///
+/// Rounding works on the shortest decimal form of `x`, so `round(2.675, 2)`
+/// is `2.68` even though the stored double is slightly below the tie.
+fn round_places(mode: RoundingMode, x: f64, places: f64) -> f64 {
+    if places.fract() != 0.0 || places.is_nan() {
+        return f64::NAN;
+    }
+    mode.round(x, places.clamp(-MAX_PLACES, MAX_PLACES) as i32)
+}
+
+/// The built-in called `name`, if there is one
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|f| f.name == name)
+}
+
+/// Resolve a call to `name` with `arity` arguments
+///
+/// Fails with `UnknownFunction` for a name that is not built in, and with
+/// `InvalidStructure` when the number of arguments is wrong.
+pub fn resolve(name: &str, arity: usize) -> Result<&'static Builtin> {
+    let function = builtin(name).ok_or_else(|| ComputeError::UnknownFunction(name.to_string()))?;
+    if !function.accepts(arity) {
+        let expected = match function.max_arity() {
+            None => format!("at least {}", function.arity()),
+            Some(max) if max > function.arity() => format!("{} or {}", function.arity(), max),
+            Some(_) => function.arity().to_string(),
+        };
+        return Err(ComputeError::InvalidStructure(format!(
+            "{}() takes {} argument{} but was given {}",
+            name,
+            expected,
+            if expected == "1" { "" } else { "s" },
+            arity
+        )));
+    }
+    Ok(function)
+}
+
+/// Resolve a call to `name` and apply it to `args`, evaluated left to right with `eval`
+pub(crate) fn call(
+    name: &str,
+    args: &[Expr],
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+    eval: impl Fn(&Expr) -> Result<f64>,
+) -> Result<f64> {
+    let function = resolve(name, args.len())?;
+    let args = function.operands(args, &eval)?;
+    if args.len() > MAX_ARITY {
+        let values = args.iter().map(&eval).collect::<Result<Vec<_>>>()?;
+        return Ok(function.eval(&values, angle_mode, rounding));
+    }
+    let mut values = [0.0; MAX_ARITY];
+    for (value, arg) in values.iter_mut().zip(args.iter()) {
+        *value = eval(arg)?;
+    }
+    Ok(function.eval(&values[..args.len()], angle_mode, rounding))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(name: &str, args: &[f64], angle_mode: AngleMode) -> f64 {
+        resolve(name, args.len()).unwrap().eval(args, angle_mode, RoundingMode::default())
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(eval("sqrt", &[16.0], AngleMode::Radians), 4.0);
+        assert_eq!(eval("log10", &[1000.0], AngleMode::Radians), 3.0);
+        assert_eq!(resolve("sqr", 1).unwrap_err(), ComputeError::UnknownFunction("sqr".into()));
+        assert!(matches!(resolve("abs", 2), Err(ComputeError::InvalidStructure(_))));
+        assert_eq!(
+            resolve("atan2", 1).unwrap_err().to_string(),
+            "atan2() takes 2 arguments but was given 1"
+        );
+        assert!(BUILTINS.windows(2).all(|w| w[0].name < w[1].name));
+    }
+
+    #[test]
+    fn test_min_max_clamp() {
+        use AngleMode::Radians;
+        assert_eq!(eval("min", &[3.0, -1.0], Radians), -1.0);
+        assert_eq!(eval("max", &[3.0, -1.0, 7.5, 2.0, 7.0], Radians), 7.5);
+        assert!(eval("max", &[1.0, f64::NAN, 2.0], Radians).is_nan());
+        assert_eq!(eval("clamp", &[5.0, 0.0, 1.0], Radians), 1.0);
+        assert_eq!(eval("clamp", &[-5.0, 0.0, 1.0], Radians), 0.0);
+        assert_eq!(eval("clamp", &[0.25, 0.0, 1.0], Radians), 0.25);
+        assert!(eval("clamp", &[0.5, 1.0, 0.0], Radians).is_nan());
+        assert_eq!(
+            resolve("min", 1).unwrap_err().to_string(),
+            "min() takes at least 2 arguments but was given 1"
+        );
+        assert_eq!(
+            resolve("clamp", 4).unwrap_err().to_string(),
+            "clamp() takes 3 arguments but was given 4"
+        );
+        assert!(resolve("max", 40).is_ok());
+    }
+
+    #[test]
+    fn test_rounding() {
+        use AngleMode::Radians;
+        assert_eq!(eval("round", &[2.5], Radians), 3.0);
+        assert_eq!(eval("round", &[-2.5], Radians), -3.0);
+        assert_eq!(eval("round", &[2.675, 2.0], Radians), 2.68);
+        assert_eq!(eval("round", &[1250.0, -2.0], Radians), 1300.0);
+        assert_eq!(eval("floor", &[-2.71, 1.0], Radians), -2.8);
+        assert_eq!(eval("floor", &[-0.5], Radians), -1.0);
+        assert_eq!(eval("ceil", &[2.01], Radians), 3.0);
+        assert_eq!(eval("ceil", &[2.71, 1.0], Radians), 2.8);
+        assert_eq!(eval("trunc", &[-2.79, 1.0], Radians), -2.7);
+        assert_eq!(eval("trunc", &[1e300, 1e9], Radians), 1e300);
+        assert_eq!(eval("round", &[123.456, -1e9], Radians), 0.0);
+        assert!(eval("round", &[1.5, 0.5], Radians).is_nan());
+        // `round` follows the configured mode, the others keep their own
+        let round = |name, args: &[f64], rounding| builtin(name).unwrap().eval(args, Radians, rounding);
+        assert_eq!(round("round", &[2.5], RoundingMode::HalfEven), 2.0);
+        assert_eq!(round("round", &[2.675, 2.0], RoundingMode::HalfDown), 2.67);
+        assert_eq!(round("round", &[-2.5], RoundingMode::TowardZero), -2.0);
+        assert_eq!(round("floor", &[2.5], RoundingMode::Ceiling), 2.0);
+        assert_eq!(round("ceil", &[2.5], RoundingMode::Floor), 3.0);
+        assert_eq!(round("trunc", &[-2.5], RoundingMode::HalfUp), -2.0);
+        assert_eq!(
+            resolve("round", 3).unwrap_err().to_string(),
+            "round() takes 1 or 2 arguments but was given 3"
+        );
+        assert_eq!(resolve("sqrt", 0).unwrap_err().to_string(), "sqrt() takes 1 argument but was given 0");
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let aggregate = |name, data: &[f64]| builtin(name).unwrap().eval(data, AngleMode::Radians, RoundingMode::default());
+        let data = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(aggregate("sum", &data), 40.0);
+        assert_eq!(aggregate("count", &data), 8.0);
+        assert_eq!(aggregate("mean", &data), 5.0);
+        assert_eq!(aggregate("median", &data), 4.5);
+        assert_eq!(aggregate("median", &[3.0, -1.0, 2.0]), 2.0);
+        assert_eq!(aggregate("variance", &data), 32.0 / 7.0);
+        assert_eq!(aggregate("stddev", &[1.0, 3.0]), 2f64.sqrt());
+        assert_eq!(aggregate("sum", &[]).to_bits(), 0.0f64.to_bits());
+        assert_eq!(aggregate("count", &[]), 0.0);
+        assert!(aggregate("mean", &[]).is_nan());
+        assert!(aggregate("variance", &[1.0]).is_nan());
+        assert!(aggregate("median", &[1.0, f64::NAN]).is_nan());
+
+        assert_eq!(aggregate("product", &[2.0, 3.0, 4.0]), 24.0);
+        assert_eq!(aggregate("product", &[]), 1.0);
+
+        let sum = builtin("sum").unwrap();
+        let bound = |e: &Expr| crate::eval_expr(e);
+        let list = [Expr::List(vec![Expr::Number(1.0)])];
+        assert_eq!(sum.operands(&list, bound).unwrap(), &[Expr::Number(1.0)][..]);
+        assert_eq!(
+            sum.operands(&[Expr::Number(1.0)], bound).unwrap_err().to_string(),
+            "Type mismatch: sum() takes a list or range, as in sum([1, 2, 3]) or sum(1..10)"
+        );
+        assert_eq!(builtin("abs").unwrap().operands(&list, bound).unwrap(), &list[..]);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let values = |lo, hi| range(lo, hi).map(|range| range.collect::<Vec<_>>());
+        assert_eq!(values(1.0, 4.0), Ok(vec![1.0, 2.0, 3.0, 4.0]));
+        assert_eq!(values(0.5, 2.9), Ok(vec![0.5, 1.5, 2.5]));
+        assert_eq!(values(-1.0, -1.0), Ok(vec![-1.0]));
+        assert_eq!(values(3.0, 1.0), Ok(vec![]));
+        assert!(values(1.0, MAX_RANGE_ITEMS as f64).is_ok());
+        assert_eq!(
+            values(0.0, MAX_RANGE_ITEMS as f64).unwrap_err().to_string(),
+            "Limit exceeded: range 0..100000 has more than 100000 items"
+        );
+        assert!(matches!(values(0.0, f64::INFINITY), Err(ComputeError::InvalidStructure(_))));
+        assert!(matches!(values(f64::NAN, 1.0), Err(ComputeError::InvalidStructure(_))));
+    }
+
+    #[test]
+    fn test_angle_modes() {
+        use AngleMode::*;
+        assert_eq!(eval("sin", &[1.0], Radians), 1f64.sin());
+        assert_eq!(eval("atan2", &[1.0, -1.0], Radians), 1f64.atan2(-1.0));
+        assert_eq!(eval("sin", &[180.0], Degrees), 0.0);
+        assert_eq!(eval("cos", &[-720.0], Degrees), 1.0);
+        assert_eq!(eval("sin", &[270.0], Degrees), -1.0);
+        assert_eq!(eval("cos", &[60.0], Degrees), 60f64.to_radians().cos());
+        assert_eq!(eval("tan", &[90.0], Degrees), f64::INFINITY);
+        assert!(eval("cos", &[90.0], Degrees).is_sign_positive());
+        assert_eq!(eval("asin", &[1.0], Degrees), 90.0);
+        assert_eq!(eval("atan2", &[-1.0, -1.0], Degrees), -135.0);
+        assert!(eval("acos", &[2.0], Degrees).is_nan());
+        assert!(eval("sin", &[f64::INFINITY], Degrees).is_nan());
+        assert_eq!(BUILTINS.iter().filter(|f| f.uses_angles()).count(), 7);
+    }
+}
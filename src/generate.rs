@@ -0,0 +1,251 @@
+//! Seeded random expressions with known values, for building test corpora
+
+use crate::prelude::*;
+use crate::{eval_expr, functions, Expr, OpKind, Result};
+
+/// Shape and content constraints for generated expressions
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorConfig {
+    /// Maximum operator nesting; 0 produces single literals
+    pub max_depth: u32,
+    /// Operators that may appear; an empty list produces single literals
+    pub operators: Vec<OpKind>,
+    /// Smallest literal value (negative values are written with unary minus)
+    pub min_value: f64,
+    /// Largest literal value
+    pub max_value: f64,
+    /// Decimal places kept on literals
+    pub decimals: u32,
+    /// Regenerate any divisor that evaluates to zero
+    pub avoid_division_by_zero: bool,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            max_depth: 4,
+            operators: vec![OpKind::Add, OpKind::Sub, OpKind::Mul, OpKind::Div, OpKind::Neg],
+            min_value: 0.0,
+            max_value: 100.0,
+            decimals: 0,
+            avoid_division_by_zero: true,
+        }
+    }
+}
+
+/// A generated expression together with its printed form and value
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedExpr {
+    pub expr: Expr,
+    /// Source text that parses back to `expr`
+    pub source: String,
+    pub expected: Result<f64>,
+}
+
+/// Deterministic expression generator: the same config and seed yield the same corpus
+#[derive(Debug, Clone)]
+pub struct ExprGenerator {
+    config: GeneratorConfig,
+    state: u64,
+}
+
+/// Attempts at drawing a non-zero divisor before falling back to a literal
+const DIVISOR_ATTEMPTS: usize = 8;
+
+impl ExprGenerator {
+    pub fn new(config: GeneratorConfig, seed: u64) -> Self {
+        ExprGenerator { config, state: seed }
+    }
+
+    pub fn config(&self) -> &GeneratorConfig {
+        &self.config
+    }
+
+    /// Generate the next expression
+    pub fn generate(&mut self) -> GeneratedExpr {
+        let expr = self.expr(0);
+        GeneratedExpr {
+            source: expr.to_string(),
+            expected: eval_expr(&expr),
+            expr,
+        }
+    }
+
+    fn expr(&mut self, depth: u32) -> Expr {
+        let leaf = depth >= self.config.max_depth
+            || self.config.operators.is_empty()
+            || (depth > 0 && self.next_f64() < 0.3);
+        if leaf {
+            return self.literal();
+        }
+
+        let index = self.below(self.config.operators.len());
+        let op = self.config.operators[index];
+        let child = depth + 1;
+        match op {
+            OpKind::Neg => Expr::Neg(Box::new(self.expr(child))),
+            OpKind::Not => Expr::Not(Box::new(self.expr(child))),
+            OpKind::And => Expr::And(Box::new(self.expr(child)), Box::new(self.expr(child))),
+            OpKind::Or => Expr::Or(Box::new(self.expr(child)), Box::new(self.expr(child))),
+            OpKind::If => Expr::If(
+                Box::new(self.expr(child)),
+                Box::new(self.expr(child)),
+                Box::new(self.expr(child)),
+            ),
+            OpKind::Add => Expr::Add(Box::new(self.expr(child)), Box::new(self.expr(child))),
+            OpKind::Sub => Expr::Sub(Box::new(self.expr(child)), Box::new(self.expr(child))),
+            OpKind::AddPercent => Expr::Add(Box::new(self.expr(child)), Box::new(self.percent())),
+            OpKind::SubPercent => Expr::Sub(Box::new(self.expr(child)), Box::new(self.percent())),
+            OpKind::Mul => Expr::Mul(Box::new(self.expr(child)), Box::new(self.expr(child))),
+            OpKind::Pow => Expr::Pow(Box::new(self.expr(child)), Box::new(self.expr(child))),
+            OpKind::Compare(relation) => {
+                Expr::Compare(relation, Box::new(self.expr(child)), Box::new(self.expr(child)))
+            }
+            OpKind::Call(name) => {
+                let function = functions::builtin(name);
+                let arity = function.map_or(1, |function| function.arity());
+                let mut args: Vec<Expr> = (0..arity).map(|_| self.expr(child)).collect();
+                if function.is_some_and(|function| function.takes_units()) {
+                    // Unit names are text, so the operands after the value are replaced
+                    args.splice(1.., [Expr::Text("km".into()), Expr::Text("mi".into())]);
+                }
+                if function.is_some_and(|function| function.takes_list()) {
+                    Expr::Call(name.to_string(), vec![Expr::List(args)])
+                } else {
+                    Expr::Call(name.to_string(), args)
+                }
+            }
+            OpKind::Div => {
+                let dividend = self.expr(child);
+                Expr::Div(Box::new(dividend), Box::new(self.divisor(child)))
+            }
+        }
+    }
+
+    fn divisor(&mut self, depth: u32) -> Expr {
+        if !self.config.avoid_division_by_zero {
+            return self.expr(depth);
+        }
+        for _ in 0..DIVISOR_ATTEMPTS {
+            let divisor = self.expr(depth);
+            if eval_expr(&divisor).is_ok_and(|v| v != 0.0) {
+                return divisor;
+            }
+        }
+        // A range containing only zero leaves no choice but a fixed divisor
+        Expr::Number(1.0)
+    }
+
+    fn literal_value(&mut self) -> f64 {
+        let (lo, hi) = (self.config.min_value, self.config.max_value);
+        let scale = 10f64.powi(self.config.decimals as i32);
+        let value = ((lo + (hi - lo) * self.next_f64()) * scale).round() / scale;
+        value.clamp(lo, hi)
+    }
+
+    fn literal(&mut self) -> Expr {
+        let value = self.literal_value();
+        if value < 0.0 {
+            Expr::Neg(Box::new(Expr::Number(-value)))
+        } else {
+            // Avoid printing a negative zero that would not reparse to a literal
+            Expr::Number(value.abs())
+        }
+    }
+
+    /// A percent literal; its magnitude is drawn like a literal's, since `a + -10%` is no percentage change
+    fn percent(&mut self) -> Expr {
+        Expr::Percent(self.literal_value().abs())
+    }
+
+    /// SplitMix64, chosen for a stable sequence across platforms and releases
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+impl Iterator for ExprGenerator {
+    type Item = GeneratedExpr;
+
+    fn next(&mut self) -> Option<GeneratedExpr> {
+        Some(self.generate())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{evaluate, parse_expression};
+
+    #[test]
+    fn test_seeded_generation_is_reproducible() {
+        let a: Vec<_> = ExprGenerator::new(GeneratorConfig::default(), 7).take(20).collect();
+        let b: Vec<_> = ExprGenerator::new(GeneratorConfig::default(), 7).take(20).collect();
+        let c: Vec<_> = ExprGenerator::new(GeneratorConfig::default(), 8).take(20).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_sources_reparse_to_expected_values() {
+        let config = GeneratorConfig {
+            min_value: -50.0,
+            max_value: 50.0,
+            decimals: 2,
+            ..Default::default()
+        };
+        for generated in ExprGenerator::new(config, 1).take(500) {
+            assert_eq!(parse_expression(&generated.source).as_ref(), Ok(&generated.expr));
+            assert_eq!(evaluate(&generated.source), generated.expected, "{}", generated.source);
+            assert!(generated.expected.is_ok(), "{}", generated.source);
+        }
+    }
+
+    #[test]
+    fn test_operator_and_range_constraints() {
+        let config = GeneratorConfig {
+            max_depth: 3,
+            operators: vec![OpKind::Add],
+            min_value: 1.0,
+            max_value: 9.0,
+            ..Default::default()
+        };
+        for generated in ExprGenerator::new(config, 3).take(100) {
+            assert!(generated.source.chars().all(|c| "123456789 +()".contains(c)), "{}", generated.source);
+        }
+
+        let zero_only = GeneratorConfig {
+            operators: vec![OpKind::Div],
+            min_value: 0.0,
+            max_value: 0.0,
+            ..Default::default()
+        };
+        for generated in ExprGenerator::new(zero_only, 5).take(20) {
+            assert!(generated.expected.is_ok(), "{}", generated.source);
+        }
+
+        let percentages = GeneratorConfig {
+            operators: vec![OpKind::AddPercent, OpKind::SubPercent],
+            min_value: -20.0,
+            max_value: 20.0,
+            ..Default::default()
+        };
+        for generated in ExprGenerator::new(percentages, 9).take(50) {
+            assert!(generated.expr.percent_change().is_some(), "{}", generated.source);
+            assert_eq!(parse_expression(&generated.source).as_ref(), Ok(&generated.expr));
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Arbitrary-precision integer evaluation, for results such as `2 ^ 200` that no f64 holds exactly
+
+use crate::prelude::*;
+use crate::verify::eval_exact_rounding;
+use crate::{AngleMode, Bindings, ComputeError, EvalOptions, Expr, Result, RoundingMode};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+/// Number type `EvalOptions` evaluates in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Arithmetic {
+    /// IEEE 754 doubles, fast but rounded at every step
+    #[default]
+    Float,
+    /// Exact arithmetic whose result must be a whole number, see `eval_integer`
+    Integer,
+}
+
+/// Evaluate `expr` exactly, failing unless the result is a whole number
+///
+/// Every operation runs on exact fractions as in `eval_exact`, so `2 ^ 200`
+/// and `product(1..30)` keep all their digits and `7 / 2 * 2` is `7`. A result
+/// with a fractional part is a `TypeMismatch` rather than a rounded value.
+pub fn eval_integer(expr: &Expr, env: &Bindings, angle_mode: AngleMode) -> Result<BigInt> {
+    eval_integer_rounding(expr, env, angle_mode, RoundingMode::default())
+}
+
+/// Like `eval_integer`, with `round` rounding in `rounding`
+pub(crate) fn eval_integer_rounding(
+    expr: &Expr,
+    env: &Bindings,
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+) -> Result<BigInt> {
+    let exact = eval_exact_rounding(expr, env, angle_mode, rounding)?;
+    if !exact.is_integer() {
+        return Err(ComputeError::TypeMismatch(format!("expected a whole number but {} has a fractional part", expr)));
+    }
+    Ok(exact.to_integer())
+}
+
+/// Parse and evaluate `expr` as a whole number with `options`, looking variables up in `env`
+pub fn evaluate_integer(expr: &str, env: &Bindings, options: &EvalOptions) -> Result<BigInt> {
+    options.parse.check_len(expr)?;
+    let expr = expr.trim();
+    if expr.is_empty() {
+        return Err(ComputeError::EmptyExpression);
+    }
+    eval_integer_rounding(&options.parse(expr)?, env, options.angle_mode, options.rounding)
+}
+
+/// `value` as an f64, which may round, or `Overflow` past the f64 range
+pub(crate) fn to_f64(value: &BigInt) -> Result<f64> {
+    value.to_f64().filter(|n| n.is_finite()).ok_or(ComputeError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn integer(source: &str) -> Result<String> {
+        evaluate_integer(source, &Bindings::new(), &EvalOptions::default()).map(|n| n.to_string())
+    }
+
+    #[test]
+    fn test_exact_integers() {
+        assert_eq!(integer("2 ^ 200"), Ok("1606938044258990275541962092341162602522202993782792835301376".into()));
+        assert_eq!(integer("product(1..25)"), Ok("15511210043330985984000000".into()));
+        assert_eq!(integer("(2 ^ 64 + 1) - 2 ^ 64"), Ok("1".into()));
+        assert_eq!(integer("sum([2 ^ 60, 1, -(2 ^ 60)])"), Ok("1".into()));
+        assert_eq!(integer("7 / 2 * 2"), Ok("7".into()));
+        assert_eq!(integer("10 + 20%"), Ok("12".into()));
+        assert_eq!(integer("-(10 ^ 30) / 10 ^ 29"), Ok("-10".into()));
+    }
+
+    #[test]
+    fn test_fractions_and_errors() {
+        assert_eq!(
+            integer("7 / 2").unwrap_err().to_string(),
+            "Type mismatch: expected a whole number but (7 / 2) has a fractional part"
+        );
+        assert!(matches!(integer("0.1"), Err(ComputeError::TypeMismatch(_))));
+        assert_eq!(integer("1 / (2 - 2)"), Err(ComputeError::DivisionByZero));
+        assert_eq!(integer(" "), Err(ComputeError::EmptyExpression));
+        assert_eq!(to_f64(&BigInt::from(3)), Ok(3.0));
+        assert_eq!(to_f64(&(BigInt::from(1) << 1024)), Err(ComputeError::Overflow));
+    }
+
+    #[test]
+    fn test_selected_through_options() {
+        use crate::{evaluate_with, Session};
+        let options = EvalOptions { arithmetic: Arithmetic::Integer, ..Default::default() };
+        assert_eq!(evaluate_with("(1e16 + 1) - 1e16", &EvalOptions::default()), Ok(0.0));
+        assert_eq!(evaluate_with("(1e16 + 1) - 1e16", &options), Ok(1.0));
+        assert_eq!(evaluate_with("1e308 * 10 / 10", &options), Ok(1e308));
+        assert_eq!(evaluate_with("2 ^ 1024", &options), Err(ComputeError::Overflow));
+        assert!(matches!(evaluate_with("1 / 3", &options), Err(ComputeError::TypeMismatch(_))));
+
+        let mut session = Session::with_options(options);
+        assert_eq!(session.eval("n = 3 * 4"), Ok(12.0));
+        assert!(session.eval("n / 5").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! Hash-consed expression trees, in which identical sub-expressions are stored once
+
+use crate::arena::{Children, ExprArena, Node, NodeId};
+use crate::prelude::*;
+use crate::{AngleMode, Bindings, Expr, Result, RoundingMode};
+use core::cell::RefCell;
+use core::hash::{Hash, Hasher};
+use core::mem;
+
+/// An `ExprArena` in which every distinct sub-tree is stored exactly once
+///
+/// Interning a node that is already present returns its existing `NodeId`,
+/// so two interned trees are structurally equal exactly when their ids are
+/// equal, and a sub-expression repeated anywhere across the trees, such as
+/// `sqrt(x ^ 2 + 1)` occurring in several statements of a program, is one
+/// node that `eval_memoized` evaluates once. Numbers are told apart by
+/// their bits, so `0` and `-0` stay distinct.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    arena: ExprArena,
+    nodes: HashMap<Key, NodeId>,
+    lists: HashMap<Vec<NodeId>, Children>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The arena holding the interned nodes
+    pub fn arena(&self) -> &ExprArena {
+        &self.arena
+    }
+
+    /// Number of distinct nodes interned so far
+    pub fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arena.is_empty()
+    }
+
+    /// Intern `expr` and every sub-tree of it, returning the id of its root
+    pub fn intern(&mut self, expr: &Expr) -> NodeId {
+        let node = match expr {
+            Expr::Number(n) => Node::Number(*n),
+            Expr::Percent(n) => Node::Percent(*n),
+            Expr::Var(name) => Node::Var(self.arena.intern(name)),
+            Expr::Text(text) => Node::Text(self.arena.intern(text)),
+            Expr::Add(l, r) => Node::Add(self.intern(l), self.intern(r)),
+            Expr::Sub(l, r) => Node::Sub(self.intern(l), self.intern(r)),
+            Expr::Mul(l, r) => Node::Mul(self.intern(l), self.intern(r)),
+            Expr::Div(l, r) => Node::Div(self.intern(l), self.intern(r)),
+            Expr::Pow(l, r) => Node::Pow(self.intern(l), self.intern(r)),
+            Expr::And(l, r) => Node::And(self.intern(l), self.intern(r)),
+            Expr::Or(l, r) => Node::Or(self.intern(l), self.intern(r)),
+            Expr::Range(l, r) => Node::Range(self.intern(l), self.intern(r)),
+            Expr::Compare(relation, l, r) => Node::Compare(*relation, self.intern(l), self.intern(r)),
+            Expr::Neg(e) => Node::Neg(self.intern(e)),
+            Expr::Not(e) => Node::Not(self.intern(e)),
+            Expr::If(cond, then, otherwise) => Node::If(self.intern(cond), self.intern(then), self.intern(otherwise)),
+            Expr::Call(name, args) => {
+                let ids: Vec<NodeId> = args.iter().map(|arg| self.intern(arg)).collect();
+                Node::Call(self.arena.intern(name), self.intern_list(ids))
+            }
+            Expr::List(elements) => {
+                let ids: Vec<NodeId> = elements.iter().map(|element| self.intern(element)).collect();
+                Node::List(self.intern_list(ids))
+            }
+        };
+        *self.nodes.entry(Key(node)).or_insert_with(|| self.arena.push(node))
+    }
+
+    /// Store a list of children once, so nodes with the same arguments compare equal
+    fn intern_list(&mut self, ids: Vec<NodeId>) -> Children {
+        if let Some(&children) = self.lists.get(&ids) {
+            return children;
+        }
+        let children = self.arena.push_children(&ids);
+        self.lists.insert(ids, children);
+        children
+    }
+
+    /// The interned tree rooted at `id` as a boxed `Expr`
+    pub fn to_expr(&self, id: NodeId) -> Expr {
+        self.arena.to_expr(id)
+    }
+
+    /// Evaluate each of `roots` in `env`, evaluating every shared sub-expression at most once
+    ///
+    /// Each node's value is remembered the first time evaluation reaches it
+    /// and reused wherever else it occurs, in the same root or another one.
+    /// Only the branches `eval_expr_in` would take are evaluated, so the
+    /// results, errors included, are exactly those of evaluating each root
+    /// on its own.
+    pub fn eval_memoized(&self, roots: &[NodeId], env: &Bindings, angle_mode: AngleMode) -> Vec<Result<f64>> {
+        let memo = RefCell::new(vec![None; self.len()]);
+        roots.iter().map(|&root| self.eval_with(root, env, angle_mode, &memo)).collect()
+    }
+
+    fn eval_with(
+        &self,
+        id: NodeId,
+        env: &Bindings,
+        angle_mode: AngleMode,
+        memo: &RefCell<Vec<Option<Result<f64>>>>,
+    ) -> Result<f64> {
+        let slot = self.arena.index(id);
+        if let Some(value) = &memo.borrow()[slot] {
+            return value.clone();
+        }
+        let eval = |child| self.eval_with(child, env, angle_mode, memo);
+        let value = self.arena.eval_node(id, env, angle_mode, RoundingMode::default(), &eval);
+        memo.borrow_mut()[slot] = Some(value.clone());
+        value
+    }
+}
+
+/// A node compared and hashed by the bits of its numbers, so every node is equal to itself
+#[derive(Debug, Clone, Copy)]
+struct Key(Node);
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.0, other.0) {
+            (Node::Number(a), Node::Number(b)) | (Node::Percent(a), Node::Percent(b)) => a.to_bits() == b.to_bits(),
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(&self.0).hash(state);
+        match self.0 {
+            Node::Number(n) | Node::Percent(n) => n.to_bits().hash(state),
+            Node::Var(symbol) | Node::Text(symbol) => symbol.hash(state),
+            Node::Neg(e) | Node::Not(e) => e.hash(state),
+            Node::Add(l, r) | Node::Sub(l, r) | Node::Mul(l, r) | Node::Div(l, r) | Node::Pow(l, r) => {
+                (l, r).hash(state)
+            }
+            Node::And(l, r) | Node::Or(l, r) | Node::Range(l, r) => (l, r).hash(state),
+            Node::Compare(relation, l, r) => (relation, l, r).hash(state),
+            Node::If(cond, then, otherwise) => (cond, then, otherwise).hash(state),
+            Node::Call(symbol, children) => (symbol, children).hash(state),
+            Node::List(children) => children.hash(state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval_expr_with_env, parse_expression, ComputeError};
+
+    #[test]
+    fn test_shares_identical_subtrees() {
+        let mut interner = Interner::new();
+        let a = interner.intern(&parse_expression("sqrt(x ^ 2 + 1) / sqrt(x ^ 2 + 1)").unwrap());
+        // sqrt(x ^ 2 + 1) is x, 2, ^, 1, +, sqrt; the division adds one more
+        assert_eq!(interner.len(), 7);
+        let b = interner.intern(&parse_expression("(sqrt((x ^ 2) + 1)) / sqrt(x^2+1)").unwrap());
+        assert_eq!(a, b);
+        assert_eq!(interner.len(), 7);
+        assert_ne!(interner.intern(&parse_expression("sqrt(x ^ 2 + 2)").unwrap()), a);
+        assert_eq!(interner.to_expr(a), parse_expression("sqrt(x ^ 2 + 1) / sqrt(x ^ 2 + 1)").unwrap());
+
+        // Numbers are compared by their bits
+        let zero = interner.intern(&Expr::Number(0.0));
+        assert_ne!(interner.intern(&Expr::Number(-0.0)), zero);
+        assert_eq!(interner.intern(&Expr::Number(f64::NAN)), interner.intern(&Expr::Number(f64::NAN)));
+        assert_ne!(interner.intern(&Expr::Percent(0.0)), zero);
+    }
+
+    #[test]
+    fn test_memoized_evaluation() {
+        let env: Bindings = [("x".to_string(), 3.0)].into_iter().collect();
+        let sources = [
+            "sqrt(x ^ 2 + 16) * 2",
+            "sqrt(x ^ 2 + 16) - max(x, 1, 2, 3, 4)",
+            "x > 5 ? 1 / 0 : sum([x, x, 1]) + 10%",
+            "x < 5 or undefined",
+            "undefined + sqrt(x ^ 2 + 16)",
+        ];
+        let mut interner = Interner::new();
+        let exprs: Vec<Expr> = sources.iter().map(|source| parse_expression(source).unwrap()).collect();
+        let roots: Vec<NodeId> = exprs.iter().map(|expr| interner.intern(expr)).collect();
+        let values = interner.eval_memoized(&roots, &env, AngleMode::Radians);
+        let expected: Vec<_> = exprs.iter().map(|expr| eval_expr_with_env(expr, &env)).collect();
+        assert_eq!(values, expected);
+        assert_eq!(values[4], Err(ComputeError::UndefinedVariable("undefined".into())));
+    }
+}
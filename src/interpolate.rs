@@ -0,0 +1,108 @@
+//! Substitution of `$NAME` environment references into expression text
+
+use crate::prelude::*;
+use crate::{format_number, ComputeError, Result};
+
+/// Replace every `$NAME` or `${NAME}` in `source` with the value of that environment variable
+///
+/// See `interpolate_with` for the rules.
+#[cfg(feature = "std")]
+pub fn interpolate_env(source: &str) -> Result<String> {
+    interpolate_with(source, |name| std::env::var(name).ok())
+}
+
+/// Replace every `$NAME` or `${NAME}` in `source` with the number `lookup` gives for it
+///
+/// Names follow identifier rules. Each value must parse as a finite number;
+/// it is written back in canonical form, parenthesized when negative so that
+/// `2 * $X` stays a product. A missing name is an `UndefinedVariable` error
+/// carrying the `$`-prefixed reference.
+pub fn interpolate_with(source: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(dollar) = rest.find('$') {
+        out.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, consumed) = match after.strip_prefix('{') {
+            Some(braced) => {
+                let end = braced.find('}').ok_or_else(|| {
+                    ComputeError::InvalidStructure("Unterminated `${` reference".to_string())
+                })?;
+                (&braced[..end], end + 2)
+            }
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        let valid = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(ComputeError::InvalidStructure(format!(
+                "`${}` is not a valid variable reference",
+                name
+            )));
+        }
+
+        let text = lookup(name).ok_or_else(|| ComputeError::UndefinedVariable(format!("${}", name)))?;
+        let value = text
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .ok_or_else(|| {
+                ComputeError::InvalidStructure(format!("${} is not a number: {:?}", name, text))
+            })?;
+        if value.is_sign_negative() {
+            out.push_str(&format!("({})", format_number(value)));
+        } else {
+            out.push_str(&format_number(value));
+        }
+        rest = &after[consumed..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "RATE" => Some("0.25".to_string()),
+            "OFFSET" => Some(" -3 ".to_string()),
+            "BUILD_ID" => Some("abc".to_string()),
+            "HUGE" => Some("1e999".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_substitutes_numbers() {
+        assert_eq!(interpolate_with("100 * $RATE", lookup).unwrap(), "100 * 0.25");
+        assert_eq!(interpolate_with("2*${OFFSET}+1", lookup).unwrap(), "2*(-3)+1");
+        assert_eq!(interpolate_with("1 + 2", lookup).unwrap(), "1 + 2");
+        let source = interpolate_with("10 - $OFFSET * 2", lookup).unwrap();
+        assert_eq!(evaluate(&source), Ok(16.0));
+    }
+
+    #[test]
+    fn test_rejects_missing_and_non_numeric_values() {
+        assert_eq!(
+            interpolate_with("$MISSING + 1", lookup),
+            Err(ComputeError::UndefinedVariable("$MISSING".into()))
+        );
+        assert!(matches!(interpolate_with("$BUILD_ID", lookup), Err(ComputeError::InvalidStructure(_))));
+        assert!(matches!(interpolate_with("$HUGE", lookup), Err(ComputeError::InvalidStructure(_))));
+        assert!(matches!(interpolate_with("$ + 1", lookup), Err(ComputeError::InvalidStructure(_))));
+        assert!(matches!(interpolate_with("${RATE", lookup), Err(ComputeError::InvalidStructure(_))));
+        assert!(matches!(interpolate_with("$1", lookup), Err(ComputeError::InvalidStructure(_))));
+    }
+}
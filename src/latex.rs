@@ -0,0 +1,425 @@
+//! Reading LaTeX math input, the notation models and papers most often write formulas in
+
+use crate::prelude::*;
+use crate::{builtin, parse_expression, ComputeError, Expr, Result, DEFAULT_MAX_NESTING};
+use core::iter::Peekable;
+use core::str::Chars;
+
+/// Parse a LaTeX math formula such as `\frac{1}{2} + 3 \cdot 4`
+///
+/// See `latex_to_source` for the subset understood.
+pub fn parse_latex(latex: &str) -> Result<Expr> {
+    parse_expression(&latex_to_source(latex)?)
+}
+
+/// Rewrite a LaTeX math formula as expression source
+///
+/// Surrounding `$`, `$$`, `\(…\)` or `\[…\]` delimiters are dropped. The
+/// subset understood covers `\frac`, `\sqrt` (with an optional index),
+/// `\cdot`, `\times`, `\div`, superscripts, braces and `\left`/`\right`
+/// groups, `|…|` and `\lfloor`/`\lceil` pairs, relations such as `\le` and
+/// `\neq`, `\pi`, `\%`, `\mathrm`/`\operatorname` names and the functions
+/// matching builtins, where `\log` is base ten. A run of letters is one
+/// name, juxtaposed factors multiply, and a command's argument is a braced
+/// group or a single token, so `\frac{1}{2}` needs its braces but `\sqrt 2`
+/// does not. Anything else is an `InvalidStructure` error, and input nested
+/// more than `DEFAULT_MAX_NESTING` levels deep is a `LimitExceeded` one.
+pub fn latex_to_source(latex: &str) -> Result<String> {
+    let mut reader = Reader {
+        chars: strip_delimiters(latex.trim()).chars().peekable(),
+        bars: 0,
+        depth: 0,
+    };
+    let mut out = Output::default();
+    reader.group(&mut out, None)?;
+    Ok(out.text.trim().to_string())
+}
+
+/// `latex` without one pair of math-mode delimiters around it
+fn strip_delimiters(latex: &str) -> &str {
+    for (open, close) in [("$$", "$$"), ("$", "$"), ("\\(", "\\)"), ("\\[", "\\]")] {
+        if let Some(inner) = latex.strip_prefix(open).and_then(|rest| rest.strip_suffix(close)) {
+            return inner.trim();
+        }
+    }
+    latex
+}
+
+/// Expression source under construction
+///
+/// Tracks whether the text ends in a complete operand, so that a factor
+/// following it is joined with an explicit `*`.
+#[derive(Default)]
+struct Output {
+    text: String,
+    after_operand: bool,
+}
+
+impl Output {
+    /// Append a complete operand
+    fn operand(&mut self, text: &str) {
+        self.open(text);
+        self.after_operand = true;
+    }
+
+    /// Append the start of an operand, such as `(` or `sqrt(`
+    fn open(&mut self, text: &str) {
+        if self.after_operand {
+            self.text.push_str(" * ");
+        }
+        self.text.push_str(text);
+        self.after_operand = false;
+    }
+
+    /// Append the end of an operand
+    fn close(&mut self) {
+        self.text.push(')');
+        self.after_operand = true;
+    }
+
+    fn operator(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.after_operand = false;
+    }
+}
+
+struct Reader<'a> {
+    chars: Peekable<Chars<'a>>,
+    /// `|` and `\lvert` groups currently open
+    bars: usize,
+    /// Tokens being converted, each inside the last, which bounds the recursion
+    depth: usize,
+}
+
+impl Reader<'_> {
+    /// Convert input until `closer`, or until the end when there is none
+    fn group(&mut self, out: &mut Output, closer: Option<char>) -> Result<()> {
+        loop {
+            self.skip_space();
+            match (self.chars.peek(), closer) {
+                (None, None) => return Ok(()),
+                (None, Some(closer)) => return Err(invalid(format!("Missing `{}`", closer))),
+                (Some(&c), Some(closer)) if c == closer => {
+                    self.chars.next();
+                    return Ok(());
+                }
+                _ => self.token(out)?,
+            }
+        }
+    }
+
+    /// Convert one token, or a whole group when it opens one
+    fn token(&mut self, out: &mut Output) -> Result<()> {
+        if self.depth == DEFAULT_MAX_NESTING {
+            return Err(ComputeError::LimitExceeded(format!(
+                "the LaTeX nests over the {} level nesting limit",
+                DEFAULT_MAX_NESTING
+            )));
+        }
+        self.depth += 1;
+        let converted = self.convert(out);
+        self.depth -= 1;
+        converted
+    }
+
+    /// Convert the token `token` reads
+    fn convert(&mut self, out: &mut Output) -> Result<()> {
+        let Some(c) = self.chars.next() else {
+            return Err(invalid("Missing an argument".to_string()));
+        };
+        match c {
+            '0'..='9' | '.' => {
+                let mut number = c.to_string();
+                while let Some(&d) = self.chars.peek().filter(|d| d.is_ascii_digit() || **d == '.') {
+                    number.push(d);
+                    self.chars.next();
+                }
+                out.operand(&number);
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut name = c.to_string();
+                while let Some(&l) = self.chars.peek().filter(|l| l.is_ascii_alphabetic()) {
+                    name.push(l);
+                    self.chars.next();
+                }
+                if self.chars.peek() == Some(&'_') {
+                    self.chars.next();
+                    name.push('_');
+                    name.push_str(&self.subscript()?);
+                }
+                self.name(out, &name)?;
+            }
+            '{' | '(' | '[' => {
+                out.open("(");
+                self.group(out, Some(if c == '{' { '}' } else if c == '(' { ')' } else { ']' }))?;
+                out.close();
+            }
+            '}' | ')' | ']' => return Err(invalid(format!("Unmatched `{}`", c))),
+            '|' => self.bar(out),
+            '+' | '-' | '*' | '/' => out.operator(&format!(" {} ", c)),
+            ',' => out.operator(", "),
+            '^' => {
+                out.operator(" ^ ");
+                let exponent = self.argument()?;
+                out.operand(&exponent);
+            }
+            '<' | '>' => {
+                let relation = if self.chars.next_if_eq(&'=').is_some() { format!("{}=", c) } else { c.to_string() };
+                out.operator(&format!(" {} ", relation));
+            }
+            '=' => out.operator(" == "),
+            '%' => out.text.push('%'),
+            '\\' => self.command(out)?,
+            other => return Err(invalid(format!("Unsupported character `{}` in LaTeX", other))),
+        }
+        Ok(())
+    }
+
+    /// Convert the command after a `\`
+    fn command(&mut self, out: &mut Output) -> Result<()> {
+        let mut name = String::new();
+        while let Some(&l) = self.chars.peek().filter(|l| l.is_ascii_alphabetic()) {
+            name.push(l);
+            self.chars.next();
+        }
+        if name.is_empty() {
+            // A single-symbol command such as `\,` or `\%`
+            match self.chars.next() {
+                Some(',' | ';' | ':' | '!' | ' ') => {}
+                Some('%') => out.text.push('%'),
+                Some('{') => out.open("("),
+                Some('}') => out.close(),
+                Some(other) => return Err(invalid(format!("Unsupported LaTeX command `\\{}`", other))),
+                None => return Err(invalid("Trailing `\\`".to_string())),
+            }
+            return Ok(());
+        }
+        match name.as_str() {
+            "quad" | "qquad" => {}
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.argument()?;
+                let denominator = self.argument()?;
+                out.operand(&format!("({} / {})", numerator, denominator));
+            }
+            "sqrt" => {
+                let index = if self.chars.next_if_eq(&'[').is_some() {
+                    let mut index = Output::default();
+                    self.group(&mut index, Some(']'))?;
+                    Some(index.text)
+                } else {
+                    None
+                };
+                let radicand = self.argument()?;
+                match index {
+                    Some(index) => out.operand(&format!("({} ^ (1 / ({})))", radicand, index)),
+                    None => out.operand(&format!("sqrt({})", radicand)),
+                }
+            }
+            "cdot" | "times" | "ast" => out.operator(" * "),
+            "div" => out.operator(" / "),
+            "lt" => out.operator(" < "),
+            "gt" => out.operator(" > "),
+            "le" | "leq" | "leqslant" => out.operator(" <= "),
+            "ge" | "geq" | "geqslant" => out.operator(" >= "),
+            "ne" | "neq" => out.operator(" != "),
+            "land" | "wedge" => out.operator(" and "),
+            "lor" | "vee" => out.operator(" or "),
+            "lnot" | "neg" => out.open("not "),
+            "left" | "right" => {
+                // The delimiter that follows does the grouping; `.` is an empty one
+                self.skip_space();
+                self.chars.next_if_eq(&'.');
+            }
+            "lvert" | "rvert" | "vert" => self.bar(out),
+            "lbrace" => out.open("("),
+            "rbrace" | "rfloor" | "rceil" => out.close(),
+            "lfloor" => out.open("floor("),
+            "lceil" => out.open("ceil("),
+            "pi" => out.operand(&core::f64::consts::PI.to_string()),
+            "mathrm" | "operatorname" | "text" => {
+                self.skip_space();
+                self.expect('{')?;
+                let mut text = String::new();
+                for c in self.chars.by_ref() {
+                    if c == '}' {
+                        return self.name(out, text.trim());
+                    }
+                    text.push(c);
+                }
+                return Err(invalid("Missing `}`".to_string()));
+            }
+            "log" => {
+                if self.chars.next_if_eq(&'_').is_some() {
+                    let base = self.subscript()?;
+                    if base != "10" {
+                        return Err(invalid(format!("Unsupported logarithm base `{}`", base)));
+                    }
+                }
+                self.call(out, "log10")?;
+            }
+            "arcsin" | "arccos" | "arctan" => self.call(out, &format!("a{}", &name[3..]))?,
+            name if builtin(name).is_some() => self.call(out, name)?,
+            other => return Err(invalid(format!("Unsupported LaTeX command `\\{}`", other))),
+        }
+        Ok(())
+    }
+
+    /// Emit `name` as a variable, or as a call when it names a builtin followed by its arguments
+    fn name(&mut self, out: &mut Output, name: &str) -> Result<()> {
+        self.skip_space();
+        if builtin(name).is_some() && (matches!(self.chars.peek(), Some('(' | '{')) || self.at_command("left")) {
+            self.call(out, name)
+        } else if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && !name.is_empty() {
+            out.operand(name);
+            Ok(())
+        } else {
+            Err(invalid(format!("`{}` is not a valid name", name)))
+        }
+    }
+
+    /// Emit a call of `function`, whose arguments are a following group or else its next token
+    fn call(&mut self, out: &mut Output, function: &str) -> Result<()> {
+        self.skip_space();
+        if matches!(self.chars.peek(), Some('(' | '{')) {
+            out.open(function);
+            self.token(out)
+        } else if self.at_command("left") {
+            // `\left` itself, then the delimiter it sizes
+            out.open(function);
+            self.token(out)?;
+            self.token(out)
+        } else {
+            let argument = self.argument()?;
+            out.operand(&format!("{}({})", function, argument));
+            Ok(())
+        }
+    }
+
+    /// Convert a command's argument: a braced group, or else the next single token
+    fn argument(&mut self) -> Result<String> {
+        self.skip_space();
+        let mut argument = Output::default();
+        if self.chars.next_if_eq(&'{').is_some() {
+            argument.open("(");
+            self.group(&mut argument, Some('}'))?;
+            argument.close();
+        } else {
+            self.token(&mut argument)?;
+        }
+        if argument.text.is_empty() {
+            return Err(invalid("Missing an argument".to_string()));
+        }
+        Ok(argument.text)
+    }
+
+    /// The text of a subscript after `_`: a braced run of letters and digits or a single one
+    fn subscript(&mut self) -> Result<String> {
+        let text: String = if self.chars.next_if_eq(&'{').is_some() {
+            let text = self.chars.by_ref().take_while(|&c| c != '}').collect();
+            text
+        } else {
+            self.chars.next().into_iter().collect()
+        };
+        let text = text.trim().to_string();
+        if text.is_empty() || !text.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(invalid(format!("Unsupported subscript `{}`", text)));
+        }
+        Ok(text)
+    }
+
+    /// An absolute-value bar, which closes an open group once it has an operand and opens one otherwise
+    fn bar(&mut self, out: &mut Output) {
+        if self.bars > 0 && out.after_operand {
+            self.bars -= 1;
+            out.close();
+        } else {
+            self.bars += 1;
+            out.open("abs(");
+        }
+    }
+
+    /// Whether the input continues with `\command`
+    fn at_command(&self, command: &str) -> bool {
+        let mut ahead = self.chars.clone();
+        ahead.next() == Some('\\') && ahead.take(command.len()).eq(command.chars())
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        match self.chars.next() {
+            Some(next) if next == c => Ok(()),
+            _ => Err(invalid(format!("Missing `{}`", c))),
+        }
+    }
+
+    fn skip_space(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+}
+
+fn invalid(message: String) -> ComputeError {
+    ComputeError::InvalidStructure(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate;
+
+    fn value(latex: &str) -> f64 {
+        crate::eval_expr(&parse_latex(latex).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_latex_to_source() {
+        assert_eq!(latex_to_source("\\frac{1}{2} + 3 \\cdot 4").unwrap(), "((1) / (2)) + 3 * 4");
+        assert_eq!(latex_to_source("$x^{2} \\le 10\\%$").unwrap(), "x ^ (2) <= 10%");
+        assert_eq!(latex_to_source("2x_{1}\\left(y + 1\\right)").unwrap(), "2 * x_1 * (y + 1)");
+        assert_eq!(latex_to_source("\\sin(x) + \\mathrm{rate}").unwrap(), "sin(x) + rate");
+        assert_eq!(latex_to_source("\\max(1, 2)").unwrap(), "max(1, 2)");
+    }
+
+    #[test]
+    fn test_parse_latex_values() {
+        assert_eq!(value("\\frac{1}{2} + 3 \\cdot 4"), 12.5);
+        assert_eq!(value("$$\\dfrac{6}{\\frac{1}{2}}$$"), 12.0);
+        assert_eq!(value("\\sqrt{16} \\times 2^{3}"), 32.0);
+        assert_eq!(value("\\sqrt[3]{27}"), 3.0);
+        assert_eq!(value("\\left| 3 - 5 \\right| + |-1|"), 3.0);
+        assert_eq!(value("2|-3|"), 6.0);
+        assert_eq!(value("\\lfloor 2.7 \\rfloor + \\lceil 0.2 \\rceil"), 3.0);
+        assert_eq!(value("\\log_{10} 1000 + \\ln{1}"), 3.0);
+        assert_eq!(value("\\sqrt 9 \\div 3"), 1.0);
+        assert_eq!(value("2\\pi"), 2.0 * core::f64::consts::PI);
+        assert_eq!(value("\\cos 0 \\neq 1"), 0.0);
+        assert_eq!(value("\\(3 \\geq 2 \\land 1 \\le 2\\)"), 1.0);
+        assert_eq!(evaluate(&latex_to_source("\\arctan(1) \\cdot 4").unwrap()), Ok(core::f64::consts::PI));
+    }
+
+    #[test]
+    fn test_unsupported_latex() {
+        for (latex, message) in [
+            ("\\int_0^1 x", "Unsupported LaTeX command `\\int`"),
+            ("\\frac{1}", "Missing an argument"),
+            ("\\frac{1}{2", "Missing `}`"),
+            ("1 + 2}", "Unmatched `}`"),
+            ("\\log_2 8", "Unsupported logarithm base `2`"),
+            ("3 & 4", "Unsupported character `&` in LaTeX"),
+        ] {
+            assert_eq!(latex_to_source(latex), Err(ComputeError::InvalidStructure(message.to_string())), "{}", latex);
+        }
+    }
+
+    #[test]
+    fn test_deep_latex_is_rejected() {
+        let shapes: [fn(usize) -> String; 3] = [
+            |n| format!("{}1{}", "(".repeat(n), ")".repeat(n)),
+            |n| format!("{}1{}", "\\frac{1}{".repeat(n), "}".repeat(n)),
+            |n| format!("{}4", "\\sqrt ".repeat(n)),
+        ];
+        for shape in shapes {
+            let deep = shape(100_000);
+            assert!(matches!(parse_latex(&deep), Err(ComputeError::LimitExceeded(_))), "{}", &deep[..8]);
+            assert!(parse_latex(&shape(20)).is_ok());
+        }
+    }
+}
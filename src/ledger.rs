@@ -0,0 +1,215 @@
+//! A calculation ledger that can be persisted to a rotating JSONL file
+
+use crate::HistoryEntry;
+use crate::prelude::*;
+use alloc::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Recent calculations, optionally appended to a JSONL file that survives restarts
+///
+/// Memory holds at most `capacity` entries, oldest dropped first. When backed
+/// by a file, every entry is also written as one JSON line; once the file
+/// would grow past `max_bytes` it is renamed to `<path>.1`, replacing any
+/// earlier rotation, and a fresh file is started.
+#[derive(Debug)]
+pub struct Ledger {
+    capacity: usize,
+    entries: VecDeque<HistoryEntry>,
+    file: Option<LedgerFile>,
+}
+
+#[derive(Debug)]
+struct LedgerFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    max_bytes: u64,
+}
+
+impl Ledger {
+    /// A ledger kept only in memory
+    pub fn new(capacity: usize) -> Self {
+        Ledger {
+            capacity,
+            entries: VecDeque::new(),
+            file: None,
+        }
+    }
+
+    /// Open or create the ledger file at `path`, loading its most recent entries
+    ///
+    /// Lines that are not valid entries, such as one cut short by a crash,
+    /// are skipped.
+    pub fn open(path: impl Into<PathBuf>, capacity: usize, max_bytes: u64) -> io::Result<Self> {
+        let path = path.into();
+        let mut ledger = Ledger::new(capacity);
+        let mut complete = true;
+        if path.exists() {
+            let mut reader = BufReader::new(File::open(&path)?);
+            let mut line = String::new();
+            while reader.read_line(&mut line)? > 0 {
+                complete = line.ends_with('\n');
+                if let Ok(entry) = serde_json::from_str(&line) {
+                    ledger.remember(entry);
+                }
+                line.clear();
+            }
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if !complete {
+            // Keep the next entry off the truncated line
+            file.write_all(b"\n")?;
+        }
+        ledger.file = Some(LedgerFile {
+            len: file.metadata()?.len(),
+            path,
+            file,
+            max_bytes,
+        });
+        Ok(ledger)
+    }
+
+    /// Add an entry, appending it to the file if there is one
+    pub fn record(&mut self, entry: HistoryEntry) -> io::Result<()> {
+        if let Some(file) = self.file.as_mut() {
+            let mut line = serde_json::to_string(&entry)?;
+            line.push('\n');
+            file.append(line.as_bytes())?;
+        }
+        self.remember(entry);
+        Ok(())
+    }
+
+    /// Entries in memory, oldest first
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Forget every entry, truncating the file and deleting its rotation
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        if let Some(file) = self.file.as_mut() {
+            file.file.set_len(0)?;
+            file.len = 0;
+            match fs::remove_file(rotated_path(&file.path)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn remember(&mut self, entry: HistoryEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+}
+
+impl LedgerFile {
+    fn append(&mut self, line: &[u8]) -> io::Result<()> {
+        let len = line.len() as u64;
+        if self.len > 0 && self.len + len > self.max_bytes {
+            fs::rename(&self.path, rotated_path(&self.path))?;
+            self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+            self.len = 0;
+        }
+        self.file.write_all(line)?;
+        self.file.flush()?;
+        self.len += len;
+        Ok(())
+    }
+}
+
+/// Where `path` is moved when it fills up
+pub fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(input: &str, value: f64) -> HistoryEntry {
+        HistoryEntry {
+            input: input.to_string(),
+            result: Ok(value),
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("compute-ledger-{}-{}.jsonl", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(rotated_path(&path));
+        path
+    }
+
+    #[test]
+    fn test_memory_capacity() {
+        let mut ledger = Ledger::new(2);
+        for i in 0..3 {
+            ledger.record(entry("x", i as f64)).unwrap();
+        }
+        let values: Vec<_> = ledger.entries().map(|e| e.result.clone().unwrap()).collect();
+        assert_eq!(values, vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_survives_reopen() {
+        let path = temp_path("reopen");
+        let mut ledger = Ledger::open(&path, 10, 1 << 20).unwrap();
+        ledger.record(entry("1 + 1", 2.0)).unwrap();
+        ledger
+            .record(HistoryEntry {
+                input: "1 / 0".to_string(),
+                result: Err("Division by zero".to_string()),
+            })
+            .unwrap();
+        drop(ledger);
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"inpu").unwrap();
+
+        let mut reopened = Ledger::open(&path, 10, 1 << 20).unwrap();
+        assert_eq!(reopened.len(), 2);
+        assert_eq!(reopened.entries().next(), Some(&entry("1 + 1", 2.0)));
+        reopened.record(entry("3", 3.0)).unwrap();
+        assert_eq!(Ledger::open(&path, 10, 1 << 20).unwrap().len(), 3);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_and_clear() {
+        let path = temp_path("rotate");
+        let line_len = serde_json::to_string(&entry("1", 1.0)).unwrap().len() as u64 + 1;
+        let mut ledger = Ledger::open(&path, 10, line_len * 2).unwrap();
+        for _ in 0..3 {
+            ledger.record(entry("1", 1.0)).unwrap();
+        }
+        assert_eq!(fs::read_to_string(rotated_path(&path)).unwrap().lines().count(), 2);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        assert_eq!(ledger.len(), 3);
+
+        ledger.clear().unwrap();
+        assert!(ledger.is_empty());
+        assert!(!rotated_path(&path).exists());
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        ledger.record(entry("2", 2.0)).unwrap();
+        assert_eq!(Ledger::open(&path, 10, 1 << 20).unwrap().len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+}
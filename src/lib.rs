@@ -1,7 +1,113 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use crate::clock::Stopwatch;
+use crate::compensated::eval_compensated_rounding;
+use crate::compile::compile_rounding;
+use crate::fuel::eval_with_fuel_rounding;
+use crate::integer::eval_integer_rounding;
+use crate::prelude::*;
+use crate::uncertain::eval_uncertain_rounding;
+use crate::verify::{is_exact_rounding, verify_rounding};
 use pest::Parser;
 use pest_derive::Parser;
+use serde::{Deserialize, Serialize};
 use pest::pratt_parser::{Assoc, Op, PrattParser};
-use std::fmt;
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use core::cmp::Ordering;
+use core::fmt;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::io::{self, BufRead};
+
+pub mod arena;
+pub mod bulk;
+pub mod cache;
+mod clock;
+pub mod compare;
+pub mod compensated;
+pub mod compile;
+#[cfg(feature = "descent")]
+pub mod descent;
+pub mod diagnostics;
+pub mod equiv;
+pub mod evaluator;
+pub mod explain;
+mod fast_path;
+pub mod fixed;
+pub mod fuel;
+pub mod format;
+pub mod functions;
+pub mod generate;
+pub mod integer;
+pub mod intern;
+pub mod interpolate;
+pub mod latex;
+#[cfg(feature = "std")]
+pub mod ledger;
+pub mod number_format;
+mod prelude;
+pub mod program;
+pub mod radix;
+pub mod render;
+pub mod report;
+pub mod rounding;
+pub mod rpn;
+pub mod session;
+pub mod sheet;
+pub mod simplify;
+pub mod spans;
+pub mod suggest;
+pub mod token;
+pub mod trace;
+pub mod uncertain;
+pub mod units;
+pub mod verify;
+pub mod visit;
+
+pub use arena::{evaluate_batch_arena, ExprArena, Node, NodeId};
+pub use bulk::{evaluate_many, Bindings};
+pub use cache::{CacheStats, LruCache};
+pub use compare::{approx_eq, compare, ulps_between, ApproxMode, Comparison};
+pub use compensated::eval_compensated;
+pub use compile::{compile, compile_in, CompiledExpr};
+pub use diagnostics::{diagnose, parse_recovering, Diagnostic, Severity};
+pub use equiv::{equivalent, semantically_eq, Counterexample, Equivalence};
+pub use evaluator::{Evaluator, OpKind, Operation, DEFAULT_PARSE_CACHE};
+pub use explain::explain_evaluation;
+pub use fixed::{evaluate_fixed, Fixed, OverflowMode, QFormat};
+pub use fuel::eval_with_fuel;
+pub use format::{format_source, to_prefix, to_sexpr, to_source};
+pub use functions::{builtin, AngleMode, Builtin, BUILTINS};
+pub use generate::{ExprGenerator, GeneratedExpr, GeneratorConfig};
+pub use integer::{eval_integer, evaluate_integer, Arithmetic};
+pub use intern::Interner;
+#[cfg(feature = "std")]
+pub use interpolate::interpolate_env;
+pub use interpolate::interpolate_with;
+pub use latex::{latex_to_source, parse_latex};
+#[cfg(feature = "std")]
+pub use ledger::Ledger;
+pub use number_format::{Notation, NumberFormat, MAX_FORMAT_DIGITS};
+pub use program::{evaluate_program, evaluate_program_with, parse_program, Program, Statement};
+pub use radix::convert_base;
+pub use render::{to_latex, to_mathml};
+pub use report::{evaluate_batch_report, BatchReport, BatchSummary};
+pub use rounding::RoundingMode;
+pub use rpn::{evaluate_rpn, parse_rpn};
+pub use session::{HistoryEntry, Session};
+pub use sheet::{evaluate_sheet, evaluate_sheet_with};
+pub use simplify::{simplify, simplify_with_steps, Rewrite, RewriteRule};
+pub use spans::{evaluate_spanned, parse_spanned, SpanTree, Spanned, SpannedError};
+pub use suggest::{closest_name, hint, suggest_fix, Suggestion};
+pub use token::{classify, tokenize, Token, TokenClass, TokenKind};
+pub use trace::{eval_expr_traced, EvalEvent, NodeKind};
+pub use uncertain::{eval_uncertain, evaluate_uncertain, Uncertain};
+pub use units::{convert, Dimension, Unit, UNITS};
+pub use verify::{eval_exact, is_exact, verify, Verification};
+pub use visit::{walk_expr, walk_fold, Folder, Visitor};
 
 #[derive(Parser)]
 #[grammar = "compute.pest"]
@@ -11,34 +117,455 @@ pub struct ComputeParser;
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Number(f64),
+    /// `n%`, worth `n / 100`; as the right operand of `+` or `-` it is a percentage of the left operand
+    Percent(f64),
+    Var(String),
     Add(Box<Expr>, Box<Expr>),
     Sub(Box<Expr>, Box<Expr>),
     Mul(Box<Expr>, Box<Expr>),
     Div(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
     Neg(Box<Expr>),
+    /// A call of the named function, which need not be a known built-in
+    Call(String, Vec<Expr>),
+    /// A comparison, which is 1 when the relation holds and 0 otherwise
+    Compare(Relation, Box<Expr>, Box<Expr>),
+    /// `cond ? then : else`; only the chosen branch is evaluated
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// `a and b`, which is 1 when both are true; `b` is evaluated only when `a` is true
+    And(Box<Expr>, Box<Expr>),
+    /// `a or b`, which is 1 when either is true; `b` is evaluated only when `a` is false
+    Or(Box<Expr>, Box<Expr>),
+    /// `not a`, which is 1 when `a` is false and 0 otherwise
+    Not(Box<Expr>),
+    /// `[a, b, ...]`, valid only as the argument of an aggregate function or as a whole expression
+    List(Vec<Expr>),
+    /// `lo..hi`, the numbers from `lo` up to `hi` in steps of 1, valid only as the argument of an aggregate function
+    Range(Box<Expr>, Box<Expr>),
+    /// `"text"`, valid only as a unit name in `convert`
+    Text(String),
+}
+
+/// How a comparison relates its two operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Relation {
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Equal,
+    NotEqual,
+}
+
+impl Relation {
+    /// The operator as written in source
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Relation::Less => "<",
+            Relation::LessEq => "<=",
+            Relation::Greater => ">",
+            Relation::GreaterEq => ">=",
+            Relation::Equal => "==",
+            Relation::NotEqual => "!=",
+        }
+    }
+
+    /// Whether `l` and `r` are related; NaN is unordered, so only `!=` holds for it
+    pub fn holds(self, l: f64, r: f64) -> bool {
+        self.accepts(l.partial_cmp(&r))
+    }
+
+    /// Whether operands ordered as `ordering` are related, with `None` for unordered operands
+    pub fn accepts(self, ordering: Option<Ordering>) -> bool {
+        use Ordering::*;
+        match (self, ordering) {
+            (Relation::NotEqual, None) => true,
+            (_, None) => false,
+            (Relation::Less, Some(o)) => o == Less,
+            (Relation::LessEq, Some(o)) => o != Greater,
+            (Relation::Greater, Some(o)) => o == Greater,
+            (Relation::GreaterEq, Some(o)) => o != Less,
+            (Relation::Equal, Some(o)) => o == Equal,
+            (Relation::NotEqual, Some(o)) => o != Equal,
+        }
+    }
+
+    /// The value of the comparison: 1 when it holds, 0 otherwise
+    pub fn eval(self, l: f64, r: f64) -> f64 {
+        truth_value(self.holds(l, r))
+    }
+
+    /// The relation written as `symbol` in source
+    pub(crate) fn from_symbol(symbol: &str) -> Option<Self> {
+        [Relation::Less, Relation::LessEq, Relation::Greater, Relation::GreaterEq, Relation::Equal, Relation::NotEqual]
+            .into_iter()
+            .find(|relation| relation.symbol() == symbol)
+    }
+
+    pub(crate) fn from_rule(rule: Rule) -> Option<Self> {
+        match rule {
+            Rule::less => Some(Relation::Less),
+            Rule::less_eq => Some(Relation::LessEq),
+            Rule::greater => Some(Relation::Greater),
+            Rule::greater_eq => Some(Relation::GreaterEq),
+            Rule::equal => Some(Relation::Equal),
+            Rule::not_equal => Some(Relation::NotEqual),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a condition selects the first branch: any value other than zero and NaN
+pub fn is_true(condition: f64) -> bool {
+    condition != 0.0 && !condition.is_nan()
+}
+
+/// `a + p%`: `a` raised by `p` percent of itself, or lowered for negative `p`
+pub fn add_percent(a: f64, p: f64) -> f64 {
+    a + a * p / 100.0
+}
+
+/// The number a comparison or logical operator gives for `holds`: 1 or 0
+pub fn truth_value(holds: bool) -> f64 {
+    if holds {
+        1.0
+    } else {
+        0.0
+    }
 }
 
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        enum Item<'a> {
+            Expr(&'a Expr),
+            Text(&'static str),
+        }
+
+        // Walk with an explicit stack so very deep trees cannot overflow the call stack
+        let mut stack = vec![Item::Expr(self)];
+        while let Some(item) = stack.pop() {
+            let (l, op, r) = match item {
+                Item::Text(text) => {
+                    f.write_str(text)?;
+                    continue;
+                }
+                Item::Expr(Expr::Number(n)) => {
+                    f.write_str(&format_number(*n))?;
+                    continue;
+                }
+                Item::Expr(Expr::Percent(n)) => {
+                    write!(f, "{}%", format_number(*n))?;
+                    continue;
+                }
+                Item::Expr(Expr::Var(name)) => {
+                    f.write_str(name)?;
+                    continue;
+                }
+                Item::Expr(Expr::Text(text)) => {
+                    write!(f, "\"{}\"", text)?;
+                    continue;
+                }
+                Item::Expr(Expr::Neg(e)) => {
+                    stack.extend([Item::Text(")"), Item::Expr(e), Item::Text("-(")]);
+                    continue;
+                }
+                Item::Expr(Expr::Not(e)) => {
+                    stack.extend([Item::Text(")"), Item::Expr(e), Item::Text("(not ")]);
+                    continue;
+                }
+                Item::Expr(Expr::Call(name, args)) => {
+                    f.write_str(name)?;
+                    stack.push(Item::Text(")"));
+                    for (i, arg) in args.iter().enumerate().rev() {
+                        stack.push(Item::Expr(arg));
+                        if i > 0 {
+                            stack.push(Item::Text(", "));
+                        }
+                    }
+                    stack.push(Item::Text("("));
+                    continue;
+                }
+                Item::Expr(Expr::Range(lo, hi)) => {
+                    // Unparenthesized, since a range is only valid as a whole call argument
+                    stack.extend([Item::Expr(hi), Item::Text(".."), Item::Expr(lo)]);
+                    continue;
+                }
+                Item::Expr(Expr::List(elements)) => {
+                    f.write_str("[")?;
+                    stack.push(Item::Text("]"));
+                    for (i, element) in elements.iter().enumerate().rev() {
+                        stack.push(Item::Expr(element));
+                        if i > 0 {
+                            stack.push(Item::Text(", "));
+                        }
+                    }
+                    continue;
+                }
+                Item::Expr(Expr::Compare(relation, l, r)) => {
+                    stack.extend([Item::Text(")"), Item::Expr(r), Item::Text(" ")]);
+                    stack.extend([Item::Text(relation.symbol()), Item::Text(" "), Item::Expr(l), Item::Text("(")]);
+                    continue;
+                }
+                Item::Expr(Expr::If(cond, then, otherwise)) => {
+                    stack.extend([Item::Text(")"), Item::Expr(otherwise), Item::Text(" : ")]);
+                    stack.extend([Item::Expr(then), Item::Text(" ? "), Item::Expr(cond), Item::Text("(")]);
+                    continue;
+                }
+                Item::Expr(Expr::Add(l, r)) => (l, " + ", r),
+                Item::Expr(Expr::Sub(l, r)) => (l, " - ", r),
+                Item::Expr(Expr::Mul(l, r)) => (l, " * ", r),
+                Item::Expr(Expr::Div(l, r)) => (l, " / ", r),
+                Item::Expr(Expr::Pow(l, r)) => (l, " ^ ", r),
+                Item::Expr(Expr::And(l, r)) => (l, " and ", r),
+                Item::Expr(Expr::Or(l, r)) => (l, " or ", r),
+            };
+            stack.extend([
+                Item::Text(")"),
+                Item::Expr(r),
+                Item::Text(op),
+                Item::Expr(l),
+                Item::Text("("),
+            ]);
+        }
+        Ok(())
+    }
+}
+
+/// Shortest text for `n` that the parser reads back as the bit-identical value
+///
+/// Digits come from the Ryū algorithm, so output is identical on every
+/// platform; integral values drop Ryū's trailing `.0` (`42`, not `42.0`).
+/// Very large and very small magnitudes use scientific notation (`1e300`).
+/// Negative values, including `-0.0`, are written with a leading `-`, which
+/// the parser applies as a negation of the magnitude. Non-finite values have
+/// no literal syntax and print as `inf`, `-inf` or `NaN`.
+pub fn format_number(n: f64) -> String {
+    if !n.is_finite() {
+        return n.to_string();
+    }
+    let mut buffer = ryu::Buffer::new();
+    let formatted = buffer.format_finite(n);
+    formatted.strip_suffix(".0").unwrap_or(formatted).to_string()
+}
+
+impl Drop for Expr {
+    fn drop(&mut self) {
+        // Detach children onto a heap stack so freeing deep trees never recurses
+        let mut stack = Vec::new();
+        self.detach_children(&mut stack);
+        while let Some(mut expr) = stack.pop() {
+            expr.detach_children(&mut stack);
+        }
+    }
+}
+
+impl Expr {
+    /// The canonical form `semantically_eq` compares: like terms combined, constants folded and
+    /// commutative operands in a fixed order
+    pub fn canonicalize(&self) -> Expr {
+        equiv::canonical(self)
+    }
+
+    /// The expression as an S-expression such as `(+ 1 (* 2 x))`, see `format::to_sexpr`
+    pub fn to_sexpr(&self) -> String {
+        format::to_sexpr(self)
+    }
+
+    /// The expression in Polish notation such as `+ 1 * 2 x`, see `format::to_prefix`
+    pub fn to_prefix(&self) -> String {
+        format::to_prefix(self)
+    }
+
+    /// Levels in the tree, a lone number or variable being 1
+    pub fn depth(&self) -> usize {
+        let mut depth = 0;
+        self.visit(|_, level| depth = depth.max(level));
+        depth
+    }
+
+    /// Nodes in the tree, leaves included
+    pub fn node_count(&self) -> usize {
+        let mut nodes = 0;
+        self.visit(|_, _| nodes += 1);
+        nodes
+    }
+
+    /// How often each operator occurs, by the names `to_sexpr` gives them, such as `+`, `neg` or `max`
+    ///
+    /// Numbers, variables and text are not operators and are not counted.
+    pub fn operator_histogram(&self) -> BTreeMap<String, usize> {
+        let mut histogram = BTreeMap::new();
+        self.visit(|expr, _| {
+            if let Some(head) = format::head(expr) {
+                *histogram.entry(head.to_string()).or_insert(0) += 1;
+            }
+        });
+        histogram
+    }
+
+    /// Print the expression so that parsing the output yields this exact tree
+    ///
+    /// `Display` only guarantees numeric equivalence: a negative literal such
+    /// as `Number(-5.0)` prints as `-5`, which reparses as `Neg(Number(5.0))`.
+    /// The grammar has no syntax for negative, negative-zero or non-finite
+    /// literals, so trees containing them are rejected here instead of being
+    /// silently printed in a form that reparses to a different structure.
+    pub fn to_exact_string(&self) -> Result<String> {
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Expr::Number(n) | Expr::Percent(n) if !n.is_finite() || n.is_sign_negative() => {
+                    return Err(ComputeError::InvalidStructure(format!(
+                        "Literal {} has no exact textual form",
+                        format_number(*n)
+                    )));
+                }
+                Expr::Text(text) if text.contains('"') => {
+                    return Err(ComputeError::InvalidStructure(format!("Text {:?} has no exact textual form", text)));
+                }
+                Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_) => {}
+                Expr::Add(l, r) | Expr::Sub(l, r) | Expr::Mul(l, r) | Expr::Div(l, r) | Expr::Pow(l, r) => {
+                    stack.push(l);
+                    stack.push(r);
+                }
+                Expr::Compare(_, l, r) | Expr::And(l, r) | Expr::Or(l, r) | Expr::Range(l, r) => {
+                    stack.push(l);
+                    stack.push(r);
+                }
+                Expr::Neg(e) | Expr::Not(e) => stack.push(e),
+                Expr::Call(_, args) | Expr::List(args) => stack.extend(args),
+                Expr::If(cond, then, otherwise) => stack.extend([&**cond, then, otherwise]),
+            }
+        }
+        // Display parenthesizes every operation, so only literals needed checking
+        Ok(self.to_string())
+    }
+
+    /// For `a + p%` and `a - p%`, the left operand `a` and the signed percentage `p` or `-p`
+    pub(crate) fn percent_change(&self) -> Option<(&Expr, f64)> {
+        match self {
+            Expr::Add(a, r) => match **r {
+                Expr::Percent(p) => Some((a, p)),
+                _ => None,
+            },
+            Expr::Sub(a, r) => match **r {
+                Expr::Percent(p) => Some((a, -p)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Move non-leaf children onto `stack`, leaving cheap leaves in their place
+    fn detach_children(&mut self, stack: &mut Vec<Expr>) {
+        let mut detach = |child: &mut Expr| {
+            if !matches!(child, Expr::Number(_)) {
+                stack.push(core::mem::replace(child, Expr::Number(0.0)));
+            }
+        };
         match self {
-            Expr::Number(n) => write!(f, "{}", n),
-            Expr::Add(l, r) => write!(f, "({} + {})", l, r),
-            Expr::Sub(l, r) => write!(f, "({} - {})", l, r),
-            Expr::Mul(l, r) => write!(f, "({} * {})", l, r),
-            Expr::Div(l, r) => write!(f, "({} / {})", l, r),
-            Expr::Neg(e) => write!(f, "-({})", e),
+            Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_) => {}
+            Expr::Add(l, r) | Expr::Sub(l, r) | Expr::Mul(l, r) | Expr::Div(l, r) | Expr::Pow(l, r) => {
+                detach(l);
+                detach(r);
+            }
+            Expr::Compare(_, l, r) | Expr::And(l, r) | Expr::Or(l, r) | Expr::Range(l, r) => {
+                detach(l);
+                detach(r);
+            }
+            Expr::Neg(e) | Expr::Not(e) => detach(e),
+            Expr::Call(_, args) | Expr::List(args) => args.iter_mut().for_each(detach),
+            Expr::If(cond, then, otherwise) => {
+                detach(cond);
+                detach(then);
+                detach(otherwise);
+            }
         }
     }
+
+    /// Call `visit` on every node with its depth, the root being at depth 1, using an explicit stack
+    pub(crate) fn visit<'a>(&'a self, mut visit: impl FnMut(&'a Expr, usize)) {
+        let mut stack = vec![(self, 1)];
+        while let Some((expr, depth)) = stack.pop() {
+            visit(expr, depth);
+            let depth = depth + 1;
+            match expr {
+                Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_) => {}
+                Expr::Add(l, r) | Expr::Sub(l, r) | Expr::Mul(l, r) | Expr::Div(l, r) | Expr::Pow(l, r) => {
+                    stack.extend([(&**l, depth), (&**r, depth)]);
+                }
+                Expr::Compare(_, l, r) | Expr::And(l, r) | Expr::Or(l, r) | Expr::Range(l, r) => {
+                    stack.extend([(&**l, depth), (&**r, depth)]);
+                }
+                Expr::Neg(e) | Expr::Not(e) => stack.push((e, depth)),
+                Expr::Call(_, args) | Expr::List(args) => stack.extend(args.iter().map(|arg| (arg, depth))),
+                Expr::If(cond, then, otherwise) => stack.extend([(&**cond, depth), (then, depth), (otherwise, depth)]),
+            }
+        }
+    }
+}
+
+/// Byte range `start..end` within a source string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Whether the byte offset `pos` falls inside the span
+    pub fn contains(&self, pos: usize) -> bool {
+        self.start <= pos && pos < self.end
+    }
+
+    /// The text covered by the span
+    pub fn slice<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+}
+
+impl From<pest::Span<'_>> for Span {
+    fn from(span: pest::Span<'_>) -> Self {
+        Span::new(span.start(), span.end())
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
 }
 
 /// Error types for expression evaluation
 #[derive(Debug, Clone, PartialEq)]
 pub enum ComputeError {
     ParseError(Box<pest::error::Error<Rule>>),
-    InvalidNumber(std::num::ParseFloatError),
+    InvalidNumber(core::num::ParseFloatError),
+    /// A divisor is zero of either sign
+    ///
+    /// IEEE 754 would divide by `-0` to give an infinity of the opposite
+    /// sign, but every evaluator treats `-0` exactly like `0`, so `1 / -0`
+    /// and `1 / (0 * -1)` fail just as `1 / 0` does. Only `evaluate_many`,
+    /// which reports failed rows as `NaN`, gives `NaN` for both instead.
     DivisionByZero,
+    Overflow,
+    UndefinedVariable(String),
+    /// A call names a function that is not built in
+    UnknownFunction(String),
     InvalidStructure(String),
     EmptyExpression,
+    /// A configured resource limit was exceeded; the message names the limit
+    LimitExceeded(String),
+    /// The input is longer than `ParseOptions::max_expression_len` bytes
+    ExpressionTooLong { len: usize, max: usize },
+    /// A list where a number is needed, or a number where a list is
+    TypeMismatch(String),
+    /// The result is NaN, reported instead of the value under `EvalOptions::strict_finite`
+    NotANumber,
 }
 
 impl fmt::Display for ComputeError {
@@ -47,15 +574,627 @@ impl fmt::Display for ComputeError {
             Self::ParseError(e) => write!(f, "{}", e),
             Self::InvalidNumber(e) => write!(f, "{}", e),
             Self::DivisionByZero => write!(f, "Division by zero"),
+            Self::Overflow => write!(f, "Numeric overflow"),
+            Self::UndefinedVariable(name) => write!(f, "Undefined variable: {}", name),
+            Self::UnknownFunction(name) => write!(f, "Unknown function: {}", name),
             Self::InvalidStructure(msg) => write!(f, "{}", msg),
             Self::EmptyExpression => write!(f, "Empty expression"),
+            Self::LimitExceeded(limit) => write!(f, "Limit exceeded: {}", limit),
+            Self::ExpressionTooLong { len, max } => {
+                write!(f, "Expression is {} bytes, over the {} byte limit", len, max)
+            }
+            Self::TypeMismatch(msg) => write!(f, "Type mismatch: {}", msg),
+            Self::NotANumber => write!(f, "Result is not a number"),
+        }
+    }
+}
+
+impl core::error::Error for ComputeError {}
+
+/// Broad class of a `ComputeError`, for callers that handle whole groups of errors alike
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorCategory {
+    /// The input is not a well-formed expression
+    Syntax,
+    /// The expression is well formed but its arithmetic fails
+    Arithmetic,
+    /// A variable or function name is not defined
+    Name,
+    /// A value of the wrong kind, such as a list where a number is needed
+    Type,
+    /// A configured resource limit stopped parsing or evaluation
+    Limit,
+}
+
+impl ComputeError {
+    /// Stable identifier of the kind of error, which unlike the message never changes between releases
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ParseError(_) => "E001_PARSE",
+            Self::DivisionByZero => "E002_DIV_ZERO",
+            Self::InvalidNumber(_) => "E003_INVALID_NUMBER",
+            Self::Overflow => "E004_OVERFLOW",
+            Self::UndefinedVariable(_) => "E005_UNDEFINED_VARIABLE",
+            Self::UnknownFunction(_) => "E006_UNKNOWN_FUNCTION",
+            Self::InvalidStructure(_) => "E007_INVALID_STRUCTURE",
+            Self::EmptyExpression => "E008_EMPTY",
+            Self::LimitExceeded(_) => "E009_LIMIT_EXCEEDED",
+            Self::ExpressionTooLong { .. } => "E010_TOO_LONG",
+            Self::TypeMismatch(_) => "E011_TYPE_MISMATCH",
+            Self::NotANumber => "E012_NOT_A_NUMBER",
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Self::ParseError(_) | Self::InvalidNumber(_) | Self::InvalidStructure(_) | Self::EmptyExpression => {
+                ErrorCategory::Syntax
+            }
+            Self::DivisionByZero | Self::Overflow | Self::NotANumber => ErrorCategory::Arithmetic,
+            Self::UndefinedVariable(_) | Self::UnknownFunction(_) => ErrorCategory::Name,
+            Self::TypeMismatch(_) => ErrorCategory::Type,
+            Self::LimitExceeded(_) | Self::ExpressionTooLong { .. } => ErrorCategory::Limit,
         }
     }
+
+    /// Where in the source a syntax error was found; other errors do not know their position, see `evaluate_spanned`
+    pub fn span(&self) -> Option<Span> {
+        let Self::ParseError(e) = self else {
+            return None;
+        };
+        Some(match e.location {
+            pest::error::InputLocation::Pos(pos) => Span::new(pos, pos),
+            pest::error::InputLocation::Span((start, end)) => Span::new(start, end),
+        })
+    }
+
+    /// The error on one line, giving pest's short message for a syntax error rather than its source excerpt
+    pub fn message(&self) -> String {
+        match self {
+            Self::ParseError(e) => e.variant.message().into_owned(),
+            other => other.to_string(),
+        }
+    }
+
+    /// The error as a JSON object with its `code`, `category` and `message`, and a `span` when known
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "code": self.code(),
+            "category": self.category(),
+            "message": self.message(),
+        });
+        if let Some(span) = self.span() {
+            json["span"] = serde_json::json!(span);
+        }
+        json
+    }
 }
 
-impl std::error::Error for ComputeError {}
+pub type Result<T> = core::result::Result<T, ComputeError>;
+
+/// What to do with a numeric literal too large to represent as a finite `f64`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum LiteralOverflow {
+    /// Reject the expression with `ComputeError::Overflow`
+    #[default]
+    Error,
+    /// Replace the literal with `f64::MAX`
+    Clamp,
+    /// Keep the literal as infinity and let it flow into arithmetic
+    Propagate,
+}
+
+/// Default for `ParseOptions::max_expression_len`: 1 MiB, far beyond any hand-written formula
+pub const DEFAULT_MAX_EXPRESSION_LEN: usize = 1 << 20;
+
+/// Default for `ParseOptions::max_nesting`, well within the stack of a 2 MiB thread even in debug builds
+pub const DEFAULT_MAX_NESTING: usize = 128;
 
-pub type Result<T> = std::result::Result<T, ComputeError>;
+/// Default for `ParseOptions::max_chain`, within the stack of an 8 MiB main thread even in debug builds
+pub const DEFAULT_MAX_CHAIN: usize = 1000;
+
+/// Options controlling how expression strings are parsed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParseOptions {
+    /// Policy for literals such as `1e999` that overflow `f64`
+    pub literal_overflow: LiteralOverflow,
+    /// Byte budget for the parse tree and AST; `None` means unlimited
+    pub memory_limit: Option<usize>,
+    /// Longest input, in bytes, that is parsed at all
+    pub max_expression_len: usize,
+    /// Deepest nesting of brackets, prefix operators, `^` and `?` that is parsed at all
+    ///
+    /// The parser recurses once per level, so this is checked by a scan of
+    /// the source before parsing starts, keeping adversarial input such as
+    /// ten thousand `(` from overflowing the stack.
+    pub max_nesting: usize,
+    /// Most binary operators a chain such as `1 + 2 + 3` may link, counting those of the chains enclosing it
+    ///
+    /// The parser reads a chain in a loop, but it becomes a tree as deep as
+    /// the chain is long, and the evaluators recurse once per tree level. The
+    /// same scan as `max_nesting` checks this before parsing starts.
+    pub max_chain: usize,
+    /// Deepest tree a parse may produce, counting a lone number as depth 1; `None` means unlimited
+    pub max_depth: Option<usize>,
+    /// Most nodes a parsed tree may have; `None` means unlimited
+    pub max_nodes: Option<usize>,
+    /// Also accept commas between digit groups, as in `1,000,000`
+    ///
+    /// A comma then separates call arguments only when the digits around it
+    /// do not form a grouped integer: `atan2(1,000)` becomes `atan2(1000)`,
+    /// while `atan2(1, 000)` keeps two arguments.
+    pub comma_separators: bool,
+    /// Read an operand directly followed by `(` or a name as a product, as in `2(3 + 4)`, `(2)(3)` or `2x`
+    ///
+    /// The implied `*` binds like an explicit one, so `1 / 2x` is `(1 / 2) * x`.
+    /// A name directly followed by `(` is still a call, and two numbers side
+    /// by side are still an error.
+    pub implicit_multiplication: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            literal_overflow: LiteralOverflow::default(),
+            memory_limit: None,
+            max_expression_len: DEFAULT_MAX_EXPRESSION_LEN,
+            max_nesting: DEFAULT_MAX_NESTING,
+            max_chain: DEFAULT_MAX_CHAIN,
+            max_depth: None,
+            max_nodes: None,
+            comma_separators: false,
+            implicit_multiplication: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Reject `expr` if it is longer than `max_expression_len`
+    pub(crate) fn check_len(&self, expr: &str) -> Result<()> {
+        if expr.len() > self.max_expression_len {
+            return Err(ComputeError::ExpressionTooLong {
+                len: expr.len(),
+                max: self.max_expression_len,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reject `expr` up front if it is too long, nested too deeply, or parsing it could exceed the memory budget
+    pub(crate) fn check_limits(&self, expr: &str) -> Result<()> {
+        self.check_len(expr)?;
+        let (nesting, chain) = nesting_depth(expr);
+        if nesting > self.max_nesting {
+            return Err(ComputeError::LimitExceeded(format!(
+                "the expression nests {} levels deep, over the {} level nesting limit",
+                nesting, self.max_nesting
+            )));
+        }
+        if chain > self.max_chain {
+            return Err(ComputeError::LimitExceeded(format!(
+                "the expression chains {} operators, over the {} operator chain limit",
+                chain, self.max_chain
+            )));
+        }
+        match self.memory_limit {
+            Some(limit) if parse_memory_estimate(expr, self.implicit_multiplication) > limit => Err(ComputeError::LimitExceeded(
+                format!("parsing needs more than the {} byte memory budget", limit),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The value of a literal read as `n`, following `literal_overflow` when it is not finite
+    pub(crate) fn literal_value(&self, n: f64) -> Result<f64> {
+        if n.is_finite() {
+            return Ok(n);
+        }
+        match self.literal_overflow {
+            LiteralOverflow::Error => Err(ComputeError::Overflow),
+            LiteralOverflow::Clamp => Ok(f64::MAX),
+            LiteralOverflow::Propagate => Ok(n),
+        }
+    }
+
+    /// Reject a parsed tree deeper than `max_depth` or larger than `max_nodes`
+    pub(crate) fn check_tree(&self, expr: Expr) -> Result<Expr> {
+        if self.max_depth.is_none() && self.max_nodes.is_none() {
+            return Ok(expr);
+        }
+        let (mut depth, mut nodes) = (0, 0);
+        expr.visit(|_, level| {
+            depth = depth.max(level);
+            nodes += 1;
+        });
+        self.check_size(depth, nodes)?;
+        Ok(expr)
+    }
+
+    /// Reject a parsed tree of `depth` levels and `nodes` nodes if it is over `max_depth` or `max_nodes`
+    pub(crate) fn check_size(&self, depth: usize, nodes: usize) -> Result<()> {
+        if let Some(max) = self.max_depth.filter(|&max| depth > max) {
+            return Err(ComputeError::LimitExceeded(format!(
+                "the expression is {} levels deep, over the {} level limit",
+                depth, max
+            )));
+        }
+        if let Some(max) = self.max_nodes.filter(|&max| nodes > max) {
+            return Err(ComputeError::LimitExceeded(format!(
+                "the expression has {} nodes, over the {} node limit",
+                nodes, max
+            )));
+        }
+        Ok(())
+    }
+
+    /// `expr` with the commas of grouped integers such as `1,000,000` turned into `_` separators
+    ///
+    /// Leaves `expr` alone unless `comma_separators` is set. Each comma is
+    /// replaced by one byte, so error spans still point into the original text.
+    pub(crate) fn group_separators<'a>(&self, expr: &'a str) -> Cow<'a, str> {
+        if !self.comma_separators || !expr.contains(',') {
+            return Cow::Borrowed(expr);
+        }
+        let mut bytes = expr.as_bytes().to_vec();
+        let mut start = 0;
+        while start < bytes.len() {
+            // A run that continues a name or a fraction is not an integer
+            let attached = start > 0 && matches!(bytes[start - 1], b'0'..=b'9' | b'a'..=b'z' | b'A'..=b'Z' | b'_' | b'.');
+            if !bytes[start].is_ascii_digit() || attached {
+                start += 1;
+                continue;
+            }
+            let len = bytes[start..].iter().take_while(|b| b.is_ascii_digit() || **b == b',').count();
+            let run = expr[start..start + len].trim_end_matches(',');
+            let mut groups = run.split(',');
+            let lead = groups.next().map_or(0, str::len);
+            if run.contains(',') && lead <= 3 && groups.all(|group| group.len() == 3) {
+                for b in &mut bytes[start..start + run.len()] {
+                    if *b == b',' {
+                        *b = b'_';
+                    }
+                }
+            }
+            start += len;
+        }
+        Cow::Owned(String::from_utf8(bytes).expect("only ASCII commas are replaced"))
+    }
+}
+
+/// Approximate heap bytes of one pest pair (a start and an end queue token)
+const PAIR_BYTES: usize = 96;
+
+/// Upper bound on the heap memory parsing `expr` needs, from one scan of its bytes
+///
+/// Every operator or separator byte becomes at most one pair and one AST
+/// node, every token run at most a primary and an atom pair plus a node, and
+/// every `(` or `?` at most three pairs. With `implicit_multiplication`, a
+/// token run may also carry an implied `*`, and a letter after a digit may
+/// start a new operand, as in `2x`. The estimate never undercounts, so inputs under budget
+/// cannot allocate more than the budget during parsing; evaluating an AST
+/// allocates nothing further.
+fn parse_memory_estimate(expr: &str, implicit_multiplication: bool) -> usize {
+    let (mut pairs, mut nodes) = (1usize, 0usize);
+    let (run_pairs, run_nodes) = if implicit_multiplication { (3, 2) } else { (2, 1) };
+    let mut in_token = false;
+    let mut after_digit = false;
+    for b in expr.bytes() {
+        if implicit_multiplication && after_digit && (b.is_ascii_alphabetic() || b == b'_') {
+            in_token = false;
+        }
+        after_digit = b.is_ascii_digit();
+        match b {
+            b'+' | b'-' | b'*' | b'/' | b'^' | b'<' | b'>' | b'=' | b'!' | b':' | b',' | b'%' => {
+                pairs += 1;
+                nodes += 1;
+                in_token = false;
+            }
+            b'(' | b'?' => {
+                pairs += 3;
+                in_token = false;
+            }
+            b'[' => {
+                pairs += 3;
+                nodes += 1;
+                in_token = false;
+            }
+            b')' | b']' | b' ' | b'\t' | b'\n' | b'\r' | b'"' => in_token = false,
+            // Every byte of a Unicode operator or space, none of which can be part of a token
+            0x80.. => {
+                pairs += 1;
+                nodes += 1;
+                in_token = false;
+            }
+            _ => {
+                if !in_token {
+                    pairs += run_pairs;
+                    nodes += run_nodes;
+                }
+                in_token = true;
+            }
+        }
+    }
+    pairs * PAIR_BYTES + nodes * core::mem::size_of::<Expr>() + expr.len()
+}
+
+/// Open brackets whose depths `nesting_depth` keeps on the stack before spilling to the heap
+const BRACKET_BUFFER: usize = 32;
+
+/// The depth and chain length each open bracket was entered at, and the depth just inside it, innermost last
+struct Brackets {
+    buffer: [(usize, usize, usize); BRACKET_BUFFER],
+    spilled: Vec<(usize, usize, usize)>,
+    len: usize,
+}
+
+impl Brackets {
+    fn new() -> Self {
+        Brackets { buffer: [(0, 0, 0); BRACKET_BUFFER], spilled: Vec::new(), len: 0 }
+    }
+
+    fn push(&mut self, level: (usize, usize, usize)) {
+        if self.len < BRACKET_BUFFER {
+            self.buffer[self.len] = level;
+        } else {
+            self.spilled.push(level);
+        }
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<(usize, usize, usize)> {
+        self.len = self.len.checked_sub(1)?;
+        if self.len < BRACKET_BUFFER {
+            Some(self.buffer[self.len])
+        } else {
+            self.spilled.pop()
+        }
+    }
+
+    fn last(&self) -> Option<(usize, usize, usize)> {
+        match self.len {
+            0 => None,
+            len if len <= BRACKET_BUFFER => Some(self.buffer[len - 1]),
+            _ => self.spilled.last().copied(),
+        }
+    }
+}
+
+/// Upper bounds on how deeply parsing `expr` recurses and on its longest operator chain, from one scan of the characters
+///
+/// Each `(` or `[` opens a level that its closing bracket ends. A `^` or `?`
+/// nests everything after it, up to the next `,` or `;` or the end of the
+/// enclosing bracket, one level further, and so does each prefix `-`, `+`
+/// or `not` until the operand it applies to. Every operator that follows an
+/// operand lengthens the chain, which a bracket carries into its contents
+/// and restores when it closes. Text in quotes is skipped, and unbalanced
+/// brackets are left for the parser to report.
+fn nesting_depth(expr: &str) -> (usize, usize) {
+    let mut open = Brackets::new();
+    let (mut depth, mut prefix, mut max) = (0usize, 0usize, 0usize);
+    let (mut chain, mut longest) = (0usize, 0usize);
+    let mut operand = false;
+    let mut chars = expr.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '(' | '[' => {
+                let inner = depth + prefix + 1;
+                open.push((depth, inner, chain));
+                (depth, prefix, operand) = (inner, 0, false);
+            }
+            ')' | ']' => {
+                (depth, chain) = open.pop().map_or((depth, chain), |(outer, _, chain)| (outer, chain));
+                (prefix, operand) = (0, true);
+            }
+            ',' | ';' => {
+                (depth, chain) = open.last().map_or((0, 0), |(_, inner, chain)| (inner, chain));
+                (prefix, operand) = (0, false);
+            }
+            '^' | '?' => {
+                depth += prefix + 1;
+                (prefix, operand) = (0, false);
+            }
+            '-' | '+' | '−' if !operand => prefix += 1,
+            '"' => {
+                while chars.next_if(|&(_, c)| c != '"').is_some() {}
+                chars.next();
+                (prefix, operand) = (0, true);
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' || c == '%' => {
+                let mut end = i + c.len_utf8();
+                while let Some((j, c)) = chars.next_if(|&(_, c)| c.is_alphanumeric() || c == '_' || c == '.') {
+                    end = j + c.len_utf8();
+                }
+                match &expr[i..end] {
+                    "not" => prefix += 1,
+                    "and" | "or" => {
+                        chain += usize::from(operand);
+                        (prefix, operand) = (0, false);
+                    }
+                    _ => (prefix, operand) = (0, true),
+                }
+            }
+            c if c.is_whitespace() => {}
+            _ => {
+                chain += usize::from(operand);
+                (prefix, operand) = (0, false);
+            }
+        }
+        max = max.max(depth + prefix);
+        longest = longest.max(chain);
+    }
+    (max, longest)
+}
+
+/// Which parser implementation turns source text into an AST
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum ParserBackend {
+    /// The pest grammar driven by a Pratt parser, the reference implementation
+    #[cfg_attr(not(feature = "descent"), default)]
+    Pest,
+    /// The hand-rolled recursive-descent parser
+    #[cfg(feature = "descent")]
+    #[cfg_attr(feature = "descent", default)]
+    Descent,
+}
+
+/// Which implementation evaluates a parsed expression in float arithmetic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum EvalBackend {
+    /// Recursive tree walking with `eval_expr_in`, the reference implementation
+    #[cfg_attr(not(feature = "bytecode"), default)]
+    Tree,
+    /// Compile to stack-machine code with `compile_in` and run that; faster only when code is reused
+    #[cfg_attr(feature = "bytecode", default)]
+    Bytecode,
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// A backend read by name, or the default if this build does not have it
+fn backend_or_default<'de, D, T>(d: D) -> core::result::Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    use serde::de::{value, IntoDeserializer};
+    let name = String::deserialize(d)?;
+    Ok(T::deserialize(IntoDeserializer::<value::Error>::into_deserializer(name)).unwrap_or_default())
+}
+
+/// Options controlling how expressions are evaluated and results presented
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvalOptions {
+    /// Options applied while parsing the expression string
+    pub parse: ParseOptions,
+    /// Parser implementation used for inputs the fast path does not handle
+    ///
+    /// Like `eval_backend`, it is left out when serialized at its default,
+    /// which depends on the build's features, and a backend this build lacks
+    /// reads back as the default, so saved options load in any build.
+    #[serde(skip_serializing_if = "is_default", deserialize_with = "backend_or_default")]
+    pub backend: ParserBackend,
+    /// Evaluator implementation used for float arithmetic without a `fuel` budget
+    #[serde(skip_serializing_if = "is_default", deserialize_with = "backend_or_default")]
+    pub eval_backend: EvalBackend,
+    /// Strategy used wherever a value is rounded to a number of decimal places
+    pub rounding: RoundingMode,
+    /// Decimal places every final result is rounded to with `result_rounding`; `None` keeps full precision
+    pub result_places: Option<i32>,
+    /// Strategy `result_places` rounds final results with; `None` uses `rounding`
+    ///
+    /// Setting this directs only the final rounding, so `round()` and the
+    /// other rounding builtins inside the expression keep following `rounding`.
+    pub result_rounding: Option<RoundingMode>,
+    /// Tolerance for checking batch results against exact arithmetic; `None` skips the check
+    pub verify: Option<f64>,
+    /// Time and measure each batch result into `EvaluationResult::metadata`
+    pub metadata: bool,
+    /// Propagate the uncertainties of measured values such as `2.0 ± 0.1` into `EvaluationResult::uncertainty`
+    ///
+    /// Sheets, whose cells pass exact values to each other, report none.
+    pub uncertainty: bool,
+    /// Unit trigonometric functions take and return angles in
+    pub angle_mode: AngleMode,
+    /// Number type results are computed in; `Integer` fails with `Overflow` past the f64 range
+    pub arithmetic: Arithmetic,
+    /// Most steps one evaluation may take, as counted by `eval_with_fuel`; `None` means unlimited
+    pub fuel: Option<u64>,
+    /// Fail with `Overflow` for an infinite result and `NotANumber` for NaN rather than returning them
+    pub strict_finite: bool,
+    /// Add chains of `+` and `-` with compensated summation, see `eval_compensated`
+    ///
+    /// Applies to float arithmetic without a `fuel` budget, in place of `eval_backend`.
+    pub compensated_sums: bool,
+    /// How the CLI and server write results out; values returned as `f64` are unaffected
+    pub number_format: NumberFormat,
+}
+
+impl EvalOptions {
+    /// Parse `expr` with the configured backend and parse options
+    pub(crate) fn parse(&self, expr: &str) -> Result<Expr> {
+        match self.backend {
+            ParserBackend::Pest => parse_expression_with(expr, &self.parse),
+            #[cfg(feature = "descent")]
+            ParserBackend::Descent => descent::parse_expression_with(expr, &self.parse),
+        }
+    }
+
+    /// Evaluate a flat `a op b [op c]` expression without parsing it, or `None` when the full route is needed
+    ///
+    /// The fast path only implements unbudgeted, uncompensated float
+    /// arithmetic without variables, and it builds no tree to hold to
+    /// `max_depth`, `max_nodes` or `memory_limit`, so setting any of those
+    /// sends every expression through the parser. The up-front limits on the
+    /// source still apply, and literals follow `literal_overflow`.
+    fn fast_path(&self, expr: &str) -> Option<Result<f64>> {
+        let plain_float = self.arithmetic == Arithmetic::Float && self.fuel.is_none() && !self.compensated_sums;
+        let parse = &self.parse;
+        let unbounded_tree = parse.max_depth.is_none() && parse.max_nodes.is_none() && parse.memory_limit.is_none();
+        if !(plain_float && unbounded_tree) {
+            return None;
+        }
+        if let Err(e) = parse.check_limits(expr) {
+            return Some(Err(e));
+        }
+        fast_path::evaluate_simple(expr, parse)
+    }
+
+    /// Evaluate a parsed expression in the configured arithmetic, looking variables up in `env`
+    ///
+    /// With a `fuel` budget, integer arithmetic first spends the same steps
+    /// in float arithmetic, so both fail on the same inputs.
+    pub(crate) fn eval(&self, expr: &Expr, env: &Bindings) -> Result<f64> {
+        let value = match (self.arithmetic, self.fuel) {
+            (Arithmetic::Float, None) if self.compensated_sums => {
+                eval_compensated_rounding(expr, env, self.angle_mode, self.rounding)
+            }
+            (Arithmetic::Float, None) => match self.eval_backend {
+                EvalBackend::Tree => eval_expr_rounding(expr, env, self.angle_mode, self.rounding),
+                EvalBackend::Bytecode => compile_rounding(expr, self.angle_mode, self.rounding).eval_with_env(env),
+            },
+            (Arithmetic::Float, Some(fuel)) => eval_with_fuel_rounding(expr, env, self.angle_mode, self.rounding, fuel),
+            (Arithmetic::Integer, fuel) => {
+                if let Some(fuel) = fuel {
+                    let budgeted = eval_with_fuel_rounding(expr, env, self.angle_mode, self.rounding, fuel);
+                    if let Err(e @ ComputeError::LimitExceeded(_)) = budgeted {
+                        return Err(e);
+                    }
+                }
+                integer::to_f64(&eval_integer_rounding(expr, env, self.angle_mode, self.rounding)?)
+            }
+        };
+        self.check_finite(value?)
+    }
+
+    /// `value`, unless `strict_finite` rejects it
+    pub(crate) fn check_finite(&self, value: f64) -> Result<f64> {
+        if !self.strict_finite || value.is_finite() {
+            Ok(value)
+        } else if value.is_nan() {
+            Err(ComputeError::NotANumber)
+        } else {
+            Err(ComputeError::Overflow)
+        }
+    }
+
+    /// Round `value` to `places` decimal places using the configured strategy
+    pub fn round(&self, value: f64, places: i32) -> f64 {
+        self.rounding.round(value, places)
+    }
+
+    /// Apply `result_places` rounding to a final result
+    pub fn round_result(&self, value: f64) -> f64 {
+        match self.result_places {
+            Some(places) => self.result_rounding.unwrap_or(self.rounding).round(value, places),
+            None => value,
+        }
+    }
+}
 
 lazy_static::lazy_static! {
     static ref PRATT_PARSER: PrattParser<Rule> = {
@@ -63,43 +1202,126 @@ lazy_static::lazy_static! {
         use Rule::*;
 
         PrattParser::new()
+            .op(Op::infix(or, Left))
+            .op(Op::infix(and, Left))
+            .op(Op::prefix(not))
+            .op(Op::infix(less, Left)
+                | Op::infix(less_eq, Left)
+                | Op::infix(greater, Left)
+                | Op::infix(greater_eq, Left)
+                | Op::infix(equal, Left)
+                | Op::infix(not_equal, Left))
             .op(Op::infix(add, Left) | Op::infix(subtract, Left))
-            .op(Op::infix(multiply, Left) | Op::infix(divide, Left))
+            .op(Op::infix(multiply, Left) | Op::infix(divide, Left) | Op::infix(implicit_multiply, Left))
+            .op(Op::infix(power, Right))
             .op(Op::prefix(neg))
     };
 }
 
 /// Evaluate an arithmetic expression string
 pub fn evaluate(expr: &str) -> Result<f64> {
+    evaluate_with(expr, &EvalOptions::default())
+}
+
+/// Evaluate an arithmetic expression string with explicit options
+pub fn evaluate_with(expr: &str, options: &EvalOptions) -> Result<f64> {
+    evaluate_in(expr, &Bindings::new(), options)
+}
+
+/// Evaluate an arithmetic expression string, looking variables up in `env`
+pub fn evaluate_with_env(expr: &str, env: &Bindings) -> Result<f64> {
+    evaluate_in(expr, env, &EvalOptions::default())
+}
+
+fn evaluate_in(expr: &str, env: &Bindings, options: &EvalOptions) -> Result<f64> {
+    evaluate_unrounded(expr, env, options).map(|value| options.round_result(value))
+}
+
+fn evaluate_unrounded(expr: &str, env: &Bindings, options: &EvalOptions) -> Result<f64> {
+    options.parse.check_len(expr)?;
     let expr = expr.trim();
     if expr.is_empty() {
         return Err(ComputeError::EmptyExpression);
     }
-    parse_expression(expr).and_then(|ast| eval_expr(&ast))
+    if let Some(result) = options.fast_path(expr) {
+        return options.check_finite(result?);
+    }
+    options.parse(expr).and_then(|ast| options.eval(&ast, env))
 }
 
 /// Parse an expression string into an AST using the Pest grammar
 pub fn parse_expression(expr: &str) -> Result<Expr> {
-    let pairs = ComputeParser::parse(Rule::equation, expr)
+    parse_expression_with(expr, &ParseOptions::default())
+}
+
+/// Parse an expression string into an AST with explicit parse options
+pub fn parse_expression_with(expr: &str, options: &ParseOptions) -> Result<Expr> {
+    options.check_limits(expr)?;
+    let expr = &*options.group_separators(expr);
+    let rule = if options.implicit_multiplication { Rule::equation_implicit } else { Rule::equation };
+    let pairs = ComputeParser::parse(rule, expr)
         .map_err(|e| ComputeError::ParseError(Box::new(e)))?;
     
     let expr_pair = pairs
         .into_iter()
         .next()
-        .ok_or(ComputeError::InvalidStructure("No expression found".into()))?;
+        .ok_or_else(|| ComputeError::InvalidStructure("No expression found".into()))?;
     
-    parse_expr(expr_pair.into_inner())
+    options.check_tree(parse_expr(expr_pair.into_inner(), options)?)
 }
 
-fn parse_expr(pairs: pest::iterators::Pairs<Rule>) -> Result<Expr> {
-    PRATT_PARSER
+/// Longest literal whose `_` separators are stripped on the stack rather than in a new `String`
+const LITERAL_BUFFER: usize = 64;
+
+/// Value of a numeric literal, ignoring `_` digit separators, under the literal overflow policy
+fn parse_literal(literal: &str, options: &ParseOptions) -> Result<f64> {
+    let n: f64 = if !literal.contains('_') {
+        literal.parse()
+    } else if literal.len() <= LITERAL_BUFFER {
+        let mut buffer = [0; LITERAL_BUFFER];
+        let mut len = 0;
+        for b in literal.bytes().filter(|&b| b != b'_') {
+            buffer[len] = b;
+            len += 1;
+        }
+        core::str::from_utf8(&buffer[..len]).expect("literals are ASCII").parse()
+    } else {
+        literal.replace('_', "").parse()
+    }
+    .map_err(ComputeError::InvalidNumber)?;
+    options.literal_value(n)
+}
+
+fn parse_number(literal: &str, options: &ParseOptions) -> Result<Expr> {
+    parse_literal(literal, options).map(Expr::Number)
+}
+
+/// Parse `n%`, whose `n` is subject to the literal overflow policy like any number
+fn parse_percent(literal: &str, options: &ParseOptions) -> Result<Expr> {
+    parse_literal(literal.trim_end_matches('%'), options).map(Expr::Percent)
+}
+
+/// Parse `a ± b` as the call `pm(a, b)`, both literals subject to the overflow policy
+fn parse_uncertain(uncertain: pest::iterators::Pair<Rule>, options: &ParseOptions) -> Result<Expr> {
+    let args = uncertain
+        .into_inner()
+        .map(|number| parse_number(number.as_str(), options))
+        .collect::<Result<_>>()?;
+    Ok(Expr::Call("pm".to_string(), args))
+}
+
+fn parse_expr(pairs: pest::iterators::Pairs<Rule>, options: &ParseOptions) -> Result<Expr> {
+    let branches = pairs.clone().find(|pair| pair.as_rule() == Rule::conditional);
+    let expr = PRATT_PARSER
         .map_primary(|primary| match primary.as_rule() {
-            Rule::number => primary
-                .as_str()
-                .parse()
-                .map(Expr::Number)
-                .map_err(ComputeError::InvalidNumber),
-            Rule::expr => parse_expr(primary.into_inner()),
+            Rule::number => parse_number(primary.as_str(), options),
+            Rule::percent => parse_percent(primary.as_str(), options),
+            Rule::uncertain => parse_uncertain(primary, options),
+            Rule::ident => Ok(Expr::Var(primary.as_str().to_string())),
+            Rule::call => parse_call(primary, options),
+            Rule::list => parse_list(primary, options),
+            Rule::text => Ok(parse_text(primary.as_str())),
+            Rule::expr => parse_expr(primary.into_inner(), options),
             Rule::primary => {
                 let mut inner = primary.into_inner();
                 let mut neg_count = 0;
@@ -116,15 +1338,17 @@ fn parse_expr(pairs: pest::iterators::Pairs<Rule>) -> Result<Expr> {
                 
                 // Parse the atom
                 let atom = inner.next()
-                    .ok_or(ComputeError::InvalidStructure("Missing atom in primary".into()))?;
+                    .ok_or_else(|| ComputeError::InvalidStructure("Missing atom in primary".into()))?;
                 
                 let mut expr = match atom.as_rule() {
-                    Rule::number => atom
-                        .as_str()
-                        .parse()
-                        .map(Expr::Number)
-                        .map_err(ComputeError::InvalidNumber)?,
-                    Rule::expr => parse_expr(atom.into_inner())?,
+                    Rule::number => parse_number(atom.as_str(), options)?,
+                    Rule::percent => parse_percent(atom.as_str(), options)?,
+                    Rule::uncertain => parse_uncertain(atom, options)?,
+                    Rule::ident => Expr::Var(atom.as_str().to_string()),
+                    Rule::call => parse_call(atom, options)?,
+                    Rule::list => parse_list(atom, options)?,
+                    Rule::text => parse_text(atom.as_str()),
+                    Rule::expr => parse_expr(atom.into_inner(), options)?,
                     _ => return Err(ComputeError::InvalidStructure(format!(
                         "Unexpected atom: {:?}",
                         atom.as_rule()
@@ -145,6 +1369,7 @@ fn parse_expr(pairs: pest::iterators::Pairs<Rule>) -> Result<Expr> {
         })
         .map_prefix(|op, rhs| match op.as_rule() {
             Rule::neg => Ok(Expr::Neg(Box::new(rhs?))),
+            Rule::not => Ok(Expr::Not(Box::new(rhs?))),
             _ => Err(ComputeError::InvalidStructure(format!(
                 "Unknown prefix operator: {:?}",
                 op.as_rule()
@@ -153,32 +1378,226 @@ fn parse_expr(pairs: pest::iterators::Pairs<Rule>) -> Result<Expr> {
         .map_infix(|lhs, op, rhs| match op.as_rule() {
             Rule::add => Ok(Expr::Add(Box::new(lhs?), Box::new(rhs?))),
             Rule::subtract => Ok(Expr::Sub(Box::new(lhs?), Box::new(rhs?))),
-            Rule::multiply => Ok(Expr::Mul(Box::new(lhs?), Box::new(rhs?))),
+            Rule::multiply | Rule::implicit_multiply => Ok(Expr::Mul(Box::new(lhs?), Box::new(rhs?))),
             Rule::divide => Ok(Expr::Div(Box::new(lhs?), Box::new(rhs?))),
-            _ => Err(ComputeError::InvalidStructure(format!(
-                "Unknown infix operator: {:?}",
-                op.as_rule()
-            ))),
+            Rule::power => Ok(Expr::Pow(Box::new(lhs?), Box::new(rhs?))),
+            Rule::and => Ok(Expr::And(Box::new(lhs?), Box::new(rhs?))),
+            Rule::or => Ok(Expr::Or(Box::new(lhs?), Box::new(rhs?))),
+            rule => match Relation::from_rule(rule) {
+                Some(relation) => Ok(Expr::Compare(relation, Box::new(lhs?), Box::new(rhs?))),
+                None => Err(ComputeError::InvalidStructure(format!("Unknown infix operator: {:?}", rule))),
+            },
         })
-        .parse(pairs)
+        .parse(pairs.filter(|pair| pair.as_rule() != Rule::conditional))?;
+
+    let Some(branches) = branches else {
+        return Ok(expr);
+    };
+    let mut branches = branches
+        .into_inner()
+        .map(|branch| parse_expr(branch.into_inner(), options).map(Box::new));
+    let mut next =
+        || branches.next().unwrap_or_else(|| Err(ComputeError::InvalidStructure("Missing branch".into())));
+    Ok(Expr::If(Box::new(expr), next()?, next()?))
+}
+
+fn parse_call(call: pest::iterators::Pair<Rule>, options: &ParseOptions) -> Result<Expr> {
+    let mut inner = call.into_inner();
+    let name = inner
+        .next()
+        .ok_or_else(|| ComputeError::InvalidStructure("Missing function name in call".into()))?;
+    let mut inner = inner.peekable();
+    let mut args = Vec::new();
+    while let Some(arg) = inner.next() {
+        let arg = parse_expr(arg.into_inner(), options)?;
+        // A range is its lower bound followed by a `range` pair holding the upper one
+        args.push(match inner.next_if(|next| next.as_rule() == Rule::range) {
+            Some(range) => {
+                let upper = range
+                    .into_inner()
+                    .next()
+                    .ok_or_else(|| ComputeError::InvalidStructure("Missing range bound".into()))?;
+                Expr::Range(Box::new(arg), Box::new(parse_expr(upper.into_inner(), options)?))
+            }
+            None => arg,
+        });
+    }
+    Ok(Expr::Call(name.as_str().to_string(), args))
+}
+
+fn parse_list(list: pest::iterators::Pair<Rule>, options: &ParseOptions) -> Result<Expr> {
+    let elements = list
+        .into_inner()
+        .map(|element| parse_expr(element.into_inner(), options))
+        .collect::<Result<_>>()?;
+    Ok(Expr::List(elements))
+}
+
+/// The text between the quotes of a `text` pair
+fn parse_text(quoted: &str) -> Expr {
+    Expr::Text(quoted[1..quoted.len() - 1].to_string())
 }
 
 /// Evaluate an AST expression to produce a numeric result
 pub fn eval_expr(expr: &Expr) -> Result<f64> {
+    eval_expr_with_env(expr, &Bindings::new())
+}
+
+/// Evaluate an AST expression, looking variables up in `env`
+pub fn eval_expr_with_env(expr: &Expr, env: &Bindings) -> Result<f64> {
+    eval_expr_in(expr, env, AngleMode::default())
+}
+
+/// Evaluate an AST expression, looking variables up in `env` and measuring angles in `angle_mode`
+pub fn eval_expr_in(expr: &Expr, env: &Bindings, angle_mode: AngleMode) -> Result<f64> {
+    eval_expr_rounding(expr, env, angle_mode, RoundingMode::default())
+}
+
+/// Like `eval_expr_in`, with `round` rounding in `rounding`
+pub(crate) fn eval_expr_rounding(expr: &Expr, env: &Bindings, angle_mode: AngleMode, rounding: RoundingMode) -> Result<f64> {
+    let eval = |e| eval_expr_rounding(e, env, angle_mode, rounding);
     match expr {
         Expr::Number(n) => Ok(*n),
-        Expr::Add(l, r) => Ok(eval_expr(l)? + eval_expr(r)?),
-        Expr::Sub(l, r) => Ok(eval_expr(l)? - eval_expr(r)?),
-        Expr::Mul(l, r) => Ok(eval_expr(l)? * eval_expr(r)?),
+        Expr::Percent(n) => Ok(n / 100.0),
+        Expr::Var(name) => env
+            .get(name)
+            .copied()
+            .ok_or_else(|| ComputeError::UndefinedVariable(name.clone())),
+        Expr::Add(_, r) | Expr::Sub(_, r) if matches!(**r, Expr::Percent(_)) => {
+            eval_uncommon_in(expr, env, angle_mode, rounding)
+        }
+        Expr::Add(l, r) => Ok(eval(l)? + eval(r)?),
+        Expr::Sub(l, r) => Ok(eval(l)? - eval(r)?),
+        Expr::Mul(l, r) => Ok(eval(l)? * eval(r)?),
         Expr::Div(l, r) => {
-            let divisor = eval_expr(r)?;
+            let divisor = eval(r)?;
             if divisor != 0.0 {
-                Ok(eval_expr(l)? / divisor)
+                Ok(eval(l)? / divisor)
             } else {
                 Err(ComputeError::DivisionByZero)
             }
         }
-        Expr::Neg(e) => eval_expr(e).map(|n| -n),
+        Expr::Pow(l, r) => Ok(eval(l)?.powf(eval(r)?)),
+        Expr::Neg(e) => eval(e).map(|n| -n),
+        Expr::Call(name, args) => {
+            functions::call(name, args, angle_mode, rounding, |e| eval_expr_rounding(e, env, angle_mode, rounding))
+        }
+        Expr::Compare(..) | Expr::If(..) | Expr::And(..) | Expr::Or(..) | Expr::Not(_) => {
+            eval_uncommon_in(expr, env, angle_mode, rounding)
+        }
+        Expr::List(_) | Expr::Range(..) | Expr::Text(_) => eval_uncommon_in(expr, env, angle_mode, rounding),
+    }
+}
+
+/// Evaluate a comparison, conditional, logical operator or percentage change, or reject a list or range
+///
+/// Kept out of line so its temporaries do not grow the stack frame that
+/// every arithmetic node pays for. Conditionals only evaluate the branch
+/// they select, and `and` and `or` skip their right operand when the left
+/// one decides the result.
+#[inline(never)]
+fn eval_uncommon_in(expr: &Expr, env: &Bindings, angle_mode: AngleMode, rounding: RoundingMode) -> Result<f64> {
+    let eval = |e| eval_expr_rounding(e, env, angle_mode, rounding);
+    if let Some((a, p)) = expr.percent_change() {
+        return Ok(add_percent(eval(a)?, p));
+    }
+    match expr {
+        Expr::Compare(relation, l, r) => Ok(relation.eval(eval(l)?, eval(r)?)),
+        Expr::If(cond, then, otherwise) => eval(if is_true(eval(cond)?) { then } else { otherwise }),
+        Expr::And(l, r) | Expr::Or(l, r) => {
+            // A true left operand decides `or` and a false one decides `and`
+            let decisive = matches!(expr, Expr::Or(..));
+            let holds = if is_true(eval(l)?) == decisive { decisive } else { is_true(eval(r)?) };
+            Ok(truth_value(holds))
+        }
+        Expr::Not(e) => Ok(truth_value(!is_true(eval(e)?))),
+        Expr::List(_) | Expr::Range(..) | Expr::Text(_) => Err(not_a_number(expr)),
+        // Every other node is arithmetic
+        _ => eval(expr),
+    }
+}
+
+/// The error for a list or range found where a number is needed
+pub(crate) fn not_a_number(found: &Expr) -> ComputeError {
+    let kind = match found {
+        Expr::Range(..) => "range",
+        Expr::Text(_) => "text",
+        _ => "list",
+    };
+    ComputeError::TypeMismatch(format!("expected a number but found the {} {}", kind, found))
+}
+
+/// The value of an expression that may be a list or text
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Number(f64),
+    List(Vec<f64>),
+    Text(String),
+}
+
+impl Value {
+    /// The value written out with every number in `format`
+    pub fn format(&self, format: &NumberFormat) -> String {
+        match self {
+            Value::Number(n) => format.format(*n),
+            Value::List(elements) => {
+                let elements: Vec<String> = elements.iter().map(|&n| format.format(n)).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Value::Text(text) => text.clone(),
+        }
+    }
+}
+
+/// Numbers are written as `format_number` writes them, or with a fixed count of decimals
+/// when a precision is given, as in `{:.2}`
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = NumberFormat {
+            decimals: f.precision().map(|places| places as u32),
+            ..Default::default()
+        };
+        f.write_str(&self.format(&format))
+    }
+}
+
+/// Evaluate an expression that may be a list literal, such as `[1, 2 * 3]`, or text, such as `to_hex(255)`
+///
+/// A list evaluates each element as a number; lists do not nest. A call to
+/// a function with a `radix` is the `Text` of its whole-number argument. Any
+/// other expression is a `Number`, evaluated like `eval_expr_in`.
+pub fn eval_value_in(expr: &Expr, env: &Bindings, angle_mode: AngleMode) -> Result<Value> {
+    eval_value_with(expr, |e| eval_expr_in(e, env, angle_mode))
+}
+
+/// Like `eval_value_in`, computing each number, list element or radix argument with `eval`
+fn eval_value_with(expr: &Expr, eval: impl Fn(&Expr) -> Result<f64>) -> Result<Value> {
+    match expr {
+        Expr::Call(name, args) if builtin(name).and_then(Builtin::radix).is_some() => {
+            let base = functions::resolve(name, args.len())?.radix().unwrap_or(10);
+            radix::format_integer(eval(&args[0])?, base).map(Value::Text)
+        }
+        Expr::List(elements) => elements.iter().map(eval).collect::<Result<_>>().map(Value::List),
+        _ => eval(expr).map(Value::Number),
+    }
+}
+
+/// Evaluate an expression string that may be a list, rounding every number by `result_places`
+///
+/// Every number is computed as `evaluate_with` computes one, so the
+/// arithmetic, evaluator, `fuel` budget and `strict_finite` check all apply,
+/// each list element getting a budget of its own.
+pub fn evaluate_value(expr: &str, env: &Bindings, options: &EvalOptions) -> Result<Value> {
+    options.parse.check_len(expr)?;
+    let expr = expr.trim();
+    if expr.is_empty() {
+        return Err(ComputeError::EmptyExpression);
+    }
+    match eval_value_with(&options.parse(expr)?, |e| options.eval(e, env))? {
+        Value::Number(n) => Ok(Value::Number(options.round_result(n))),
+        Value::List(elements) => Ok(Value::List(elements.into_iter().map(|n| options.round_result(n)).collect())),
+        text => Ok(text),
     }
 }
 
@@ -187,20 +1606,225 @@ pub fn eval_expr(expr: &Expr) -> Result<f64> {
 pub struct EvaluationResult {
     pub expression: String,
     pub value: Result<f64>,
+    /// The exact-arithmetic cross-check, when `EvalOptions::verify` asks for one and the input parses
+    pub verification: Option<Verification>,
+    /// What evaluating the expression cost, when `EvalOptions::metadata` asks for it and the input parses
+    pub metadata: Option<ResultMetadata>,
+    /// The uncertainty of the value, see `eval_uncertain`, when `EvalOptions::uncertainty` asks for it and the value is
+    /// computed
+    pub uncertainty: Option<f64>,
+}
+
+/// Cost and exactness of evaluating one expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResultMetadata {
+    /// Time spent parsing the expression, zero without the `std` feature
+    pub parse_time: Duration,
+    /// Time spent evaluating the parsed expression, zero without the `std` feature
+    pub eval_time: Duration,
+    /// Levels in the expression tree, see `Expr::depth`
+    pub depth: usize,
+    /// Nodes in the expression tree, see `Expr::node_count`
+    pub node_count: usize,
+    /// Whether the reported value is exactly the expression's value, see `is_exact`
+    pub exact: bool,
+}
+
+impl ResultMetadata {
+    /// The metadata as a JSON object, with times in microseconds
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "parse_us": self.parse_time.as_secs_f64() * 1e6,
+            "eval_us": self.eval_time.as_secs_f64() * 1e6,
+            "depth": self.depth,
+            "node_count": self.node_count,
+            "exact": self.exact,
+        })
+    }
 }
 
 /// Evaluate multiple expressions in a batch
 pub fn evaluate_batch(expressions: &[&str]) -> Vec<EvaluationResult> {
+    evaluate_batch_with(expressions, &EvalOptions::default())
+}
+
+/// Evaluate multiple expressions in a batch with explicit options
+///
+/// Each distinct expression string is evaluated once and its result copied
+/// to every position it occurs at, so heavily repetitive batches cost only
+/// as much as their unique entries.
+pub fn evaluate_batch_with(expressions: &[&str], options: &EvalOptions) -> Vec<EvaluationResult> {
+    let mut seen: HashMap<&str, EvaluationResult> = HashMap::new();
     expressions
         .iter()
-        .map(|&expr| EvaluationResult {
-            expression: expr.to_string(),
-            value: evaluate(expr),
+        .map(|&expr| {
+            seen.entry(expr).or_insert_with(|| evaluate_entry(expr, options)).clone()
         })
         .collect()
 }
 
+/// Evaluate expressions lazily, one result per input in order
+///
+/// Each expression is evaluated only when its result is pulled, and nothing
+/// is kept between items, so an input of millions of expressions, such as
+/// the lines of a file, streams through in constant memory. Unlike
+/// `evaluate_batch`, repeated expressions are evaluated every time.
+pub fn evaluate_iter<I>(expressions: I) -> impl Iterator<Item = EvaluationResult>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let options = EvalOptions::default();
+    expressions.into_iter().map(move |expr| evaluate_entry(expr.as_ref(), &options))
+}
+
+/// Like `evaluate_iter`, with explicit options
+pub fn evaluate_iter_with<'a, I>(
+    expressions: I,
+    options: &'a EvalOptions,
+) -> impl Iterator<Item = EvaluationResult> + 'a
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+    I::IntoIter: 'a,
+{
+    expressions.into_iter().map(move |expr| evaluate_entry(expr.as_ref(), options))
+}
+
+fn evaluate_entry(expr: &str, options: &EvalOptions) -> EvaluationResult {
+    if options.metadata {
+        return evaluate_measured(expr, &Bindings::new(), options);
+    }
+    EvaluationResult {
+        expression: expr.to_string(),
+        value: evaluate_with(expr, options),
+        verification: verify_with(expr, options),
+        metadata: None,
+        uncertainty: uncertainty_with(expr, options),
+    }
+}
+
+/// Evaluate `expr` in `env`, timing parsing and evaluation into `EvaluationResult::metadata`
+///
+/// The expression always goes through the parser and the configured
+/// evaluator, never the fast path, so the times add up to the whole cost.
+/// Input that does not parse gets no metadata. The result is verified when
+/// `EvalOptions::verify` asks, like a batch result.
+pub fn evaluate_measured(expr: &str, env: &Bindings, options: &EvalOptions) -> EvaluationResult {
+    let started = Stopwatch::start();
+    let parsed = options.parse.check_len(expr).and_then(|_| match expr.trim() {
+        "" => Err(ComputeError::EmptyExpression),
+        trimmed => options.parse(trimmed),
+    });
+    let parse_time = started.elapsed();
+    let ast = match parsed {
+        Ok(ast) => ast,
+        Err(e) => {
+            return EvaluationResult {
+                expression: expr.to_string(),
+                value: Err(e),
+                verification: None,
+                metadata: None,
+                uncertainty: None,
+            }
+        }
+    };
+    let started = Stopwatch::start();
+    let unrounded = options.eval(&ast, env);
+    let eval_time = started.elapsed();
+    let value = unrounded.clone().map(|value| options.round_result(value));
+    EvaluationResult {
+        expression: expr.to_string(),
+        verification: options
+            .verify
+            .map(|tolerance| verify_rounding(&ast, env, &unrounded, tolerance, options.angle_mode, options.rounding)),
+        metadata: Some(ResultMetadata {
+            parse_time,
+            eval_time,
+            depth: ast.depth(),
+            node_count: ast.node_count(),
+            exact: is_exact_rounding(&ast, env, &value, options.angle_mode, options.rounding),
+        }),
+        uncertainty: (options.uncertainty && value.is_ok())
+            .then(|| eval_uncertain_rounding(&ast, env, options.angle_mode, options.rounding).ok())
+            .flatten()
+            .map(|result| result.uncertainty),
+        value,
+    }
+}
+
+/// Cross-check `expr` as `EvalOptions::verify` asks, comparing the unrounded f64 result
+fn verify_with(expr: &str, options: &EvalOptions) -> Option<Verification> {
+    let tolerance = options.verify?;
+    let ast = options.parse(expr.trim()).ok()?;
+    let env = Bindings::new();
+    let value = eval_expr_rounding(&ast, &env, options.angle_mode, options.rounding);
+    Some(verify_rounding(&ast, &env, &value, tolerance, options.angle_mode, options.rounding))
+}
+
+/// Propagate the uncertainties of `expr` as `EvalOptions::uncertainty` asks, for an expression whose value is computed
+fn uncertainty_with(expr: &str, options: &EvalOptions) -> Option<f64> {
+    if !options.uncertainty {
+        return None;
+    }
+    evaluate_uncertain(expr, options).ok().map(|result| result.uncertainty)
+}
+
+/// Evaluate each line read from `reader`, passing `(line_number, result)` to `sink`
+///
+/// Lines are read into one reused buffer and handed off as soon as they are
+/// evaluated, so memory use stays constant no matter how large the input is.
+/// Blank lines are skipped; line numbers start at 1. An I/O error, including
+/// invalid UTF-8, stops the stream and is returned.
+#[cfg(feature = "std")]
+pub fn evaluate_lines<R: BufRead>(reader: R, sink: impl FnMut(usize, EvaluationResult)) -> io::Result<()> {
+    evaluate_lines_with(reader, evaluate, sink)
+}
+
+/// Like `evaluate_lines`, computing each line's value with `eval`
+#[cfg(feature = "std")]
+pub fn evaluate_lines_with<R: BufRead>(
+    reader: R,
+    mut eval: impl FnMut(&str) -> Result<f64>,
+    sink: impl FnMut(usize, EvaluationResult),
+) -> io::Result<()> {
+    let result = |expr: &str| EvaluationResult {
+        expression: expr.to_string(),
+        value: eval(expr),
+        verification: None,
+        metadata: None,
+        uncertainty: None,
+    };
+    evaluate_lines_measured(reader, result, sink)
+}
+
+/// Like `evaluate_lines`, computing each line's whole result with `evaluate`, such as `evaluate_measured`
+///
+/// The result may be of any type, so `evaluate_value` works here as well.
+#[cfg(feature = "std")]
+pub fn evaluate_lines_measured<R: BufRead, T>(
+    mut reader: R,
+    mut evaluate: impl FnMut(&str) -> T,
+    mut sink: impl FnMut(usize, T),
+) -> io::Result<()> {
+    let mut line = String::new();
+    let mut line_number = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        line_number += 1;
+        let expr = line.trim();
+        if expr.is_empty() {
+            continue;
+        }
+        sink(line_number, evaluate(expr));
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::approx_constant)]
 mod tests {
     use super::*;
 
@@ -255,6 +1879,116 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_parse_power() {
+        // Right associative, binding tighter than `*` but looser than prefix minus
+        let expr = parse_expression("2 * -x ^ 3 ^ 2").unwrap();
+        assert_eq!(expr, Expr::Mul(
+            Box::new(Expr::Number(2.0)),
+            Box::new(Expr::Pow(
+                Box::new(Expr::Neg(Box::new(Expr::Var("x".into())))),
+                Box::new(Expr::Pow(
+                    Box::new(Expr::Number(3.0)),
+                    Box::new(Expr::Number(2.0))
+                ))
+            ))
+        ));
+        assert_eq!(expr.to_string(), "(2 * (-(x) ^ (3 ^ 2)))");
+    }
+
+    #[test]
+    fn test_parse_comparisons_and_conditionals() {
+        // Comparisons bind loosest of the infix operators; `?:` nests to the right
+        let expr = parse_expression("a + 1 < b * 2 == c ? x : y ? 1 : 0").unwrap();
+        assert_eq!(expr.to_string(), "((((a + 1) < (b * 2)) == c) ? x : (y ? 1 : 0))");
+        assert_eq!(parse_expression("a ? b ? 1 : 2 : 3").unwrap().to_string(), "(a ? (b ? 1 : 2) : 3)");
+        assert_eq!(
+            parse_expression("f(x >= 0 ? x : -x, y != 2)").unwrap().to_string(),
+            "f(((x >= 0) ? x : -(x)), (y != 2))"
+        );
+        for source in ["a = b", "a =< b", "a ? b", "a : b", "a ? b : c : d", "!a"] {
+            assert!(matches!(parse_expression(source), Err(ComputeError::ParseError(_))), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_comparisons_and_conditionals() {
+        assert_eq!(evaluate("2 < 3").unwrap(), 1.0);
+        assert_eq!(evaluate("2 >= 3").unwrap(), 0.0);
+        assert_eq!(evaluate("0.1 + 0.2 == 0.3").unwrap(), 0.0);
+        assert_eq!(evaluate("(1 < 2) + (2 <= 2) + (3 != 3)").unwrap(), 2.0);
+        assert_eq!(evaluate("-1 > 0 ? 1 : -1").unwrap(), -1.0);
+        // NaN is unordered, and as a condition it selects the second branch
+        assert_eq!(evaluate("sqrt(-1) != sqrt(-1)").unwrap(), 1.0);
+        assert_eq!(evaluate("sqrt(-1) ? 1 : 2").unwrap(), 2.0);
+
+        // Only the selected branch is evaluated
+        let env: Bindings = [("x".to_string(), 0.0)].into_iter().collect();
+        let guarded = parse_expression("x == 0 ? 0 : 1 / x").unwrap();
+        assert_eq!(eval_expr_with_env(&guarded, &env), Ok(0.0));
+        let capped = parse_expression("x > 10 ? 10 : x").unwrap();
+        assert_eq!(eval_expr_with_env(&capped, &[("x".to_string(), 12.5)].into_iter().collect()), Ok(10.0));
+        assert_eq!(evaluate("1 ? 2 : 1 / 0"), Ok(2.0));
+        assert_eq!(evaluate("1 / 0 ? 2 : 3"), Err(ComputeError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_parse_logical_operators() {
+        // `or` binds loosest, then `and`, then `not`, all below the comparisons
+        let expr = parse_expression("not a < b and c or not not d ? 1 : 0").unwrap();
+        assert_eq!(expr.to_string(), "((((not (a < b)) and c) or (not (not d))) ? 1 : 0)");
+        assert_eq!(parse_expression("a or b or c").unwrap().to_string(), "((a or b) or c)");
+        assert_eq!(parse_expression("android and notx").unwrap().to_string(), "(android and notx)");
+        for source in ["a and", "not", "and + 1", "a < not b", "2 * not x", "-not x", "or(1)", "a andb"] {
+            assert!(matches!(parse_expression(source), Err(ComputeError::ParseError(_))), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_logical_operators() {
+        assert_eq!(evaluate("1 < 2 and 3 < 4"), Ok(1.0));
+        assert_eq!(evaluate("2 and 0 or -3"), Ok(1.0));
+        assert_eq!(evaluate("not 5"), Ok(0.0));
+        assert_eq!(evaluate("not sqrt(-1)"), Ok(1.0));
+        // The right operand is only evaluated when the left does not decide the result
+        assert_eq!(evaluate("0 and 1 / 0"), Ok(0.0));
+        assert_eq!(evaluate("1 or 1 / 0"), Ok(1.0));
+        assert_eq!(evaluate("1 and 1 / 0"), Err(ComputeError::DivisionByZero));
+        let env: Bindings = [("x".to_string(), 0.0)].into_iter().collect();
+        let valid = parse_expression("x != 0 and 1 / x > 2").unwrap();
+        assert_eq!(eval_expr_with_env(&valid, &env), Ok(0.0));
+    }
+
+    #[test]
+    fn test_parse_percentages() {
+        assert_eq!(parse_expression("12.5%"), Ok(Expr::Percent(12.5)));
+        assert_eq!(parse_expression("200 + 10%").unwrap().to_string(), "(200 + 10%)");
+        // A percentage binds like any other literal
+        assert_eq!(parse_expression("x - 5% * 2").unwrap().to_string(), "(x - (5% * 2))");
+        assert_eq!(parse_expression("-50%^2").unwrap().to_string(), "(-(50%) ^ 2)");
+        for source in ["10 %", "%", "x%", "(1 + 2)%", "10%%", "10%5"] {
+            assert!(matches!(parse_expression(source), Err(ComputeError::ParseError(_))), "{}", source);
+        }
+        assert_eq!(parse_expression("1e999%"), Err(ComputeError::Overflow));
+    }
+
+    #[test]
+    fn test_evaluate_percentages() {
+        assert_eq!(evaluate("50%"), Ok(0.5));
+        assert_eq!(evaluate("200 + 10%"), Ok(220.0));
+        assert_eq!(evaluate("200 - 10%"), Ok(180.0));
+        assert_eq!(evaluate("100 - 10% - 10%"), Ok(81.0));
+        assert_eq!(evaluate("2 * 50% + 1"), Ok(2.0));
+        assert_eq!(evaluate("-50%"), Ok(-0.5));
+        // Only a percentage directly on the right of `+` or `-` is taken relative to the left
+        assert_eq!(evaluate("200 + 10% * 2"), Ok(200.2));
+        assert_eq!(evaluate("200 + (10%)"), Ok(220.0));
+        assert_eq!(evaluate("200 + -10%"), Ok(199.9));
+        assert_eq!(evaluate("10% + 200"), Ok(200.1));
+        assert_eq!(evaluate("2 * 100 + 10%"), Ok(220.0));
+        assert_eq!(evaluate("1 < 2 + 50%"), Ok(1.0));
+    }
+
     #[test]
     fn test_parse_parentheses() {
         let expr = parse_expression("(2 + 3) * 4").unwrap();
@@ -267,6 +2001,17 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_unicode_operators_and_spaces() {
+        assert_eq!(parse_expression("6 × 2 ÷ 4 − −1"), parse_expression("6 * 2 / 4 - -1"));
+        assert_eq!(evaluate("\u{3000}2\u{A0}×\u{202F}(1 − 4)\u{3000}"), Ok(-6.0));
+        assert_eq!(evaluate("2 −−1"), Ok(3.0));
+        // A no-break space separates tokens like any other space, so it is no digit separator
+        for source in ["1\u{A0}000", "2 ×", "÷ 2", "2 ×× 2", "2 ∙ 3", "1\u{2009}+ 1"] {
+            assert!(matches!(parse_expression(source), Err(ComputeError::ParseError(_))), "{}", source);
+        }
+    }
+
     #[test]
     fn test_parse_unary_minus() {
         let expr = parse_expression("-(2 + 3)").unwrap();
@@ -292,6 +2037,13 @@ mod tests {
         assert_eq!(evaluate("2 + 3 * 4").unwrap(), 14.0);
         assert_eq!(evaluate("10 - 2 * 3").unwrap(), 4.0);
         assert_eq!(evaluate("12 / 3 + 2").unwrap(), 6.0);
+        assert_eq!(evaluate("2 * 3 ^ 2").unwrap(), 18.0);
+        assert_eq!(evaluate("2 ^ 3 ^ 2").unwrap(), 512.0);
+        assert_eq!(evaluate("(2 ^ 3) ^ 2").unwrap(), 64.0);
+        assert_eq!(evaluate("-2 ^ 2").unwrap(), 4.0);
+        assert_eq!(evaluate("-(2 ^ 2)").unwrap(), -4.0);
+        assert_eq!(evaluate("4 ^ -0.5").unwrap(), 0.5);
+        assert!(evaluate("(-8) ^ (1 / 3)").unwrap().is_nan());
     }
 
     #[test]
@@ -320,8 +2072,20 @@ mod tests {
         assert!(matches!(evaluate(""), Err(ComputeError::EmptyExpression)));
         assert!(matches!(evaluate("   "), Err(ComputeError::EmptyExpression)));
         assert!(matches!(evaluate("2 +"), Err(ComputeError::ParseError(_))));
-        assert!(matches!(evaluate("hello"), Err(ComputeError::ParseError(_))));
-        assert!(matches!(evaluate("2 + + 3"), Err(ComputeError::ParseError(_))));
+        assert!(matches!(evaluate("hello"), Err(ComputeError::UndefinedVariable(_))));
+        assert!(matches!(evaluate("1a2"), Err(ComputeError::ParseError(_))));
+        assert!(matches!(evaluate("2 + * 3"), Err(ComputeError::ParseError(_))));
+        assert!(matches!(evaluate("2 * +"), Err(ComputeError::ParseError(_))));
+    }
+
+    #[test]
+    fn test_unary_plus() {
+        assert_eq!(evaluate("+5"), Ok(5.0));
+        assert_eq!(evaluate("3 * +2"), Ok(6.0));
+        assert_eq!(evaluate("2 + + 3"), Ok(5.0));
+        assert_eq!(evaluate("-+-+4 ^ 2"), Ok(16.0));
+        assert_eq!(parse_expression("+x"), parse_expression("x"));
+        assert_eq!(parse_expression("+-x").unwrap().to_string(), "-(x)");
     }
 
     #[test]
@@ -340,6 +2104,351 @@ mod tests {
         assert_eq!(evaluate("2\t+\t3").unwrap(), 5.0);
     }
 
+    #[test]
+    fn test_batch_duplicates_keep_positions() {
+        let results = evaluate_batch(&["1 + 1", "1 / 0", "1 + 1", "2 * 3", "1 / 0"]);
+        let values: Vec<_> = results.iter().map(|r| r.value.clone()).collect();
+        assert_eq!(
+            values,
+            vec![
+                Ok(2.0),
+                Err(ComputeError::DivisionByZero),
+                Ok(2.0),
+                Ok(6.0),
+                Err(ComputeError::DivisionByZero),
+            ]
+        );
+        assert_eq!(results[2].expression, "1 + 1");
+    }
+
+    #[test]
+    fn test_batch_verification() {
+        assert!(evaluate_batch(&["1 + 1"])[0].verification.is_none());
+
+        let options = EvalOptions { verify: Some(1e-9), result_places: Some(0), ..Default::default() };
+        let results = evaluate_batch_with(&["1 / 3", "(1e16 + 1) - 1e16", "2 +"], &options);
+        // Rounding for presentation is not a discrepancy
+        assert_eq!(results[0].value, Ok(0.0));
+        assert!(results[0].verification.as_ref().unwrap().agrees);
+        let cancelled = results[1].verification.as_ref().unwrap();
+        assert!(!cancelled.agrees);
+        assert_eq!(cancelled.reference, Ok(1.0));
+        assert!(results[2].verification.is_none());
+    }
+
+    #[test]
+    fn test_batch_metadata() {
+        assert!(evaluate_batch(&["1 + 1"])[0].metadata.is_none());
+
+        let options = EvalOptions { metadata: true, verify: Some(1e-9), ..Default::default() };
+        let results = evaluate_batch_with(&["(1 + 2) * 4", "0.1 + 0.2", "1 / 0", "2 +"], &options);
+        let metadata = results[0].metadata.unwrap();
+        assert_eq!((metadata.depth, metadata.node_count, metadata.exact), (3, 5, true));
+        assert_eq!(results[0].value, Ok(12.0));
+        assert!(results[0].verification.as_ref().unwrap().agrees);
+        assert!(!results[1].metadata.unwrap().exact);
+        assert_eq!(results[2].value, Err(ComputeError::DivisionByZero));
+        assert!(!results[2].metadata.unwrap().exact);
+        assert!(results[3].metadata.is_none());
+
+        let json = metadata.to_json();
+        assert_eq!(json["node_count"], 5);
+        assert!(json["parse_us"].as_f64().unwrap() >= 0.0);
+    }
+
+    #[test]
+    fn test_evaluate_iter() {
+        let values: Vec<_> = evaluate_iter(["1 + 1", "1 / 0", "1 + 1"]).map(|r| r.value).collect();
+        assert_eq!(values, vec![Ok(2.0), Err(ComputeError::DivisionByZero), Ok(2.0)]);
+
+        // Inputs are consumed only as results are pulled
+        let mut pulled = 0;
+        let lines = (1..).inspect(|_| pulled += 1).map(|n| format!("{} * 2", n));
+        let first: Vec<_> = evaluate_iter(lines).take(3).map(|r| r.value).collect();
+        assert_eq!(first, vec![Ok(2.0), Ok(4.0), Ok(6.0)]);
+        assert_eq!(pulled, 3);
+
+        let options = EvalOptions { verify: Some(1e-9), result_places: Some(1), ..Default::default() };
+        let result = evaluate_iter_with(vec![String::from(" 1 / 3 ")], &options).next().unwrap();
+        assert_eq!(result.expression, " 1 / 3 ");
+        assert_eq!(result.value, Ok(0.3));
+        assert!(result.verification.unwrap().agrees);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_evaluate_lines() {
+        let input = "1 + 1\n\n  2 * 3  \r\n1 / 0\nx";
+        let mut results = Vec::new();
+        evaluate_lines(input.as_bytes(), |line, result| results.push((line, result))).unwrap();
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].0, 1);
+        assert_eq!(results[0].1.value, Ok(2.0));
+        assert_eq!(results[1].0, 3);
+        assert_eq!(results[1].1.expression, "2 * 3");
+        assert_eq!(results[2].1.value, Err(ComputeError::DivisionByZero));
+        assert_eq!(results[3].0, 5);
+        assert!(matches!(results[3].1.value, Err(ComputeError::UndefinedVariable(_))));
+
+        let invalid: &[u8] = b"1 + 1\n\xff\n";
+        assert!(evaluate_lines(invalid, |_, _| {}).is_err());
+    }
+
+    #[test]
+    fn test_evaluate_with_env() {
+        let env: Bindings = [("x".to_string(), 4.0)].into_iter().collect();
+        assert_eq!(evaluate_with_env("x * 2 + 1", &env), Ok(9.0));
+        assert_eq!(evaluate_with_env("1 + 1", &env), Ok(2.0));
+        assert_eq!(evaluate_with_env("y", &env), Err(ComputeError::UndefinedVariable("y".into())));
+
+        #[cfg(feature = "std")]
+        {
+            let mut values = Vec::new();
+            let eval = |expr: &str| evaluate_with_env(expr, &env);
+            evaluate_lines_with("x\nx / 0".as_bytes(), eval, |_, r| values.push(r.value)).unwrap();
+            assert_eq!(values, vec![Ok(4.0), Err(ComputeError::DivisionByZero)]);
+        }
+    }
+
+    #[test]
+    fn test_literal_overflow_policy() {
+        assert_eq!(evaluate("1e308").unwrap(), 1e308);
+        assert_eq!(evaluate("1.7976931348623157e308").unwrap(), f64::MAX);
+        // Still within half an ulp of f64::MAX, so it rounds down rather than overflowing
+        assert_eq!(evaluate("1.7976931348623158e308").unwrap(), f64::MAX);
+        assert!(matches!(evaluate("1.7976931348623159e308"), Err(ComputeError::Overflow)));
+        assert!(matches!(evaluate("1e999"), Err(ComputeError::Overflow)));
+        assert!(matches!(evaluate("1 + -1e400 * 0"), Err(ComputeError::Overflow)));
+
+        let clamp = ParseOptions { literal_overflow: LiteralOverflow::Clamp, ..Default::default() };
+        assert_eq!(parse_expression_with("1e999", &clamp).unwrap(), Expr::Number(f64::MAX));
+        assert_eq!(
+            parse_expression_with("-1e999", &clamp).unwrap(),
+            Expr::Neg(Box::new(Expr::Number(f64::MAX)))
+        );
+
+        let propagate = ParseOptions { literal_overflow: LiteralOverflow::Propagate, ..Default::default() };
+        let ast = parse_expression_with("1e999 - 1", &propagate).unwrap();
+        assert_eq!(eval_expr(&ast).unwrap(), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_evaluate_with_options() {
+        assert_eq!(evaluate_with("2 + 3 * 4", &EvalOptions::default()), evaluate("2 + 3 * 4"));
+
+        let clamp = EvalOptions {
+            parse: ParseOptions { literal_overflow: LiteralOverflow::Clamp, ..Default::default() },
+            ..Default::default()
+        };
+        assert_eq!(evaluate("1e999 - 1e308"), Err(ComputeError::Overflow));
+        assert_eq!(evaluate_with("1e999 - 1e308", &clamp), Ok(f64::MAX - 1e308));
+
+        let pest = EvalOptions { backend: ParserBackend::Pest, ..Default::default() };
+        assert_eq!(evaluate_with("-(1 + 2) / 4", &pest), Ok(-0.75));
+        assert_eq!(evaluate_with("  ", &pest), Err(ComputeError::EmptyExpression));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let small = ParseOptions { memory_limit: Some(4096), ..Default::default() };
+        assert_eq!(parse_expression_with("1 + 2 * 3", &small), parse_expression("1 + 2 * 3"));
+
+        let deep = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        assert!(matches!(
+            parse_expression_with(&deep, &small),
+            Err(ComputeError::LimitExceeded(_))
+        ));
+        assert!(parse_expression(&deep).is_ok());
+        assert!(parse_memory_estimate(&deep, false) > 300 * PAIR_BYTES);
+        assert!(parse_memory_estimate("2pi x", true) > parse_memory_estimate("2 * pi * x", false));
+    }
+
+    #[test]
+    fn test_tree_limits() {
+        let shallow = ParseOptions { max_depth: Some(3), ..Default::default() };
+        assert!(parse_expression_with("1 + 2 * 3", &shallow).is_ok());
+        assert!(parse_expression_with("sum([1, 2, 3])", &shallow).is_ok());
+        assert_eq!(
+            parse_expression_with("1 + 2 * -3", &shallow).unwrap_err().to_string(),
+            "Limit exceeded: the expression is 4 levels deep, over the 3 level limit"
+        );
+
+        let small = ParseOptions { max_nodes: Some(5), ..Default::default() };
+        assert!(parse_expression_with("(1 + 2) * 3", &small).is_ok());
+        assert_eq!(
+            parse_expression_with("max(1, 2, 3, 4, 5)", &small).unwrap_err().to_string(),
+            "Limit exceeded: the expression has 6 nodes, over the 5 node limit"
+        );
+
+        // Evaluation, sessions and the descent parser share the limits
+        let options = EvalOptions { parse: small, ..Default::default() };
+        assert!(matches!(evaluate_with("1 + 2 + 3 + 4", &options), Err(ComputeError::LimitExceeded(_))));
+        let mut session = Session::with_options(options);
+        assert!(matches!(session.eval("x = 1 + 2 + 3 + 4"), Err(ComputeError::LimitExceeded(_))));
+        #[cfg(feature = "descent")]
+        assert!(matches!(
+            descent::parse_expression_with("1 + 2 * -3", &shallow),
+            Err(ComputeError::LimitExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn test_signed_zero_divisors() {
+        for source in ["1 / -0", "-1 / -0", "1 / (0 * -1)", "2 / -0%", "0 / -0", "1 / -(3 - 3)"] {
+            let expr = parse_expression(source).unwrap();
+            assert_eq!(evaluate(source), Err(ComputeError::DivisionByZero), "{}", source);
+            assert_eq!(eval_expr(&expr), Err(ComputeError::DivisionByZero), "{}", source);
+            assert_eq!(eval_with_fuel(&expr, &Bindings::new(), AngleMode::Radians, 100), eval_expr(&expr));
+            assert_eq!(eval_expr_traced(&expr, &mut Vec::new()), eval_expr(&expr));
+            assert_eq!(eval_exact(&expr, &Bindings::new(), AngleMode::Radians), Err(ComputeError::DivisionByZero));
+            assert_eq!(evaluate_fixed(source, &QFormat::new(8)), Err(ComputeError::DivisionByZero));
+            let mut evaluator = Evaluator::new();
+            evaluator.on_operation(|_| {});
+            assert_eq!(evaluator.evaluate(source), Err(ComputeError::DivisionByZero), "{}", source);
+            assert!(evaluate_many(&expr, &[Bindings::new()])[0].is_nan(), "{}", source);
+        }
+        // A negative zero elsewhere is an ordinary zero
+        assert_eq!(evaluate("-0 / 5"), Ok(-0.0));
+        assert_eq!(evaluate("5 * -0 + 1"), Ok(1.0));
+    }
+
+    #[test]
+    fn test_strict_finite() {
+        let strict = EvalOptions { strict_finite: true, ..Default::default() };
+        assert_eq!(evaluate("1e308 * 10"), Ok(f64::INFINITY));
+        assert_eq!(evaluate_with("1e308 * 10", &strict), Err(ComputeError::Overflow));
+        assert_eq!(evaluate_with("-1e308 - 1e308", &strict), Err(ComputeError::Overflow));
+        assert_eq!(evaluate_with("sqrt(-1)", &strict), Err(ComputeError::NotANumber));
+        assert_eq!(evaluate_with("0 * (1e308 * 10)", &strict), Err(ComputeError::NotANumber));
+        assert_eq!(ComputeError::NotANumber.to_string(), "Result is not a number");
+        // Only the result must be finite, not every intermediate value
+        assert_eq!(evaluate_with("1 / (1e308 * 10)", &strict), Ok(0.0));
+
+        let env: Bindings = [("x".to_string(), f64::NAN)].into_iter().collect();
+        assert_eq!(evaluate_in("x + 1", &env, &strict), Err(ComputeError::NotANumber));
+        assert_eq!(Session::with_options(strict).eval("y = ln(0)"), Err(ComputeError::Overflow));
+    }
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(
+            evaluate("1 / 0").unwrap_err().to_json(),
+            serde_json::json!({ "code": "E002_DIV_ZERO", "category": "arithmetic", "message": "Division by zero" })
+        );
+        let parse = evaluate("1 + * 2").unwrap_err();
+        assert_eq!((parse.code(), parse.category()), ("E001_PARSE", ErrorCategory::Syntax));
+        assert_eq!(parse.to_json()["span"], serde_json::json!({ "start": 4, "end": 4 }));
+        assert!(!parse.to_json()["message"].as_str().unwrap().contains('\n'));
+        assert_eq!(evaluate("x").unwrap_err().category(), ErrorCategory::Name);
+        assert_eq!(evaluate("sum(1..1e9)").unwrap_err().category(), ErrorCategory::Limit);
+        assert_eq!(evaluate("[1] + 1").unwrap_err().code(), "E011_TYPE_MISMATCH");
+        assert_eq!(evaluate("x").unwrap_err().span(), None);
+    }
+
+    #[test]
+    fn test_max_nesting() {
+        assert_eq!(nesting_depth("1 + 2 * 3 - 4"), (0, 3));
+        assert_eq!(nesting_depth("2 ^ 3 ^ 4"), (2, 0));
+        assert_eq!(nesting_depth("max(a ? b : c, d)"), (2, 1));
+        assert_eq!(nesting_depth("not not x and -(-1)"), (3, 1));
+        assert_eq!(nesting_depth("len(\"(((\") - -1"), (1, 1));
+        assert_eq!(nesting_depth("1 + (2 + 3) + (4 + 5 + 6), 7 + 8"), (1, 4));
+
+        let options = EvalOptions {
+            parse: ParseOptions { max_nesting: 2, ..Default::default() },
+            ..Default::default()
+        };
+        assert_eq!(evaluate_with("((1))", &options), Ok(1.0));
+        assert_eq!(
+            evaluate_with("(((1)))", &options).unwrap_err().to_string(),
+            "Limit exceeded: the expression nests 3 levels deep, over the 2 level nesting limit"
+        );
+
+        let options = EvalOptions {
+            parse: ParseOptions { max_chain: 2, ..Default::default() },
+            ..Default::default()
+        };
+        assert_eq!(evaluate_with("1 + (2 + 3)", &options), Ok(6.0));
+        assert_eq!(
+            evaluate_with("1 + 2 + 3 + 4", &options).unwrap_err().to_string(),
+            "Limit exceeded: the expression chains 3 operators, over the 2 operator chain limit"
+        );
+    }
+
+    #[test]
+    fn test_limits_apply_to_flat_expressions() {
+        // `a op b [op c]` skips the parser when no limit needs its tree
+        let limited = |parse| EvalOptions { parse, ..Default::default() };
+        for parse in [
+            ParseOptions { max_depth: Some(1), ..Default::default() },
+            ParseOptions { max_nodes: Some(1), ..Default::default() },
+            ParseOptions { memory_limit: Some(1), ..Default::default() },
+        ] {
+            for expr in ["1 + 2", "2 * 3 - 4", "10 / 0"] {
+                let result = evaluate_with(expr, &limited(parse.clone()));
+                assert!(matches!(result, Err(ComputeError::LimitExceeded(_))), "{} {:?}: {:?}", expr, parse, result);
+            }
+        }
+        let tight = limited(ParseOptions { max_depth: Some(2), max_nodes: Some(3), ..Default::default() });
+        assert_eq!(evaluate_with("1 + 2", &tight), Ok(3.0));
+        assert!(matches!(evaluate_with("1 + 2 * 3", &tight), Err(ComputeError::LimitExceeded(_))));
+        let clamped = limited(ParseOptions { literal_overflow: LiteralOverflow::Clamp, ..Default::default() });
+        assert_eq!(evaluate_with("1e999 - 1", &clamped), Ok(f64::MAX));
+    }
+
+    #[test]
+    fn test_max_expression_len() {
+        let short = EvalOptions {
+            parse: ParseOptions { max_expression_len: 8, ..Default::default() },
+            ..Default::default()
+        };
+        assert_eq!(evaluate_with("1 + 2", &short), Ok(3.0));
+        assert_eq!(
+            evaluate_with("1 + 2 + 3", &short),
+            Err(ComputeError::ExpressionTooLong { len: 9, max: 8 })
+        );
+        assert_eq!(
+            parse_expression_with("(1 + 2) * 3", &short.parse),
+            Err(ComputeError::ExpressionTooLong { len: 11, max: 8 })
+        );
+
+        let huge = "1+".repeat(DEFAULT_MAX_EXPRESSION_LEN);
+        assert!(matches!(evaluate(&huge), Err(ComputeError::ExpressionTooLong { .. })));
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(0.0), "0");
+        assert_eq!(format_number(-0.0), "-0");
+        assert_eq!(format_number(2.5), "2.5");
+        assert_eq!(format_number(-42.0), "-42");
+        assert_eq!(format_number(1e300), "1e300");
+        assert_eq!(format_number(1.5e-7), "1.5e-7");
+        assert_eq!(format_number(123456.0), "123456");
+        assert_eq!(format_number(f64::INFINITY), "inf");
+        assert_eq!(format_number(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(format_number(1e16), "1e16");
+        assert_eq!(format_number(f64::MIN_POSITIVE), "2.2250738585072014e-308");
+        assert_eq!(format_number(5e-324), "5e-324");
+
+        let printed = Expr::Neg(Box::new(Expr::Number(-0.0))).to_string();
+        assert_eq!(printed, "-(-0)");
+        assert!(evaluate(&Expr::Number(-0.0).to_string()).unwrap().is_sign_negative());
+    }
+
+    #[test]
+    fn test_exact_printing() {
+        let expr = parse_expression("-5 * -(2 - -3) / 1e-300").unwrap();
+        let printed = expr.to_exact_string().unwrap();
+        assert_eq!(parse_expression(&printed).unwrap(), expr);
+
+        let negative = Expr::Add(Box::new(Expr::Number(1.0)), Box::new(Expr::Number(-5.0)));
+        assert!(matches!(negative.to_exact_string(), Err(ComputeError::InvalidStructure(_))));
+        assert!(Expr::Number(-0.0).to_exact_string().is_err());
+        assert!(Expr::Number(f64::NAN).to_exact_string().is_err());
+    }
+
     #[test]
     fn test_decimal_numbers() {
         assert_eq!(evaluate("3.14").unwrap(), 3.14);
@@ -347,4 +2456,213 @@ mod tests {
         assert_eq!(evaluate("10.0 / 4.0").unwrap(), 2.5);
         assert_eq!(evaluate("-3.14").unwrap(), -3.14);
     }
+
+    #[test]
+    fn test_leading_and_trailing_points() {
+        for (source, value) in [(".5", 0.5), ("5.", 5.0), ("1_000.", 1000.0), (".25e2", 25.0), ("2.e-1", 0.2)] {
+            assert_eq!(parse_expression(source), Ok(Expr::Number(value)), "{}", source);
+        }
+        assert_eq!(evaluate("5. * .5 - -.5"), Ok(3.0));
+        assert_eq!(evaluate("50.%"), Ok(0.5));
+        for source in [".", "1..2", "1.2.3", "..5", ". 5", "5 .", "._5"] {
+            assert!(matches!(parse_expression(source), Err(ComputeError::ParseError(_))), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_scientific_notation() {
+        for (source, value) in [("1e10", 1e10), ("1E10", 1e10), ("1e+10", 1e10), ("1.5e-3", 1.5e-3), ("2.5E+2", 250.0)] {
+            assert_eq!(parse_expression(source), Ok(Expr::Number(value)), "{}", source);
+        }
+        assert_eq!(evaluate("1E3 - 2e-1 * 5E+0"), Ok(999.0));
+        for source in ["1e", "1e+", "1E-", ".e5", "1e 5", "1e5.5", "1e++5"] {
+            assert!(matches!(parse_expression(source), Err(ComputeError::ParseError(_))), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_lists_and_aggregates() {
+        assert_eq!(evaluate("sum([1, 2, 3]) / count([1, 2, 3])"), Ok(2.0));
+        assert_eq!(evaluate("mean([2, 4 * 2]) + median([5, 1, 3]) - stddev([1, 1])"), Ok(8.0));
+        assert_eq!(evaluate("variance([1, 2, 3, 4])"), Ok(5.0 / 3.0));
+        assert_eq!(evaluate("sum([]) + count([])"), Ok(0.0));
+        assert_eq!(parse_expression("sum([1, x])").unwrap().to_string(), "sum([1, x])");
+
+        let env: Bindings = [("x".to_string(), 5.0)].into_iter().collect();
+        assert_eq!(evaluate_with_env("max(sum([x, -x]), mean([x]))", &env), Ok(5.0));
+        let thirds = EvalOptions { result_places: Some(2), ..EvalOptions::default() };
+        assert_eq!(evaluate_value("[1 / 3, x]", &env, &thirds), Ok(Value::List(vec![0.33, 5.0])));
+        assert_eq!(evaluate_value("sum([1 / 3])", &env, &thirds), Ok(Value::Number(0.33)));
+        // Lists follow the evaluation options like single numbers do
+        let strict = EvalOptions { strict_finite: true, fuel: Some(5), ..EvalOptions::default() };
+        assert_eq!(evaluate_value("[1, 1 / 0]", &env, &strict), Err(ComputeError::DivisionByZero));
+        assert_eq!(evaluate_value("[1e308 * 10]", &env, &strict), Err(ComputeError::Overflow));
+        assert!(matches!(evaluate_value("[sum(1..100)]", &env, &strict), Err(ComputeError::LimitExceeded(_))));
+        assert_eq!(Value::List(vec![1.5, -2.0]).to_string(), "[1.5, -2]");
+        assert_eq!(serde_json::to_string(&Value::List(vec![1.0])).unwrap(), "[1.0]");
+
+        assert_eq!(
+            evaluate("[1, 2] + 1").unwrap_err().to_string(),
+            "Type mismatch: expected a number but found the list [1, 2]"
+        );
+        assert_eq!(
+            evaluate("sum(1, 2)").unwrap_err().to_string(),
+            "sum() takes 1 argument but was given 2"
+        );
+        for source in ["sum(3)", "mean(x)", "abs([1])", "[]", "sum([[1]])"] {
+            assert!(matches!(evaluate(source), Err(ComputeError::TypeMismatch(_))), "{}", source);
+        }
+        for source in ["[1, 2", "[1,]", "[1 2]", "sum[1]", "]"] {
+            assert!(matches!(evaluate(source), Err(ComputeError::ParseError(_))), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_base_output() {
+        let env: Bindings = [("x".to_string(), 10.0)].into_iter().collect();
+        let options = EvalOptions::default();
+        let text = |source| evaluate_value(source, &env, &options).map(|value| value.to_string());
+        assert_eq!(text("to_hex(255)"), Ok("0xff".into()));
+        assert_eq!(text("to_bin(x + 5)"), Ok("0b1111".into()));
+        assert_eq!(text("to_oct(-x * 8)"), Ok("-0o120".into()));
+        assert_eq!(evaluate_value("to_hex(16)", &env, &options), Ok(Value::Text("0x10".into())));
+        assert_eq!(serde_json::to_string(&Value::Text("0x10".into())).unwrap(), "\"0x10\"");
+
+        assert!(matches!(text("to_hex(1 / 2)"), Err(ComputeError::TypeMismatch(_))));
+        assert_eq!(text("to_bin(1, 2)").unwrap_err().to_string(), "to_bin() takes 1 argument but was given 2");
+        assert_eq!(
+            evaluate("to_hex(255) + 1").unwrap_err().to_string(),
+            "Type mismatch: to_hex() gives text, so it can only be the whole expression, as in to_hex(255)"
+        );
+    }
+
+    #[test]
+    fn test_unit_conversion() {
+        assert_eq!(evaluate("convert(5, \"km\", \"m\") + convert(1, \"h\", \"s\")"), Ok(8600.0));
+        assert_eq!(evaluate("convert(98.6, \"F\", \"C\")"), Ok(37.0));
+        let env: Bindings = [("size".to_string(), 3.0)].into_iter().collect();
+        assert_eq!(evaluate_with_env("convert(size * 1024, \"MiB\", \"GiB\")", &env), Ok(3.0));
+        let source = "convert(-x, \"°C\", \"K\")";
+        assert_eq!(parse_expression(source).unwrap().to_string(), source.replace("-x", "-(x)"));
+
+        assert_eq!(
+            evaluate("convert(1, \"kg\", \"s\")").unwrap_err().to_string(),
+            "Type mismatch: cannot convert the mass \"kg\" to the time \"s\""
+        );
+        assert!(matches!(evaluate("convert(1, \"parsec\", \"m\")"), Err(ComputeError::InvalidStructure(_))));
+        for source in ["convert(1, km, m)", "convert(\"km\", 1, \"m\")", "\"km\" + 1", "abs(\"m\")"] {
+            assert!(matches!(evaluate(source), Err(ComputeError::TypeMismatch(_))), "{}", source);
+        }
+        assert!(matches!(evaluate("convert(1, \"km)"), Err(ComputeError::ParseError(_))));
+        let quoted = Expr::Text("say \"hi\"".into());
+        assert!(matches!(quoted.to_exact_string(), Err(ComputeError::InvalidStructure(_))));
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(evaluate("sum(1..100)"), Ok(5050.0));
+        assert_eq!(evaluate("product(1..10)"), Ok(3628800.0));
+        assert_eq!(evaluate("count(1.5..4) + sum(3..1) + product(2..1)"), Ok(4.0));
+        // An empty range sums to positive zero on every evaluator
+        let options = [
+            EvalOptions::default(),
+            EvalOptions { eval_backend: EvalBackend::Bytecode, ..Default::default() },
+            EvalOptions { fuel: Some(100), ..Default::default() },
+            EvalOptions { compensated_sums: true, ..Default::default() },
+            EvalOptions { arithmetic: Arithmetic::Integer, ..Default::default() },
+        ];
+        for options in &options {
+            let empty = evaluate_with("sum(5..1)", options).unwrap();
+            assert_eq!(empty.to_bits(), 0.0f64.to_bits(), "{:?}", options);
+            assert_eq!(evaluate_batch_arena(&["sum(5..1)"], options)[0].value.as_ref().map(|v| v.to_bits()), Ok(0));
+        }
+        assert_eq!(evaluate("mean(-2 .. 2 * 2)"), Ok(1.0));
+        assert_eq!(parse_expression("sum(1..n + 1)").unwrap().to_string(), "sum(1..(n + 1))");
+
+        let env: Bindings = [("n".to_string(), 4.0)].into_iter().collect();
+        assert_eq!(evaluate_with_env("sum(1..n) * product(n..n)", &env), Ok(40.0));
+        assert_eq!(
+            evaluate("sum(0..1e6)").unwrap_err().to_string(),
+            "Limit exceeded: range 0..1000000 has more than 100000 items"
+        );
+        assert!(matches!(evaluate("sum(1..-ln(0))"), Err(ComputeError::InvalidStructure(_))));
+        assert!(matches!(evaluate("abs(1..3)"), Err(ComputeError::TypeMismatch(_))));
+        for source in ["1..2", "[1..2]", "sum(1..2, 3)", "sum(1..)", "sum(..2)", "sum((1..2))"] {
+            assert!(matches!(evaluate(source), Err(ComputeError::ParseError(_))), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_implicit_multiplication() {
+        let implicit = ParseOptions { implicit_multiplication: true, ..Default::default() };
+        let parse = |source| parse_expression_with(source, &implicit).map(|expr| expr.to_string());
+        assert_eq!(parse("2(3 + 4)"), Ok("(2 * (3 + 4))".into()));
+        assert_eq!(parse("(2)(3)"), Ok("(2 * 3)".into()));
+        assert_eq!(parse("2pi"), Ok("(2 * pi)".into()));
+        assert_eq!(parse("-2 x y ^ 2"), Ok("((-(2) * x) * (y ^ 2))".into()));
+        assert_eq!(parse("1 / 2x + 3 sqrt(4)"), Ok("(((1 / 2) * x) + (3 * sqrt(4)))".into()));
+        assert_eq!(parse("2 and x or not y"), Ok("((2 and x) or (not y))".into()));
+        assert_eq!(parse("x(2)"), Ok("x(2)".into()));
+        for source in ["2 3", "(2)3", "2 -", "x not y", "2()"] {
+            assert!(matches!(parse(source), Err(ComputeError::ParseError(_))), "{}", source);
+        }
+
+        // Strict parsing, the default, still rejects every implied product
+        for source in ["2(3 + 4)", "(2)(3)", "2pi", "2 x"] {
+            assert!(matches!(parse_expression(source), Err(ComputeError::ParseError(_))), "{}", source);
+        }
+        let options = EvalOptions { parse: implicit, ..Default::default() };
+        let env: Bindings = [("r".to_string(), 3.0)].into_iter().collect();
+        assert_eq!(evaluate_in("2(r + 1)r", &env, &options), Ok(24.0));
+        let program = |source| evaluate_program_with(source, &mut Bindings::new(), &options);
+        assert_eq!(program("a = 2; 3(1 + a)a"), Ok(18.0));
+        // A name followed by parentheses is a call, even with a space between
+        assert_eq!(program("a = 2; 3a (1 + a)"), Err(ComputeError::UnknownFunction("a".into())));
+    }
+
+    #[test]
+    fn test_digit_separators() {
+        assert_eq!(parse_expression("1_000_000"), Ok(Expr::Number(1e6)));
+        assert_eq!(evaluate("1_000.000_5 * 2e1"), Ok(20000.01));
+        // Exponents are never grouped
+        for source in ["1__0", "1_", "1_.5", "1._5", "2e1_0", "1,000"] {
+            assert!(matches!(parse_expression(source), Err(ComputeError::ParseError(_))), "{}", source);
+        }
+
+        let commas = ParseOptions { comma_separators: true, ..Default::default() };
+        assert_eq!(parse_expression_with("1,234,567.5", &commas), Ok(Expr::Number(1234567.5)));
+        assert_eq!(parse_expression_with("12,345%", &commas), Ok(Expr::Percent(12345.0)));
+        assert_eq!(
+            parse_expression_with("atan2(1,000, 2) + atan2(1, 000) + atan2(x1,000)", &commas).unwrap().to_string(),
+            "((atan2(1000, 2) + atan2(1, 0)) + atan2(x1, 0))"
+        );
+        for source in ["1,0000", "1234,567", "1.5,000", "1,,000"] {
+            assert_eq!(
+                parse_expression_with(source, &commas).unwrap_err().to_string(),
+                parse_expression(source).unwrap_err().to_string(),
+                "{}",
+                source
+            );
+        }
+        assert_eq!(
+            evaluate_program_with("a = 2,500; a + 1,000", &mut Bindings::new(), &EvalOptions {
+                parse: commas,
+                ..Default::default()
+            }),
+            Ok(3500.0)
+        );
+    }
+
+    #[test]
+    fn test_tree_metrics() {
+        let leaf = parse_expression("x").unwrap();
+        assert_eq!((leaf.depth(), leaf.node_count()), (1, 1));
+        assert!(leaf.operator_histogram().is_empty());
+
+        let expr = parse_expression("-x + max(2, 10%) * 3 ^ y + max(1, 2)").unwrap();
+        assert_eq!(expr.depth(), 5);
+        assert_eq!(expr.node_count(), 14);
+        let expected = [("*", 1), ("+", 2), ("^", 1), ("max", 2), ("neg", 1)].map(|(name, n)| (name.to_string(), n));
+        assert_eq!(expr.operator_histogram(), BTreeMap::from(expected));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,237 @@
+//! Presentation of results: decimal places, significant figures, exponent notation and digit grouping
+
+use crate::prelude::*;
+use crate::{format_number, ComputeError, Result};
+use serde::{Deserialize, Serialize};
+
+/// Most digits `decimals` or `significant` may ask for, enough to write any `f64` exactly
+pub const MAX_FORMAT_DIGITS: u32 = 1074;
+
+/// How a number is written out for display
+///
+/// The default writes the shortest text that reads back as the same `f64`,
+/// exactly as `format_number` does, so `0.1 + 0.2` shows as
+/// `0.30000000000000004`; asking for 15 significant figures shows `0.3`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NumberFormat {
+    /// Layout of the digits
+    pub notation: Notation,
+    /// Digits after the decimal point (of the mantissa, with an exponent), trailing zeros included;
+    /// takes precedence over `significant`
+    pub decimals: Option<u32>,
+    /// Significant figures to round to, dropping trailing zeros as C's `%g` does
+    pub significant: Option<u32>,
+    /// Character placed between groups of three integer digits, as in `1,234,567`
+    pub thousands_separator: Option<char>,
+}
+
+/// Layout of a formatted number's digits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Notation {
+    /// Positional, as in `1234.5`; without `decimals`, magnitudes `format_number` writes
+    /// with an exponent keep it
+    #[default]
+    Auto,
+    /// One digit before the point and a power of ten, as in `1.2345e3`
+    Scientific,
+    /// One to three digits before the point and a power of ten that is a multiple of three,
+    /// as in `1.2345e3` or `12.5e-6`
+    Engineering,
+}
+
+impl NumberFormat {
+    /// Fail with `LimitExceeded` if `decimals` or `significant` is above `MAX_FORMAT_DIGITS`
+    pub fn check(&self) -> Result<()> {
+        for (name, digits) in [("decimals", self.decimals), ("significant", self.significant)] {
+            if let Some(digits) = digits.filter(|&digits| digits > MAX_FORMAT_DIGITS) {
+                return Err(ComputeError::LimitExceeded(format!(
+                    "{} is {}, more than {}",
+                    name, digits, MAX_FORMAT_DIGITS
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write `n` in this format; infinities and NaN are written as `format_number` writes them
+    ///
+    /// `decimals` and `significant` above `MAX_FORMAT_DIGITS` are treated as `MAX_FORMAT_DIGITS`.
+    pub fn format(&self, n: f64) -> String {
+        if !n.is_finite() {
+            return format_number(n);
+        }
+        let capped = NumberFormat {
+            decimals: self.decimals.map(|digits| digits.min(MAX_FORMAT_DIGITS)),
+            significant: self.significant.map(|digits| digits.min(MAX_FORMAT_DIGITS)),
+            ..*self
+        };
+        capped.format_capped(n)
+    }
+
+    fn format_capped(&self, n: f64) -> String {
+        let text = match self.notation {
+            Notation::Auto => match (self.decimals, self.significant) {
+                (Some(decimals), _) => format!("{:.*}", decimals as usize, n),
+                (None, Some(figures)) => format_number(round_significant(n, figures)),
+                (None, None) => format_number(n),
+            },
+            Notation::Scientific => self.exponential(n, 1),
+            Notation::Engineering => self.exponential(n, 3),
+        };
+        match self.thousands_separator {
+            Some(separator) => group_thousands(&text, separator),
+            None => text,
+        }
+    }
+
+    /// `n` with an exponent that is a multiple of `step`
+    fn exponential(&self, n: f64, step: i32) -> String {
+        // `{:e}` gives the shortest mantissa that reads back exactly, `{:.Ne}` one with N decimals
+        let scientific = |decimals: Option<usize>| match decimals {
+            Some(decimals) => format!("{:.*e}", decimals, n),
+            None => format!("{:e}", n),
+        };
+        let exponent = |text: &str| -> i32 { text[text.find('e').expect("an exponent") + 1..].parse().unwrap_or(0) };
+        let mut text = scientific(self.significant.map(|figures| figures.max(1) as usize - 1));
+        if let Some(decimals) = self.decimals {
+            // Shifting the point right by `shift` places leaves `decimals` of the `decimals + shift`
+            let mut shift = exponent(&text).rem_euclid(step);
+            text = scientific(Some(decimals as usize + shift as usize));
+            if exponent(&text).rem_euclid(step) != shift {
+                // Rounding carried into the next power of ten
+                shift = exponent(&text).rem_euclid(step);
+                text = scientific(Some(decimals as usize + shift as usize));
+            }
+        }
+
+        let (mantissa, _) = text.split_once('e').expect("an exponent");
+        let exponent = exponent(&text);
+        let shift = exponent.rem_euclid(step) as usize;
+        let (sign, mantissa) = match mantissa.strip_prefix('-') {
+            Some(magnitude) => ("-", magnitude),
+            None => ("", mantissa),
+        };
+        let mut digits = mantissa.replace('.', "");
+        while digits.len() < shift + 1 {
+            digits.push('0');
+        }
+        let (whole, fraction) = digits.split_at(shift + 1);
+        let fraction = if self.decimals.is_none() { fraction.trim_end_matches('0') } else { fraction };
+        let point = if fraction.is_empty() { "" } else { "." };
+        format!("{}{}{}{}e{}", sign, whole, point, fraction, exponent - shift as i32)
+    }
+}
+
+/// `n` rounded to `figures` significant figures, at least one
+fn round_significant(n: f64, figures: u32) -> f64 {
+    // Formatting rounds the decimal digits exactly, so parsing them back is the nearest f64
+    format!("{:.*e}", figures.max(1) as usize - 1, n).parse().unwrap_or(n)
+}
+
+/// `text` with `separator` between each group of three digits of its integer part
+fn group_thousands(text: &str, separator: char) -> String {
+    let (sign, rest) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text),
+    };
+    let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let (whole, tail) = rest.split_at(end);
+    let mut grouped = String::from(sign);
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped.push_str(tail);
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with(notation: Notation, decimals: Option<u32>, significant: Option<u32>) -> NumberFormat {
+        NumberFormat {
+            notation,
+            decimals,
+            significant,
+            thousands_separator: None,
+        }
+    }
+
+    #[test]
+    fn test_default_matches_format_number() {
+        for n in [0.1 + 0.2, 42.0, -0.0, 1e300, 1.5e-7, f64::NAN, f64::NEG_INFINITY] {
+            assert_eq!(NumberFormat::default().format(n), format_number(n));
+        }
+    }
+
+    #[test]
+    fn test_positional() {
+        use Notation::Auto;
+        assert_eq!(with(Auto, None, Some(15)).format(0.1 + 0.2), "0.3");
+        assert_eq!(with(Auto, None, Some(3)).format(123_456.0), "123000");
+        assert_eq!(with(Auto, None, Some(2)).format(-0.012_34), "-0.012");
+        assert_eq!(with(Auto, Some(2), None).format(0.1 + 0.2), "0.30");
+        assert_eq!(with(Auto, Some(0), None).format(2.5), "2");
+        assert_eq!(with(Auto, Some(3), Some(1)).format(1.0 / 3.0), "0.333");
+    }
+
+    #[test]
+    fn test_exponential() {
+        use Notation::{Engineering, Scientific};
+        assert_eq!(with(Scientific, None, None).format(1234.5), "1.2345e3");
+        assert_eq!(with(Scientific, Some(2), None).format(-1234.5), "-1.23e3");
+        assert_eq!(with(Scientific, None, Some(3)).format(0.000_999_96), "1e-3");
+        assert_eq!(with(Engineering, None, None).format(1234.5), "1.2345e3");
+        assert_eq!(with(Engineering, None, None).format(0.000_012_5), "12.5e-6");
+        assert_eq!(with(Engineering, None, None).format(100_000.0), "100e3");
+        assert_eq!(with(Engineering, Some(1), None).format(999.96), "1.0e3");
+        assert_eq!(with(Engineering, Some(2), None).format(47_000.0), "47.00e3");
+        assert_eq!(with(Engineering, None, Some(2)).format(-0.0), "-0e0");
+    }
+
+    #[test]
+    fn test_digit_limits() {
+        use Notation::{Auto, Engineering, Scientific};
+        let most = MAX_FORMAT_DIGITS;
+        assert!(with(Auto, Some(most), None).check().is_ok());
+        for notation in [Auto, Scientific, Engineering] {
+            for (decimals, significant) in [(Some(most + 1), None), (None, Some(u32::MAX)), (Some(u32::MAX), Some(1))] {
+                let format = with(notation, decimals, significant);
+                assert!(matches!(format.check(), Err(ComputeError::LimitExceeded(_))));
+                let capped = with(notation, decimals.map(|_| most), significant.map(|_| most));
+                assert_eq!(format.format(1.0 / 3.0), capped.format(1.0 / 3.0));
+            }
+        }
+        // The smallest subnormal written out in full
+        let smallest = with(Auto, Some(most), None).format(f64::from_bits(1));
+        assert!(smallest.starts_with("0.000") && smallest.ends_with("533447265625"), "{}", smallest);
+    }
+
+    #[test]
+    fn test_thousands_separator() {
+        let grouped = |separator, n| {
+            NumberFormat { thousands_separator: Some(separator), ..Default::default() }.format(n)
+        };
+        assert_eq!(grouped(',', 1_234_567.25), "1,234,567.25");
+        assert_eq!(grouped('_', -1000.0), "-1_000");
+        assert_eq!(grouped(',', 999.0), "999");
+        let fixed = NumberFormat { decimals: Some(2), thousands_separator: Some(' '), ..Default::default() };
+        assert_eq!(fixed.format(12_345.678), "12 345.68");
+    }
+
+    #[test]
+    fn test_value_display() {
+        use crate::Value;
+        let list = Value::List(vec![0.1 + 0.2, 1500.0]);
+        assert_eq!(list.to_string(), "[0.30000000000000004, 1500]");
+        assert_eq!(format!("{:.2}", list), "[0.30, 1500.00]");
+        let engineering = NumberFormat { notation: Notation::Engineering, ..Default::default() };
+        assert_eq!(list.format(&engineering), "[300.00000000000004e-3, 1.5e3]");
+        assert_eq!(format!("{:.2}", Value::Text("0xff".to_string())), "0xff");
+    }
+}
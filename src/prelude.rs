@@ -0,0 +1,16 @@
+//! What every module takes from `alloc` rather than the standard library prelude, and without `std`, float math
+//!
+//! Each module starts with `use crate::prelude::*;`, so the library builds
+//! the same with and without the `std` feature.
+
+pub use alloc::boxed::Box;
+pub use alloc::string::{String, ToString};
+pub use alloc::vec::Vec;
+pub use alloc::{format, vec};
+
+/// `hashbrown`'s map, with or without the standard library, so `Bindings` is one type whatever the features
+pub use hashbrown::HashMap;
+
+/// `sqrt`, `powf`, `sin` and the rest of the float methods, which only the standard library defines on `f64` itself
+#[cfg(not(feature = "std"))]
+pub use num_traits::Float;
@@ -0,0 +1,153 @@
+//! Multi-statement programs: `;`-separated assignments ending in a result
+
+use crate::prelude::*;
+use crate::{
+    eval_expr_rounding, parse_expr, AngleMode, Bindings, ComputeError, ComputeParser, EvalOptions, Expr,
+    ParseOptions, Result, RoundingMode, Rule,
+};
+use core::fmt;
+use pest::Parser;
+
+/// One statement of a program
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    /// `name = expr`: bind the value of the expression to `name`
+    Assign(String, Expr),
+    Expr(Expr),
+}
+
+/// A sequence of statements, such as `a = 1 + 2; b = a * 3; b - 1`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Assign(name, expr) => write!(f, "{} = {}", name, expr),
+            Statement::Expr(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, statement) in self.statements.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", statement)?;
+        }
+        Ok(())
+    }
+}
+
+impl Program {
+    /// Run the statements in order, reading and updating variables in `env`
+    ///
+    /// The value is that of the last statement; an assignment's value is the
+    /// value it binds. Evaluation stops at the first error, keeping any
+    /// assignments already made.
+    pub fn eval(&self, env: &mut Bindings, angle_mode: AngleMode) -> Result<f64> {
+        self.eval_rounding(env, angle_mode, RoundingMode::default())
+    }
+
+    /// Like `eval`, with `round` rounding in `rounding`
+    pub(crate) fn eval_rounding(&self, env: &mut Bindings, angle_mode: AngleMode, rounding: RoundingMode) -> Result<f64> {
+        let mut value = Err(ComputeError::EmptyExpression);
+        for statement in &self.statements {
+            value = match statement {
+                Statement::Assign(name, expr) => {
+                    let result = eval_expr_rounding(expr, env, angle_mode, rounding)?;
+                    env.insert(name.clone(), result);
+                    Ok(result)
+                }
+                Statement::Expr(expr) => Ok(eval_expr_rounding(expr, env, angle_mode, rounding)?),
+            };
+        }
+        value
+    }
+}
+
+/// Parse a program using the Pest grammar
+pub fn parse_program(source: &str) -> Result<Program> {
+    parse_program_with(source, &ParseOptions::default())
+}
+
+/// Parse a program with explicit parse options
+pub fn parse_program_with(source: &str, options: &ParseOptions) -> Result<Program> {
+    options.check_limits(source)?;
+    let source = &*options.group_separators(source);
+    if source.trim().is_empty() {
+        return Err(ComputeError::EmptyExpression);
+    }
+    let rule = if options.implicit_multiplication { Rule::program_implicit } else { Rule::program };
+    let pairs = ComputeParser::parse(rule, source).map_err(|e| ComputeError::ParseError(Box::new(e)))?;
+    let statements = pairs
+        .filter(|pair| pair.as_rule() != Rule::EOI)
+        .map(|pair| match pair.as_rule() {
+            Rule::assignment => {
+                let mut inner = pair.into_inner();
+                let name = inner
+                    .next()
+                    .ok_or_else(|| ComputeError::InvalidStructure("Missing name in assignment".into()))?;
+                let expr = inner
+                    .next()
+                    .ok_or_else(|| ComputeError::InvalidStructure("Missing value in assignment".into()))?;
+                Ok(Statement::Assign(name.as_str().to_string(), parse_expr(expr.into_inner(), options)?))
+            }
+            _ => Ok(Statement::Expr(parse_expr(pair.into_inner(), options)?)),
+        })
+        .collect::<Result<_>>()?;
+    Ok(Program { statements })
+}
+
+/// Evaluate a program in a fresh environment, returning the value of its last statement
+pub fn evaluate_program(source: &str) -> Result<f64> {
+    evaluate_program_with(source, &mut Bindings::new(), &EvalOptions::default())
+}
+
+/// Evaluate a program with explicit options, reading and updating variables in `env`
+///
+/// Only the final value is rounded by `result_places`; assignments keep full precision.
+pub fn evaluate_program_with(source: &str, env: &mut Bindings, options: &EvalOptions) -> Result<f64> {
+    let program = parse_program_with(source, &options.parse)?;
+    program.eval_rounding(env, options.angle_mode, options.rounding).map(|value| options.round_result(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_program() {
+        let program = parse_program("a = 1 + 2; b = a * 3; b - 1").unwrap();
+        assert_eq!(program.statements.len(), 3);
+        assert_eq!(program.to_string(), "a = (1 + 2); b = (a * 3); (b - 1)");
+        assert_eq!(parse_program("x;").unwrap().statements, vec![Statement::Expr(Expr::Var("x".into()))]);
+        assert!(matches!(parse_program(" "), Err(ComputeError::EmptyExpression)));
+        for source in ["a = ", "a = = 1", "1; ; 2", "1 = 2", ";", "not = 1"] {
+            assert!(matches!(parse_program(source), Err(ComputeError::ParseError(_))), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_program() {
+        assert_eq!(evaluate_program("a = 1 + 2; b = a * 3; b - 1"), Ok(8.0));
+        assert_eq!(evaluate_program("2 ^ 10"), Ok(1024.0));
+        assert_eq!(evaluate_program("x = 4; x = x * x"), Ok(16.0));
+        assert_eq!(evaluate_program("a = 1; b"), Err(ComputeError::UndefinedVariable("b".into())));
+
+        let mut env: Bindings = [("rate".to_string(), 0.5)].into_iter().collect();
+        let degrees = EvalOptions {
+            angle_mode: AngleMode::Degrees,
+            result_places: Some(1),
+            ..EvalOptions::default()
+        };
+        assert_eq!(evaluate_program_with("y = rate / 3; sin(90) + y", &mut env, &degrees), Ok(1.2));
+        assert_eq!(env["y"], 0.5 / 3.0);
+        assert_eq!(evaluate_program_with("z = 1; 1 / 0", &mut env, &degrees), Err(ComputeError::DivisionByZero));
+        assert_eq!(env["z"], 1.0);
+    }
+}
@@ -0,0 +1,180 @@
+//! Integer conversion between number bases
+
+use crate::prelude::*;
+use crate::{ComputeError, Result};
+
+/// Rewrite the integer `value` from `from_base` to `to_base`
+///
+/// Bases run from 2 to 36. `value` may have a leading `-`, a `0x`, `0o` or
+/// `0b` prefix matching `from_base`, and `_` separators; the result uses
+/// lowercase digits without a prefix.
+///
+/// With a `width`, values are `width`-bit two's complement: the input may be
+/// any signed or unsigned value that fits, decimal output is the signed value
+/// of the bit pattern, and other bases print the pattern padded to the full
+/// width, so `-1` in 8 bits is `ff` in hex and `ff` is `-1` in decimal.
+pub fn convert_base(value: &str, from_base: u32, to_base: u32, width: Option<u32>) -> Result<String> {
+    for base in [from_base, to_base] {
+        if !(2..=36).contains(&base) {
+            return Err(ComputeError::InvalidStructure(format!(
+                "Base {} is not between 2 and 36",
+                base
+            )));
+        }
+    }
+    let (negative, magnitude) = parse_integer(value, from_base)?;
+
+    let Some(width) = width else {
+        let sign = if negative && magnitude != 0 { "-" } else { "" };
+        return Ok(format!("{}{}", sign, to_digits(magnitude, to_base)));
+    };
+    if !(1..=128).contains(&width) {
+        return Err(ComputeError::InvalidStructure(format!(
+            "Width {} is not between 1 and 128 bits",
+            width
+        )));
+    }
+    let mask = u128::MAX >> (128 - width);
+    let sign_bit = 1 << (width - 1);
+    let fits = if negative { magnitude <= sign_bit } else { magnitude <= mask };
+    if !fits {
+        return Err(ComputeError::Overflow);
+    }
+    let pattern = if negative { magnitude.wrapping_neg() & mask } else { magnitude };
+
+    if to_base == 10 {
+        return Ok(if pattern & sign_bit != 0 {
+            format!("-{}", pattern.wrapping_neg() & mask)
+        } else {
+            pattern.to_string()
+        });
+    }
+    let digits = to_digits(pattern, to_base);
+    let full_width = to_digits(mask, to_base).len();
+    Ok(format!("{:0>1$}", digits, full_width))
+}
+
+/// Split `value` into a sign and magnitude
+fn parse_integer(value: &str, base: u32) -> Result<(bool, u128)> {
+    let trimmed = value.trim();
+    let (negative, unsigned) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    let body = prefixes(base)
+        .and_then(|prefixes| prefixes.iter().find_map(|p| unsigned.strip_prefix(p)))
+        .unwrap_or(unsigned);
+    let digits: String = body.chars().filter(|&c| c != '_').collect();
+
+    let invalid = || ComputeError::InvalidStructure(format!("'{}' is not a base {} integer", value, base));
+    if digits.is_empty() || digits.starts_with(['+', '-']) {
+        return Err(invalid());
+    }
+    let mut magnitude: u128 = 0;
+    for c in digits.chars() {
+        let digit = c.to_digit(base).ok_or_else(invalid)?;
+        magnitude = magnitude
+            .checked_mul(u128::from(base))
+            .and_then(|m| m.checked_add(u128::from(digit)))
+            .ok_or(ComputeError::Overflow)?;
+    }
+    Ok((negative, magnitude))
+}
+
+/// The prefixes marking a literal in `base`, lowercase first
+fn prefixes(base: u32) -> Option<[&'static str; 2]> {
+    match base {
+        16 => Some(["0x", "0X"]),
+        8 => Some(["0o", "0O"]),
+        2 => Some(["0b", "0B"]),
+        _ => None,
+    }
+}
+
+/// The whole number `value` in `base` with its lowercase prefix, as `to_hex(-255)` gives `-0xff`
+///
+/// Fails with `TypeMismatch` for a value with a fractional part, and with
+/// `Overflow` for one whose magnitude does not fit in 128 bits.
+pub fn format_integer(value: f64, base: u32) -> Result<String> {
+    if value.is_nan() || (value.is_finite() && value.fract() != 0.0) {
+        return Err(ComputeError::TypeMismatch(format!(
+            "expected a whole number but found {}",
+            crate::format_number(value)
+        )));
+    }
+    if value.abs() >= u128::MAX as f64 {
+        return Err(ComputeError::Overflow);
+    }
+    let sign = if value < 0.0 { "-" } else { "" };
+    let prefix = prefixes(base).map_or("", |[lower, _]| lower);
+    Ok(format!("{}{}{}", sign, prefix, to_digits(value.abs() as u128, base)))
+}
+
+fn to_digits(mut n: u128, base: u32) -> String {
+    let mut digits = Vec::new();
+    loop {
+        let digit = (n % u128::from(base)) as u32;
+        digits.push(char::from_digit(digit, base).expect("digit is below the base"));
+        n /= u128::from(base);
+        if n == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_between_bases() {
+        assert_eq!(convert_base("255", 10, 16, None).unwrap(), "ff");
+        assert_eq!(convert_base("0xFF", 16, 2, None).unwrap(), "11111111");
+        assert_eq!(convert_base("0o17", 8, 10, None).unwrap(), "15");
+        assert_eq!(convert_base("-1010_1010", 2, 10, None).unwrap(), "-170");
+        assert_eq!(convert_base("-0", 10, 10, None).unwrap(), "0");
+        assert_eq!(convert_base("zz", 36, 10, None).unwrap(), "1295");
+        assert_eq!(
+            convert_base(&u128::MAX.to_string(), 10, 16, None).unwrap(),
+            "f".repeat(32)
+        );
+    }
+
+    #[test]
+    fn test_twos_complement() {
+        assert_eq!(convert_base("-1", 10, 16, Some(8)).unwrap(), "ff");
+        assert_eq!(convert_base("ff", 16, 10, Some(8)).unwrap(), "-1");
+        assert_eq!(convert_base("5", 10, 2, Some(8)).unwrap(), "00000101");
+        assert_eq!(convert_base("-128", 10, 16, Some(8)).unwrap(), "80");
+        assert_eq!(convert_base("127", 10, 10, Some(8)).unwrap(), "127");
+        assert_eq!(convert_base("-1", 10, 16, Some(128)).unwrap(), "f".repeat(32));
+        assert_eq!(convert_base("256", 10, 16, Some(8)), Err(ComputeError::Overflow));
+        assert_eq!(convert_base("-129", 10, 16, Some(8)), Err(ComputeError::Overflow));
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(matches!(convert_base("12", 1, 10, None), Err(ComputeError::InvalidStructure(_))));
+        assert!(matches!(convert_base("12", 10, 37, None), Err(ComputeError::InvalidStructure(_))));
+        assert!(matches!(convert_base("12", 2, 10, None), Err(ComputeError::InvalidStructure(_))));
+        assert!(matches!(convert_base("", 10, 2, None), Err(ComputeError::InvalidStructure(_))));
+        assert!(matches!(convert_base("--1", 10, 2, None), Err(ComputeError::InvalidStructure(_))));
+        assert!(matches!(convert_base("1", 10, 2, Some(0)), Err(ComputeError::InvalidStructure(_))));
+        let huge = "1".repeat(40);
+        assert_eq!(convert_base(&huge, 10, 16, None), Err(ComputeError::Overflow));
+    }
+
+    #[test]
+    fn test_format_integer() {
+        assert_eq!(format_integer(255.0, 16).unwrap(), "0xff");
+        assert_eq!(format_integer(-10.0, 2).unwrap(), "-0b1010");
+        assert_eq!(format_integer(8.0, 8).unwrap(), "0o10");
+        assert_eq!(format_integer(-0.0, 16).unwrap(), "0x0");
+        assert_eq!(format_integer(2f64.powi(100), 16).unwrap(), format!("0x1{}", "0".repeat(25)));
+        assert!(matches!(format_integer(2.5, 16), Err(ComputeError::TypeMismatch(_))));
+        assert!(matches!(format_integer(f64::NAN, 2), Err(ComputeError::TypeMismatch(_))));
+        assert_eq!(format_integer(f64::INFINITY, 8), Err(ComputeError::Overflow));
+        assert_eq!(format_integer(2f64.powi(128), 16), Err(ComputeError::Overflow));
+    }
+}
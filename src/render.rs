@@ -0,0 +1,463 @@
+//! Typeset renderings of expressions: LaTeX and presentation MathML
+
+use crate::prelude::*;
+use crate::{format_number, Expr, Relation};
+
+/// Render `expr` as LaTeX math (without surrounding `$` delimiters)
+///
+/// Division becomes `\frac`, multiplication `\cdot`, powers superscripts,
+/// conditionals a `cases` block, and only the parentheses needed to keep the
+/// tree's structure are emitted.
+pub fn to_latex(expr: &Expr) -> String {
+    let mut out = String::new();
+    latex(expr, &mut out);
+    out
+}
+
+/// Render `expr` as a presentation MathML `<math>` element
+pub fn to_mathml(expr: &Expr) -> String {
+    let mut out = String::from("<math xmlns=\"http://www.w3.org/1998/Math/MathML\">");
+    mathml(expr, &mut out);
+    out.push_str("</math>");
+    out
+}
+
+/// Binding strength used to decide where parentheses are required
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        // A conditional's block is open on the right, so it only stands alone
+        Expr::If(..) | Expr::Range(..) => 1,
+        Expr::Or(..) => 2,
+        Expr::And(..) => 3,
+        Expr::Not(_) => 4,
+        Expr::Compare(..) => 5,
+        Expr::Add(..) | Expr::Sub(..) => 6,
+        Expr::Neg(_) => 7,
+        Expr::Mul(..) => 8,
+        // Fractions, powers, calls, lists and atoms are visually self-delimiting
+        Expr::Div(..) | Expr::Pow(..) | Expr::Call(..) | Expr::List(_) => 9,
+        Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_) => 9,
+    }
+}
+
+/// Operands of `parent`, each paired with whether it must be parenthesized
+fn operands(parent: &Expr) -> Vec<(&Expr, bool)> {
+    match parent {
+        Expr::If(cond, then, otherwise) => vec![(cond, false), (then, false), (otherwise, false)],
+        Expr::Range(lo, hi) => vec![(lo, false), (hi, false)],
+        Expr::Or(l, r) => vec![(l, precedence(l) < 2), (r, precedence(r) <= 2)],
+        Expr::And(l, r) => vec![(l, precedence(l) < 3), (r, precedence(r) <= 3)],
+        Expr::Not(e) => vec![(e, precedence(e) < 4)],
+        Expr::Compare(_, l, r) => vec![(l, precedence(l) < 5), (r, precedence(r) <= 5)],
+        Expr::Add(l, r) | Expr::Sub(l, r) => vec![(l, precedence(l) < 6), (r, precedence(r) <= 7)],
+        Expr::Mul(l, r) => vec![(l, precedence(l) < 7), (r, precedence(r) <= 8)],
+        Expr::Neg(e) => vec![(e, precedence(e) <= 7)],
+        Expr::Div(l, r) => vec![(l, false), (r, false)],
+        Expr::Pow(l, r) => vec![(l, !is_plain_base(l)), (r, false)],
+        Expr::Call(_, args) | Expr::List(args) => args.iter().map(|arg| (arg, false)).collect(),
+        Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_) => Vec::new(),
+    }
+}
+
+/// Whether `expr` can carry a superscript without parentheses
+fn is_plain_base(expr: &Expr) -> bool {
+    match expr {
+        Expr::Var(_) => true,
+        Expr::Number(n) => n.is_finite() && !n.is_sign_negative() && split_number(*n).1.is_none(),
+        _ => false,
+    }
+}
+
+/// Split a variable such as `x_1` into its base and subscript
+fn split_name(name: &str) -> (&str, Option<&str>) {
+    match name.split_once('_') {
+        Some((base, sub)) if !base.is_empty() && !sub.is_empty() => (base, Some(sub)),
+        _ => (name, None),
+    }
+}
+
+/// Split a printed number into mantissa and exponent, e.g. `1.5e-7`
+fn split_number(n: f64) -> (String, Option<String>) {
+    let printed = format_number(n);
+    match printed.split_once('e') {
+        Some((mantissa, exponent)) => (mantissa.to_string(), Some(exponent.to_string())),
+        None => (printed, None),
+    }
+}
+
+fn latex(expr: &Expr, out: &mut String) {
+    let operand = |(e, parens): (&Expr, bool), out: &mut String| {
+        if parens {
+            out.push_str("\\left(");
+            latex(e, out);
+            out.push_str("\\right)");
+        } else {
+            latex(e, out);
+        }
+    };
+    let mut children = operands(expr).into_iter();
+    match expr {
+        Expr::Number(n) if n.is_infinite() => {
+            out.push_str(if *n > 0.0 { "\\infty" } else { "-\\infty" })
+        }
+        Expr::Number(n) => match split_number(*n) {
+            (mantissa, Some(exponent)) if mantissa == "1" => {
+                out.push_str(&format!("10^{{{}}}", exponent))
+            }
+            (mantissa, Some(exponent)) => {
+                out.push_str(&format!("{} \\times 10^{{{}}}", mantissa, exponent))
+            }
+            (digits, None) => out.push_str(&digits),
+        },
+        Expr::Percent(n) => {
+            latex(&Expr::Number(*n), out);
+            out.push_str("\\%");
+        }
+        Expr::Var(name) => {
+            let (base, sub) = split_name(name);
+            if base.chars().count() > 1 {
+                out.push_str(&format!("\\mathrm{{{}}}", base));
+            } else {
+                out.push_str(base);
+            }
+            if let Some(sub) = sub {
+                out.push_str(&format!("_{{{}}}", sub.replace('_', "\\_")));
+            }
+        }
+        Expr::Text(text) => {
+            out.push_str("\\text{");
+            for c in text.chars() {
+                match c {
+                    '\\' => out.push_str("\\textbackslash{}"),
+                    '~' => out.push_str("\\textasciitilde{}"),
+                    '^' => out.push_str("\\textasciicircum{}"),
+                    '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                        out.push('\\');
+                        out.push(c);
+                    }
+                    c => out.push(c),
+                }
+            }
+            out.push('}');
+        }
+        Expr::Div(..) => {
+            out.push_str("\\frac{");
+            operand(children.next().unwrap(), out);
+            out.push_str("}{");
+            operand(children.next().unwrap(), out);
+            out.push('}');
+        }
+        Expr::Pow(..) => {
+            operand(children.next().unwrap(), out);
+            out.push_str("^{");
+            operand(children.next().unwrap(), out);
+            out.push('}');
+        }
+        Expr::Neg(_) => {
+            out.push('-');
+            operand(children.next().unwrap(), out);
+        }
+        Expr::Not(_) => {
+            out.push_str("\\lnot ");
+            operand(children.next().unwrap(), out);
+        }
+        Expr::Call(name, _) => {
+            let (open, close) = match name.as_str() {
+                "sqrt" => ("\\sqrt{".to_string(), "}"),
+                "abs" => ("\\left|".to_string(), "\\right|"),
+                "ln" | "exp" | "sin" | "cos" | "tan" => (format!("\\{}\\left(", name), "\\right)"),
+                "asin" | "acos" | "atan" => (format!("\\arc{}\\left(", &name[1..]), "\\right)"),
+                "log10" => ("\\log_{10}\\left(".to_string(), "\\right)"),
+                _ => (format!("\\operatorname{{{}}}\\left(", name.replace('_', "\\_")), "\\right)"),
+            };
+            out.push_str(&open);
+            for (i, child) in children.enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                operand(child, out);
+            }
+            out.push_str(close);
+        }
+        Expr::Range(..) => {
+            operand(children.next().unwrap(), out);
+            out.push_str(" \\ldots ");
+            operand(children.next().unwrap(), out);
+        }
+        Expr::List(_) => {
+            out.push_str("\\left[");
+            for (i, child) in children.enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                operand(child, out);
+            }
+            out.push_str("\\right]");
+        }
+        Expr::If(..) => {
+            let mut next = || children.next().unwrap();
+            let (cond, then, otherwise) = (next(), next(), next());
+            out.push_str("\\begin{cases} ");
+            operand(then, out);
+            out.push_str(" & \\text{if } ");
+            operand(cond, out);
+            out.push_str(" \\\\ ");
+            operand(otherwise, out);
+            out.push_str(" & \\text{otherwise} \\end{cases}");
+        }
+        Expr::Add(..) | Expr::Sub(..) | Expr::Mul(..) | Expr::Compare(..) | Expr::And(..) | Expr::Or(..) => {
+            let symbol = match expr {
+                Expr::Add(..) => " + ",
+                Expr::Sub(..) => " - ",
+                Expr::And(..) => " \\land ",
+                Expr::Or(..) => " \\lor ",
+                Expr::Compare(relation, ..) => match relation {
+                    Relation::Less => " < ",
+                    Relation::LessEq => " \\le ",
+                    Relation::Greater => " > ",
+                    Relation::GreaterEq => " \\ge ",
+                    Relation::Equal => " = ",
+                    Relation::NotEqual => " \\ne ",
+                },
+                _ => " \\cdot ",
+            };
+            operand(children.next().unwrap(), out);
+            out.push_str(symbol);
+            operand(children.next().unwrap(), out);
+        }
+    }
+}
+
+fn mathml(expr: &Expr, out: &mut String) {
+    let operand = |(e, parens): (&Expr, bool), out: &mut String| {
+        if parens {
+            out.push_str("<mrow><mo>(</mo>");
+            mathml(e, out);
+            out.push_str("<mo>)</mo></mrow>");
+        } else {
+            mathml(e, out);
+        }
+    };
+    let mut children = operands(expr).into_iter();
+    match expr {
+        Expr::Number(n) if n.is_infinite() => {
+            out.push_str(if *n > 0.0 {
+                "<mi>&#x221E;</mi>"
+            } else {
+                "<mrow><mo>&#x2212;</mo><mi>&#x221E;</mi></mrow>"
+            })
+        }
+        Expr::Number(n) => match split_number(*n) {
+            (mantissa, Some(exponent)) => out.push_str(&format!(
+                "<mrow><mn>{}</mn><mo>&#xD7;</mo><msup><mn>10</mn><mn>{}</mn></msup></mrow>",
+                mantissa, exponent
+            )),
+            (digits, None) => out.push_str(&format!("<mn>{}</mn>", digits)),
+        },
+        Expr::Percent(n) => {
+            out.push_str("<mrow>");
+            mathml(&Expr::Number(*n), out);
+            out.push_str("<mo>%</mo></mrow>");
+        }
+        Expr::Var(name) => match split_name(name) {
+            (base, Some(sub)) => {
+                let tag = if sub.chars().all(|c| c.is_ascii_digit()) { "mn" } else { "mi" };
+                out.push_str(&format!("<msub><mi>{}</mi><{tag}>{}</{tag}></msub>", base, sub))
+            }
+            (base, None) => out.push_str(&format!("<mi>{}</mi>", base)),
+        },
+        Expr::Text(text) => {
+            let escaped = text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+            out.push_str(&format!("<ms>{}</ms>", escaped));
+        }
+        Expr::Div(..) => {
+            out.push_str("<mfrac><mrow>");
+            operand(children.next().unwrap(), out);
+            out.push_str("</mrow><mrow>");
+            operand(children.next().unwrap(), out);
+            out.push_str("</mrow></mfrac>");
+        }
+        Expr::Pow(..) => {
+            out.push_str("<msup>");
+            operand(children.next().unwrap(), out);
+            operand(children.next().unwrap(), out);
+            out.push_str("</msup>");
+        }
+        Expr::Neg(_) | Expr::Not(_) => {
+            let symbol = if matches!(expr, Expr::Neg(_)) { "&#x2212;" } else { "&#xAC;" };
+            out.push_str(&format!("<mrow><mo>{}</mo>", symbol));
+            operand(children.next().unwrap(), out);
+            out.push_str("</mrow>");
+        }
+        Expr::Call(name, _) if name == "sqrt" || name == "abs" => {
+            let (open, close) = if name == "sqrt" {
+                ("<msqrt>", "</msqrt>")
+            } else {
+                ("<mrow><mo>|</mo>", "<mo>|</mo></mrow>")
+            };
+            out.push_str(open);
+            children.for_each(|child| operand(child, out));
+            out.push_str(close);
+        }
+        Expr::Call(name, _) => {
+            // U+2061 FUNCTION APPLICATION joins the name to its arguments
+            let head = match name.as_str() {
+                "log10" => "<msub><mi>log</mi><mn>10</mn></msub>".to_string(),
+                "asin" | "acos" | "atan" => format!("<mi>arc{}</mi>", &name[1..]),
+                _ => format!("<mi>{}</mi>", name),
+            };
+            out.push_str(&format!("<mrow>{}<mo>&#x2061;</mo><mrow><mo>(</mo>", head));
+            for (i, child) in children.enumerate() {
+                if i > 0 {
+                    out.push_str("<mo>,</mo>");
+                }
+                operand(child, out);
+            }
+            out.push_str("<mo>)</mo></mrow></mrow>");
+        }
+        Expr::Range(..) => {
+            out.push_str("<mrow>");
+            operand(children.next().unwrap(), out);
+            out.push_str("<mo>&#x2026;</mo>");
+            operand(children.next().unwrap(), out);
+            out.push_str("</mrow>");
+        }
+        Expr::List(_) => {
+            out.push_str("<mrow><mo>[</mo>");
+            for (i, child) in children.enumerate() {
+                if i > 0 {
+                    out.push_str("<mo>,</mo>");
+                }
+                operand(child, out);
+            }
+            out.push_str("<mo>]</mo></mrow>");
+        }
+        Expr::If(..) => {
+            let mut next = || children.next().unwrap();
+            let (cond, then, otherwise) = (next(), next(), next());
+            out.push_str("<mrow><mo>{</mo><mtable><mtr><mtd>");
+            operand(then, out);
+            out.push_str("</mtd><mtd><mtext>if&#xA0;</mtext>");
+            operand(cond, out);
+            out.push_str("</mtd></mtr><mtr><mtd>");
+            operand(otherwise, out);
+            out.push_str("</mtd><mtd><mtext>otherwise</mtext></mtd></mtr></mtable></mrow>");
+        }
+        Expr::Add(..) | Expr::Sub(..) | Expr::Mul(..) | Expr::Compare(..) | Expr::And(..) | Expr::Or(..) => {
+            let symbol = match expr {
+                Expr::Add(..) => "+",
+                Expr::Sub(..) => "&#x2212;",
+                Expr::And(..) => "&#x2227;",
+                Expr::Or(..) => "&#x2228;",
+                Expr::Compare(relation, ..) => match relation {
+                    Relation::Less => "&lt;",
+                    Relation::LessEq => "&#x2264;",
+                    Relation::Greater => "&gt;",
+                    Relation::GreaterEq => "&#x2265;",
+                    Relation::Equal => "=",
+                    Relation::NotEqual => "&#x2260;",
+                },
+                _ => "&#x22C5;",
+            };
+            out.push_str("<mrow>");
+            operand(children.next().unwrap(), out);
+            out.push_str(&format!("<mo>{}</mo>", symbol));
+            operand(children.next().unwrap(), out);
+            out.push_str("</mrow>");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_expression;
+
+    fn latex_of(src: &str) -> String {
+        to_latex(&parse_expression(src).unwrap())
+    }
+
+    #[test]
+    fn test_latex_rendering() {
+        assert_eq!(latex_of("1 / 2 + 3 * 4"), "\\frac{1}{2} + 3 \\cdot 4");
+        assert_eq!(latex_of("(a + b) * -c"), "\\left(a + b\\right) \\cdot \\left(-c\\right)");
+        assert_eq!(latex_of("x - (y - z)"), "x - \\left(y - z\\right)");
+        assert_eq!(latex_of("--(1 + 2)"), "-\\left(-\\left(1 + 2\\right)\\right)");
+        assert_eq!(latex_of("rate_2 * 1.5e-7"), "\\mathrm{rate}_{2} \\cdot 1.5 \\times 10^{-7}");
+        assert_eq!(latex_of("(x + 1) / (x - 1)"), "\\frac{x + 1}{x - 1}");
+        assert_eq!(latex_of("-x_1 ^ 2 ^ n"), "\\left(-x_{1}\\right)^{2^{n}}");
+        assert_eq!(latex_of("-(2 ^ (a + 1)) * 1e30 ^ 2"), "-2^{a + 1} \\cdot \\left(10^{30}\\right)^{2}");
+        assert_eq!(to_latex(&Expr::Number(f64::INFINITY)), "\\infty");
+        assert_eq!(
+            latex_of("sqrt(x + 1) ^ 2 - abs(-y) * ln(log10(z))"),
+            "\\left(\\sqrt{x + 1}\\right)^{2} - \\left|-y\\right| \\cdot \\ln\\left(\\log_{10}\\left(z\\right)\\right)"
+        );
+        assert_eq!(latex_of("my_fn(1, 2)"), "\\operatorname{my\\_fn}\\left(1, 2\\right)");
+        assert_eq!(latex_of("sin(x) / asin(y)"), "\\frac{\\sin\\left(x\\right)}{\\arcsin\\left(y\\right)}");
+        assert_eq!(latex_of("atan2(y, x)"), "\\operatorname{atan2}\\left(y, x\\right)");
+        assert_eq!(
+            latex_of("sum([1, x / 2])"),
+            "\\operatorname{sum}\\left(\\left[1, \\frac{x}{2}\\right]\\right)"
+        );
+        assert_eq!(latex_of("product(1..n + 1)"), "\\operatorname{product}\\left(1 \\ldots n + 1\\right)");
+        assert_eq!(latex_of("x + 12.5% - 50% ^ 2"), "x + 12.5\\% - \\left(50\\%\\right)^{2}");
+        assert_eq!(latex_of("(a <= b) != (c - 1 > 0)"), "a \\le b \\ne \\left(c - 1 > 0\\right)");
+        assert_eq!(
+            latex_of("not (a or b) and not x > 1 or c"),
+            "\\lnot \\left(a \\lor b\\right) \\land \\lnot x > 1 \\lor c"
+        );
+        assert_eq!(
+            latex_of("(x > 10 ? 10 : x) * 2"),
+            "\\left(\\begin{cases} 10 & \\text{if } x > 10 \\\\ x & \\text{otherwise} \\end{cases}\\right) \\cdot 2"
+        );
+    }
+
+    #[test]
+    fn test_mathml_rendering() {
+        assert_eq!(
+            to_mathml(&parse_expression("x_1 / -2").unwrap()),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+             <mfrac><mrow><msub><mi>x</mi><mn>1</mn></msub></mrow>\
+             <mrow><mrow><mo>&#x2212;</mo><mn>2</mn></mrow></mrow></mfrac></math>"
+        );
+        assert_eq!(
+            to_mathml(&parse_expression("2 * (a - 1)").unwrap()),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+             <mrow><mn>2</mn><mo>&#x22C5;</mo><mrow><mo>(</mo>\
+             <mrow><mi>a</mi><mo>&#x2212;</mo><mn>1</mn></mrow><mo>)</mo></mrow></mrow></math>"
+        );
+        assert_eq!(
+            to_mathml(&parse_expression("(x + 1) ^ 2").unwrap()),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+             <msup><mrow><mo>(</mo><mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow><mo>)</mo></mrow>\
+             <mn>2</mn></msup></math>"
+        );
+        assert_eq!(
+            to_mathml(&parse_expression("sqrt(exp(x))").unwrap()),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+             <msqrt><mrow><mi>exp</mi><mo>&#x2061;</mo><mrow><mo>(</mo><mi>x</mi><mo>)</mo></mrow></mrow></msqrt></math>"
+        );
+        assert_eq!(
+            to_mathml(&parse_expression("x < 0 ? -x : x").unwrap()),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+             <mrow><mo>{</mo><mtable><mtr><mtd><mrow><mo>&#x2212;</mo><mi>x</mi></mrow></mtd>\
+             <mtd><mtext>if&#xA0;</mtext><mrow><mi>x</mi><mo>&lt;</mo><mn>0</mn></mrow></mtd></mtr>\
+             <mtr><mtd><mi>x</mi></mtd><mtd><mtext>otherwise</mtext></mtd></mtr></mtable></mrow></math>"
+        );
+        assert_eq!(
+            to_mathml(&parse_expression("not a and b").unwrap()),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+             <mrow><mrow><mo>&#xAC;</mo><mi>a</mi></mrow><mo>&#x2227;</mo><mi>b</mi></mrow></math>"
+        );
+        assert_eq!(
+            to_mathml(&parse_expression("x - 5%").unwrap()),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+             <mrow><mi>x</mi><mo>&#x2212;</mo><mrow><mn>5</mn><mo>%</mo></mrow></mrow></math>"
+        );
+        assert_eq!(
+            to_mathml(&parse_expression("acos(x)").unwrap()),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+             <mrow><mi>arccos</mi><mo>&#x2061;</mo><mrow><mo>(</mo><mi>x</mi><mo>)</mo></mrow></mrow></math>"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Totals and value statistics over the results of a batch
+
+use crate::clock::Stopwatch;
+use crate::prelude::*;
+use crate::{evaluate_batch_with, EvalOptions, EvaluationResult, Value};
+use core::time::Duration;
+
+/// The results of a batch together with their summary
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchReport {
+    /// One result per input expression, in input order
+    pub results: Vec<EvaluationResult>,
+    pub summary: BatchSummary,
+}
+
+/// Counts of a batch's results and statistics of its successful values
+///
+/// Summaries are built one result at a time with `record`, so a batch
+/// streamed in chunks is summarized exactly as if it were evaluated whole.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BatchSummary {
+    /// Results recorded, successful or not
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Successful results that were single numbers, the values the statistics below are taken over
+    pub numbers: usize,
+    /// Smallest successful value; NaN values are left out
+    pub min: Option<f64>,
+    /// Largest successful value; NaN values are left out
+    pub max: Option<f64>,
+    /// Sum of the successful values, from which `mean` is computed
+    pub sum: f64,
+    /// Wall-clock time the batch took, as set by whoever timed it, zero from `evaluate_batch_report` without the `std`
+    /// feature
+    pub elapsed: Duration,
+}
+
+impl BatchSummary {
+    /// Count one result: its value when it succeeded, `None` when it failed
+    pub fn record(&mut self, value: Option<f64>) {
+        self.total += 1;
+        let Some(value) = value else {
+            self.failed += 1;
+            return;
+        };
+        self.succeeded += 1;
+        self.numbers += 1;
+        self.sum += value;
+        if !value.is_nan() {
+            self.min = Some(self.min.map_or(value, |min| min.min(value)));
+            self.max = Some(self.max.map_or(value, |max| max.max(value)));
+        }
+    }
+
+    /// Count one result that may be a list or text, which adds to the counts but not the statistics
+    pub fn record_value(&mut self, value: Option<&Value>) {
+        match value {
+            Some(&Value::Number(n)) => self.record(Some(n)),
+            Some(_) => {
+                self.total += 1;
+                self.succeeded += 1;
+            }
+            None => self.record(None),
+        }
+    }
+
+    /// The summary of `results`, with no elapsed time
+    pub fn of(results: &[EvaluationResult]) -> Self {
+        let mut summary = BatchSummary::default();
+        for result in results {
+            summary.record(result.value.as_ref().ok().copied());
+        }
+        summary
+    }
+
+    /// Mean of the successful values, or `None` when there are none
+    pub fn mean(&self) -> Option<f64> {
+        (self.numbers > 0).then(|| self.sum / self.numbers as f64)
+    }
+
+    /// The summary as a JSON object, with the elapsed time in milliseconds
+    ///
+    /// Statistics of a batch with no successful values, and non-finite
+    /// statistics, which JSON cannot represent, are `null`.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "total": self.total,
+            "succeeded": self.succeeded,
+            "failed": self.failed,
+            "min": self.min,
+            "max": self.max,
+            "mean": self.mean(),
+            "elapsed_ms": self.elapsed.as_secs_f64() * 1e3,
+        })
+    }
+}
+
+/// Evaluate a batch like `evaluate_batch_with`, summarizing the results and timing the whole batch
+pub fn evaluate_batch_report(expressions: &[&str], options: &EvalOptions) -> BatchReport {
+    let started = Stopwatch::start();
+    let results = evaluate_batch_with(expressions, options);
+    let summary = BatchSummary { elapsed: started.elapsed(), ..BatchSummary::of(&results) };
+    BatchReport { results, summary }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let report = evaluate_batch_report(&["1 + 1", "1 / 0", "-4", "6 * 2", "x"], &EvalOptions::default());
+        assert_eq!(report.results.len(), 5);
+        let summary = report.summary;
+        assert_eq!((summary.total, summary.succeeded, summary.failed), (5, 3, 2));
+        assert_eq!((summary.min, summary.max, summary.mean()), (Some(-4.0), Some(12.0), Some(10.0 / 3.0)));
+
+        let json = summary.to_json();
+        assert_eq!(json["failed"], 2);
+        assert_eq!(json["max"], 12.0);
+        assert!(json["elapsed_ms"].as_f64().unwrap() >= 0.0);
+
+        // Lists and text count as successes but stay out of the statistics
+        let mut mixed = BatchSummary::default();
+        mixed.record_value(Some(&Value::Number(4.0)));
+        mixed.record_value(Some(&Value::List(vec![1.0, 2.0])));
+        mixed.record_value(Some(&Value::Text("ff".into())));
+        mixed.record_value(None);
+        assert_eq!((mixed.total, mixed.succeeded, mixed.failed, mixed.numbers), (4, 3, 1, 1));
+        assert_eq!((mixed.min, mixed.mean()), (Some(4.0), Some(4.0)));
+    }
+
+    #[test]
+    fn test_empty_and_nan() {
+        let empty = BatchSummary::of(&[]);
+        assert_eq!((empty.total, empty.min, empty.mean()), (0, None, None));
+        assert_eq!(empty.to_json()["mean"], serde_json::Value::Null);
+
+        let mut chunked = BatchSummary::default();
+        for value in [Some(2.0), None, Some(f64::NAN), Some(-1.0)] {
+            chunked.record(value);
+        }
+        assert_eq!((chunked.succeeded, chunked.failed), (3, 1));
+        assert_eq!((chunked.min, chunked.max), (Some(-1.0), Some(2.0)));
+        assert!(chunked.mean().unwrap().is_nan());
+        assert_eq!(chunked.to_json()["mean"], serde_json::Value::Null);
+    }
+}
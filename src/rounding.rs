@@ -0,0 +1,205 @@
+//! Decimal rounding strategies shared by every place a result gets rounded
+
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How to resolve a value that falls between two representable results
+///
+/// Rounding is performed on the shortest decimal representation of the
+/// value, so `2.675` is treated as the tie it looks like rather than the
+/// slightly smaller binary double it is stored as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum RoundingMode {
+    /// Ties round away from zero (the behaviour of `f64::round`)
+    #[default]
+    HalfUp,
+    /// Ties round toward zero
+    HalfDown,
+    /// Ties round to the nearest even digit (banker's rounding)
+    HalfEven,
+    /// Always round toward negative infinity
+    Floor,
+    /// Always round toward positive infinity
+    Ceiling,
+    /// Always round toward zero, discarding the extra digits
+    TowardZero,
+}
+
+impl RoundingMode {
+    /// Round `value` to `places` decimal places (negative places round to tens, hundreds, ...)
+    pub fn round(self, value: f64, places: i32) -> f64 {
+        if !value.is_finite() || value == 0.0 {
+            return value;
+        }
+
+        // `{:e}` yields the shortest digits that round-trip, e.g. "2.675e0"
+        let formatted = format!("{:e}", value.abs());
+        let (mantissa, exponent) = formatted
+            .split_once('e')
+            .expect("scientific formatting always contains an exponent");
+        let exponent: i32 = exponent.parse().expect("exponent is an integer");
+        let digits: Vec<u8> = mantissa
+            .bytes()
+            .filter(u8::is_ascii_digit)
+            .map(|d| d - b'0')
+            .collect();
+
+        // Number of significant digits that survive rounding
+        let keep = exponent + 1 + places;
+        if keep >= digits.len() as i32 {
+            return value;
+        }
+
+        let (kept, remainder) = if keep < 0 {
+            (0u64, Remainder::BelowHalf)
+        } else {
+            let keep = keep as usize;
+            let kept = digits[..keep]
+                .iter()
+                .fold(0u64, |acc, &d| acc * 10 + u64::from(d));
+            (kept, Remainder::classify(&digits[keep..]))
+        };
+
+        let negative = value.is_sign_negative();
+        let bump = match self {
+            RoundingMode::HalfUp => remainder >= Remainder::Half,
+            RoundingMode::HalfDown => remainder > Remainder::Half,
+            RoundingMode::HalfEven => match remainder {
+                Remainder::Half => kept % 2 == 1,
+                other => other > Remainder::Half,
+            },
+            RoundingMode::Floor => negative,
+            RoundingMode::Ceiling => !negative,
+            RoundingMode::TowardZero => false,
+        };
+
+        let magnitude: f64 = format!("{}e{}", kept + u64::from(bump), -places)
+            .parse()
+            .expect("rounded digits form a valid float");
+        if negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+}
+
+/// Position of the discarded digits relative to half a unit in the last place
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Remainder {
+    BelowHalf,
+    Half,
+    AboveHalf,
+}
+
+impl Remainder {
+    fn classify(discarded: &[u8]) -> Self {
+        match discarded.split_first() {
+            Some((5, rest)) => {
+                if rest.iter().all(|&d| d == 0) {
+                    Remainder::Half
+                } else {
+                    Remainder::AboveHalf
+                }
+            }
+            Some((&first, _)) if first > 5 => Remainder::AboveHalf,
+            _ => Remainder::BelowHalf,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        evaluate_batch_arena, evaluate_program_with, evaluate_with, Arithmetic, Bindings, EvalBackend, EvalOptions,
+        Session,
+    };
+
+    #[test]
+    fn test_half_even_ties() {
+        let mode = RoundingMode::HalfEven;
+        assert_eq!(mode.round(0.5, 0), 0.0);
+        assert_eq!(mode.round(1.5, 0), 2.0);
+        assert_eq!(mode.round(2.5, 0), 2.0);
+        assert_eq!(mode.round(-2.5, 0), -2.0);
+        assert_eq!(mode.round(2.675, 2), 2.68);
+        assert_eq!(mode.round(2.665, 2), 2.66);
+        assert_eq!(mode.round(2.6651, 2), 2.67);
+    }
+
+    #[test]
+    fn test_half_up_and_half_down() {
+        assert_eq!(RoundingMode::HalfUp.round(2.5, 0), 3.0);
+        assert_eq!(RoundingMode::HalfUp.round(-2.5, 0), -3.0);
+        assert_eq!(RoundingMode::HalfUp.round(1.005, 2), 1.01);
+        assert_eq!(RoundingMode::HalfDown.round(2.5, 0), 2.0);
+        assert_eq!(RoundingMode::HalfDown.round(-2.5, 0), -2.0);
+        assert_eq!(RoundingMode::HalfDown.round(2.51, 0), 3.0);
+    }
+
+    #[test]
+    fn test_floor_and_ceiling() {
+        assert_eq!(RoundingMode::Floor.round(2.71, 1), 2.7);
+        assert_eq!(RoundingMode::Floor.round(-2.71, 1), -2.8);
+        assert_eq!(RoundingMode::Ceiling.round(2.71, 1), 2.8);
+        assert_eq!(RoundingMode::Ceiling.round(-2.71, 1), -2.7);
+        assert_eq!(RoundingMode::Ceiling.round(2.7, 1), 2.7);
+        assert_eq!(RoundingMode::TowardZero.round(2.79, 1), 2.7);
+        assert_eq!(RoundingMode::TowardZero.round(-2.79, 1), -2.7);
+        assert_eq!(RoundingMode::TowardZero.round(-0.0004, 2), -0.0);
+    }
+
+    #[test]
+    fn test_negative_places_and_small_values() {
+        assert_eq!(RoundingMode::HalfUp.round(1250.0, -2), 1300.0);
+        assert_eq!(RoundingMode::HalfEven.round(1250.0, -2), 1200.0);
+        assert_eq!(RoundingMode::HalfUp.round(0.0004, 2), 0.0);
+        assert_eq!(RoundingMode::HalfUp.round(0.6, 0), 1.0);
+        assert_eq!(RoundingMode::Ceiling.round(0.0004, 2), 0.01);
+        assert_eq!(RoundingMode::Floor.round(-0.0004, 2), -0.01);
+    }
+
+    #[test]
+    fn test_passthrough_values() {
+        assert_eq!(RoundingMode::HalfEven.round(1.25, 5), 1.25);
+        assert!(RoundingMode::HalfEven.round(f64::NAN, 2).is_nan());
+        assert_eq!(RoundingMode::Floor.round(f64::INFINITY, 2), f64::INFINITY);
+        assert_eq!(RoundingMode::HalfUp.round(123.0, 0), 123.0);
+    }
+
+    #[test]
+    fn test_result_rounding() {
+        let costs = EvalOptions { rounding: RoundingMode::Ceiling, result_places: Some(2), ..Default::default() };
+        assert_eq!(evaluate_with("10 / 3", &costs), Ok(3.34));
+        assert_eq!(evaluate_with("1 + 0.001", &costs), Ok(1.01));
+        let mut session = Session::with_options(costs);
+        assert_eq!(session.eval("x = 1 / 3"), Ok(0.34));
+
+        // A separate final direction leaves `round` inside the expression alone
+        let up = EvalOptions {
+            result_rounding: Some(RoundingMode::Ceiling),
+            result_places: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(evaluate_with("2.1", &up), Ok(3.0));
+        assert_eq!(evaluate_with("round(2.1)", &up), Ok(2.0));
+    }
+
+    #[test]
+    fn test_round_follows_mode_on_every_evaluator() {
+        // `floor` and friends keep their own direction
+        let banker = EvalOptions { rounding: RoundingMode::HalfEven, ..Default::default() };
+        let budgeted = EvalOptions { fuel: Some(100), ..banker.clone() };
+        let compensated = EvalOptions { compensated_sums: true, ..banker.clone() };
+        let integer = EvalOptions { arithmetic: Arithmetic::Integer, ..banker.clone() };
+        let bytecode = EvalOptions { eval_backend: EvalBackend::Bytecode, ..banker.clone() };
+        for options in [&banker, &budgeted, &compensated, &integer, &bytecode] {
+            assert_eq!(evaluate_with("round(2.5) + round(4.5)", options), Ok(6.0));
+            assert_eq!(evaluate_with("floor(2.5) + ceil(2.5)", options), Ok(5.0));
+        }
+        assert_eq!(evaluate_with("round(2.5) + round(4.5)", &EvalOptions::default()), Ok(8.0));
+        assert_eq!(evaluate_batch_arena(&["round(3.5)", "round(4.5)"], &banker)[1].value, Ok(4.0));
+        assert_eq!(evaluate_program_with("a = 4.5; round(a)", &mut Bindings::new(), &banker), Ok(4.0));
+    }
+}
@@ -0,0 +1,183 @@
+//! Reverse Polish Notation input, as on HP calculators: `3 4 + 2 *` is `(3 + 4) * 2`
+
+use crate::prelude::*;
+use crate::{builtin, eval_expr, parse_number, parse_percent, ComputeError, Expr, ParseOptions, Relation, Result};
+
+/// Parse RPN source into the tree `parse_expression` builds for the same expression in infix form
+///
+/// Words are separated by whitespace. A number, percentage, quoted text or
+/// name pushes itself; `+ - * / ^`, the relations, `and`, `or` and `range`
+/// pop two operands, `neg` and `not` one and `if` three. A builtin function
+/// pops as many arguments as it takes at least, or `N` when written `name/N`
+/// as `to_prefix` prints it, and `list/N` collects `N` elements. Exactly one
+/// value must be left on the stack.
+pub fn parse_rpn(source: &str) -> Result<Expr> {
+    let options = ParseOptions::default();
+    options.check_len(source)?;
+    let mut stack = Vec::new();
+    for word in words(source)? {
+        let expr = match word {
+            word if word.starts_with('"') => Expr::Text(word[1..word.len() - 1].to_string()),
+            word if word.starts_with(|c: char| c.is_ascii_digit() || c == '.') => match word.strip_suffix('%') {
+                Some(_) => parse_percent(word, &options)?,
+                None => parse_number(word, &options)?,
+            },
+            word if word.strip_prefix('-').is_some_and(|n| n.starts_with(|c: char| c.is_ascii_digit())) => {
+                // A negative literal, which infix source also reads as a negation
+                Expr::Neg(Box::new(parse_number(&word[1..], &options)?))
+            }
+            "neg" | "not" => {
+                let [operand] = pop(&mut stack, word)?;
+                let operand = Box::new(operand);
+                if word == "neg" { Expr::Neg(operand) } else { Expr::Not(operand) }
+            }
+            "if" => {
+                let [cond, then, otherwise] = pop(&mut stack, word)?;
+                Expr::If(Box::new(cond), Box::new(then), Box::new(otherwise))
+            }
+            "+" | "-" | "*" | "/" | "^" | "and" | "or" | "range" | "<" | "<=" | ">" | ">=" | "==" | "!=" => {
+                let [l, r] = pop(&mut stack, word)?;
+                binary(word, Box::new(l), Box::new(r))
+            }
+            word => match word.split_once('/') {
+                Some((name, count)) => {
+                    let count = count
+                        .parse()
+                        .map_err(|_| invalid(format!("`{}` needs an argument count after the `/`", word)))?;
+                    if stack.len() < count {
+                        return Err(underflow(word, count, stack.len()));
+                    }
+                    let operands = stack.split_off(stack.len() - count);
+                    match name {
+                        "list" => Expr::List(operands),
+                        name if is_name(name) => Expr::Call(name.to_string(), operands),
+                        _ => return Err(invalid(format!("`{}` is not a function name", name))),
+                    }
+                }
+                None => match builtin(word) {
+                    Some(function) => {
+                        if stack.len() < function.arity() {
+                            return Err(underflow(word, function.arity(), stack.len()));
+                        }
+                        Expr::Call(word.to_string(), stack.split_off(stack.len() - function.arity()))
+                    }
+                    None if is_name(word) => Expr::Var(word.to_string()),
+                    None => return Err(invalid(format!("`{}` is not a number, name or operator", word))),
+                },
+            },
+        };
+        stack.push(expr);
+    }
+    match stack.len() {
+        0 => Err(ComputeError::EmptyExpression),
+        1 => options.check_tree(stack.pop().expect("one value is left")),
+        n => Err(invalid(format!("RPN input leaves {} values on the stack instead of one", n))),
+    }
+}
+
+/// Evaluate RPN source, as in `evaluate_rpn("3 4 + 2 *")`
+pub fn evaluate_rpn(source: &str) -> Result<f64> {
+    eval_expr(&parse_rpn(source)?)
+}
+
+/// The whitespace-separated words of `source`, keeping quoted text whole
+fn words(source: &str) -> Result<Vec<&str>> {
+    let mut words = Vec::new();
+    let mut rest = source.trim_start();
+    while !rest.is_empty() {
+        let end = match rest.strip_prefix('"') {
+            Some(text) => text
+                .find('"')
+                .map(|close| close + 2)
+                .ok_or_else(|| invalid("Unterminated text in RPN input".to_string()))?,
+            None => rest.find(char::is_whitespace).unwrap_or(rest.len()),
+        };
+        words.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    Ok(words)
+}
+
+/// The top `N` operands of the stack, deepest first
+fn pop<const N: usize>(stack: &mut Vec<Expr>, word: &str) -> Result<[Expr; N]> {
+    if stack.len() < N {
+        return Err(underflow(word, N, stack.len()));
+    }
+    Ok(stack.split_off(stack.len() - N).try_into().unwrap_or_else(|_| unreachable!("split off {} operands", N)))
+}
+
+fn binary(operator: &str, l: Box<Expr>, r: Box<Expr>) -> Expr {
+    match operator {
+        "+" => Expr::Add(l, r),
+        "-" => Expr::Sub(l, r),
+        "*" => Expr::Mul(l, r),
+        "/" => Expr::Div(l, r),
+        "^" => Expr::Pow(l, r),
+        "and" => Expr::And(l, r),
+        "or" => Expr::Or(l, r),
+        "range" => Expr::Range(l, r),
+        symbol => Expr::Compare(Relation::from_symbol(symbol).expect("a relation symbol"), l, r),
+    }
+}
+
+fn is_name(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn underflow(word: &str, needed: usize, available: usize) -> ComputeError {
+    invalid(format!(
+        "`{}` needs {} operand{} but the stack holds {}",
+        word,
+        needed,
+        if needed == 1 { "" } else { "s" },
+        available
+    ))
+}
+
+fn invalid(message: String) -> ComputeError {
+    ComputeError::InvalidStructure(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_expression;
+
+    #[test]
+    fn test_builds_the_infix_tree() {
+        for (rpn, infix) in [
+            ("3 4 + 2 *", "(3 + 4) * 2"),
+            ("1 2 3 * + 4 -", "1 + 2 * 3 - 4"),
+            ("2 3 ^ neg x /", "-(2 ^ 3) / x"),
+            ("-5 abs 10% +", "abs(-5) + 10%"),
+            ("1 2 max 4 9 16 min/3 sqrt atan2/2", "atan2(max(1, 2), sqrt(min(4, 9, 16)))"),
+            ("1 5 range sum/1", "sum(1..5)"),
+            ("1 2 list/2 mean", "mean([1, 2])"),
+            ("x 0 > x 0 x - if", "x > 0 ? x : 0 - x"),
+            ("a 1 >= b not and", "a >= 1 and not b"),
+            ("1 \"ft in\" \"in\" convert", "convert(1, \"ft in\", \"in\")"),
+            ("2.5 round/1", "round(2.5)"),
+        ] {
+            assert_eq!(parse_rpn(rpn), parse_expression(infix), "{}", rpn);
+        }
+        assert_eq!(evaluate_rpn("3 4 + 2 *"), Ok(14.0));
+        assert_eq!(evaluate_rpn("  15 7 1 1 + - / 3 * 2 1 1 + + -  "), Ok(5.0));
+    }
+
+    #[test]
+    fn test_rpn_errors() {
+        for (rpn, message) in [
+            ("3 +", "`+` needs 2 operands but the stack holds 1"),
+            ("1 2", "RPN input leaves 2 values on the stack instead of one"),
+            ("sqrt", "`sqrt` needs 1 operand but the stack holds 0"),
+            ("1 max/x", "`max/x` needs an argument count after the `/`"),
+            ("1 2 $", "`$` is not a number, name or operator"),
+            ("\"open", "Unterminated text in RPN input"),
+        ] {
+            assert_eq!(parse_rpn(rpn), Err(ComputeError::InvalidStructure(message.to_string())), "{}", rpn);
+        }
+        assert_eq!(parse_rpn(" "), Err(ComputeError::EmptyExpression));
+        assert!(matches!(parse_rpn("1e999"), Err(ComputeError::Overflow)));
+    }
+}
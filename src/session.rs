@@ -0,0 +1,256 @@
+//! Stateful calculator sessions: history, variables, `ans`, and options
+
+use crate::prelude::*;
+use crate::{format_number, Bindings, ComputeError, EvalOptions, Result};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Name under which the most recent successful result is available
+pub const ANS: &str = "ans";
+
+/// One input evaluated in a session, with its outcome
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub input: String,
+    /// The value, or the error message if evaluation failed
+    #[serde(with = "float_result")]
+    pub result: core::result::Result<f64, String>,
+}
+
+/// Calculator state shared by embedders: evaluate inputs one after another
+///
+/// Inputs are either expressions or assignments of the form `name = expr`.
+/// Every successful result is also bound to `ans`. Sessions serialize to
+/// JSON (or any serde format), with non-finite numbers written as strings
+/// so that `inf` and `NaN` results survive a round trip.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub options: EvalOptions,
+    #[serde(with = "float_map")]
+    variables: Bindings,
+    history: Vec<HistoryEntry>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: EvalOptions) -> Self {
+        Session {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Evaluate an expression or `name = expr` assignment and record it in the history
+    pub fn eval(&mut self, input: &str) -> Result<f64> {
+        let input = input.trim();
+        let result = self.eval_input(input);
+        self.history.push(HistoryEntry {
+            input: input.to_string(),
+            result: result.clone().map_err(|e| e.to_string()),
+        });
+        if let Ok(value) = result {
+            self.variables.insert(ANS.to_string(), value);
+        }
+        result
+    }
+
+    fn eval_input(&mut self, input: &str) -> Result<f64> {
+        let (target, expr) = match split_assignment(input) {
+            Some((name, expr)) => (Some(name), expr),
+            None => (None, input),
+        };
+        if expr.is_empty() {
+            return Err(ComputeError::EmptyExpression);
+        }
+        let ast = self.options.parse(expr)?;
+        let value = self.options.round_result(self.options.eval(&ast, &self.variables)?);
+        if let Some(name) = target {
+            self.variables.insert(name.to_string(), value);
+        }
+        Ok(value)
+    }
+
+    /// Bind a variable without going through the history
+    pub fn set(&mut self, name: impl Into<String>, value: f64) {
+        self.variables.insert(name.into(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.variables.get(name).copied()
+    }
+
+    /// The most recent successful result
+    pub fn ans(&self) -> Option<f64> {
+        self.get(ANS)
+    }
+
+    /// All bound variables, including `ans`
+    pub fn variables(&self) -> &Bindings {
+        &self.variables
+    }
+
+    pub fn history(&self) -> &[HistoryEntry] {
+        &self.history
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+}
+
+/// Split `name = expr` into its parts, if `input` is an assignment
+pub fn split_assignment(input: &str) -> Option<(&str, &str)> {
+    let (name, expr) = input.split_once('=')?;
+    if expr.starts_with('=') {
+        // `a == b` is a comparison
+        return None;
+    }
+    let name = name.trim();
+    let mut chars = name.chars();
+    let valid_name = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid_name.then(|| (name, expr.trim()))
+}
+
+/// Serde representation of an `f64` that keeps non-finite values
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Float {
+    Number(f64),
+    Text(String),
+}
+
+impl Float {
+    fn new(value: f64) -> Self {
+        if value.is_finite() {
+            Float::Number(value)
+        } else {
+            Float::Text(format_number(value))
+        }
+    }
+
+    fn value<E: de::Error>(self) -> core::result::Result<f64, E> {
+        match self {
+            Float::Number(n) => Ok(n),
+            Float::Text(text) => match text.as_str() {
+                "inf" => Ok(f64::INFINITY),
+                "-inf" => Ok(f64::NEG_INFINITY),
+                "NaN" => Ok(f64::NAN),
+                _ => Err(E::invalid_value(de::Unexpected::Str(&text), &"a number, inf, -inf, or NaN")),
+            },
+        }
+    }
+}
+
+mod float_map {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(map: &Bindings, s: S) -> core::result::Result<S::Ok, S::Error> {
+        // Sorted so saved sessions are stable across runs
+        let mut entries: Vec<_> = map.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        s.collect_map(entries.into_iter().map(|(k, v)| (k, Float::new(*v))))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> core::result::Result<Bindings, D::Error> {
+        HashMap::<String, Float>::deserialize(d)?
+            .into_iter()
+            .map(|(k, v)| Ok((k, v.value()?)))
+            .collect()
+    }
+}
+
+mod float_result {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    enum Repr {
+        Ok(Float),
+        Err(String),
+    }
+
+    pub fn serialize<S: Serializer>(
+        result: &core::result::Result<f64, String>,
+        s: S,
+    ) -> core::result::Result<S::Ok, S::Error> {
+        match result {
+            Ok(v) => Repr::Ok(Float::new(*v)),
+            Err(e) => Repr::Err(e.clone()),
+        }
+        .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> core::result::Result<core::result::Result<f64, String>, D::Error> {
+        match Repr::deserialize(d)? {
+            Repr::Ok(v) => v.value().map(Ok),
+            Repr::Err(e) => Ok(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assignments_and_ans() {
+        let mut session = Session::new();
+        assert_eq!(session.eval("x = 2 + 3"), Ok(5.0));
+        assert_eq!(session.eval("y=x * 2"), Ok(10.0));
+        assert_eq!(session.eval("ans + x"), Ok(15.0));
+        assert_eq!(session.ans(), Some(15.0));
+        assert_eq!(session.get("y"), Some(10.0));
+
+        assert!(matches!(session.eval("z + 1"), Err(ComputeError::UndefinedVariable(_))));
+        assert_eq!(session.ans(), Some(15.0));
+        assert!(matches!(session.eval("2x = 1"), Err(ComputeError::ParseError(_))));
+        assert_eq!(session.eval("w ="), Err(ComputeError::EmptyExpression));
+        assert_eq!(session.history().len(), 6);
+        assert_eq!(session.history()[3].result, Err("Undefined variable: z".into()));
+        assert_eq!(session.eval("x == 5"), Ok(1.0));
+    }
+
+    #[test]
+    fn test_round_trip_through_json() {
+        let mut session = Session::new();
+        session.eval("big = 1e308 * 10").unwrap();
+        session.eval("1 / 0").unwrap_err();
+        session.set("rate", 0.25);
+
+        let json = serde_json::to_string(&session).unwrap();
+        let restored: Session = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, session);
+        assert_eq!(restored.get("big"), Some(f64::INFINITY));
+        assert_eq!(restored.history()[1].result, Err("Division by zero".into()));
+
+        let empty: Session = serde_json::from_str("{}").unwrap();
+        assert_eq!(empty, Session::new());
+    }
+
+    #[test]
+    fn test_backends_load_in_any_build() {
+        // Default backends depend on the build's features, so they are not saved
+        let json = serde_json::to_string(&Session::new()).unwrap();
+        assert!(!json.contains("backend"), "{}", json);
+
+        // A backend this build lacks, say from a `descent` build, falls back to the default
+        let json = r#"{"options": {"backend": "Descent", "eval_backend": "Quantum", "fuel": 10}}"#;
+        let restored: Session = serde_json::from_str(json).unwrap();
+        let options = &restored.options;
+        #[cfg(not(feature = "descent"))]
+        assert_eq!(options.backend, crate::ParserBackend::Pest);
+        assert_eq!(options.eval_backend, crate::EvalBackend::default());
+        assert_eq!(options.fuel, Some(10));
+
+        let tree = EvalOptions { eval_backend: crate::EvalBackend::Tree, ..Default::default() };
+        let json = serde_json::to_string(&Session::with_options(tree.clone())).unwrap();
+        assert_eq!(serde_json::from_str::<Session>(&json).unwrap().options, tree);
+    }
+}
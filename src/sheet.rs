@@ -0,0 +1,249 @@
+//! Batches whose entries can name their results for other entries to use, like cells of a spreadsheet
+
+use crate::clock::Stopwatch;
+use crate::prelude::*;
+use crate::session::split_assignment;
+use crate::verify::{is_exact_rounding, verify_rounding};
+use crate::{Bindings, ComputeError, EvalOptions, EvaluationResult, Expr, Result, ResultMetadata};
+use alloc::collections::VecDeque;
+use core::time::Duration;
+
+/// Evaluate a batch in which an entry written `name = expr` defines `name` for every other entry
+///
+/// Entries are evaluated in dependency order rather than input order, so an
+/// entry may use a name defined further down. Results come back in input
+/// order, each for the entry's full text. An entry that uses a name whose
+/// entry failed fails with the same error, as a spreadsheet propagates
+/// `#DIV/0!`; entries on or depending on a circular reference fail with
+/// `InvalidStructure`, as does a second definition of the same name.
+pub fn evaluate_sheet(entries: &[&str]) -> Vec<EvaluationResult> {
+    evaluate_sheet_with(entries, &EvalOptions::default())
+}
+
+/// Like `evaluate_sheet`, with explicit options
+///
+/// Names keep full precision; `result_places` rounds only the reported results.
+pub fn evaluate_sheet_with(entries: &[&str], options: &EvalOptions) -> Vec<EvaluationResult> {
+    let cells: Vec<Cell> = entries.iter().map(|entry| Cell::parse(entry, options)).collect();
+
+    let mut definitions: HashMap<&str, usize> = HashMap::new();
+    let mut values: Vec<Option<Result<f64>>> = vec![None; cells.len()];
+    let mut eval_times = vec![Duration::ZERO; cells.len()];
+    for (i, cell) in cells.iter().enumerate() {
+        if let Some(name) = cell.name {
+            if definitions.contains_key(name) {
+                values[i] = Some(Err(invalid(format!("`{}` is defined more than once", name))));
+            } else {
+                definitions.insert(name, i);
+            }
+        }
+    }
+
+    // Each cell's dependencies are the cells defining the names it uses
+    let dependencies: Vec<Vec<usize>> = cells
+        .iter()
+        .map(|cell| {
+            let mut dependencies: Vec<usize> =
+                cell.names_used().iter().filter_map(|name| definitions.get(name.as_str()).copied()).collect();
+            dependencies.sort_unstable();
+            dependencies.dedup();
+            dependencies
+        })
+        .collect();
+    let mut dependents = vec![Vec::new(); cells.len()];
+    let mut waiting: Vec<usize> = dependencies.iter().map(Vec::len).collect();
+    for (i, dependencies) in dependencies.iter().enumerate() {
+        for &dependency in dependencies {
+            dependents[dependency].push(i);
+        }
+    }
+
+    let mut env = Bindings::new();
+    let mut ready: VecDeque<usize> = (0..cells.len()).filter(|&i| waiting[i] == 0).collect();
+    while let Some(i) = ready.pop_front() {
+        let failed_dependency = dependencies[i].iter().find_map(|&dependency| match &values[dependency] {
+            Some(Err(e)) => Some(e.clone()),
+            _ => None,
+        });
+        let started = Stopwatch::start();
+        let value = match (&cells[i].expr, failed_dependency) {
+            (_, Some(e)) => Err(e),
+            (Err(e), None) => Err(e.clone()),
+            (Ok(expr), None) => options.eval(expr, &env),
+        };
+        eval_times[i] = started.elapsed();
+        if let (Some(name), Ok(value)) = (cells[i].name, &value) {
+            if definitions.get(name) == Some(&i) {
+                env.insert(name.to_string(), *value);
+            }
+        }
+        values[i].get_or_insert(value);
+        for &dependent in &dependents[i] {
+            waiting[dependent] -= 1;
+            if waiting[dependent] == 0 {
+                ready.push_back(dependent);
+            }
+        }
+    }
+
+    cells
+        .iter()
+        .enumerate()
+        .map(|(i, cell)| {
+            let value = match values[i].take() {
+                Some(value) => value,
+                None => Err(invalid(format!("circular reference: {}", cycle(i, &dependencies, &waiting, &cells)))),
+            };
+            let verification = match (options.verify, &cell.expr) {
+                (Some(tolerance), Ok(expr)) => Some(verify_rounding(expr, &env, &value, tolerance, options.angle_mode, options.rounding)),
+                _ => None,
+            };
+            let value = value.map(|value| options.round_result(value));
+            let metadata = match (options.metadata, &cell.expr) {
+                (true, Ok(expr)) => Some(ResultMetadata {
+                    parse_time: cell.parse_time,
+                    eval_time: eval_times[i],
+                    depth: expr.depth(),
+                    node_count: expr.node_count(),
+                    exact: is_exact_rounding(expr, &env, &value, options.angle_mode, options.rounding),
+                }),
+                _ => None,
+            };
+            EvaluationResult {
+                expression: cell.source.to_string(),
+                value,
+                verification,
+                metadata,
+                // Cells hold exact values, so a cell's uncertainty could not reach the cells using it
+                uncertainty: None,
+            }
+        })
+        .collect()
+}
+
+/// One entry of a sheet
+struct Cell<'a> {
+    source: &'a str,
+    name: Option<&'a str>,
+    expr: Result<Expr>,
+    parse_time: Duration,
+}
+
+impl<'a> Cell<'a> {
+    fn parse(source: &'a str, options: &EvalOptions) -> Self {
+        let (name, expr) = match split_assignment(source.trim()) {
+            Some((name, expr)) => (Some(name), expr),
+            None => (None, source.trim()),
+        };
+        let started = Stopwatch::start();
+        let expr = options.parse.check_len(source).and_then(|_| match expr {
+            "" => Err(ComputeError::EmptyExpression),
+            expr => options.parse(expr),
+        });
+        Cell { source, name, expr, parse_time: started.elapsed() }
+    }
+
+    fn names_used(&self) -> Vec<&String> {
+        let mut names = Vec::new();
+        if let Ok(expr) = &self.expr {
+            expr.visit(|expr, _| {
+                if let Expr::Var(name) = expr {
+                    names.push(name);
+                }
+            });
+        }
+        names
+    }
+}
+
+/// The circular reference cell `start` is on or depends on, written `a -> b -> a`
+///
+/// Every cell left unevaluated is still waiting on another such cell, so
+/// following unevaluated dependencies from `start` must come back around.
+fn cycle(start: usize, dependencies: &[Vec<usize>], waiting: &[usize], cells: &[Cell]) -> String {
+    let mut path = vec![start];
+    let mut position: HashMap<_, _> = HashMap::from([(start, 0)]);
+    loop {
+        let last = *path.last().expect("the path starts with `start`");
+        let next = *dependencies[last]
+            .iter()
+            .find(|&&dependency| waiting[dependency] > 0)
+            .expect("an unevaluated cell waits on an unevaluated dependency");
+        if let Some(&first) = position.get(&next) {
+            path.push(next);
+            let names = path[first..].iter().map(|&i| cells[i].name.expect("a cycle runs through definitions"));
+            return names.collect::<Vec<_>>().join(" -> ");
+        }
+        position.insert(next, path.len());
+        path.push(next);
+    }
+}
+
+fn invalid(message: String) -> ComputeError {
+    ComputeError::InvalidStructure(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(entries: &[&str]) -> Vec<Result<f64>> {
+        evaluate_sheet(entries).into_iter().map(|result| result.value).collect()
+    }
+
+    #[test]
+    fn test_dependency_order() {
+        assert_eq!(values(&["a = 1 + 2", "a * 10"]), vec![Ok(3.0), Ok(30.0)]);
+        assert_eq!(
+            values(&["total = net + tax", "tax = net * 20%", "net = 50", "total / 2"]),
+            vec![Ok(60.0), Ok(10.0), Ok(50.0), Ok(30.0)]
+        );
+        assert_eq!(values(&["2 + 2", "x"]), vec![Ok(4.0), Err(ComputeError::UndefinedVariable("x".into()))]);
+        assert_eq!(values(&["a == 1", "a = 1"]), vec![Ok(1.0), Ok(1.0)]);
+
+        let results = evaluate_sheet(&[" rate = 1 / 3 "]);
+        assert_eq!(results[0].expression, " rate = 1 / 3 ");
+        let rounded = EvalOptions { result_places: Some(2), ..Default::default() };
+        let results = evaluate_sheet_with(&["third = 1 / 3", "third * 3"], &rounded);
+        assert_eq!(results[0].value, Ok(0.33));
+        assert_eq!(results[1].value, Ok(1.0));
+
+        let measured = EvalOptions { metadata: true, ..Default::default() };
+        let results = evaluate_sheet_with(&["half = 1 / 2", "half * (3 + half)", "x ="], &measured);
+        assert_eq!(results[1].metadata.map(|m| (m.depth, m.node_count, m.exact)), Some((3, 5, true)));
+        assert!(results[2].metadata.is_none());
+    }
+
+    #[test]
+    fn test_failures_propagate() {
+        assert_eq!(
+            values(&["a = 1 / 0", "b = a + 1", "c = 2", "b + c"]),
+            vec![
+                Err(ComputeError::DivisionByZero),
+                Err(ComputeError::DivisionByZero),
+                Ok(2.0),
+                Err(ComputeError::DivisionByZero)
+            ]
+        );
+        assert_eq!(
+            values(&["a = 1", "a = 2", "a"]),
+            vec![Ok(1.0), Err(invalid("`a` is defined more than once".into())), Ok(1.0)]
+        );
+        assert!(matches!(values(&["a = ", "a + 1"])[..], [Err(ComputeError::EmptyExpression), Err(_)]));
+    }
+
+    #[test]
+    fn test_circular_references() {
+        assert_eq!(
+            values(&["a = b + 1", "b = c * 2", "c = a", "d = 5", "e = c + d"]),
+            vec![
+                Err(invalid("circular reference: a -> b -> c -> a".into())),
+                Err(invalid("circular reference: b -> c -> a -> b".into())),
+                Err(invalid("circular reference: c -> a -> b -> c".into())),
+                Ok(5.0),
+                Err(invalid("circular reference: c -> a -> b -> c".into())),
+            ]
+        );
+        assert_eq!(values(&["x = x + 1"]), vec![Err(invalid("circular reference: x -> x".into()))]);
+    }
+}
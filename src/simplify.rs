@@ -0,0 +1,283 @@
+//! Algebraic simplification by value-preserving rewrite rules
+
+use crate::prelude::*;
+use crate::{eval_expr, functions, is_true, truth_value, Expr};
+use alloc::borrow::Cow;
+use core::fmt;
+
+/// A rewrite rule applied during simplification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RewriteRule {
+    /// Evaluate an operation whose operands are all literals
+    FoldConstants,
+    /// `x + 0` and `0 + x` become `x`
+    AddZero,
+    /// `x - 0` becomes `x`, and `0 - x` becomes `-x`
+    SubtractZero,
+    /// `x * 1` and `1 * x` become `x`
+    MultiplyByOne,
+    /// `x * -1` and `-1 * x` become `-x`
+    MultiplyByMinusOne,
+    /// `x / 1` becomes `x`
+    DivideByOne,
+    /// `x ^ 1` becomes `x`
+    PowerOfOne,
+    /// `-(-x)` becomes `x`
+    DoubleNegation,
+    /// `(-a) * (-b)` and `(-a) / (-b)` drop both signs
+    CancelSigns,
+    /// `c ? a : b` with a literal condition becomes the branch it selects, and
+    /// `c and x` or `c or x` becomes the value a literal `c` decides
+    KnownCondition,
+}
+
+impl fmt::Display for RewriteRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RewriteRule::FoldConstants => "fold constants",
+            RewriteRule::AddZero => "add zero",
+            RewriteRule::SubtractZero => "subtract zero",
+            RewriteRule::MultiplyByOne => "multiply by one",
+            RewriteRule::MultiplyByMinusOne => "multiply by minus one",
+            RewriteRule::DivideByOne => "divide by one",
+            RewriteRule::PowerOfOne => "power of one",
+            RewriteRule::DoubleNegation => "double negation",
+            RewriteRule::CancelSigns => "cancel signs",
+            RewriteRule::KnownCondition => "known condition",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One rewrite applied to a subexpression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rewrite {
+    pub rule: RewriteRule,
+    pub before: Expr,
+    pub after: Expr,
+}
+
+impl fmt::Display for Rewrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.rule, self.before, self.after)
+    }
+}
+
+/// Simplify `expr` bottom-up until no rule applies
+///
+/// Every rule gives the same value as the original for all variable values,
+/// including which error evaluation reports: operations that fail, such as
+/// division by a literal zero, are never folded, and nothing that mentions a
+/// variable is discarded, except a conditional branch or a right-hand logical
+/// operand that is never evaluated.
+pub fn simplify(expr: &Expr) -> Expr {
+    simplify_with_steps(expr).0
+}
+
+/// Simplify `expr`, also returning each rewrite in the order it was applied
+pub fn simplify_with_steps(expr: &Expr) -> (Expr, Vec<Rewrite>) {
+    let mut steps = Vec::new();
+    let simplified = simplify_node(expr, &mut steps);
+    (simplified, steps)
+}
+
+fn simplify_node(expr: &Expr, steps: &mut Vec<Rewrite>) -> Expr {
+    let boxed = |e: &Expr, steps: &mut Vec<Rewrite>| Box::new(simplify_node(e, steps));
+    let mut current = match expr {
+        Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_) => return expr.clone(),
+        Expr::Add(l, r) => Expr::Add(boxed(l, steps), boxed(r, steps)),
+        Expr::Sub(l, r) => Expr::Sub(boxed(l, steps), boxed(r, steps)),
+        Expr::Mul(l, r) => Expr::Mul(boxed(l, steps), boxed(r, steps)),
+        Expr::Div(l, r) => Expr::Div(boxed(l, steps), boxed(r, steps)),
+        Expr::Pow(l, r) => Expr::Pow(boxed(l, steps), boxed(r, steps)),
+        Expr::Neg(e) => Expr::Neg(boxed(e, steps)),
+        Expr::Call(name, args) => Expr::Call(name.clone(), args.iter().map(|arg| simplify_node(arg, steps)).collect()),
+        Expr::Compare(relation, l, r) => Expr::Compare(*relation, boxed(l, steps), boxed(r, steps)),
+        Expr::If(cond, then, otherwise) => Expr::If(boxed(cond, steps), boxed(then, steps), boxed(otherwise, steps)),
+        Expr::And(l, r) => Expr::And(boxed(l, steps), boxed(r, steps)),
+        Expr::Or(l, r) => Expr::Or(boxed(l, steps), boxed(r, steps)),
+        Expr::Not(e) => Expr::Not(boxed(e, steps)),
+        Expr::List(elements) => Expr::List(elements.iter().map(|element| simplify_node(element, steps)).collect()),
+        Expr::Range(lo, hi) => Expr::Range(boxed(lo, steps), boxed(hi, steps)),
+    };
+    // Children are already simplified, so rewriting only needs to continue at this node
+    while let Some((rule, mut after)) = rewrite(&current) {
+        // A percent literal that moves up could land on the right of `+` or `-`,
+        // where it would mean a percentage of the left operand instead
+        if let Expr::Percent(n) = after {
+            after = Expr::Number(n / 100.0);
+        }
+        steps.push(Rewrite {
+            rule,
+            before: current,
+            after: after.clone(),
+        });
+        current = after;
+    }
+    current
+}
+
+/// The value of a literal, where `-n` also counts as a literal
+fn literal(expr: &Expr) -> Option<f64> {
+    match expr {
+        Expr::Number(n) => Some(*n),
+        Expr::Neg(e) => match &**e {
+            Expr::Number(n) => Some(-n),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The literal for `value`, written with unary minus when negative as the parser would
+fn to_literal(value: f64) -> Expr {
+    if value.is_sign_negative() {
+        Expr::Neg(Box::new(Expr::Number(-value)))
+    } else {
+        Expr::Number(value)
+    }
+}
+
+fn neg(expr: &Expr) -> Expr {
+    Expr::Neg(Box::new(expr.clone()))
+}
+
+/// The first rule that applies at the root of `expr`, with its result
+fn rewrite(expr: &Expr) -> Option<(RewriteRule, Expr)> {
+    use RewriteRule::*;
+
+    // `a + p%` scales `a`, so none of the rules for sums apply
+    if expr.percent_change().is_some() {
+        return None;
+    }
+    if literal(expr).is_none() && children_are_literals(expr) {
+        if let Ok(value) = eval_expr(expr) {
+            // Infinities and NaN have no literal syntax
+            if value.is_finite() {
+                return Some((FoldConstants, to_literal(value)));
+            }
+        }
+    }
+
+    let rewritten = match expr {
+        Expr::Add(l, r) if literal(r) == Some(0.0) => (AddZero, (**l).clone()),
+        Expr::Add(l, r) if literal(l) == Some(0.0) => (AddZero, (**r).clone()),
+        Expr::Sub(l, r) if literal(r) == Some(0.0) => (SubtractZero, (**l).clone()),
+        Expr::Sub(l, r) if literal(l) == Some(0.0) => (SubtractZero, neg(r)),
+        Expr::Mul(l, r) if literal(r) == Some(1.0) => (MultiplyByOne, (**l).clone()),
+        Expr::Mul(l, r) if literal(l) == Some(1.0) => (MultiplyByOne, (**r).clone()),
+        Expr::Mul(l, r) if literal(r) == Some(-1.0) => (MultiplyByMinusOne, neg(l)),
+        Expr::Mul(l, r) if literal(l) == Some(-1.0) => (MultiplyByMinusOne, neg(r)),
+        Expr::Div(l, r) if literal(r) == Some(1.0) => (DivideByOne, (**l).clone()),
+        Expr::Pow(l, r) if literal(r) == Some(1.0) => (PowerOfOne, (**l).clone()),
+        Expr::If(cond, then, otherwise) => match literal(cond) {
+            Some(value) => (KnownCondition, if is_true(value) { (**then).clone() } else { (**otherwise).clone() }),
+            None => return None,
+        },
+        // A true left operand decides `or` and a false one decides `and`
+        Expr::And(l, _) | Expr::Or(l, _) => match literal(l).map(is_true) {
+            Some(holds) if holds == matches!(expr, Expr::Or(..)) => (KnownCondition, Expr::Number(truth_value(holds))),
+            _ => return None,
+        },
+        Expr::Neg(e) => match &**e {
+            Expr::Neg(inner) => (DoubleNegation, (**inner).clone()),
+            _ => return None,
+        },
+        Expr::Mul(l, r) | Expr::Div(l, r) => match (&**l, &**r) {
+            (Expr::Neg(a), Expr::Neg(b)) => {
+                let (a, b) = (Box::new((**a).clone()), Box::new((**b).clone()));
+                let after = if matches!(expr, Expr::Mul(..)) { Expr::Mul(a, b) } else { Expr::Div(a, b) };
+                (CancelSigns, after)
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(rewritten)
+}
+
+fn children_are_literals(expr: &Expr) -> bool {
+    match expr {
+        Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_) => false,
+        Expr::Add(l, r)
+        | Expr::Sub(l, r)
+        | Expr::Mul(l, r)
+        | Expr::Div(l, r)
+        | Expr::Pow(l, r)
+        | Expr::Compare(_, l, r)
+        | Expr::And(l, r)
+        | Expr::Or(l, r) => literal(l).is_some() && literal(r).is_some(),
+        Expr::Neg(e) | Expr::Not(e) => literal(e).is_some(),
+        // Handled by `KnownCondition`, which needs only the condition to be known
+        Expr::If(..) => false,
+        // The angle unit is only chosen at evaluation time, so trigonometry is never folded
+        // An aggregate folds once every element of its list, or both bounds of its range, are known
+        Expr::Call(name, args) => {
+            let function = functions::builtin(name);
+            let operands = function.and_then(|function| function.operands(args, eval_expr).ok());
+            let operands = operands.unwrap_or(Cow::Borrowed(args));
+            !function.is_some_and(|function| function.uses_angles())
+                && operands.iter().all(|arg| literal(arg).is_some())
+        }
+        // A list or range has no single value to fold into
+        Expr::List(_) | Expr::Range(..) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_expression;
+
+    fn simplified(src: &str) -> String {
+        simplify(&parse_expression(src).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_simplify_identities_and_constants() {
+        assert_eq!(simplified("x * 1 + 0"), "x");
+        assert_eq!(simplified("(2 + 3) * x"), "(5 * x)");
+        assert_eq!(simplified("0 - y"), "-(y)");
+        assert_eq!(simplified("--x / 1"), "x");
+        assert_eq!(simplified("sum([1, 2 * 3]) + mean([x * 1])"), "(7 + mean([x]))");
+        assert_eq!(simplified("-a * -b"), "(a * b)");
+        assert_eq!(simplified("x * (3 - 4)"), "-(x)");
+        assert_eq!(simplified("2 - 5"), "-(3)");
+        assert_eq!(simplified("x * 2"), "(x * 2)");
+        assert_eq!(simplified("x ^ (3 - 2) + 2 ^ 3 ^ 2"), "(x + 512)");
+        assert_eq!(simplified("sqrt(x * 1) + abs(2 - 6)"), "(sqrt(x) + 4)");
+        assert_eq!(simplified("sin(60 + 30) * 1"), "sin(90)");
+        assert_eq!(simplified("(2 > 1) + (x <= 1 * y)"), "(1 + (x <= y))");
+        assert_eq!(simplified("1 - 1 ? x / 0 : y + 0"), "y");
+        assert_eq!(simplified("x ? 2 * 3 : z"), "(x ? 6 : z)");
+        assert_eq!(simplified("not 2 > 3 and 1"), "1");
+        assert_eq!(simplified("0 and x / 0 or 2 - 2 or y"), "(0 or y)");
+        assert_eq!(simplified("x and 1 * y"), "(x and y)");
+        assert_eq!(simplified("0 + 10% + 0"), "(0 + 10%)");
+        assert_eq!(simplified("x - (1 ? 5% : y) * 1"), "(x - 0.05)");
+    }
+
+    #[test]
+    fn test_failing_operations_are_kept() {
+        assert_eq!(simplified("1 / 0 + 0"), "(1 / 0)");
+        assert_eq!(simplified("1e308 * 10"), "(1e308 * 10)");
+        assert_eq!(simplified("x * 0"), "(x * 0)");
+        assert_eq!(simplified("-8 ^ (1 / 3)"), "(-(8) ^ 0.3333333333333333)");
+        assert_eq!(simplified("ln(0) + f(1 + 1)"), "(ln(0) + f(2))");
+    }
+
+    #[test]
+    fn test_steps_record_each_rewrite() {
+        let (result, steps) = simplify_with_steps(&parse_expression("(x + 0) * (2 - 1)").unwrap());
+        assert_eq!(result.to_string(), "x");
+        let lines: Vec<_> = steps.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "add zero: (x + 0) -> x",
+                "fold constants: (2 - 1) -> 1",
+                "multiply by one: (x * 1) -> x",
+            ]
+        );
+    }
+}
@@ -0,0 +1,400 @@
+//! Source spans for every node of a parsed expression, so an error can point at the sub-expression behind it
+
+use crate::prelude::*;
+use crate::visit::children;
+use crate::{
+    add_percent, functions, hint, is_true, not_a_number, parse_expr, truth_value, AngleMode, Bindings, ComputeError,
+    ComputeParser, EvalOptions, Expr, ParseOptions, Result, RoundingMode, Rule, Span, PRATT_PARSER,
+};
+use core::cell::Cell;
+use core::fmt;
+use pest::iterators::{Pair, Pairs};
+use pest::Parser;
+
+/// The span of an expression node, with the spans of its children in the order `walk_expr` visits them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanTree {
+    pub span: Span,
+    pub children: Vec<SpanTree>,
+}
+
+impl SpanTree {
+    fn leaf(pair: &Pair<Rule>) -> Self {
+        SpanTree {
+            span: pair.as_span().into(),
+            children: Vec::new(),
+        }
+    }
+
+    /// A node from `start` to the end of its last child
+    fn spanning(start: usize, children: Vec<SpanTree>) -> Self {
+        let end = children.last().map_or(start, |child| child.span.end);
+        SpanTree {
+            span: Span::new(start, end),
+            children,
+        }
+    }
+}
+
+/// A parsed expression together with the source span of each of its nodes
+///
+/// `spans` has the same shape as `expr`, and the span of a parenthesized
+/// group includes its parentheses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned {
+    pub expr: Expr,
+    pub spans: SpanTree,
+}
+
+impl Spanned {
+    /// The span of `node`, which must be a node of `self.expr` itself rather than an equal copy
+    pub fn span_of(&self, node: &Expr) -> Option<Span> {
+        self.span_map().get(&(node as *const Expr)).copied()
+    }
+
+    /// Every node's span, keyed by the node's address
+    fn span_map(&self) -> HashMap<*const Expr, Span> {
+        let mut map = HashMap::new();
+        let mut stack = vec![(&self.expr, &self.spans)];
+        while let Some((expr, tree)) = stack.pop() {
+            map.insert(expr as *const Expr, tree.span);
+            stack.extend(children(expr).into_iter().zip(&tree.children));
+        }
+        map
+    }
+}
+
+/// Parse `source` with the pest grammar, recording the span of every node
+pub fn parse_spanned(source: &str, options: &ParseOptions) -> Result<Spanned> {
+    options.check_limits(source)?;
+    // Digit group separators become underscores, so offsets into the rewritten text still fit `source`
+    let source = &*options.group_separators(source);
+    let rule = if options.implicit_multiplication { Rule::equation_implicit } else { Rule::equation };
+    let expr_pair = ComputeParser::parse(rule, source)
+        .map_err(|e| ComputeError::ParseError(Box::new(e)))?
+        .next()
+        .ok_or_else(|| ComputeError::InvalidStructure("No expression found".into()))?;
+    let spans = span_tree(expr_pair.clone().into_inner());
+    let expr = options.check_tree(parse_expr(expr_pair.into_inner(), options)?)?;
+    Ok(Spanned { expr, spans })
+}
+
+/// The spans of the tree `parse_expr` builds from `pairs`, which must already have parsed
+fn span_tree(pairs: Pairs<Rule>) -> SpanTree {
+    let branches = pairs.clone().find(|pair| pair.as_rule() == Rule::conditional);
+    let tree = PRATT_PARSER
+        .map_primary(|primary| {
+            let outer = primary.as_span();
+            let pairs: Vec<_> = primary.into_inner().collect();
+            let (atom, negations) = pairs.split_last().expect("a parsed primary has an atom");
+            let mut tree = atom_tree(atom.clone());
+            if atom.as_rule() == Rule::expr {
+                // A parenthesized group spans its parentheses
+                let before = &outer.as_str()[..atom.as_span().start() - outer.start()];
+                tree.span = Span::new(outer.start() + before.rfind('(').unwrap_or(0), outer.end());
+            }
+            // The innermost negation is the last one written
+            negations
+                .iter()
+                .rev()
+                .fold(tree, |tree, neg| SpanTree::spanning(neg.as_span().start(), vec![tree]))
+        })
+        .map_prefix(|op, rhs| SpanTree::spanning(op.as_span().start(), vec![rhs]))
+        .map_infix(|lhs, _, rhs| SpanTree::spanning(lhs.span.start, vec![lhs, rhs]))
+        .parse(pairs.filter(|pair| pair.as_rule() != Rule::conditional));
+
+    let Some(branches) = branches else {
+        return tree;
+    };
+    let start = tree.span.start;
+    let mut children = vec![tree];
+    children.extend(branches.into_inner().map(|branch| span_tree(branch.into_inner())));
+    SpanTree::spanning(start, children)
+}
+
+fn atom_tree(atom: Pair<Rule>) -> SpanTree {
+    let span: Span = atom.as_span().into();
+    let children = match atom.as_rule() {
+        Rule::call => {
+            let mut args = atom.into_inner().skip(1).peekable();
+            let mut children = Vec::new();
+            while let Some(arg) = args.next() {
+                let tree = span_tree(arg.into_inner());
+                // A range spans from its lower bound to the end of the `range` pair holding its upper one
+                children.push(match args.next_if(|next| next.as_rule() == Rule::range) {
+                    Some(range) => {
+                        let start = tree.span.start;
+                        let mut bounds = vec![tree];
+                        bounds.extend(range.into_inner().map(|upper| span_tree(upper.into_inner())));
+                        SpanTree::spanning(start, bounds)
+                    }
+                    None => tree,
+                });
+            }
+            children
+        }
+        Rule::list => atom.into_inner().map(|element| span_tree(element.into_inner())).collect(),
+        Rule::uncertain => atom.into_inner().map(|number| SpanTree::leaf(&number)).collect(),
+        Rule::expr => return span_tree(atom.into_inner()),
+        _ => return SpanTree::leaf(&atom),
+    };
+    SpanTree { span, children }
+}
+
+/// An error with the span of the source it arose from
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedError {
+    pub error: ComputeError,
+    pub span: Span,
+    /// The first and last characters of `span` as columns counted from 1
+    pub columns: (usize, usize),
+    /// A likely fix, as `hint` gives
+    pub hint: Option<String>,
+}
+
+impl SpannedError {
+    fn new(error: ComputeError, span: Span, source: &str, env: &Bindings) -> Self {
+        let column = |offset: usize| source[..offset].chars().count();
+        let first = column(span.start) + 1;
+        SpannedError {
+            hint: hint(source, &error, env),
+            error,
+            span,
+            columns: (first, column(span.end).max(first)),
+        }
+    }
+
+    /// `ComputeError::to_json` with this error's `span` and `columns`, and its `hint` when there is one
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = self.error.to_json();
+        json["span"] = serde_json::json!(self.span);
+        json["columns"] = serde_json::json!(self.columns);
+        if let Some(hint) = &self.hint {
+            json["hint"] = serde_json::json!(hint);
+        }
+        json
+    }
+
+    /// The line of `source` the error is on, with carets under its span and the hint, if any, below
+    ///
+    /// ```text
+    /// error[E002_DIV_ZERO]: Division by zero
+    ///   |
+    /// 1 | 1 + (2 / (1 - 1))
+    ///   |     ^^^^^^^^^^^^^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let Span { start, end } = self.span;
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+        let number = source[..line_start].matches('\n').count() + 1;
+        let indent = " ".repeat(source[line_start..start].chars().count());
+        // A span running onto later lines is underlined to the end of its first
+        let carets = "^".repeat(source[start..end.min(line_end)].chars().count().max(1));
+        let gutter = " ".repeat(number.to_string().len());
+        let line = source[line_start..line_end].trim_end_matches('\r');
+        let mut rendered = format!(
+            "error[{}]: {}\n{} |\n{} | {}\n{} | {}{}",
+            self.error.code(),
+            self.error.message(),
+            gutter,
+            number,
+            line,
+            gutter,
+            indent,
+            carets
+        );
+        if let Some(hint) = &self.hint {
+            rendered.push_str(&format!("\n{} = help: {}", gutter, hint));
+        }
+        rendered
+    }
+}
+
+impl fmt::Display for SpannedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self.error.message();
+        match self.columns {
+            (first, last) if first == last => write!(f, "{} at column {}", message, first),
+            (first, last) => write!(f, "{} at columns {}–{}", message, first, last),
+        }
+    }
+}
+
+impl core::error::Error for SpannedError {}
+
+/// Parse and evaluate `source` like `evaluate_with` without the final rounding, pointing any error at
+/// the sub-expression it arose in
+///
+/// A syntax error points where pest stopped, and an evaluation error at the
+/// innermost node that failed with it, such as the division in
+/// `1 + 10 / (5 - 5)`. Errors with no single origin, like an exhausted step
+/// budget, cover the whole expression. Parsing always uses the pest grammar.
+pub fn evaluate_spanned(source: &str, env: &Bindings, options: &EvalOptions) -> core::result::Result<f64, SpannedError> {
+    let whole = Span::new(0, source.len());
+    if source.trim().is_empty() {
+        return Err(SpannedError::new(ComputeError::EmptyExpression, whole, source, env));
+    }
+    let spanned = parse_spanned(source, &options.parse)
+        .map_err(|error| SpannedError::new(error.clone(), error.span().unwrap_or(whole), source, env))?;
+    options.eval(&spanned.expr, env).map_err(|error| {
+        let locator = Locator {
+            spans: spanned.span_map(),
+            failed: Cell::new(None),
+            env,
+            angle_mode: options.angle_mode,
+            rounding: options.rounding,
+        };
+        // The float evaluation finds the node unless the error is peculiar to the configured options
+        let span = match (locator.eval(&spanned.expr), locator.failed.get()) {
+            (Err(found), Some(span)) if found == error => span,
+            _ => spanned.spans.span,
+        };
+        SpannedError::new(error, span, source, env)
+    })
+}
+
+/// Evaluates like `eval_expr_in`, remembering the span of the first node to fail
+struct Locator<'a> {
+    spans: HashMap<*const Expr, Span>,
+    failed: Cell<Option<Span>>,
+    env: &'a Bindings,
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+}
+
+impl Locator<'_> {
+    fn eval(&self, expr: &Expr) -> Result<f64> {
+        let result = self.eval_node(expr);
+        // Children fail before their parents, so the first failure seen is where the error arose
+        if result.is_err() && self.failed.get().is_none() {
+            self.failed.set(self.spans.get(&(expr as *const Expr)).copied());
+        }
+        result
+    }
+
+    fn eval_node(&self, expr: &Expr) -> Result<f64> {
+        let eval = |e: &Expr| self.eval(e);
+        if let Some((a, p)) = expr.percent_change() {
+            return Ok(add_percent(eval(a)?, p));
+        }
+        match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Percent(n) => Ok(n / 100.0),
+            Expr::Var(name) => self
+                .env
+                .get(name)
+                .copied()
+                .ok_or_else(|| ComputeError::UndefinedVariable(name.clone())),
+            Expr::Add(l, r) => Ok(eval(l)? + eval(r)?),
+            Expr::Sub(l, r) => Ok(eval(l)? - eval(r)?),
+            Expr::Mul(l, r) => Ok(eval(l)? * eval(r)?),
+            Expr::Div(l, r) => {
+                let divisor = eval(r)?;
+                if divisor != 0.0 {
+                    Ok(eval(l)? / divisor)
+                } else {
+                    Err(ComputeError::DivisionByZero)
+                }
+            }
+            Expr::Pow(l, r) => Ok(eval(l)?.powf(eval(r)?)),
+            Expr::Neg(e) => eval(e).map(|n| -n),
+            Expr::Call(name, args) => functions::call(name, args, self.angle_mode, self.rounding, eval),
+            Expr::Compare(relation, l, r) => Ok(relation.eval(eval(l)?, eval(r)?)),
+            Expr::If(cond, then, otherwise) => eval(if is_true(eval(cond)?) { then } else { otherwise }),
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                // A true left operand decides `or` and a false one decides `and`
+                let decisive = matches!(expr, Expr::Or(..));
+                let holds = if is_true(eval(l)?) == decisive { decisive } else { is_true(eval(r)?) };
+                Ok(truth_value(holds))
+            }
+            Expr::Not(e) => Ok(truth_value(!is_true(eval(e)?))),
+            Expr::List(_) | Expr::Range(..) | Expr::Text(_) => Err(not_a_number(expr)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_expression;
+
+    fn located(source: &str) -> SpannedError {
+        evaluate_spanned(source, &Bindings::new(), &EvalOptions::default()).unwrap_err()
+    }
+
+    #[test]
+    fn test_spans_match_tree() {
+        let source = "-x + max(2 * y, sum(1..3)) / [1, 2] ? not 1 : (2 - 3)";
+        let spanned = parse_spanned(source, &ParseOptions::default()).unwrap();
+        assert_eq!(spanned.expr, parse_expression(source).unwrap());
+        let texts = |tree: &SpanTree| tree.children.iter().map(|c| c.span.slice(source)).collect::<Vec<_>>();
+        let root = &spanned.spans;
+        assert_eq!(root.span.slice(source), source);
+        assert_eq!(texts(root), ["-x + max(2 * y, sum(1..3)) / [1, 2]", "not 1", "(2 - 3)"]);
+        let sum = &root.children[0];
+        assert_eq!(texts(sum), ["-x", "max(2 * y, sum(1..3)) / [1, 2]"]);
+        assert_eq!(texts(&sum.children[0]), ["x"]);
+        let call = &sum.children[1].children[0];
+        assert_eq!(texts(call), ["2 * y", "sum(1..3)"]);
+        assert_eq!(texts(&call.children[1]), ["1..3"]);
+        assert_eq!(texts(&call.children[1].children[0]), ["1", "3"]);
+        assert_eq!(texts(&sum.children[1].children[1]), ["1", "2"]);
+
+        let Expr::If(cond, ..) = &spanned.expr else { panic!("expected a conditional") };
+        assert_eq!(spanned.span_of(cond).map(|span| span.slice(source)), Some(texts(root)[0]));
+        assert_eq!(spanned.span_of(&cond.clone()), None);
+    }
+
+    #[test]
+    fn test_error_locations() {
+        let error = located("1 + 2 * (10 / (5 - 5))");
+        assert_eq!(error.error, ComputeError::DivisionByZero);
+        assert_eq!(error.to_string(), "Division by zero at columns 9–22");
+        assert_eq!(located("2 * y + 1").to_string(), "Undefined variable: y at column 5");
+        assert_eq!(located("1 + sqrt([1, 2])").span, Span::new(9, 15));
+        // Offsets count bytes and columns count characters
+        let error = located("2 × (1 ÷ 0)");
+        assert_eq!((error.span, error.columns), (Span::new(5, 13), (5, 11)));
+        assert!(matches!(located("1 + * 2").error, ComputeError::ParseError(_)));
+        assert_eq!(located("1 +* 2").columns, (4, 4));
+
+        // An error that only the configured options raise covers the whole expression
+        let options = EvalOptions { fuel: Some(2), ..Default::default() };
+        let error = evaluate_spanned(" 1 + 2", &Bindings::new(), &options).unwrap_err();
+        assert_eq!((error.span, error.columns), (Span::new(1, 6), (2, 6)));
+        assert_eq!(evaluate_spanned("1 + 2", &Bindings::new(), &EvalOptions::default()), Ok(3.0));
+
+        let error = located("sqr(4) + 1");
+        assert_eq!(error.hint.as_deref(), Some("did you mean `sqrt`?"));
+        assert_eq!(
+            error.to_json(),
+            serde_json::json!({
+                "code": "E006_UNKNOWN_FUNCTION",
+                "category": "name",
+                "message": "Unknown function: sqr",
+                "span": { "start": 0, "end": 6 },
+                "columns": [1, 6],
+                "hint": "did you mean `sqrt`?",
+            })
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "1 + (2 / (1 - 1))";
+        assert_eq!(
+            located(source).render(source),
+            "error[E002_DIV_ZERO]: Division by zero\n  |\n1 | 1 + (2 / (1 - 1))\n  |     ^^^^^^^^^^^^^"
+        );
+        let source = "1 +\n 2 ×\n sqr(4)";
+        assert_eq!(
+            located(source).render(source),
+            "error[E006_UNKNOWN_FUNCTION]: Unknown function: sqr\n  |\n3 |  sqr(4)\n  |  ^^^^^^\n\
+             \x20 = help: did you mean `sqrt`?"
+        );
+        let source = "2 × (3 +";
+        assert_eq!(located(source).render(source).lines().nth(3), Some("  |         ^"));
+        assert!(located(source).to_string().ends_with("at column 9"));
+    }
+}
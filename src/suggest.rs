@@ -0,0 +1,255 @@
+//! Likely fixes for inputs that fail to parse or name unknown identifiers
+
+use crate::prelude::*;
+use crate::{tokenize, Bindings, ComputeError, ComputeParser, Rule, TokenKind, BUILTINS};
+use core::fmt;
+use pest::error::{ErrorVariant, InputLocation};
+use pest::Parser;
+
+/// A proposed correction for a source string that does not parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    /// What appears to be wrong, when it can be named
+    pub problem: Option<String>,
+    /// A corrected source string that parses
+    pub replacement: Option<String>,
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.problem, &self.replacement) {
+            (Some(problem), Some(replacement)) => write!(f, "{}: did you mean `{}`?", problem, replacement),
+            (None, Some(replacement)) => write!(f, "did you mean `{}`?", replacement),
+            (Some(problem), None) => f.write_str(problem),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Corrections applied in sequence before giving up on finding a parseable input
+const MAX_FIXES: usize = 8;
+
+/// Suggest a fix for `source` based on where parsing fails and what was expected there
+///
+/// Look-alike operators are replaced (`2 x 3` becomes `2 * 3`), adjacent
+/// operands get an explicit `*`, and unbalanced parentheses are closed or
+/// removed. Fixes are repeated until the input parses; if it never does,
+/// only the first problem found is reported. Returns `None` when `source`
+/// parses or nothing recognizable is wrong.
+pub fn suggest_fix(source: &str) -> Option<Suggestion> {
+    let mut first_problem = None;
+    let mut problem = None;
+    let mut current = source.to_string();
+    for i in 0..MAX_FIXES {
+        let error = match ComputeParser::parse(Rule::equation, &current) {
+            Ok(_) if i == 0 => return None,
+            Ok(_) => {
+                return Some(Suggestion {
+                    problem: problem.map(str::to_string),
+                    replacement: Some(current),
+                })
+            }
+            Err(e) => e,
+        };
+        let (note, fixed) = fix_step(&current, &error);
+        if i == 0 {
+            first_problem = note;
+        }
+        problem = problem.or(note);
+        match fixed {
+            Some(fixed) => current = fixed,
+            None => break,
+        }
+    }
+    // Problems found after a speculative fix may not exist in the original input
+    first_problem.map(|problem| Suggestion {
+        problem: Some(problem.to_string()),
+        replacement: None,
+    })
+}
+
+/// Diagnose one parse error, returning the problem and a rewritten source if one is known
+fn fix_step(source: &str, error: &pest::error::Error<Rule>) -> (Option<&'static str>, Option<String>) {
+    let pos = match error.location {
+        InputLocation::Pos(pos) => pos,
+        InputLocation::Span((start, _)) => start,
+    };
+    let expected: &[Rule] = match &error.variant {
+        ErrorVariant::ParsingError { positives, .. } => positives,
+        ErrorVariant::CustomError { .. } => &[],
+    };
+    let opens = source.matches('(').count();
+    let closes = source.matches(')').count();
+
+    let Some(token) = tokenize(source).into_iter().find(|t| t.span.start == pos) else {
+        // The input ended where more was expected
+        if opens > closes {
+            let closed = format!("{}{}", source.trim_end(), ")".repeat(opens - closes));
+            return (Some("missing closing parenthesis"), Some(closed));
+        }
+        if expected.contains(&Rule::primary) && !source.trim().is_empty() {
+            return (Some("the expression ends with an operator"), None);
+        }
+        return (None, None);
+    };
+
+    let before = source[..token.span.start].trim_end();
+    let text = token.span.slice(source);
+    let after = source[token.span.end..].trim_start();
+    let look_alike = match text {
+        "x" | "X" | "·" | "∙" => Some("*"),
+        "–" => Some("-"),
+        _ => None,
+    };
+    if let Some(op) = look_alike {
+        // `2 x` more likely multiplies by a variable than ends in an operator
+        if !after.is_empty() || token.kind == TokenKind::Invalid {
+            return (None, Some(join(before, op, after)));
+        }
+    }
+    if expected.contains(&Rule::multiply) {
+        // `1.2.3` reads as `1.2` followed by a literal starting with a point
+        let stray_point =
+            text.starts_with('.') && source[..token.span.start].ends_with(|c: char| c.is_ascii_digit() || c == '.');
+        match token.kind {
+            TokenKind::RightParen if closes > opens => {
+                return (Some("unmatched closing parenthesis"), Some(join(before, "", after)));
+            }
+            TokenKind::Number if stray_point => {
+                return (Some("a number has more than one decimal point"), None);
+            }
+            TokenKind::Operator if text == ".." => {
+                return (Some("a range can only be the argument of a function, as in `sum(1..10)`"), None);
+            }
+            TokenKind::Number | TokenKind::Identifier | TokenKind::LeftParen => {
+                // Implicit multiplication, as in `2(3)` or `2 x`
+                return (None, Some(join(before, "*", &source[token.span.start..])));
+            }
+            _ => {}
+        }
+    }
+    if expected.contains(&Rule::primary) && token.kind == TokenKind::Operator {
+        return (Some("missing a number or variable before an operator"), None);
+    }
+    (None, None)
+}
+
+/// A "did you mean" hint for an error from evaluating `source` in `env`, if one is found
+///
+/// Parse errors get the fix from `suggest_fix`; undefined variables get the
+/// closest bound name, and unknown functions the closest built-in.
+pub fn hint(source: &str, error: &ComputeError, env: &Bindings) -> Option<String> {
+    match error {
+        ComputeError::ParseError(_) => suggest_fix(source).map(|s| s.to_string()),
+        ComputeError::UndefinedVariable(name) => {
+            let closest = closest_name(name, env.keys().map(String::as_str))?;
+            Some(format!("did you mean `{}`?", closest))
+        }
+        ComputeError::UnknownFunction(name) => {
+            let closest = closest_name(name, BUILTINS.iter().map(|f| f.name))?;
+            Some(format!("did you mean `{}`?", closest))
+        }
+        _ => None,
+    }
+}
+
+/// The candidate nearest to `name` by edit distance, if any is close enough to be a typo
+///
+/// A candidate qualifies when at most a third of `name`'s characters (and at
+/// least one) must change; ties go to the first candidate in sorted order.
+pub fn closest_name<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance over characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        core::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Join the non-empty parts with single spaces
+fn join(before: &str, middle: &str, after: &str) -> String {
+    [before, middle, after]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suggestion(source: &str) -> String {
+        suggest_fix(source).map(|s| s.to_string()).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_operator_fixes() {
+        assert_eq!(suggestion("2 x 3"), "did you mean `2 * 3`?");
+        assert_eq!(suggestion("2 · 3 – 4"), "did you mean `2 * 3 - 4`?");
+        assert_eq!(suggestion("2 × 3 ÷ 4 − 1"), "");
+        assert_eq!(suggestion("2(3 + 1)"), "did you mean `2 * (3 + 1)`?");
+        assert_eq!(suggestion("2 x"), "did you mean `2 * x`?");
+        assert_eq!(suggestion("2 + 3"), "");
+    }
+
+    #[test]
+    fn test_parenthesis_fixes() {
+        assert_eq!(
+            suggestion("(1 + 2"),
+            "missing closing parenthesis: did you mean `(1 + 2)`?"
+        );
+        assert_eq!(
+            suggestion("1 + 2)"),
+            "unmatched closing parenthesis: did you mean `1 + 2`?"
+        );
+        assert_eq!(
+            suggestion("((2 x 3"),
+            "missing closing parenthesis: did you mean `((2 * 3))`?"
+        );
+    }
+
+    #[test]
+    fn test_problems_without_a_fix() {
+        assert_eq!(suggestion("2 +"), "the expression ends with an operator");
+        assert_eq!(suggestion("2 + * 3"), "missing a number or variable before an operator");
+        assert_eq!(suggestion("1..2"), "a range can only be the argument of a function, as in `sum(1..10)`");
+        assert_eq!(suggestion("1.2.3 + 1"), "a number has more than one decimal point");
+        assert_eq!(suggestion("2 .5"), "did you mean `2 * .5`?");
+    }
+
+    #[test]
+    fn test_closest_names() {
+        let names = ["rate", "total", "tax_rate", "ans"];
+        assert_eq!(closest_name("rat", names), Some("rate"));
+        assert_eq!(closest_name("totl", names), Some("total"));
+        assert_eq!(closest_name("tax_rte", names), Some("tax_rate"));
+        assert_eq!(closest_name("price", names), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+
+        let env: Bindings = [("rate".to_string(), 0.5)].into_iter().collect();
+        let error = ComputeError::UndefinedVariable("rte".into());
+        assert_eq!(hint("rte * 2", &error, &env).as_deref(), Some("did you mean `rate`?"));
+        assert_eq!(hint("1 / 0", &ComputeError::DivisionByZero, &env), None);
+        let error = ComputeError::UnknownFunction("sqr".into());
+        assert_eq!(hint("sqr(2)", &error, &env).as_deref(), Some("did you mean `sqrt`?"));
+    }
+}
@@ -0,0 +1,386 @@
+//! A standalone tokenizer that follows the grammar's lexical rules
+
+use crate::prelude::*;
+use crate::{ComputeParser, Rule, Span};
+use core::fmt;
+use pest::error::InputLocation;
+use pest::Parser;
+
+/// Lexical category of a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Number,
+    Identifier,
+    /// An operator such as `+`, `<=`, `?` or `and`; whether `-` is binary or unary is left to the parser
+    Operator,
+    LeftParen,
+    RightParen,
+    /// `[`, opening a list
+    LeftBracket,
+    RightBracket,
+    /// Quoted text, quotes included, such as `"km"`
+    Text,
+    /// A character the grammar never accepts
+    Invalid,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TokenKind::Number => "number",
+            TokenKind::Identifier => "identifier",
+            TokenKind::Operator => "operator",
+            TokenKind::LeftParen | TokenKind::RightParen => "paren",
+            TokenKind::LeftBracket | TokenKind::RightBracket => "bracket",
+            TokenKind::Text => "text",
+            TokenKind::Invalid => "invalid",
+        };
+        f.pad(name)
+    }
+}
+
+/// A token and where it appears in the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// Split `source` into tokens, skipping whitespace
+///
+/// Tokenizing never fails: characters the grammar does not accept become
+/// `Invalid` tokens, so the stream shows exactly where parsing would stop.
+/// Numbers use the grammar's longest match, so `2e` is the number `2`
+/// followed by the identifier `e`, and a directly following `%` belongs to
+/// the number. The keywords `and`, `or` and `not` are operators.
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let start = pos;
+        let kind = match bytes[pos] {
+            b' ' | b'\t' | b'\n' | b'\r' => {
+                pos += 1;
+                continue;
+            }
+            b if b.is_ascii_digit() || (b == b'.' && bytes.get(pos + 1).is_some_and(u8::is_ascii_digit)) => {
+                pos = number_end(bytes, pos);
+                if bytes.get(pos) == Some(&b'%') {
+                    pos += 1;
+                }
+                TokenKind::Number
+            }
+            b if b.is_ascii_alphabetic() || b == b'_' => {
+                while pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_') {
+                    pos += 1;
+                }
+                if matches!(&source[start..pos], "and" | "or" | "not") {
+                    TokenKind::Operator
+                } else {
+                    TokenKind::Identifier
+                }
+            }
+            b'+' | b'-' | b'*' | b'/' | b'^' | b'?' | b':' => {
+                pos += 1;
+                TokenKind::Operator
+            }
+            b'<' | b'>' | b'=' | b'!' if bytes.get(pos + 1) == Some(&b'=') => {
+                pos += 2;
+                TokenKind::Operator
+            }
+            b'<' | b'>' => {
+                pos += 1;
+                TokenKind::Operator
+            }
+            b'.' if bytes.get(pos + 1) == Some(&b'.') => {
+                pos += 2;
+                TokenKind::Operator
+            }
+            b'(' => {
+                pos += 1;
+                TokenKind::LeftParen
+            }
+            b')' => {
+                pos += 1;
+                TokenKind::RightParen
+            }
+            b'[' => {
+                pos += 1;
+                TokenKind::LeftBracket
+            }
+            b']' => {
+                pos += 1;
+                TokenKind::RightBracket
+            }
+            // An unterminated quote is invalid on its own, and the text after it is tokenized as usual
+            b'"' => match source[pos + 1..].find('"') {
+                Some(len) => {
+                    pos += len + 2;
+                    TokenKind::Text
+                }
+                None => {
+                    pos += 1;
+                    TokenKind::Invalid
+                }
+            },
+            _ => {
+                // Keep multi-byte characters whole so spans stay on char boundaries
+                let c = source[pos..].chars().next().expect("pos is within source");
+                pos += c.len_utf8();
+                match c {
+                    '\u{A0}' | '\u{202F}' | '\u{3000}' => continue,
+                    '−' | '×' | '÷' => TokenKind::Operator,
+                    _ => TokenKind::Invalid,
+                }
+            }
+        };
+        tokens.push(Token {
+            kind,
+            span: Span::new(start, pos),
+        });
+    }
+    tokens
+}
+
+/// Highlighting class of a source region
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    Number,
+    Variable,
+    Operator,
+    /// A parenthesis or list bracket
+    Paren,
+    /// Quoted text
+    Text,
+    /// An invalid character, an unbalanced parenthesis or bracket, or the token where parsing fails
+    Error,
+}
+
+impl fmt::Display for TokenClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TokenClass::Number => "number",
+            TokenClass::Variable => "variable",
+            TokenClass::Operator => "operator",
+            TokenClass::Paren => "paren",
+            TokenClass::Text => "text",
+            TokenClass::Error => "error",
+        };
+        f.pad(name)
+    }
+}
+
+/// Classify every token of `source` for syntax highlighting
+///
+/// Regions follow `tokenize`, so whitespace is never classified. Besides
+/// invalid characters, a token is marked as an error if it is a parenthesis
+/// or bracket without a partner of its own kind, or the token at which the
+/// grammar rejects the input.
+pub fn classify(source: &str) -> Vec<(Span, TokenClass)> {
+    let tokens = tokenize(source);
+    let mut classes: Vec<_> = tokens
+        .iter()
+        .map(|token| match token.kind {
+            TokenKind::Number => TokenClass::Number,
+            TokenKind::Identifier => TokenClass::Variable,
+            TokenKind::Operator => TokenClass::Operator,
+            TokenKind::LeftParen | TokenKind::RightParen => TokenClass::Paren,
+            TokenKind::LeftBracket | TokenKind::RightBracket => TokenClass::Paren,
+            TokenKind::Text => TokenClass::Text,
+            TokenKind::Invalid => TokenClass::Error,
+        })
+        .collect();
+
+    let mut open: Vec<usize> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let opener = match token.kind {
+            TokenKind::LeftParen | TokenKind::LeftBracket => {
+                open.push(i);
+                continue;
+            }
+            TokenKind::RightParen => TokenKind::LeftParen,
+            TokenKind::RightBracket => TokenKind::LeftBracket,
+            _ => continue,
+        };
+        if open.last().is_some_and(|&j| tokens[j].kind == opener) {
+            open.pop();
+        } else {
+            classes[i] = TokenClass::Error;
+        }
+    }
+    for i in open {
+        classes[i] = TokenClass::Error;
+    }
+
+    if let Err(e) = ComputeParser::parse(Rule::equation, source) {
+        let pos = match e.location {
+            InputLocation::Pos(pos) => pos,
+            InputLocation::Span((start, _)) => start,
+        };
+        if let Some(i) = tokens.iter().position(|t| t.span.start == pos) {
+            classes[i] = TokenClass::Error;
+        }
+    }
+
+    tokens.iter().map(|t| t.span).zip(classes).collect()
+}
+
+/// End of the longest `scientific | decimal | integer` match starting at `pos`
+fn number_end(bytes: &[u8], start: usize) -> usize {
+    let digits = |mut pos: usize| {
+        while bytes.get(pos).is_some_and(u8::is_ascii_digit) {
+            pos += 1;
+        }
+        pos
+    };
+    let grouped = |start: usize| {
+        let mut pos = digits(start);
+        while pos > start && bytes.get(pos) == Some(&b'_') && bytes.get(pos + 1).is_some_and(u8::is_ascii_digit) {
+            pos = digits(pos + 1);
+        }
+        pos
+    };
+    let mut pos = grouped(start);
+    // Either side of the point may be empty, but not both, and a point followed by another starts a range
+    let fraction = match bytes.get(pos + 1) {
+        Some(b'.') => false,
+        next => pos > start || next.is_some_and(u8::is_ascii_digit),
+    };
+    if bytes.get(pos) == Some(&b'.') && fraction {
+        pos = grouped(pos + 1);
+    }
+    if let Some(b'e' | b'E') = bytes.get(pos) {
+        let mut exponent = pos + 1;
+        if let Some(b'+' | b'-') = bytes.get(exponent) {
+            exponent += 1;
+        }
+        // An exponent marker without digits is not part of the literal
+        if bytes.get(exponent).is_some_and(u8::is_ascii_digit) {
+            pos = digits(exponent);
+        }
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds_and_text(source: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(source)
+            .into_iter()
+            .map(|t| (t.kind, t.span.slice(source)))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize_expression() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds_and_text("-(rate_2 * 1.5e-3)/4"),
+            vec![
+                (Operator, "-"),
+                (LeftParen, "("),
+                (Identifier, "rate_2"),
+                (Operator, "*"),
+                (Number, "1.5e-3"),
+                (RightParen, ")"),
+                (Operator, "/"),
+                (Number, "4"),
+            ]
+        );
+        assert_eq!(tokenize(" 12 ")[0].span, Span::new(1, 3));
+        assert_eq!(kinds_and_text("x^2"), vec![(Identifier, "x"), (Operator, "^"), (Number, "2")]);
+        assert_eq!(kinds_and_text("[1]"), vec![(LeftBracket, "["), (Number, "1"), (RightBracket, "]")]);
+        assert_eq!(
+            kinds_and_text("a<=b?1:c!=2"),
+            vec![
+                (Identifier, "a"),
+                (Operator, "<="),
+                (Identifier, "b"),
+                (Operator, "?"),
+                (Number, "1"),
+                (Operator, ":"),
+                (Identifier, "c"),
+                (Operator, "!="),
+                (Number, "2"),
+            ]
+        );
+        assert_eq!(kinds_and_text("a = !b"), vec![(Identifier, "a"), (Invalid, "="), (Invalid, "!"), (Identifier, "b")]);
+        assert_eq!(
+            kinds_and_text("not x or android"),
+            vec![(Operator, "not"), (Identifier, "x"), (Operator, "or"), (Identifier, "android")]
+        );
+        assert_eq!(
+            kinds_and_text("x \"°C\"\"F) \" \"open"),
+            vec![(Identifier, "x"), (Text, "\"°C\""), (Text, "\"F) \""), (Invalid, "\""), (Identifier, "open")]
+        );
+    }
+
+    #[test]
+    fn test_longest_match_and_invalid_characters() {
+        use TokenKind::*;
+        assert_eq!(kinds_and_text("2e"), vec![(Number, "2"), (Identifier, "e")]);
+        assert_eq!(kinds_and_text("1. + .5e1"), vec![(Number, "1."), (Operator, "+"), (Number, ".5e1")]);
+        assert_eq!(kinds_and_text("1..2 ."), vec![(Number, "1"), (Operator, ".."), (Number, "2"), (Invalid, ".")]);
+        assert_eq!(kinds_and_text("1...5"), vec![(Number, "1"), (Operator, ".."), (Number, ".5")]);
+        assert_eq!(kinds_and_text("5e1%+x"), vec![(Number, "5e1%"), (Operator, "+"), (Identifier, "x")]);
+        assert_eq!(kinds_and_text("5 %"), vec![(Number, "5"), (Invalid, "%")]);
+        assert_eq!(kinds_and_text("1_000.5_5 1__0"), vec![(Number, "1_000.5_5"), (Number, "1"), (Identifier, "__0")]);
+        assert_eq!(
+            kinds_and_text("3 × 4\u{A0}÷ −2 ∙ 1"),
+            vec![
+                (Number, "3"),
+                (Operator, "×"),
+                (Number, "4"),
+                (Operator, "÷"),
+                (Operator, "−"),
+                (Number, "2"),
+                (Invalid, "∙"),
+                (Number, "1"),
+            ]
+        );
+        assert_eq!(kinds_and_text(" \t\n"), vec![]);
+    }
+
+    fn classes(source: &str) -> Vec<(&str, TokenClass)> {
+        classify(source)
+            .into_iter()
+            .map(|(span, class)| (span.slice(source), class))
+            .collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        use TokenClass::*;
+        assert_eq!(
+            classes("-(x * 2)"),
+            vec![
+                ("-", Operator),
+                ("(", Paren),
+                ("x", Variable),
+                ("*", Operator),
+                ("2", Number),
+                (")", Paren),
+            ]
+        );
+        assert_eq!(
+            classes("(1 + 2"),
+            vec![("(", Error), ("1", Number), ("+", Operator), ("2", Number)]
+        );
+        assert_eq!(classes("1 + 2)"), vec![("1", Number), ("+", Operator), ("2", Number), (")", Error)]);
+        assert_eq!(classes("2 3 @"), vec![("2", Number), ("3", Error), ("@", Error)]);
+        assert_eq!(
+            classes("sum([x)]"),
+            vec![
+                ("sum", Variable),
+                ("(", Error),
+                ("[", Paren),
+                ("x", Variable),
+                (")", Error),
+                ("]", Paren),
+            ]
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Structured event log of an evaluation
+
+use crate::prelude::*;
+use crate::{
+    add_percent, functions, is_true, not_a_number, truth_value, AngleMode, ComputeError, Expr, Result, RoundingMode,
+};
+use core::fmt;
+
+/// Kind of AST node an evaluation event refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    Number,
+    Percent,
+    Var,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Neg,
+    Call,
+    Compare,
+    If,
+    And,
+    Or,
+    Not,
+    List,
+    Range,
+    Text,
+}
+
+impl NodeKind {
+    pub fn of(expr: &Expr) -> Self {
+        match expr {
+            Expr::Number(_) => NodeKind::Number,
+            Expr::Percent(_) => NodeKind::Percent,
+            Expr::Var(_) => NodeKind::Var,
+            Expr::Add(..) => NodeKind::Add,
+            Expr::Sub(..) => NodeKind::Sub,
+            Expr::Mul(..) => NodeKind::Mul,
+            Expr::Div(..) => NodeKind::Div,
+            Expr::Pow(..) => NodeKind::Pow,
+            Expr::Neg(_) => NodeKind::Neg,
+            Expr::Call(..) => NodeKind::Call,
+            Expr::Compare(..) => NodeKind::Compare,
+            Expr::If(..) => NodeKind::If,
+            Expr::And(..) => NodeKind::And,
+            Expr::Or(..) => NodeKind::Or,
+            Expr::Not(_) => NodeKind::Not,
+            Expr::List(_) => NodeKind::List,
+            Expr::Range(..) => NodeKind::Range,
+            Expr::Text(_) => NodeKind::Text,
+        }
+    }
+}
+
+/// One step of an evaluation; `depth` is 0 for the root node
+///
+/// Every `Enter` is closed by exactly one `Value` or `Error` at the same
+/// depth, so the stream can be folded back into a tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalEvent {
+    Enter { kind: NodeKind, depth: usize },
+    Value { value: f64, depth: usize },
+    Error { error: ComputeError, depth: usize },
+}
+
+impl fmt::Display for EvalEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (EvalEvent::Enter { depth, .. }
+        | EvalEvent::Value { depth, .. }
+        | EvalEvent::Error { depth, .. }) = self;
+        write!(f, "{:1$}", "", depth * 2)?;
+        match self {
+            EvalEvent::Enter { kind, .. } => write!(f, "enter {:?}", kind),
+            EvalEvent::Value { value, .. } => write!(f, "value {}", crate::format_number(*value)),
+            EvalEvent::Error { error, .. } => write!(f, "error {}", error),
+        }
+    }
+}
+
+/// Evaluate like `eval_expr`, appending an event for every node to `events`
+pub fn eval_expr_traced(expr: &Expr, events: &mut Vec<EvalEvent>) -> Result<f64> {
+    eval_traced(expr, 0, events)
+}
+
+fn eval_traced(expr: &Expr, depth: usize, events: &mut Vec<EvalEvent>) -> Result<f64> {
+    events.push(EvalEvent::Enter {
+        kind: NodeKind::of(expr),
+        depth,
+    });
+    let child = depth + 1;
+    let result = match expr {
+        Expr::Number(n) => Ok(*n),
+        Expr::Percent(n) => Ok(n / 100.0),
+        Expr::Var(name) => Err(ComputeError::UndefinedVariable(name.clone())),
+        Expr::Add(l, r) | Expr::Sub(l, r) if matches!(**r, Expr::Percent(_)) => {
+            eval_traced(l, child, events).and_then(|l| {
+                // The percent literal reports its value, but scales the left operand instead of adding to it
+                eval_traced(r, child, events)?;
+                let (_, p) = expr.percent_change().expect("percent literal on the right");
+                Ok(add_percent(l, p))
+            })
+        }
+        Expr::Add(l, r) => eval_traced(l, child, events)
+            .and_then(|l| Ok(l + eval_traced(r, child, events)?)),
+        Expr::Sub(l, r) => eval_traced(l, child, events)
+            .and_then(|l| Ok(l - eval_traced(r, child, events)?)),
+        Expr::Mul(l, r) => eval_traced(l, child, events)
+            .and_then(|l| Ok(l * eval_traced(r, child, events)?)),
+        Expr::Div(l, r) => eval_traced(r, child, events).and_then(|divisor| {
+            if divisor != 0.0 {
+                Ok(eval_traced(l, child, events)? / divisor)
+            } else {
+                Err(ComputeError::DivisionByZero)
+            }
+        }),
+        Expr::Pow(l, r) => eval_traced(l, child, events)
+            .and_then(|l| Ok(l.powf(eval_traced(r, child, events)?))),
+        Expr::Neg(e) => eval_traced(e, child, events).map(|n| -n),
+        // An aggregate's list or range is not entered; its bounds and elements are traced as the call's children
+        Expr::Call(name, args) => functions::resolve(name, args.len()).and_then(|function| {
+            let values = function
+                .operands(args, |bound| eval_traced(bound, child, events))?
+                .iter()
+                .map(|arg| eval_traced(arg, child, events))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(function.eval(&values, AngleMode::Radians, RoundingMode::default()))
+        }),
+        Expr::Compare(relation, l, r) => eval_traced(l, child, events)
+            .and_then(|l| Ok(relation.eval(l, eval_traced(r, child, events)?))),
+        Expr::If(cond, then, otherwise) => eval_traced(cond, child, events).and_then(|cond| {
+            eval_traced(if is_true(cond) { then } else { otherwise }, child, events)
+        }),
+        Expr::And(l, r) | Expr::Or(l, r) => {
+            // A true left operand decides `or` and a false one decides `and`
+            let decisive = matches!(expr, Expr::Or(..));
+            eval_traced(l, child, events).and_then(|l| {
+                if is_true(l) == decisive {
+                    Ok(truth_value(decisive))
+                } else {
+                    eval_traced(r, child, events).map(|r| truth_value(is_true(r)))
+                }
+            })
+        }
+        Expr::Not(e) => eval_traced(e, child, events).map(|n| truth_value(!is_true(n))),
+        Expr::List(_) | Expr::Range(..) | Expr::Text(_) => Err(not_a_number(expr)),
+    };
+    events.push(match &result {
+        Ok(value) => EvalEvent::Value {
+            value: *value,
+            depth,
+        },
+        Err(error) => EvalEvent::Error {
+            error: error.clone(),
+            depth,
+        },
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval_expr, parse_expression};
+
+    fn trace(src: &str) -> (Result<f64>, Vec<String>) {
+        let mut events = Vec::new();
+        let result = eval_expr_traced(&parse_expression(src).unwrap(), &mut events);
+        (result, events.iter().map(|e| e.to_string()).collect())
+    }
+
+    #[test]
+    fn test_event_stream() {
+        let (result, events) = trace("2 * -3");
+        assert_eq!(result, Ok(-6.0));
+        assert_eq!(
+            events,
+            vec![
+                "enter Mul",
+                "  enter Number",
+                "  value 2",
+                "  enter Neg",
+                "    enter Number",
+                "    value 3",
+                "  value -3",
+                "value -6",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors_propagate_to_root() {
+        let (result, events) = trace("1 + 4 / (2 - 2)");
+        assert_eq!(result, Err(ComputeError::DivisionByZero));
+        assert_eq!(events[events.len() - 2], "  error Division by zero");
+        assert_eq!(events[events.len() - 1], "error Division by zero");
+
+        // A zero divisor fails before the dividend is entered
+        let (result, events) = trace("x / 0");
+        assert_eq!(result, Err(ComputeError::DivisionByZero));
+        assert_eq!(events, vec!["enter Div", "  enter Number", "  value 0", "error Division by zero"]);
+    }
+
+    #[test]
+    fn test_matches_eval_expr() {
+        for src in ["1 + 2 * 3", "-(4 - 6) / 8", "x * 0", "1 / 0 + y", "x / (1 - 1)", "3 - 12.5% + 50%"] {
+            let ast = parse_expression(src).unwrap();
+            let mut events = Vec::new();
+            assert_eq!(eval_expr_traced(&ast, &mut events), eval_expr(&ast), "{}", src);
+            let enters = events.iter().filter(|e| matches!(e, EvalEvent::Enter { .. })).count();
+            assert_eq!(enters * 2, events.len(), "{}", src);
+        }
+    }
+}
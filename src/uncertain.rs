@@ -0,0 +1,278 @@
+//! Evaluation that propagates the uncertainties of measured values such as `2.0 ± 0.1`
+
+use crate::functions::{self, Apply};
+use crate::prelude::*;
+use crate::{
+    add_percent, is_true, not_a_number, truth_value, AngleMode, Bindings, ComputeError, EvalOptions, Expr, Result,
+    RoundingMode,
+};
+use core::fmt;
+
+/// A value together with its standard uncertainty
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Uncertain {
+    pub value: f64,
+    /// One standard deviation, `0` for a value computed from exact inputs
+    pub uncertainty: f64,
+}
+
+impl Uncertain {
+    /// Significant figures of `value` that `uncertainty` leaves meaningful, with the uncertainty given to two
+    ///
+    /// `None` for an exact or non-finite result, whose figures are not limited.
+    pub fn significant_figures(&self) -> Option<u32> {
+        let places = self.decimal_places()?;
+        let magnitude = if self.value == 0.0 { 0 } else { self.value.abs().log10().floor() as i32 };
+        Some((magnitude + places + 1).max(1) as u32)
+    }
+
+    /// Decimal places at which two significant figures of the uncertainty end, negative left of the point
+    fn decimal_places(&self) -> Option<i32> {
+        let finite = self.value.is_finite() && self.uncertainty.is_finite();
+        (finite && self.uncertainty > 0.0).then(|| 1 - self.uncertainty.log10().floor() as i32)
+    }
+}
+
+/// `value ± uncertainty`, both rounded to where two significant figures of the uncertainty end, as in `4.00 ± 0.20`
+impl fmt::Display for Uncertain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.decimal_places() {
+            Some(places) if places > 0 => {
+                let places = places as usize;
+                write!(f, "{:.*} ± {:.*}", places, self.value, places, self.uncertainty)
+            }
+            Some(places) => {
+                let unit = 10f64.powi(-places);
+                write!(f, "{} ± {}", (self.value / unit).round() * unit, (self.uncertainty / unit).round() * unit)
+            }
+            None => write!(f, "{} ± {}", crate::format_number(self.value), self.uncertainty),
+        }
+    }
+}
+
+/// Evaluate `expr` like `eval_expr_in`, also propagating the uncertainty of every `pm(value, uncertainty)` call
+///
+/// Each call, which `2.0 ± 0.1` parses to, is an independent measurement,
+/// so writing `2 ± 0.1` twice is two measurements. Uncertainties propagate
+/// to first order: an operation's is the root sum of squares of each
+/// operand's uncertainty times the operation's derivative with respect to
+/// it. Derivatives are exact for arithmetic and numerical for built-in
+/// functions. Comparisons and logic give exact results, and a conditional
+/// has the uncertainty of the branch it selects. Values, errors included,
+/// are exactly those of `eval_expr_in`.
+pub fn eval_uncertain(expr: &Expr, env: &Bindings, angle_mode: AngleMode) -> Result<Uncertain> {
+    eval_uncertain_rounding(expr, env, angle_mode, RoundingMode::default())
+}
+
+/// Like `eval_uncertain`, with `round` rounding in `rounding`
+pub(crate) fn eval_uncertain_rounding(
+    expr: &Expr,
+    env: &Bindings,
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+) -> Result<Uncertain> {
+    Propagation { env, angle_mode, rounding }.eval(expr)
+}
+
+/// Evaluate an expression string under `options`, propagating its uncertainties with `eval_uncertain`
+///
+/// The value is rounded by `result_places` and checked by `strict_finite`
+/// like that of `evaluate_with`; the uncertainty is not rounded.
+pub fn evaluate_uncertain(expr: &str, options: &EvalOptions) -> Result<Uncertain> {
+    options.parse.check_len(expr)?;
+    let ast = match expr.trim() {
+        "" => return Err(ComputeError::EmptyExpression),
+        trimmed => options.parse(trimmed)?,
+    };
+    let result = eval_uncertain_rounding(&ast, &Bindings::new(), options.angle_mode, options.rounding)?;
+    let value = options.round_result(options.check_finite(result.value)?);
+    Ok(Uncertain { value, ..result })
+}
+
+impl Uncertain {
+    fn exact(value: f64) -> Self {
+        Uncertain { value, uncertainty: 0.0 }
+    }
+
+    /// `value`, with the uncertainties of `self` and `other` scaled by the derivatives `da` and `db`
+    fn combine(&self, da: f64, other: &Uncertain, db: f64, value: f64) -> Uncertain {
+        Uncertain { value, uncertainty: self.scaled(da).hypot(other.scaled(db)) }
+    }
+
+    /// The uncertainty times `derivative`, which an exact value leaves `0` even where the derivative is not finite
+    fn scaled(&self, derivative: f64) -> f64 {
+        if self.uncertainty == 0.0 {
+            0.0
+        } else {
+            (self.uncertainty * derivative).abs()
+        }
+    }
+}
+
+struct Propagation<'a> {
+    env: &'a Bindings,
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+}
+
+impl Propagation<'_> {
+    fn eval(&self, expr: &Expr) -> Result<Uncertain> {
+        let eval = |e: &Expr| self.eval(e);
+        let value = |e: &Expr| self.eval(e).map(|x| x.value);
+        if let Some((a, p)) = expr.percent_change() {
+            let a = eval(a)?;
+            return Ok(Uncertain { value: add_percent(a.value, p), uncertainty: a.scaled(add_percent(1.0, p)) });
+        }
+        match expr {
+            Expr::Number(n) => Ok(Uncertain::exact(*n)),
+            Expr::Percent(n) => Ok(Uncertain::exact(n / 100.0)),
+            Expr::Var(name) => self
+                .env
+                .get(name)
+                .map(|&n| Uncertain::exact(n))
+                .ok_or_else(|| ComputeError::UndefinedVariable(name.clone())),
+            Expr::Add(l, r) => {
+                let (l, r) = (eval(l)?, eval(r)?);
+                Ok(l.combine(1.0, &r, 1.0, l.value + r.value))
+            }
+            Expr::Sub(l, r) => {
+                let (l, r) = (eval(l)?, eval(r)?);
+                Ok(l.combine(1.0, &r, 1.0, l.value - r.value))
+            }
+            Expr::Mul(l, r) => {
+                let (l, r) = (eval(l)?, eval(r)?);
+                Ok(l.combine(r.value, &r, l.value, l.value * r.value))
+            }
+            Expr::Div(l, r) => {
+                let divisor = eval(r)?;
+                if divisor.value == 0.0 {
+                    return Err(ComputeError::DivisionByZero);
+                }
+                let l = eval(l)?;
+                let quotient = l.value / divisor.value;
+                Ok(l.combine(1.0 / divisor.value, &divisor, quotient / divisor.value, quotient))
+            }
+            Expr::Pow(l, r) => {
+                let (l, r) = (eval(l)?, eval(r)?);
+                let power = l.value.powf(r.value);
+                Ok(l.combine(r.value * l.value.powf(r.value - 1.0), &r, power * l.value.ln(), power))
+            }
+            Expr::Neg(e) => eval(e).map(|e| Uncertain { value: -e.value, ..e }),
+            Expr::Call(name, args) => self.call(name, args),
+            Expr::Compare(relation, l, r) => Ok(Uncertain::exact(relation.eval(value(l)?, value(r)?))),
+            Expr::If(cond, then, otherwise) => eval(if is_true(value(cond)?) { then } else { otherwise }),
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                // A true left operand decides `or` and a false one decides `and`
+                let decisive = matches!(expr, Expr::Or(..));
+                let holds = if is_true(value(l)?) == decisive { decisive } else { is_true(value(r)?) };
+                Ok(Uncertain::exact(truth_value(holds)))
+            }
+            Expr::Not(e) => Ok(Uncertain::exact(truth_value(!is_true(value(e)?)))),
+            Expr::List(_) | Expr::Range(..) | Expr::Text(_) => Err(not_a_number(expr)),
+        }
+    }
+
+    /// Apply a built-in, differentiating it numerically in each uncertain operand
+    fn call(&self, name: &str, args: &[Expr]) -> Result<Uncertain> {
+        let function = functions::resolve(name, args.len())?;
+        let operands = function.operands(args, |e| self.eval(e).map(|x| x.value))?;
+        let operands = operands.iter().map(|e| self.eval(e)).collect::<Result<Vec<_>>>()?;
+        let mut values: Vec<f64> = operands.iter().map(|x| x.value).collect();
+        let value = function.eval(&values, self.angle_mode, self.rounding);
+        if name == "pm" {
+            let measured = Uncertain { value, uncertainty: operands[1].value.abs() };
+            return Ok(operands[0].combine(1.0, &measured, 1.0, value));
+        }
+        // Rounding is flat wherever it is defined
+        if matches!(function.apply, Apply::Rounding(_)) {
+            return Ok(Uncertain::exact(value));
+        }
+        let mut result = Uncertain::exact(value);
+        for (i, operand) in operands.iter().enumerate().filter(|(_, x)| x.uncertainty != 0.0) {
+            let x = values[i];
+            let step = f64::EPSILON.cbrt() * x.abs().max(1.0);
+            values[i] = x + step;
+            let above = function.eval(&values, self.angle_mode, self.rounding);
+            values[i] = x - step;
+            let below = function.eval(&values, self.angle_mode, self.rounding);
+            values[i] = x;
+            result = result.combine(1.0, operand, (above - below) / (2.0 * step), value);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval_expr_in, parse_expression};
+
+    fn uncertain(source: &str) -> Uncertain {
+        evaluate_uncertain(source, &EvalOptions::default()).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() <= 1e-9 * expected.abs().max(1.0), "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_propagation() {
+        let sum = uncertain("2.0 ± 0.3 + 1.0 +/- 0.4");
+        assert_eq!(sum.value, 3.0);
+        assert_close(sum.uncertainty, 0.5);
+
+        // Relative uncertainties add in quadrature through products and quotients
+        let product = uncertain("(10 ± 0.3) * (20 ± 0.8) / 2");
+        assert_eq!(product.value, 100.0);
+        assert_close(product.uncertainty, 100.0 * 0.05);
+        let square = uncertain("(3 ± 0.1) ^ 2");
+        assert_close(square.uncertainty, 0.6);
+        assert_close(uncertain("sqrt(16 ± 0.8)").uncertainty, 0.1);
+        assert_close(uncertain("2 ^ (3 ± 0.1)").uncertainty, 0.8 * 2f64.ln());
+
+        // Each written measurement is independent of the others
+        let env = Bindings::new();
+        let scaled = eval_uncertain(&parse_expression("pm(5, 0.1) * 0").unwrap(), &env, AngleMode::Radians).unwrap();
+        assert_eq!(scaled.uncertainty, 0.0);
+        assert_close(uncertain("2 ± 0.1 - 2 ± 0.1").uncertainty, 0.1 * 2f64.sqrt());
+        assert_close(uncertain("sum([1 ± 0.3, 2, 3 ± 0.4])").uncertainty, 0.5);
+
+        // Exact inputs, rounding and logic give exact results
+        assert_eq!(uncertain("1 + 2 * 3"), Uncertain { value: 7.0, uncertainty: 0.0 });
+        assert_eq!(uncertain("floor(2.5 ± 0.1)").uncertainty, 0.0);
+        assert_eq!(uncertain("2 ± 1 > 1 ? 3 ± 0.5 : 0"), Uncertain { value: 3.0, uncertainty: 0.5 });
+    }
+
+    #[test]
+    fn test_values_match_eval_expr() {
+        let env: Bindings = [("x".to_string(), 4.0)].into_iter().collect();
+        for source in [
+            "2.5 ± 0.1 * x - 10%",
+            "x / (x - 4 ± 1)",
+            "sum(1..x) + max(x, 2 ± 1, 3)",
+            "sin(30 ± 1) + atan2(1, 1 ± 0.1)",
+            "convert(x ± 0.5, \"ft\", \"in\")",
+            "1e308 ± 1e307 * 10",
+            "pm(1, 2, 3)",
+            "[1, 2] + 1",
+            "y + 2 ± 1",
+        ] {
+            let Ok(expr) = parse_expression(source) else {
+                continue;
+            };
+            let expected = eval_expr_in(&expr, &env, AngleMode::Degrees);
+            let value = eval_uncertain(&expr, &env, AngleMode::Degrees).map(|result| result.value);
+            assert!(value == expected || value.as_ref().is_ok_and(|v| v.is_nan()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let result = uncertain("(2.0 ± 0.1) * 2");
+        assert_eq!(result.to_string(), "4.00 ± 0.20");
+        assert_eq!(result.significant_figures(), Some(3));
+        assert_eq!(uncertain("12344 ± 678").to_string(), "12340 ± 680");
+        assert_eq!(uncertain("1 / 4").to_string(), "0.25 ± 0");
+        assert_eq!(uncertain("1 / 4").significant_figures(), None);
+    }
+}
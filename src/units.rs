@@ -0,0 +1,198 @@
+//! Units of measure for `convert(value, "from", "to")`
+
+use crate::prelude::*;
+use crate::{closest_name, ComputeError, Result};
+use core::fmt;
+
+/// The quantity a unit measures; only units of one dimension convert into each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    Length,
+    Mass,
+    Temperature,
+    DataSize,
+    Time,
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Dimension::Length => "length",
+            Dimension::Mass => "mass",
+            Dimension::Temperature => "temperature",
+            Dimension::DataSize => "data size",
+            Dimension::Time => "time",
+        })
+    }
+}
+
+/// A unit, in which a value `x` is `(x + offset) * numerator / denominator` of its dimension's base unit
+///
+/// Scales are kept as fractions of integers so that conversions such as
+/// feet to inches or Celsius to Fahrenheit multiply and divide by exact
+/// whole numbers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    /// The symbol first, then any other accepted spellings
+    pub names: &'static [&'static str],
+    pub dimension: Dimension,
+    offset: f64,
+    numerator: f64,
+    denominator: f64,
+}
+
+impl Unit {
+    const fn new(names: &'static [&'static str], dimension: Dimension, numerator: f64, denominator: f64) -> Self {
+        Unit {
+            names,
+            dimension,
+            offset: 0.0,
+            numerator,
+            denominator,
+        }
+    }
+
+    const fn offset(self, offset: f64) -> Self {
+        Unit { offset, ..self }
+    }
+}
+
+use Dimension::*;
+
+/// Every known unit; the base units are the metre, gram, degree Celsius, byte and second
+pub const UNITS: &[Unit] = &[
+    Unit::new(&["m", "meter", "meters", "metre", "metres"], Length, 1.0, 1.0),
+    Unit::new(&["km", "kilometer", "kilometers", "kilometre", "kilometres"], Length, 1000.0, 1.0),
+    Unit::new(&["cm", "centimeter", "centimeters", "centimetre", "centimetres"], Length, 1.0, 100.0),
+    Unit::new(&["mm", "millimeter", "millimeters", "millimetre", "millimetres"], Length, 1.0, 1000.0),
+    Unit::new(&["um", "µm", "micrometer", "micrometers", "micrometre", "micrometres"], Length, 1.0, 1e6),
+    Unit::new(&["nm", "nanometer", "nanometers", "nanometre", "nanometres"], Length, 1.0, 1e9),
+    Unit::new(&["mi", "mile", "miles"], Length, 1_609_344.0, 1000.0),
+    Unit::new(&["yd", "yard", "yards"], Length, 9144.0, 10_000.0),
+    Unit::new(&["ft", "foot", "feet"], Length, 3048.0, 10_000.0),
+    Unit::new(&["in", "inch", "inches"], Length, 254.0, 10_000.0),
+    Unit::new(&["nmi", "nautical_mile", "nautical_miles"], Length, 1852.0, 1.0),
+    Unit::new(&["g", "gram", "grams"], Mass, 1.0, 1.0),
+    Unit::new(&["kg", "kilogram", "kilograms"], Mass, 1000.0, 1.0),
+    Unit::new(&["mg", "milligram", "milligrams"], Mass, 1.0, 1000.0),
+    Unit::new(&["t", "tonne", "tonnes"], Mass, 1e6, 1.0),
+    Unit::new(&["lb", "lbs", "pound", "pounds"], Mass, 45_359_237.0, 100_000.0),
+    Unit::new(&["oz", "ounce", "ounces"], Mass, 45_359_237.0, 1_600_000.0),
+    Unit::new(&["st", "stone", "stones"], Mass, 635_029_318.0, 100_000.0),
+    Unit::new(&["C", "°C", "celsius"], Temperature, 1.0, 1.0),
+    Unit::new(&["F", "°F", "fahrenheit"], Temperature, 5.0, 9.0).offset(-32.0),
+    Unit::new(&["K", "kelvin"], Temperature, 1.0, 1.0).offset(-273.15),
+    Unit::new(&["bit", "bits"], DataSize, 1.0, 8.0),
+    Unit::new(&["B", "byte", "bytes"], DataSize, 1.0, 1.0),
+    Unit::new(&["kB", "KB", "kilobyte", "kilobytes"], DataSize, 1e3, 1.0),
+    Unit::new(&["MB", "megabyte", "megabytes"], DataSize, 1e6, 1.0),
+    Unit::new(&["GB", "gigabyte", "gigabytes"], DataSize, 1e9, 1.0),
+    Unit::new(&["TB", "terabyte", "terabytes"], DataSize, 1e12, 1.0),
+    Unit::new(&["KiB", "kibibyte", "kibibytes"], DataSize, 1024.0, 1.0),
+    Unit::new(&["MiB", "mebibyte", "mebibytes"], DataSize, 1_048_576.0, 1.0),
+    Unit::new(&["GiB", "gibibyte", "gibibytes"], DataSize, 1_073_741_824.0, 1.0),
+    Unit::new(&["TiB", "tebibyte", "tebibytes"], DataSize, 1_099_511_627_776.0, 1.0),
+    Unit::new(&["ms", "millisecond", "milliseconds"], Time, 1.0, 1000.0),
+    Unit::new(&["s", "sec", "second", "seconds"], Time, 1.0, 1.0),
+    Unit::new(&["min", "minute", "minutes"], Time, 60.0, 1.0),
+    Unit::new(&["h", "hr", "hour", "hours"], Time, 3600.0, 1.0),
+    Unit::new(&["d", "day", "days"], Time, 86_400.0, 1.0),
+    Unit::new(&["wk", "week", "weeks"], Time, 604_800.0, 1.0),
+    // The Julian year of 365.25 days
+    Unit::new(&["yr", "year", "years"], Time, 31_557_600.0, 1.0),
+];
+
+/// The unit called `name`, matching any of its spellings exactly or, for spelled-out names, ignoring ASCII case
+///
+/// Symbols must match exactly, since some differ only in case: `Mb` is not
+/// `MB`, and `Ms` is not `ms`.
+pub fn unit(name: &str) -> Option<&'static Unit> {
+    let names = || UNITS.iter().flat_map(|unit| unit.names.iter().map(move |n| (unit, *n)));
+    let spelled_out = |n: &str| n.len() >= 3 && n.bytes().all(|b| b.is_ascii_lowercase() || b == b'_');
+    names()
+        .find(|(_, n)| *n == name)
+        .or_else(|| names().find(|(_, n)| spelled_out(n) && n.eq_ignore_ascii_case(name)))
+        .map(|(unit, _)| unit)
+}
+
+/// Look up `name`, suggesting a near spelling if it is unknown
+fn lookup(name: &str) -> Result<&'static Unit> {
+    unit(name).ok_or_else(|| {
+        let names = UNITS.iter().flat_map(|unit| unit.names.iter().copied());
+        ComputeError::InvalidStructure(match closest_name(name, names) {
+            Some(near) => format!("unknown unit \"{}\"; did you mean \"{}\"?", name, near),
+            None => format!("unknown unit \"{}\"", name),
+        })
+    })
+}
+
+/// The numbers `[offset, numerator, denominator, shift]` for which `x` in `from` is
+/// `(x + offset) * numerator / denominator - shift` in `to`, see `apply`
+pub(crate) fn conversion(from: &str, to: &str) -> Result<[f64; 4]> {
+    let (source, target) = (lookup(from)?, lookup(to)?);
+    if source.dimension != target.dimension {
+        return Err(ComputeError::TypeMismatch(format!(
+            "cannot convert the {} \"{}\" to the {} \"{}\"",
+            source.dimension, from, target.dimension, to
+        )));
+    }
+    Ok([
+        source.offset,
+        source.numerator * target.denominator,
+        source.denominator * target.numerator,
+        target.offset,
+    ])
+}
+
+/// Convert `[x, offset, numerator, denominator, shift]`, a value followed by a `conversion`
+pub(crate) fn apply(args: &[f64]) -> f64 {
+    (args[0] + args[1]) * args[2] / args[3] - args[4]
+}
+
+/// Convert `value` from the unit called `from` to the unit called `to`
+pub fn convert(value: f64, from: &str, to: &str) -> Result<f64> {
+    let [offset, numerator, denominator, shift] = conversion(from, to)?;
+    Ok(apply(&[value, offset, numerator, denominator, shift]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(convert(5.0, "km", "m"), Ok(5000.0));
+        assert_eq!(convert(1.0, "ft", "in"), Ok(12.0));
+        assert_eq!(convert(1.0, "mi", "ft"), Ok(5280.0));
+        assert_eq!(convert(100.0, "C", "F"), Ok(212.0));
+        assert_eq!(convert(-40.0, "fahrenheit", "celsius"), Ok(-40.0));
+        assert_eq!(convert(0.0, "K", "°C"), Ok(-273.15));
+        assert_eq!(convert(1.0, "lb", "oz"), Ok(16.0));
+        assert_eq!(convert(1.0, "GiB", "MiB"), Ok(1024.0));
+        assert_eq!(convert(1.0, "byte", "bits"), Ok(8.0));
+        assert_eq!(convert(1.5, "h", "min"), Ok(90.0));
+        assert_eq!(convert(1.0, "wk", "d"), Ok(7.0));
+    }
+
+    #[test]
+    fn test_unit_names() {
+        assert_eq!(unit("Metres").map(|u| u.names[0]), Some("m"));
+        assert_eq!(unit("KILOBYTES"), unit("kB"));
+        for symbol in ["kb", "Mb", "Ms", "MM"] {
+            assert_eq!(unit(symbol), None, "{}", symbol);
+        }
+        assert_eq!(
+            convert(1.0, "Mb", "kB").unwrap_err().to_string(),
+            "unknown unit \"Mb\"; did you mean \"MB\"?"
+        );
+        assert!(UNITS.iter().all(|u| u.names.iter().all(|name| unit(name) == Some(u))));
+        assert_eq!(
+            convert(1.0, "kgs", "g").unwrap_err().to_string(),
+            "unknown unit \"kgs\"; did you mean \"kg\"?"
+        );
+        assert_eq!(
+            convert(1.0, "kg", "km").unwrap_err().to_string(),
+            "Type mismatch: cannot convert the mass \"kg\" to the length \"km\""
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Cross-checking f64 results against an exact rational backend
+
+use crate::compare::{approx_eq, ApproxMode};
+use crate::prelude::*;
+use crate::{format_number, functions, not_a_number, AngleMode, Bindings, ComputeError, Expr, Result, RoundingMode};
+use core::mem;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+/// Outcome of checking a result against exact rational arithmetic
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verification {
+    /// The exact value rounded to the nearest `f64`, or the error exact evaluation hit
+    pub reference: Result<f64>,
+    /// Whether the checked result matches `reference` within the tolerance
+    pub agrees: bool,
+}
+
+/// Check `value`, the f64 result of `expr`, against exact rational evaluation
+///
+/// Exact evaluation computes every operation on arbitrary-precision fractions
+/// of the same literals and only rounds at the end, so it shares no rounding
+/// with the f64 path. The two agree when both succeed within `tolerance`
+/// (absolute or relative, like `compare`) or both fail with the same kind of
+/// error. A large disagreement points at catastrophic cancellation or an
+/// intermediate overflow, e.g. `1e308 * 10 / 10` is infinite in f64 but
+/// exactly `1e308`. Trigonometric functions measure angles in `angle_mode`.
+pub fn verify(
+    expr: &Expr,
+    env: &Bindings,
+    value: &Result<f64>,
+    tolerance: f64,
+    angle_mode: AngleMode,
+) -> Verification {
+    verify_rounding(expr, env, value, tolerance, angle_mode, RoundingMode::default())
+}
+
+/// Like `verify`, with `round` rounding in `rounding`
+pub(crate) fn verify_rounding(
+    expr: &Expr,
+    env: &Bindings,
+    value: &Result<f64>,
+    tolerance: f64,
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+) -> Verification {
+    let reference = eval_exact_rounding(expr, env, angle_mode, rounding).map(|exact| to_f64(&exact));
+    let agrees = match (value, &reference) {
+        (Ok(v), Ok(r)) => {
+            approx_eq(*v, *r, ApproxMode::Absolute(tolerance)) || approx_eq(*v, *r, ApproxMode::Relative(tolerance))
+        }
+        (Err(a), Err(b)) => mem::discriminant(a) == mem::discriminant(b),
+        _ => false,
+    };
+    Verification { reference, agrees }
+}
+
+/// Whether `value`, the f64 result of `expr`, is exactly what rational arithmetic gives
+///
+/// False when either evaluation fails. Functions and powers that
+/// `eval_exact` computes in f64 count as exact, so for them this only says
+/// that the surrounding arithmetic lost nothing.
+pub fn is_exact(expr: &Expr, env: &Bindings, value: &Result<f64>, angle_mode: AngleMode) -> bool {
+    is_exact_rounding(expr, env, value, angle_mode, RoundingMode::default())
+}
+
+/// Like `is_exact`, with `round` rounding in `rounding`
+pub(crate) fn is_exact_rounding(
+    expr: &Expr,
+    env: &Bindings,
+    value: &Result<f64>,
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+) -> bool {
+    let Ok(value) = value else {
+        return false;
+    };
+    match (eval_exact_rounding(expr, env, angle_mode, rounding), BigRational::from_float(*value)) {
+        (Ok(exact), Some(value)) => exact == value,
+        _ => false,
+    }
+}
+
+/// Largest exponent magnitude raised exactly, bounding the size of the result
+const MAX_EXACT_EXPONENT: u32 = 4096;
+
+/// Evaluate `expr` exactly, looking variables up in `env` and measuring angles in `angle_mode`
+///
+/// Literals and variables are taken at their exact binary values, and a
+/// percent literal `n%` at exactly `n / 100`; values that are not finite
+/// have no exact form and give `Overflow`. Powers are
+/// exact for integer exponents up to `MAX_EXACT_EXPONENT` in magnitude;
+/// other powers, and built-in functions other than `abs`, `min`, `max`,
+/// `clamp`, `sum`, `product` and `count`, round their operands to f64 and use
+/// the f64 implementation.
+pub fn eval_exact(expr: &Expr, env: &Bindings, angle_mode: AngleMode) -> Result<BigRational> {
+    eval_exact_rounding(expr, env, angle_mode, RoundingMode::default())
+}
+
+/// Like `eval_exact`, with `round` rounding in `rounding`
+pub(crate) fn eval_exact_rounding(
+    expr: &Expr,
+    env: &Bindings,
+    angle_mode: AngleMode,
+    rounding: RoundingMode,
+) -> Result<BigRational> {
+    let eval = |e: &Expr| eval_exact_rounding(e, env, angle_mode, rounding);
+    let exact = |n: f64| BigRational::from_float(n).ok_or(ComputeError::Overflow);
+    let hundredths = |n: f64| Ok(exact(n)? / BigRational::from_integer(100.into()));
+    if let Some((a, p)) = expr.percent_change() {
+        let a = eval(a)?;
+        return Ok(a.clone() + a * hundredths(p)?);
+    }
+    match expr {
+        Expr::Number(n) => exact(*n),
+        Expr::Percent(n) => hundredths(*n),
+        Expr::Var(name) => env
+            .get(name)
+            .ok_or_else(|| ComputeError::UndefinedVariable(name.clone()))
+            .and_then(|n| exact(*n)),
+        Expr::Add(l, r) => Ok(eval(l)? + eval(r)?),
+        Expr::Sub(l, r) => Ok(eval(l)? - eval(r)?),
+        Expr::Mul(l, r) => Ok(eval(l)? * eval(r)?),
+        Expr::Div(l, r) => {
+            let divisor = eval(r)?;
+            if divisor.is_zero() {
+                Err(ComputeError::DivisionByZero)
+            } else {
+                Ok(eval(l)? / divisor)
+            }
+        }
+        Expr::Pow(l, r) => {
+            let (base, exponent) = (eval(l)?, eval(r)?);
+            match exponent.to_integer().to_i32().filter(|_| exponent.is_integer()) {
+                Some(n) if n.unsigned_abs() <= MAX_EXACT_EXPONENT => {
+                    if n < 0 && base.is_zero() {
+                        Err(ComputeError::DivisionByZero)
+                    } else {
+                        Ok(base.pow(n))
+                    }
+                }
+                _ => {
+                    let value = to_f64(&base).powf(to_f64(&exponent));
+                    if value.is_nan() {
+                        return Err(ComputeError::InvalidStructure(format!(
+                            "{} ^ {} has no real value",
+                            format_number(to_f64(&base)),
+                            format_number(to_f64(&exponent))
+                        )));
+                    }
+                    exact(value)
+                }
+            }
+        }
+        Expr::Neg(e) => eval(e).map(|n| -n),
+        Expr::Call(name, args) => {
+            let function = functions::resolve(name, args.len())?;
+            let args = function.operands(args, |bound| eval(bound).map(|exact| to_f64(&exact)))?;
+            let args = args.iter().map(eval).collect::<Result<Vec<_>>>()?;
+            match function.name {
+                "abs" => return Ok(args[0].abs()),
+                "min" => return Ok(args.into_iter().min().expect("at least two arguments")),
+                "max" => return Ok(args.into_iter().max().expect("at least two arguments")),
+                "clamp" if args[1] <= args[2] => return Ok(args[0].clone().clamp(args[1].clone(), args[2].clone())),
+                "sum" => return Ok(args.into_iter().sum()),
+                "product" => return Ok(args.into_iter().product()),
+                "count" => return Ok(BigRational::from_integer(args.len().into())),
+                _ => {}
+            }
+            let args: Vec<f64> = args.iter().map(to_f64).collect();
+            let value = function.eval(&args, angle_mode, rounding);
+            if value.is_nan() {
+                let args: Vec<String> = args.into_iter().map(format_number).collect();
+                return Err(ComputeError::InvalidStructure(format!(
+                    "{}({}) has no real value",
+                    name,
+                    args.join(", ")
+                )));
+            }
+            exact(value)
+        }
+        Expr::Compare(relation, l, r) => {
+            let (l, r) = (eval(l)?, eval(r)?);
+            Ok(exact_truth(relation.accepts(l.partial_cmp(&r))))
+        }
+        Expr::If(cond, then, otherwise) => eval(if eval(cond)?.is_zero() { otherwise } else { then }),
+        Expr::And(l, r) | Expr::Or(l, r) => {
+            // A true left operand decides `or` and a false one decides `and`
+            let decisive = matches!(expr, Expr::Or(..));
+            let holds = if eval(l)?.is_zero() != decisive { decisive } else { !eval(r)?.is_zero() };
+            Ok(exact_truth(holds))
+        }
+        Expr::Not(e) => Ok(exact_truth(eval(e)?.is_zero())),
+        Expr::List(_) | Expr::Range(..) | Expr::Text(_) => Err(not_a_number(expr)),
+    }
+}
+
+/// The exact 1 or 0 for a comparison or logical result
+fn exact_truth(holds: bool) -> BigRational {
+    if holds {
+        BigRational::one()
+    } else {
+        BigRational::zero()
+    }
+}
+
+/// Nearest f64, saturating to an infinity past the f64 range
+fn to_f64(exact: &BigRational) -> f64 {
+    exact.to_f64().unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare::DEFAULT_TOLERANCE;
+    use crate::{eval_expr, parse_expression};
+
+    fn check(source: &str) -> Verification {
+        let expr = parse_expression(source).unwrap();
+        verify(&expr, &Bindings::new(), &eval_expr(&expr), DEFAULT_TOLERANCE, AngleMode::Radians)
+    }
+
+    #[test]
+    fn test_agreement() {
+        assert_eq!(check("0.1 + 0.2"), Verification { reference: Ok(0.30000000000000004), agrees: true });
+        assert!(check("(1 + 2) * 3 / 7").agrees);
+        let zero = check("1 / (2 - 2)");
+        assert_eq!(zero.reference, Err(ComputeError::DivisionByZero));
+        assert!(zero.agrees);
+        assert_eq!(check("1.5 ^ 3 ^ 2").reference, Ok(1.5f64.powi(9)));
+        assert!(check("2 ^ -0.5").agrees);
+        assert!(check("sqrt(2) * abs(-3) + exp(1)").agrees);
+        assert!(check("atan2(1, 2) - sin(0.5) ^ 2").agrees);
+        assert!(check("2 > 1 ? 1 / 3 : 1 / 0").agrees);
+        assert!(check("(1 + 2 >= 3) + (1 - 2 == -1)").agrees);
+        assert!(check("0 and 1 / 0 or not 1 / 3 > 0.3").agrees);
+        assert_eq!(check("200 + 10% - 12.5%").reference, Ok(192.5));
+    }
+
+    #[test]
+    fn test_discrepancies() {
+        let overflow = check("1e308 * 10 / 10");
+        assert_eq!(overflow.reference, Ok(1e308));
+        assert!(!overflow.agrees);
+
+        // Cancellation: 1e16 + 1 rounds back to 1e16 in f64
+        let cancel = check("(1e16 + 1) - 1e16");
+        assert_eq!(cancel.reference, Ok(1.0));
+        assert!(!cancel.agrees);
+
+        let huge = check("2 ^ 1100 / 2 ^ 1000");
+        assert_eq!(huge.reference, Ok(2f64.powi(100)));
+        assert!(!huge.agrees);
+        assert_eq!(check("0 ^ -1").reference, Err(ComputeError::DivisionByZero));
+        assert!(matches!(check("(-8) ^ (1 / 3)").reference, Err(ComputeError::InvalidStructure(_))));
+        assert_eq!(check("ln(0)").reference, Err(ComputeError::Overflow));
+        assert!(check("ln(-1)").reference.is_err());
+        assert!(matches!(check("asin(2)").reference, Err(ComputeError::InvalidStructure(_))));
+
+        // The f64 sum rounds back to 1e16 and takes the other branch
+        let branch = check("1e16 + 1 == 1e16 ? 0 : 100");
+        assert_eq!(branch.reference, Ok(100.0));
+        assert!(!branch.agrees);
+    }
+
+    #[test]
+    fn test_exactness() {
+        let exact = |source: &str| {
+            let expr = parse_expression(source).unwrap();
+            is_exact(&expr, &Bindings::new(), &eval_expr(&expr), AngleMode::Radians)
+        };
+        assert!(exact("1.5 * 4 - 2 ^ 10"));
+        assert!(exact("0.5 + 0.25"));
+        assert!(!exact("0.1 + 0.2"));
+        assert!(!exact("1 / 3"));
+        assert!(!exact("(1e16 + 1) - 1e16"));
+        assert!(!exact("1 / 0"));
+    }
+
+    #[test]
+    fn test_variables_and_huge_values() {
+        let env: Bindings = [("x".to_string(), 0.5)].into_iter().collect();
+        assert_eq!(eval_exact(&parse_expression("x * 4").unwrap(), &env, AngleMode::Radians).map(|e| to_f64(&e)), Ok(2.0));
+        let big = eval_exact(&parse_expression("1e308 * 1e308").unwrap(), &env, AngleMode::Radians).unwrap();
+        assert_eq!(to_f64(&big), f64::INFINITY);
+        assert_eq!(to_f64(&-big), f64::NEG_INFINITY);
+    }
+}
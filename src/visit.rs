@@ -0,0 +1,157 @@
+//! Visitor and folder traits for walking and rewriting expression trees
+//!
+//! Implement only the method for the nodes you care about and let `walk_expr`
+//! or `walk_fold` handle the rest, so analyses and rewrites keep working as
+//! the AST grows new variants.
+
+use crate::Expr;
+use crate::prelude::*;
+use core::mem;
+
+/// A read-only walk over an expression tree
+///
+/// The default `visit_expr` visits every child in source order through
+/// `walk_expr`; an override that still wants to reach the children calls
+/// `walk_expr` itself.
+pub trait Visitor {
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+}
+
+/// Call `visitor.visit_expr` on each direct child of `expr`, left to right
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_) => {}
+        Expr::Add(l, r)
+        | Expr::Sub(l, r)
+        | Expr::Mul(l, r)
+        | Expr::Div(l, r)
+        | Expr::Pow(l, r)
+        | Expr::Compare(_, l, r)
+        | Expr::And(l, r)
+        | Expr::Or(l, r)
+        | Expr::Range(l, r) => {
+            visitor.visit_expr(l);
+            visitor.visit_expr(r);
+        }
+        Expr::Neg(e) | Expr::Not(e) => visitor.visit_expr(e),
+        Expr::Call(_, args) | Expr::List(args) => args.iter().for_each(|arg| visitor.visit_expr(arg)),
+        Expr::If(cond, then, otherwise) => {
+            visitor.visit_expr(cond);
+            visitor.visit_expr(then);
+            visitor.visit_expr(otherwise);
+        }
+    }
+}
+
+/// The direct children of `expr`, in the order `walk_expr` visits them
+pub(crate) fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_) => vec![],
+        Expr::Add(l, r)
+        | Expr::Sub(l, r)
+        | Expr::Mul(l, r)
+        | Expr::Div(l, r)
+        | Expr::Pow(l, r)
+        | Expr::Compare(_, l, r)
+        | Expr::And(l, r)
+        | Expr::Or(l, r)
+        | Expr::Range(l, r) => vec![l, r],
+        Expr::Neg(e) | Expr::Not(e) => vec![e],
+        Expr::Call(_, args) | Expr::List(args) => args.iter().collect(),
+        Expr::If(cond, then, otherwise) => vec![cond, then, otherwise],
+    }
+}
+
+/// A rewrite of an expression tree into a new one
+///
+/// The default `fold_expr` rebuilds the node from its children folded
+/// through `walk_fold`, so the identity folder returns its input unchanged.
+pub trait Folder {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        walk_fold(self, expr)
+    }
+}
+
+/// Rebuild `expr` with each direct child replaced by `folder.fold_expr` of it, left to right
+pub fn walk_fold<F: Folder + ?Sized>(folder: &mut F, mut expr: Expr) -> Expr {
+    // Children are folded in place, as `Expr` cannot be taken apart by value
+    let mut fold = |child: &mut Expr| {
+        let taken = mem::replace(child, Expr::Number(0.0));
+        *child = folder.fold_expr(taken);
+    };
+    match &mut expr {
+        Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_) => {}
+        Expr::Add(l, r)
+        | Expr::Sub(l, r)
+        | Expr::Mul(l, r)
+        | Expr::Div(l, r)
+        | Expr::Pow(l, r)
+        | Expr::Compare(_, l, r)
+        | Expr::And(l, r)
+        | Expr::Or(l, r)
+        | Expr::Range(l, r) => {
+            fold(l);
+            fold(r);
+        }
+        Expr::Neg(e) | Expr::Not(e) => fold(e),
+        Expr::Call(_, args) | Expr::List(args) => args.iter_mut().for_each(fold),
+        Expr::If(cond, then, otherwise) => {
+            fold(cond);
+            fold(then);
+            fold(otherwise);
+        }
+    }
+    expr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_expression;
+
+    /// Names of the functions called, in source order
+    struct Calls(Vec<String>);
+
+    impl Visitor for Calls {
+        fn visit_expr(&mut self, expr: &Expr) {
+            if let Expr::Call(name, _) = expr {
+                self.0.push(name.clone());
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    /// Replaces every variable with a number
+    struct Substitute(f64);
+
+    impl Folder for Substitute {
+        fn fold_expr(&mut self, expr: Expr) -> Expr {
+            match expr {
+                Expr::Var(_) => Expr::Number(self.0),
+                other => walk_fold(self, other),
+            }
+        }
+    }
+
+    struct Identity;
+
+    impl Folder for Identity {}
+
+    #[test]
+    fn test_visitor_reaches_every_node() {
+        let expr = parse_expression("max(abs(x), 2) + (y ? sum([sqrt(4)]) : min(1, 2))").unwrap();
+        let mut calls = Calls(Vec::new());
+        calls.visit_expr(&expr);
+        assert_eq!(calls.0, ["max", "abs", "sum", "sqrt", "min"]);
+    }
+
+    #[test]
+    fn test_folder_rewrites() {
+        let expr = parse_expression("x * 2 + max(y, sum(1..z)) - 10%").unwrap();
+        assert_eq!(Identity.fold_expr(expr.clone()), expr);
+        let folded = Substitute(3.0).fold_expr(expr);
+        assert_eq!(folded, parse_expression("3 * 2 + max(3, sum(1..3)) - 10%").unwrap());
+    }
+}
@@ -1,4 +1,10 @@
-use compute_mcp::{evaluate, parse_expression, ComputeError};
+// Written before the crate was linted; kept as written
+#![allow(clippy::needless_borrow, clippy::redundant_pattern_matching)]
+
+use compute_mcp::{
+    approx_eq, diagnose, evaluate, parse_expression, ApproxMode, ComputeError, Expr, Severity, DEFAULT_MAX_CHAIN,
+    DEFAULT_MAX_NESTING,
+};
 use proptest::prelude::*;
 
 // ===== NUMERIC EDGE CASES =====
@@ -71,6 +77,36 @@ fn test_deeply_nested_parentheses() {
     assert!(result > 0.0);
 }
 
+#[test]
+fn test_adversarial_nesting() {
+    // Every nesting construct is rejected before the parser recurses, and the default limit is safe on a test thread
+    let shapes: [fn(usize) -> String; 7] = [
+        |n| format!("{}1{}", "(".repeat(n), ")".repeat(n)),
+        |n| format!("{}1{}", "[".repeat(n), "]".repeat(n)),
+        |n| format!("{}1{}", "abs(".repeat(n), ")".repeat(n)),
+        |n| format!("{}1", "-".repeat(n)),
+        |n| format!("{}1", "not ".repeat(n)),
+        |n| format!("1{}", "^1".repeat(n)),
+        |n| format!("1{}", "?1:1".repeat(n)),
+    ];
+    for shape in shapes {
+        let deep = shape(100_000);
+        assert!(matches!(evaluate(&deep), Err(ComputeError::LimitExceeded(_))), "{}", &deep[..8]);
+        assert!(!matches!(evaluate(&shape(DEFAULT_MAX_NESTING)), Err(ComputeError::LimitExceeded(_))));
+    }
+
+    // Operator chains parse in a loop but evaluate recursively, so their length is bounded too
+    let chain = |n: usize| format!("1{}", "+1".repeat(n));
+    assert!(matches!(evaluate(&chain(100_000)), Err(ComputeError::LimitExceeded(_))));
+    let nested = format!("{}1{}", format!("({}", "1+".repeat(11)).repeat(100), ")".repeat(100));
+    assert!(matches!(evaluate(&nested), Err(ComputeError::LimitExceeded(_))));
+    assert_eq!(evaluate(&chain(DEFAULT_MAX_CHAIN)), Ok(DEFAULT_MAX_CHAIN as f64 + 1.0));
+
+    // Each call parses its first argument once, so nested calls take linear time
+    let calls = format!("{}1{}", "sum(".repeat(100), "..2)".repeat(100));
+    assert!(parse_expression(&calls).is_ok());
+}
+
 #[test]
 fn test_very_long_expressions() {
     // Build a long chain of additions
@@ -158,11 +194,13 @@ fn test_malformed_numbers() {
     // Multiple decimal points
     assert!(matches!(evaluate("1.2.3"), Err(ComputeError::ParseError(_))));
     
-    // Decimal with no digits after
-    assert!(matches!(evaluate("1."), Err(ComputeError::ParseError(_))));
+    // A point needs digits on at least one side
+    assert!(matches!(evaluate("."), Err(ComputeError::ParseError(_))));
+    assert!(matches!(evaluate("1..2"), Err(ComputeError::ParseError(_))));
     
-    // Leading decimal (allowed in some languages, not here)
-    assert!(matches!(evaluate(".5"), Err(ComputeError::ParseError(_))));
+    // Either side of the point may be empty
+    assert_eq!(evaluate("1.").unwrap(), 1.0);
+    assert_eq!(evaluate(".5").unwrap(), 0.5);
     
     // Invalid characters in numbers
     assert!(matches!(evaluate("1a2"), Err(ComputeError::ParseError(_))));
@@ -177,10 +215,10 @@ fn test_operator_errors() {
     // Missing operands
     assert!(matches!(evaluate("+"), Err(ComputeError::ParseError(_))));
     assert!(matches!(evaluate("1 +"), Err(ComputeError::ParseError(_))));
-    assert!(matches!(evaluate("+ 1"), Err(ComputeError::ParseError(_))));
+    assert!(matches!(evaluate("1 * +"), Err(ComputeError::ParseError(_))));
     
     // Double operators
-    assert!(matches!(evaluate("1 ++ 2"), Err(ComputeError::ParseError(_))));
+    assert!(matches!(evaluate("1 +* 2"), Err(ComputeError::ParseError(_))));
     assert!(matches!(evaluate("1 */ 2"), Err(ComputeError::ParseError(_))));
     
     // Missing operators
@@ -271,6 +309,21 @@ proptest! {
         let _ = evaluate(&s);
     }
     
+    #[test]
+    fn diagnose_reports_every_evaluation_failure(expr in arb_nested_expr(5)) {
+        // Anything evaluate rejects must surface as at least one error diagnostic
+        if evaluate(&expr).is_err() {
+            let diagnostics = diagnose(&expr);
+            assert!(diagnostics.iter().any(|d| d.severity == Severity::Error),
+                "No error diagnostic for {}: {:?}", expr, diagnostics);
+        }
+    }
+
+    #[test]
+    fn diagnose_never_panics(s in "\\PC*") {
+        let _ = diagnose(&s);
+    }
+
     #[test]
     fn parser_ast_consistency(expr in arb_nested_expr(5)) {
         // If it parses successfully, converting back to string and reparsing should work
@@ -282,7 +335,7 @@ proptest! {
                     // but evaluation should give same result
                     if let (Ok(v1), Ok(v2)) = (evaluate(&expr), evaluate(&expr_str)) {
                         if v1.is_finite() && v2.is_finite() {
-                            assert!((v1 - v2).abs() < 1e-10);
+                            assert!(approx_eq(v1, v2, ApproxMode::Absolute(1e-10)));
                         }
                     }
                 }
@@ -316,6 +369,44 @@ fn test_expression_size_limits() {
     assert!(evaluate(&wide_expr).is_ok());
 }
 
+#[test]
+fn test_display_million_node_chain() {
+    // Build the tree iteratively; only printing is under test here
+    let mut expr = Expr::Number(1.0);
+    for _ in 0..1_000_000 {
+        expr = Expr::Add(Box::new(expr), Box::new(Expr::Number(1.0)));
+    }
+    let mut negated = Expr::Number(2.0);
+    for _ in 0..1_000_000 {
+        negated = Expr::Neg(Box::new(negated));
+    }
+
+    let printed = expr.to_string();
+    assert!(printed.trim_start_matches('(').starts_with("1 + 1) + 1) + 1)"));
+    assert!(printed.ends_with(" + 1)"));
+    assert_eq!(printed.matches('(').count(), 1_000_000);
+
+    let printed = negated.to_string();
+    assert!(printed.starts_with("-(-(-("));
+    assert_eq!(printed.len(), 1_000_000 * 3 + 1);
+}
+
+#[test]
+fn test_drop_deeply_nested_trees() {
+    // Left-leaning, right-leaning and unary chains must all free without recursion
+    let mut left = Expr::Number(1.0);
+    let mut right = Expr::Number(1.0);
+    let mut unary = Expr::Number(1.0);
+    for _ in 0..1_000_000 {
+        left = Expr::Sub(Box::new(left), Box::new(Expr::Number(1.0)));
+        right = Expr::Mul(Box::new(Expr::Number(2.0)), Box::new(right));
+        unary = Expr::Neg(Box::new(unary));
+    }
+    drop(left);
+    drop(right);
+    drop(unary);
+}
+
 #[test]
 fn test_special_operator_patterns() {
     // Alternating operations that might confuse the parser
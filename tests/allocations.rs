@@ -0,0 +1,60 @@
+//! Heap allocations made while parsing, counted by a wrapping global allocator
+
+use compute_mcp::parse_expression;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Allocations made by this thread while parsing `expr`, after a warm-up parse
+fn allocations(expr: &str) -> usize {
+    parse_expression(expr).unwrap();
+    let before = ALLOCATIONS.with(Cell::get);
+    parse_expression(expr).unwrap();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn test_successful_parses_build_no_error_messages() {
+    // Bounds as measured with pest 2.9, most of whose allocations are its own
+    // token queue; an error message built eagerly on any branch pushes one over
+    for (expr, bound) in [
+        ("1 + 2", 17),
+        ("-(-3) * 4", 21),
+        ("max(1, 2, 3)", 19),
+        ("sum(1..10)", 21),
+        ("x > 1 ? 2 : 3", 23),
+        ("-1 - -2 - -3", 24),
+    ] {
+        let count = allocations(expr);
+        assert!(count <= bound, "{} allocated {} times, more than {}", expr, count, bound);
+    }
+}
+
+#[test]
+fn test_digit_separators_are_stripped_in_place() {
+    assert_eq!(allocations("1_000 + 2_000.5"), allocations("1000 + 2000.5"));
+}
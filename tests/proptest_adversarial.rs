@@ -1,3 +1,6 @@
+// Written before the crate was linted; kept as written
+#![allow(clippy::len_zero, clippy::single_match)]
+
 use compute_mcp::*;
 use proptest::prelude::*;
 use proptest::num::f64::{POSITIVE, NEGATIVE, NORMAL, SUBNORMAL};
@@ -33,6 +36,22 @@ fn arb_number() -> impl Strategy<Value = f64> {
 }
 
 // Generate arbitrary AST expressions with controlled depth
+fn arb_relation() -> impl Strategy<Value = Relation> {
+    prop::sample::select(vec![
+        Relation::Less,
+        Relation::LessEq,
+        Relation::Greater,
+        Relation::GreaterEq,
+        Relation::Equal,
+        Relation::NotEqual,
+    ])
+}
+
+// Built-ins whose value is a number, leaving out text functions such as `to_hex`
+fn arb_numeric_builtin() -> impl Strategy<Value = Builtin> {
+    prop::sample::select(BUILTINS.iter().filter(|function| function.radix().is_none()).copied().collect::<Vec<_>>())
+}
+
 fn arb_expr() -> impl Strategy<Value = Expr> {
     let leaf = arb_number().prop_map(Expr::Number);
     
@@ -47,6 +66,28 @@ fn arb_expr() -> impl Strategy<Value = Expr> {
                 (inner.clone(), inner.clone()).prop_map(|(l, r)| Expr::Sub(Box::new(l), Box::new(r))),
                 (inner.clone(), inner.clone()).prop_map(|(l, r)| Expr::Mul(Box::new(l), Box::new(r))),
                 (inner.clone(), inner.clone()).prop_map(|(l, r)| Expr::Div(Box::new(l), Box::new(r))),
+                (inner.clone(), inner.clone()).prop_map(|(l, r)| Expr::Pow(Box::new(l), Box::new(r))),
+                // Built-in function calls
+                (arb_numeric_builtin(), prop::collection::vec(inner.clone(), 3))
+                    .prop_map(|(function, mut args)| {
+                        args.truncate(function.arity());
+                        if function.takes_list() {
+                            args = vec![Expr::List(args)];
+                        }
+                        if function.takes_units() {
+                            args.splice(1.., [Expr::Text("km".into()), Expr::Text("mi".into())]);
+                        }
+                        Expr::Call(function.name.to_string(), args)
+                    }),
+                // Comparisons and conditionals
+                (arb_relation(), inner.clone(), inner.clone())
+                    .prop_map(|(rel, l, r)| Expr::Compare(rel, Box::new(l), Box::new(r))),
+                (inner.clone(), inner.clone(), inner.clone())
+                    .prop_map(|(c, a, b)| Expr::If(Box::new(c), Box::new(a), Box::new(b))),
+                // Logical operations
+                (inner.clone(), inner.clone()).prop_map(|(l, r)| Expr::And(Box::new(l), Box::new(r))),
+                (inner.clone(), inner.clone()).prop_map(|(l, r)| Expr::Or(Box::new(l), Box::new(r))),
+                inner.clone().prop_map(|e| Expr::Not(Box::new(e))),
                 // Unary operations
                 inner.prop_map(|e| Expr::Neg(Box::new(e))),
             ]
@@ -59,15 +100,17 @@ fn arb_deep_expr(depth: u32) -> BoxedStrategy<Expr> {
     if depth == 0 {
         arb_number().prop_map(Expr::Number).boxed()
     } else {
+        // Share one inner strategy so construction stays linear in depth
+        let inner = arb_deep_expr(depth - 1);
         prop_oneof![
             // Deep left nesting
-            (arb_deep_expr(depth - 1), arb_number().prop_map(Expr::Number))
+            (inner.clone(), arb_number().prop_map(Expr::Number))
                 .prop_map(|(l, r)| Expr::Add(Box::new(l), Box::new(r))),
             // Deep right nesting  
-            (arb_number().prop_map(Expr::Number), arb_deep_expr(depth - 1))
+            (arb_number().prop_map(Expr::Number), inner.clone())
                 .prop_map(|(l, r)| Expr::Mul(Box::new(l), Box::new(r))),
             // Deep parentheses nesting
-            arb_deep_expr(depth - 1).prop_map(|e| Expr::Neg(Box::new(e))),
+            inner.prop_map(|e| Expr::Neg(Box::new(e))),
         ].boxed()
     }
 }
@@ -111,13 +154,9 @@ fn arb_expr_with_whitespace() -> impl Strategy<Value = String> {
         })
 }
 
-// Helper function to check if two f64 values are approximately equal
+// Like the library's absolute comparison, but NaN matches NaN
 fn approx_eq(a: f64, b: f64, epsilon: f64) -> bool {
-    if a.is_finite() && b.is_finite() {
-        (a - b).abs() < epsilon
-    } else {
-        a == b || (a.is_nan() && b.is_nan())
-    }
+    (a.is_nan() && b.is_nan()) || compute_mcp::approx_eq(a, b, ApproxMode::Absolute(epsilon))
 }
 
 proptest! {
@@ -167,7 +206,7 @@ proptest! {
         // Test subtraction (left associative)
         let sub_str = format!("{} - {} - {}", a, b, c);
         let sub_parsed = parse_expression(&sub_str).unwrap();
-        match sub_parsed {
+        match &sub_parsed {
             Expr::Sub(left, _right) => {
                 match left.as_ref() {
                     Expr::Sub(_, _) => {}, // (a - b) - c
@@ -181,7 +220,7 @@ proptest! {
         if b != 0.0 && c != 0.0 {
             let div_str = format!("{} / {} / {}", a, b, c);
             let div_parsed = parse_expression(&div_str).unwrap();
-            match div_parsed {
+            match &div_parsed {
                 Expr::Div(left, _) => {
                     match left.as_ref() {
                         Expr::Div(_, _) => {}, // (a / b) / c
@@ -350,7 +389,21 @@ proptest! {
     // Test 12: Whitespace insensitivity
     #[test]
     fn whitespace_insensitivity(expr_str in arb_expr_with_whitespace()) {
-        let stripped = expr_str.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+        // Keep one space between two words, so `not 1` does not become the name `not1`
+        let word = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+        let mut stripped = String::new();
+        let mut spaced = false;
+        for c in expr_str.chars() {
+            if c.is_whitespace() {
+                spaced = true;
+                continue;
+            }
+            if spaced && word(c) && stripped.ends_with(word) {
+                stripped.push(' ');
+            }
+            spaced = false;
+            stripped.push(c);
+        }
         
         // Both should parse to equivalent expressions
         match (parse_expression(&expr_str), parse_expression(&stripped)) {
@@ -455,18 +508,7 @@ proptest! {
     // Test 15: Expression depth is finite and reasonable
     #[test]
     fn finite_expression_depth(expr in arb_expr()) {
-        fn depth(e: &Expr) -> u32 {
-            match e {
-                Expr::Number(_) => 1,
-                Expr::Add(l, r) | Expr::Sub(l, r) | Expr::Mul(l, r) | Expr::Div(l, r) => {
-                    1 + depth(l).max(depth(r))
-                }
-                Expr::Neg(e) => 1 + depth(e),
-            }
-        }
-        
-        let d = depth(&expr);
-        prop_assert!(d <= MAX_DEPTH + 1); // Allow for some recursion overshoot
+        prop_assert!(expr.depth() <= MAX_DEPTH as usize + 1); // Allow for some recursion overshoot
     }
 
     // Test 16: Malformed input handling
@@ -542,10 +584,268 @@ proptest! {
 
     // Test 20: Parser doesn't accept invalid operators
     #[test]
-    fn invalid_operators(a in arb_number(), b in arb_number(), op in "[&|^%@#$!]") {
+    fn invalid_operators(a in arb_number(), b in arb_number(), op in "[&|%@#$!]") {
         let expr_str = format!("{} {} {}", a, op, b);
         prop_assert!(matches!(evaluate(&expr_str), Err(ComputeError::ParseError(_))));
     }
+
+    // Test 21: Printed numbers reparse to the bit-identical value
+    #[test]
+    fn number_formatting_round_trips_bits(n in any::<f64>().prop_filter("finite", |n| n.is_finite())) {
+        let printed = format_number(n);
+        let value = evaluate(&printed).unwrap();
+        prop_assert_eq!(value.to_bits(), n.to_bits(), "{} printed as {}", n, printed);
+
+        // The same holds inside larger printed expressions
+        let expr = Expr::Mul(Box::new(Expr::Number(1.0)), Box::new(Expr::Number(n)));
+        let value = evaluate(&expr.to_string()).unwrap();
+        prop_assert_eq!(value.to_bits(), n.to_bits(), "{} printed as {}", n, expr);
+    }
+
+    // Test 22: Exact printing reparses to a structurally identical tree
+    #[test]
+    fn exact_printing_preserves_structure(expr in arb_expr()) {
+        match expr.to_exact_string() {
+            Ok(printed) => {
+                let reparsed = parse_expression(&printed).unwrap();
+                prop_assert_eq!(&reparsed, &expr, "{} reparsed differently", printed);
+            }
+            Err(_) => {
+                // Only trees with literals the grammar cannot express are rejected
+                fn has_unprintable_literal(e: &Expr) -> bool {
+                    match e {
+                        Expr::Number(n) | Expr::Percent(n) => !n.is_finite() || n.is_sign_negative(),
+                        Expr::Var(_) | Expr::Text(_) => false,
+                        Expr::Add(l, r)
+                        | Expr::Sub(l, r)
+                        | Expr::Mul(l, r)
+                        | Expr::Div(l, r)
+                        | Expr::Pow(l, r)
+                        | Expr::Compare(_, l, r)
+                        | Expr::And(l, r)
+                        | Expr::Or(l, r)
+                        | Expr::Range(l, r) => {
+                            has_unprintable_literal(l) || has_unprintable_literal(r)
+                        }
+                        Expr::Neg(e) | Expr::Not(e) => has_unprintable_literal(e),
+                        Expr::Call(_, args) | Expr::List(args) => args.iter().any(has_unprintable_literal),
+                        Expr::If(c, a, b) => [c, a, b].into_iter().any(|e| has_unprintable_literal(e)),
+                    }
+                }
+                prop_assert!(has_unprintable_literal(&expr));
+            }
+        }
+    }
+
+    // Test 23: The flat fast path agrees with the full parser
+    #[test]
+    fn fast_path_matches_full_parser(
+        terms in prop::collection::vec((arb_number(), any::<bool>()), 2..=3),
+        ops in prop::collection::vec(prop_oneof![Just("+"), Just("-"), Just("*"), Just("/")], 2),
+    ) {
+        let mut expr = String::new();
+        for (i, (n, scientific)) in terms.iter().enumerate() {
+            if i > 0 {
+                expr.push_str(&format!(" {} ", ops[i - 1]));
+            }
+            let n = n.abs();
+            expr.push_str(&if *scientific { format!("{:e}", n) } else { n.to_string() });
+        }
+
+        let full = parse_expression(&expr).and_then(|ast| eval_expr(&ast));
+        match (evaluate(&expr), full) {
+            (Ok(fast), Ok(full)) => prop_assert!(
+                fast.to_bits() == full.to_bits() || (fast.is_nan() && full.is_nan()),
+                "{}: fast {} vs full {}", expr, fast, full
+            ),
+            (fast, full) => prop_assert_eq!(fast, full, "{}", expr),
+        }
+    }
+
+    // Test 24: The recursive-descent parser agrees with the pest parser
+    #[cfg(feature = "descent")]
+    #[test]
+    fn descent_parser_matches_pest(
+        expr in prop_oneof![
+            arb_expr_string(),
+            arb_expr_with_whitespace(),
+            "[0-9a-zE_.+*/^(), -]{0,24}",
+        ]
+    ) {
+        match (descent::parse_expression(&expr), parse_expression(&expr)) {
+            (Ok(descent), Ok(pest)) => prop_assert_eq!(descent, pest, "{}", expr),
+            (Err(descent), Err(pest)) => prop_assert_eq!(
+                std::mem::discriminant(&descent),
+                std::mem::discriminant(&pest),
+                "{}: {} vs {}", expr, descent, pest
+            ),
+            (descent, pest) => prop_assert!(false, "{}: {:?} vs {:?}", expr, descent, pest),
+        }
+    }
+
+    // Test 25: Simplification preserves the value, including failures
+    #[test]
+    fn simplify_preserves_value(seed in any::<u64>(), decimals in 0u32..2) {
+        let config = GeneratorConfig {
+            min_value: -2.0,
+            max_value: 2.0,
+            decimals,
+            avoid_division_by_zero: false,
+            ..Default::default()
+        };
+        for generated in ExprGenerator::new(config, seed).take(20) {
+            let simplified = simplify(&generated.expr);
+            match (eval_expr(&simplified), &generated.expected) {
+                (Ok(a), Ok(b)) => prop_assert!(
+                    a == *b || (a.is_nan() && b.is_nan()),
+                    "{} -> {}: {} vs {}", generated.source, simplified, a, b
+                ),
+                (a, b) => prop_assert_eq!(&a, b, "{} -> {}", generated.source, simplified),
+            }
+        }
+    }
+
+    // Test 26: Formatting preserves the tree and is idempotent
+    #[test]
+    fn format_source_preserves_tree(expr_str in arb_expr_string()) {
+        let original = parse_expression(&expr_str).unwrap();
+        let formatted = format_source(&expr_str).unwrap();
+        prop_assert_eq!(parse_expression(&formatted).unwrap(), original, "{}", formatted);
+        prop_assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    // Test 27: Power is right associative and binds tighter than `*` but not prefix minus
+    #[test]
+    fn power_associativity_and_precedence(a in arb_number(), b in arb_number(), c in arb_number()) {
+        let num = |n: f64| Box::new(Expr::Number(n));
+        let (a, b, c) = (a.abs(), b.abs(), c.abs());
+
+        let chain = parse_expression(&format!("{} ^ {} ^ {}", a, b, c)).unwrap();
+        prop_assert_eq!(chain, Expr::Pow(num(a), Box::new(Expr::Pow(num(b), num(c)))));
+
+        let product = parse_expression(&format!("{} * {} ^ {}", a, b, c)).unwrap();
+        prop_assert_eq!(product, Expr::Mul(num(a), Box::new(Expr::Pow(num(b), num(c)))));
+
+        let negated = parse_expression(&format!("-{} ^ {}", a, b)).unwrap();
+        prop_assert_eq!(negated, Expr::Pow(Box::new(Expr::Neg(num(a))), num(b)));
+    }
+
+    // Test 28: Power evaluates with `f64::powf`
+    #[test]
+    fn power_matches_powf(a in arb_number(), b in arb_number()) {
+        let expr = Expr::Pow(Box::new(Expr::Number(a)), Box::new(Expr::Number(b)));
+        let expected = a.powf(b);
+        let value = evaluate(&expr.to_string()).unwrap();
+        prop_assert!(
+            value.to_bits() == expected.to_bits() || (value.is_nan() && expected.is_nan()),
+            "{}: {} vs {}", expr, value, expected
+        );
+    }
+
+    // Test 29: Calls apply the named built-in or format its text, and other names are unknown functions
+    #[test]
+    fn calls_resolve_builtins(
+        function in prop::sample::select(BUILTINS),
+        n in arb_number(),
+        name in "[a-z_][a-z0-9_]{0,8}",
+    ) {
+        let args = vec![n; function.arity()];
+        let source: Vec<String> = args.iter().map(|&arg| Expr::Number(arg).to_string()).collect();
+        if let Some(base) = function.radix() {
+            let call = format!("{}({})", function.name, source[0]);
+            let text = evaluate_value(&call, &Bindings::new(), &EvalOptions::default());
+            prop_assert_eq!(text, radix::format_integer(n, base).map(Value::Text));
+        } else {
+            let (expected, source) = if function.takes_units() {
+                (convert(n, "ft", "in").unwrap(), format!("{}, \"ft\", \"in\"", source[0]))
+            } else if function.takes_list() {
+                (function.eval(&args, AngleMode::Radians, RoundingMode::default()), format!("[{}]", source.join(", ")))
+            } else {
+                (function.eval(&args, AngleMode::Radians, RoundingMode::default()), source.join(", "))
+            };
+            let value = evaluate(&format!("{}({})", function.name, source)).unwrap();
+            prop_assert!(
+                value.to_bits() == expected.to_bits() || (value.is_nan() && expected.is_nan()),
+                "{}({}): {} vs {}", function.name, n, value, expected
+            );
+        }
+
+        let result = evaluate(&format!("{}(1)", name));
+        // The logical keywords are operators, never names
+        if builtin(&name).is_none() && !matches!(name.as_str(), "and" | "or" | "not") {
+            prop_assert_eq!(result, Err(ComputeError::UnknownFunction(name)));
+        }
+    }
+
+    // Test 30: Adding or subtracting a percentage scales the left operand
+    #[test]
+    fn percent_change_scales(a in -1e6f64..1e6, p in 0.0f64..1e3) {
+        let percent = format!("{}%", Expr::Number(p));
+        let raised = evaluate(&format!("{} + {}", Expr::Number(a), percent)).unwrap();
+        prop_assert_eq!(raised.to_bits(), add_percent(a, p).to_bits());
+        let lowered = evaluate(&format!("{} - {}", Expr::Number(a), percent)).unwrap();
+        prop_assert_eq!(lowered.to_bits(), add_percent(a, -p).to_bits());
+        prop_assert!(approx_eq(raised, a * (1.0 + p / 100.0), 1e-6));
+    }
+
+    // Test 31: Every spelling of a finite literal, plain or scientific, parses back to the same value
+    #[test]
+    fn scientific_notation_round_trip(n in POSITIVE | NORMAL | SUBNORMAL, upper in any::<bool>(), plus in any::<bool>()) {
+        let n = n.abs();
+        prop_assume!(n.is_finite());
+        let scientific = format!("{:e}", n);
+        let mut spelled = if plus && !scientific.contains("e-") {
+            scientific.replace('e', "e+")
+        } else {
+            scientific.clone()
+        };
+        if upper {
+            spelled = spelled.to_uppercase();
+        }
+        for source in [n.to_string(), scientific, spelled] {
+            prop_assert_eq!(parse_expression(&source), Ok(Expr::Number(n)), "{}", source);
+        }
+    }
+
+    // Test 32: A divisor of negative zero fails exactly like one of positive zero
+    #[test]
+    fn signed_zero_divisors(n in arb_number(), factor in arb_number()) {
+        let dividend = Expr::Number(n);
+        let zero = Expr::Mul(Box::new(Expr::Number(0.0)), Box::new(Expr::Number(factor)));
+        for divisor in [Expr::Neg(Box::new(Expr::Number(0.0))), zero] {
+            let expr = Expr::Div(Box::new(dividend.clone()), Box::new(divisor));
+            prop_assert_eq!(eval_expr(&expr), Err(ComputeError::DivisionByZero), "{}", expr);
+            prop_assert_eq!(evaluate(&expr.to_string()), Err(ComputeError::DivisionByZero), "{}", expr);
+        }
+    }
+
+    // Test 33: Tree metrics agree: every node is a leaf or a counted operator, and no tree is deeper than it is large
+    #[test]
+    fn tree_metrics_agree(expr in arb_expr()) {
+        struct Leaves(usize);
+        impl Visitor for Leaves {
+            fn visit_expr(&mut self, expr: &Expr) {
+                if matches!(expr, Expr::Number(_) | Expr::Percent(_) | Expr::Var(_) | Expr::Text(_)) {
+                    self.0 += 1;
+                }
+                walk_expr(self, expr);
+            }
+        }
+        let mut leaves = Leaves(0);
+        leaves.visit_expr(&expr);
+        let operators: usize = expr.operator_histogram().values().sum();
+        prop_assert_eq!(expr.node_count(), leaves.0 + operators);
+        prop_assert!(expr.depth() <= expr.node_count());
+    }
+
+    // Test 34: A compiled expression evaluates to exactly what walking its tree gives
+    #[test]
+    fn compiled_matches_tree(expr in arb_expr()) {
+        let expected = eval_expr(&expr);
+        let value = compile(&expr).eval_with_env(&Bindings::new());
+        let nan = |result: &compute_mcp::Result<f64>| result.as_ref().is_ok_and(|v| v.is_nan());
+        prop_assert!(value == expected || (nan(&value) && nan(&expected)), "{}", expr);
+    }
 }
 
 #[cfg(test)]
@@ -1,4 +1,8 @@
-use compute_mcp::{evaluate, ComputeError, Expr};
+use compute_mcp::{
+    add_percent, approx_eq, builtin, convert, eval_expr_with_env, evaluate, evaluate_with, is_true, parse_expression,
+    truth_value, AngleMode, ApproxMode, Bindings, Builtin, ComputeError, EvalOptions, Expr, Relation, RoundingMode,
+    BUILTINS,
+};
 use proptest::prelude::*;
 
 mod proptest_adversarial;
@@ -17,6 +21,8 @@ fn operator_precedence() {
     assert_eq!(evaluate("2 + 3 * 4").unwrap(), 14.0); // Not 20
     assert_eq!(evaluate("10 - 2 * 3").unwrap(), 4.0); // Not 24
     assert_eq!(evaluate("(2 + 3) * 4").unwrap(), 20.0); // Parentheses override
+    assert_eq!(evaluate("2 * 3 ^ 2").unwrap(), 18.0); // Not 36
+    assert_eq!(evaluate("2 ^ 3 ^ 2").unwrap(), 512.0); // Not 64
 }
 
 #[test]
@@ -26,6 +32,89 @@ fn negative_numbers() {
     assert_eq!(evaluate("-(2 + 3)").unwrap(), -5.0);
 }
 
+#[test]
+fn builtin_functions() {
+    assert_eq!(evaluate("sqrt(16) + abs(-2)").unwrap(), 6.0);
+    assert_eq!(evaluate("ln(exp(2))").unwrap(), 2.0);
+    assert_eq!(evaluate("log10(1000)").unwrap(), 3.0);
+    assert_eq!(evaluate("min(4, -1, 2 * 3) + max(1, 2)").unwrap(), 1.0);
+    assert_eq!(evaluate("clamp(1 / 0.1, 0, 5)").unwrap(), 5.0);
+    assert_eq!(evaluate("round(10 / 3, 2) + floor(-1.5) + trunc(ceil(0.1))").unwrap(), 2.33);
+    assert_eq!(
+        evaluate("max(1)").unwrap_err().to_string(),
+        "max() takes at least 2 arguments but was given 1"
+    );
+    assert!(matches!(
+        evaluate("cbrt(8)"),
+        Err(ComputeError::UnknownFunction(name)) if name == "cbrt"
+    ));
+}
+
+#[test]
+fn trigonometry_and_angle_modes() {
+    assert_eq!(evaluate("sin(0) + cos(0)").unwrap(), 1.0);
+    assert_eq!(evaluate("atan2(1, 1) * 4").unwrap(), std::f64::consts::PI);
+
+    let degrees = EvalOptions {
+        angle_mode: AngleMode::Degrees,
+        ..EvalOptions::default()
+    };
+    assert_eq!(evaluate_with("sin(30 + 60) + cos(180)", &degrees).unwrap(), 0.0);
+    assert_eq!(evaluate_with("asin(1) + atan(1)", &degrees).unwrap(), 135.0);
+    assert!(approx_eq(
+        evaluate_with("tan(45)", &degrees).unwrap(),
+        1.0,
+        ApproxMode::Ulps(4)
+    ));
+    assert!(matches!(
+        evaluate("atan2(1)"),
+        Err(ComputeError::InvalidStructure(_))
+    ));
+}
+
+#[test]
+fn variables() {
+    let expr = parse_expression("x * 2 + y").unwrap();
+    let env: Bindings = [("x".to_string(), 3.0), ("y".to_string(), 0.5)].into_iter().collect();
+    assert_eq!(eval_expr_with_env(&expr, &env).unwrap(), 6.5);
+    assert!(matches!(
+        eval_expr_with_env(&expr, &Bindings::new()),
+        Err(ComputeError::UndefinedVariable(name)) if name == "x"
+    ));
+}
+
+#[test]
+fn comparisons_and_conditionals() {
+    assert_eq!(evaluate("1 + 1 == 2").unwrap(), 1.0);
+    assert_eq!(evaluate("3 < 2 * 1").unwrap(), 0.0);
+    let abs = parse_expression("x < 0 ? -x : x").unwrap();
+    for x in [-2.5, 0.0, 4.0] {
+        let env: Bindings = [("x".to_string(), x)].into_iter().collect();
+        assert_eq!(eval_expr_with_env(&abs, &env).unwrap(), x.abs());
+    }
+}
+
+#[test]
+fn logical_operators() {
+    assert_eq!(evaluate("1 < 2 and not 3 < 2").unwrap(), 1.0);
+    assert_eq!(evaluate("0 or 2 > 3").unwrap(), 0.0);
+    let in_range = parse_expression("x >= 0 and x <= 10 or x == -1").unwrap();
+    for (x, expected) in [(-1.0, 1.0), (-0.5, 0.0), (5.0, 1.0), (11.0, 0.0)] {
+        let env: Bindings = [("x".to_string(), x)].into_iter().collect();
+        assert_eq!(eval_expr_with_env(&in_range, &env).unwrap(), expected);
+    }
+}
+
+#[test]
+fn percentages() {
+    assert_eq!(evaluate("25%").unwrap(), 0.25);
+    assert_eq!(evaluate("80 + 25%").unwrap(), 100.0);
+    assert_eq!(evaluate("80 - 25% * 2").unwrap(), 79.5);
+    let price = parse_expression("x - 20% + 5%").unwrap();
+    let env: Bindings = [("x".to_string(), 50.0)].into_iter().collect();
+    assert_eq!(eval_expr_with_env(&price, &env).unwrap(), 42.0);
+}
+
 #[test]
 fn division_by_zero() {
     assert!(matches!(
@@ -44,7 +133,7 @@ fn parse_errors() {
     assert!(matches!(evaluate("2 +"), Err(ComputeError::ParseError(_))));
     assert!(matches!(
         evaluate("hello"),
-        Err(ComputeError::ParseError(_))
+        Err(ComputeError::UndefinedVariable(_))
     ));
 }
 
@@ -52,8 +141,16 @@ fn parse_errors() {
 fn direct_eval(expr: &Expr) -> f64 {
     match expr {
         Expr::Number(n) => *n,
-        Expr::Add(l, r) => direct_eval(l) + direct_eval(r),
-        Expr::Sub(l, r) => direct_eval(l) - direct_eval(r),
+        Expr::Percent(p) => p / 100.0,
+        Expr::Var(_) => f64::NAN,
+        Expr::Add(l, r) => match **r {
+            Expr::Percent(p) => add_percent(direct_eval(l), p),
+            _ => direct_eval(l) + direct_eval(r),
+        },
+        Expr::Sub(l, r) => match **r {
+            Expr::Percent(p) => add_percent(direct_eval(l), -p),
+            _ => direct_eval(l) - direct_eval(r),
+        },
         Expr::Mul(l, r) => direct_eval(l) * direct_eval(r),
         Expr::Div(l, r) => {
             let divisor = direct_eval(r);
@@ -63,13 +160,78 @@ fn direct_eval(expr: &Expr) -> f64 {
                 direct_eval(l) / divisor
             }
         }
+        Expr::Pow(l, r) => direct_eval(l).powf(direct_eval(r)),
+        Expr::Call(name, args) => match (builtin(name), args.as_slice()) {
+            (Some(function), [value, Expr::Text(from), Expr::Text(to)]) if function.takes_units() => {
+                convert(direct_eval(value), from, to).unwrap_or(f64::NAN)
+            }
+            (Some(function), [Expr::List(elements)]) if function.takes_list() => {
+                let elements: Vec<f64> = elements.iter().map(direct_eval).collect();
+                function.eval(&elements, AngleMode::Radians, RoundingMode::default())
+            }
+            (Some(function), _)
+                if function.accepts(args.len()) && !function.takes_list() && !function.takes_units() =>
+            {
+                let args: Vec<f64> = args.iter().map(direct_eval).collect();
+                function.eval(&args, AngleMode::Radians, RoundingMode::default())
+            }
+            _ => f64::NAN,
+        },
         Expr::Neg(e) => -direct_eval(e),
+        Expr::Compare(relation, l, r) => {
+            let (l, r) = (direct_eval(l), direct_eval(r));
+            if l.is_nan() || r.is_nan() {
+                f64::NAN
+            } else {
+                relation.eval(l, r)
+            }
+        }
+        Expr::If(cond, then, otherwise) => match direct_eval(cond) {
+            c if c.is_nan() => f64::NAN,
+            c if is_true(c) => direct_eval(then),
+            _ => direct_eval(otherwise),
+        },
+        Expr::And(l, r) | Expr::Or(l, r) => {
+            let decisive = matches!(expr, Expr::Or(..));
+            match direct_eval(l) {
+                l if l.is_nan() => f64::NAN,
+                l if is_true(l) == decisive => truth_value(decisive),
+                _ => match direct_eval(r) {
+                    r if r.is_nan() => f64::NAN,
+                    r => truth_value(is_true(r)),
+                },
+            }
+        }
+        Expr::Not(e) => match direct_eval(e) {
+            n if n.is_nan() => f64::NAN,
+            n => truth_value(!is_true(n)),
+        },
+        Expr::List(_) | Expr::Range(..) | Expr::Text(_) => f64::NAN,
     }
 }
 
+fn arb_relation() -> impl Strategy<Value = Relation> {
+    prop::sample::select(vec![
+        Relation::Less,
+        Relation::LessEq,
+        Relation::Greater,
+        Relation::GreaterEq,
+        Relation::Equal,
+        Relation::NotEqual,
+    ])
+}
+
+// Built-ins whose value is a number, leaving out text functions such as `to_hex`
+fn arb_numeric_builtin() -> impl Strategy<Value = Builtin> {
+    prop::sample::select(BUILTINS.iter().filter(|function| function.radix().is_none()).copied().collect::<Vec<_>>())
+}
+
 // Strategy for generating expression trees
 fn arb_expr() -> impl Strategy<Value = Expr> {
-    let leaf = (-100.0f64..100.0).prop_map(Expr::Number);
+    let leaf = prop_oneof![
+        (-100.0f64..100.0).prop_map(Expr::Number),
+        (0.0f64..100.0).prop_map(Expr::Percent),
+    ];
 
     leaf.prop_recursive(3, 20, 5, |inner| {
         prop_oneof![
@@ -77,6 +239,24 @@ fn arb_expr() -> impl Strategy<Value = Expr> {
             (inner.clone(), inner.clone()).prop_map(|(l, r)| Expr::Sub(l.into(), r.into())),
             (inner.clone(), inner.clone()).prop_map(|(l, r)| Expr::Mul(l.into(), r.into())),
             (inner.clone(), inner.clone()).prop_map(|(l, r)| Expr::Div(l.into(), r.into())),
+            (inner.clone(), inner.clone()).prop_map(|(l, r)| Expr::Pow(l.into(), r.into())),
+            (arb_numeric_builtin(), prop::collection::vec(inner.clone(), 3))
+                .prop_map(|(function, mut args)| {
+                    args.truncate(function.arity());
+                    if function.takes_list() {
+                        args = vec![Expr::List(args)];
+                    }
+                    if function.takes_units() {
+                        args.splice(1.., [Expr::Text("km".into()), Expr::Text("mi".into())]);
+                    }
+                    Expr::Call(function.name.into(), args)
+                }),
+            (arb_relation(), inner.clone(), inner.clone()).prop_map(|(rel, l, r)| Expr::Compare(rel, l.into(), r.into())),
+            (inner.clone(), inner.clone(), inner.clone())
+                .prop_map(|(c, a, b)| Expr::If(c.into(), a.into(), b.into())),
+            (inner.clone(), inner.clone()).prop_map(|(l, r)| Expr::And(l.into(), r.into())),
+            (inner.clone(), inner.clone()).prop_map(|(l, r)| Expr::Or(l.into(), r.into())),
+            inner.clone().prop_map(|e| Expr::Not(e.into())),
             inner.prop_map(|e| Expr::Neg(e.into())),
         ]
     })
@@ -102,7 +282,7 @@ proptest! {
         // Both should give the same result
         match parsed_result {
             Ok(value) => {
-                assert!((value - direct_result).abs() < 0.0001,
+                assert!(approx_eq(value, direct_result, ApproxMode::Absolute(0.0001)),
                     "Mismatch for {}: parsed {} vs direct {}",
                     expr_str, value, direct_result);
             }